@@ -1,46 +1,277 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicU32, Ordering},
+};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
 use rodio::Source;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{SAMPLE_RATE, UNISON_DETUNE_MAX_CENTS, VELOCITY_DEFAULT};
 
 /// boxed Rodio source producing mono `f32` samples, `Send` so it can live across threads
 pub type SynthSource = Box<dyn Source<Item = f32> + Send>;
 
+/// `f32` stored as bits in an `AtomicU32`, for values the audio thread reads every sample
+#[derive(Debug, Default)]
+pub struct AtomicF32(AtomicU32);
+
+impl AtomicF32 {
+    pub const fn new(v: f32) -> Self {
+        Self(AtomicU32::new(v.to_bits()))
+    }
+
+    #[inline]
+    pub fn load(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    #[inline]
+    pub fn store(&self, v: f32) {
+        self.0.store(v.to_bits(), Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn swap(&self, v: f32) -> f32 {
+        f32::from_bits(self.0.swap(v.to_bits(), Ordering::Relaxed))
+    }
+
+    /// only meaningful for non-negative values, whose bit patterns sort like the floats do
+    #[inline]
+    pub fn fetch_max(&self, v: f32) -> f32 {
+        f32::from_bits(self.0.fetch_max(v.to_bits(), Ordering::Relaxed))
+    }
+}
+
+/// per-voice frequency multiplier (1.0 = the note's own pitch), read by the oscillator every sample
+/// so bends, vibrato and glide can move a sounding voice without rebuilding its source
+pub type FreqMul = Arc<AtomicF32>;
+
+pub fn new_freq_mul() -> FreqMul {
+    Arc::new(AtomicF32::new(1.0))
+}
+
+/// names one parameter within the patch or node that has it
+pub type ParamId = &'static str;
+
+/// a parameter's value: a number, or a switch
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ParamValue {
+    Bool(bool),
+    Float(f32),
+}
+
+impl ParamValue {
+    pub fn float(self) -> Result<f32, Box<dyn std::error::Error>> {
+        match self {
+            ParamValue::Float(v) if v.is_finite() => Ok(v),
+            other => Err(format!("expected a number, got {other}").into()),
+        }
+    }
+
+    pub fn bool(self) -> Result<bool, Box<dyn std::error::Error>> {
+        match self {
+            ParamValue::Bool(b) => Ok(b),
+            other => Err(format!("expected on/off, got {other}").into()),
+        }
+    }
+}
+
+impl std::fmt::Display for ParamValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParamValue::Bool(b) => f.write_str(if *b { "on" } else { "off" }),
+            ParamValue::Float(v) => write!(f, "{v:.2}"),
+        }
+    }
+}
+
+/// one parameter as a UI sees it: where it is now and how far it can go. switches ignore the range
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ParamDesc {
+    pub id: ParamId,
+    pub value: ParamValue,
+    pub min: f32,
+    pub max: f32,
+    /// one nudge of a knob
+    pub step: f32,
+}
+
+impl ParamDesc {
+    pub fn float(id: ParamId, value: f32, min: f32, max: f32, step: f32) -> Self {
+        Self { id, value: ParamValue::Float(value), min, max, step }
+    }
+
+    pub fn switch(id: ParamId, on: bool) -> Self {
+        Self { id, value: ParamValue::Bool(on), min: 0.0, max: 1.0, step: 1.0 }
+    }
+
+    /// a step up or down from the current value (switches flip), kept in range
+    pub fn nudged(&self, up: bool) -> ParamValue {
+        match self.value {
+            ParamValue::Bool(b) => ParamValue::Bool(!b),
+            ParamValue::Float(v) => {
+                let step = if up { self.step } else { -self.step };
+                ParamValue::Float((v + step).clamp(self.min, self.max))
+            }
+        }
+    }
+
+    /// `value` as a number in range
+    pub fn clamp(&self, value: ParamValue) -> Result<f32, Box<dyn std::error::Error>> {
+        Ok(value.float()?.clamp(self.min, self.max))
+    }
+}
+
+/// the `desc` called `id`, or the error `set_param` gives for it
+pub fn find_param(params: &[ParamDesc], owner: &str, id: &str) -> Result<ParamDesc, Box<dyn std::error::Error>> {
+    params.iter().find(|p| p.id == id).copied().ok_or_else(|| format!("{owner} has no parameter {id}").into())
+}
+
 /// something that can produce audio for a given frequency (oscillator, sampler, full patch, etc)
 pub trait AudioSource: Send + Sync {
-    fn create_source(&self, frequency: f32) -> SynthSource;
+    fn create_source(&self, frequency: f32, freq_mul: FreqMul) -> SynthSource;
     fn name(&self) -> &'static str;
+
+    /// what can be tweaked on this patch while it plays; none by default
+    fn params(&self) -> Vec<ParamDesc> {
+        vec![]
+    }
+
+    /// changes one of `params`. takes `&self` since patches are shared with the voices, so the
+    /// values live in atomics; sounding notes follow where the patch can manage it
+    fn set_param(&self, id: &str, value: ParamValue) -> Result<(), Box<dyn std::error::Error>> {
+        let _ = value;
+        Err(format!("{} has no parameter {id}", self.name()).into())
+    }
+
+    /// `create_source` for a note played at `velocity` (0..1). `VELOCITY_DEFAULT` is the patch's
+    /// own level; patches that change timbre with velocity can override this
+    fn create_source_with_velocity(&self, frequency: f32, freq_mul: FreqMul, velocity: f32) -> SynthSource {
+        Box::new(self.create_source(frequency, freq_mul).amplify(velocity / VELOCITY_DEFAULT))
+    }
 }
 
 /// an effect/processor that transforms one source into another (filter, gain, ADSR, etc).
 pub trait Node: Send + Sync {
     fn apply(&self, input: SynthSource) -> SynthSource;
     fn name(&self) -> &'static str;
+
+    /// same as `AudioSource::params`
+    fn params(&self) -> Vec<ParamDesc> {
+        vec![]
+    }
+
+    fn set_param(&self, id: &str, value: ParamValue) -> Result<(), Box<dyn std::error::Error>> {
+        let _ = value;
+        Err(format!("{} has no parameter {id}", self.name()).into())
+    }
+
+    /// the transport's tempo, for the nodes with a rate that can sync to it
+    fn set_bpm(&self, bpm: f32) {
+        let _ = bpm;
+    }
+}
+
+/// a node's parameter values by id, as presets keep them
+pub type ParamValues = BTreeMap<String, ParamValue>;
+
+/// every parameter `node` has, by id
+pub fn param_values(node: &dyn Node) -> ParamValues {
+    node.params().into_iter().map(|p| (p.id.to_string(), p.value)).collect()
+}
+
+/// sets the ones in `values` that `node` has; ids it does not know (any more) are skipped
+pub fn set_param_values(node: &dyn Node, values: &ParamValues) {
+    for (id, value) in values {
+        let _ = node.set_param(id, *value);
+    }
 }
 
 /// a root source factory for a patch (oscillator/noise generator/etc), before nodes run
 pub trait Generator: Send + Sync {
-    fn create(&self, frequency: f32) -> SynthSource;
+    fn create(&self, frequency: f32, freq_mul: FreqMul) -> SynthSource;
     fn name(&self) -> &'static str;
 }
 
+/// several copies of the generator per note, detuned against each other and mixed into one voice
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Unison {
+    pub voices: u32,
+    /// distance between the lowest and highest copy is twice this, in cents
+    pub detune_cents: f32,
+}
+
+impl Default for Unison {
+    fn default() -> Self {
+        Self { voices: 1, detune_cents: 0.0 }
+    }
+}
+
+impl Unison {
+    /// frequency ratio of each copy, spread evenly over ±`detune_cents`
+    fn ratios(self) -> Vec<f32> {
+        let n = self.voices.max(1);
+        if n == 1 {
+            return vec![1.0];
+        }
+        (0..n)
+            .map(|i| {
+                let cents = self.detune_cents * (2.0 * i as f32 / (n - 1) as f32 - 1.0);
+                2f32.powf(cents / 1200.0)
+            })
+            .collect()
+    }
+}
+
 /// a patch = one generator feeding a chain of nodes (generator → node1 → node2 → ...)
 pub struct PatchSource {
     generator: Box<dyn Generator>,
     nodes: Vec<Box<dyn Node>>,
+    unison: Unison,
+    /// `unison.detune_cents`, live; new notes pick it up
+    detune_cents: AtomicF32,
+    name: Option<&'static str>,
 }
 
 impl PatchSource {
     pub fn new(generator: Box<dyn Generator>) -> Self {
-        Self { generator, nodes: vec![] }
+        Self { generator, nodes: vec![], unison: Unison::default(), detune_cents: AtomicF32::new(0.0), name: None }
     }
 
     pub fn push_node(mut self, node: Box<dyn Node>) -> Self {
         self.nodes.push(node);
         self
     }
+
+    pub fn with_unison(mut self, unison: Unison) -> Self {
+        self.unison = unison;
+        self.detune_cents.store(unison.detune_cents);
+        self
+    }
+
+    /// patch name when it should differ from the generator's
+    pub fn named(mut self, name: &'static str) -> Self {
+        self.name = Some(name);
+        self
+    }
 }
 
 impl AudioSource for PatchSource {
-    fn create_source(&self, frequency: f32) -> SynthSource {
-        let mut src = self.generator.create(frequency);
+    fn create_source(&self, frequency: f32, freq_mul: FreqMul) -> SynthSource {
+        let unison = Unison { detune_cents: self.detune_cents.load(), ..self.unison };
+        let ratios = unison.ratios();
+        // nodes are built for the engine rate, so the generator is brought to it first
+        let create = |f: f32, m: FreqMul| at_engine_rate(self.generator.create(f, m));
+        let mut src = if ratios.len() == 1 {
+            create(frequency, freq_mul)
+        } else {
+            // every copy reads the same freq_mul, so bends and vibrato move the whole stack
+            let copies = ratios.iter().map(|r| create(frequency * r, freq_mul.clone())).collect();
+            Box::new(UnisonMix::new(copies))
+        };
         for n in &self.nodes {
             src = n.apply(src);
         }
@@ -48,6 +279,115 @@ impl AudioSource for PatchSource {
     }
 
     fn name(&self) -> &'static str {
-        self.generator.name()
+        self.name.unwrap_or_else(|| self.generator.name())
+    }
+
+    /// the unison detune when there is a stack, then whatever the nodes have
+    fn params(&self) -> Vec<ParamDesc> {
+        let mut params = vec![];
+        if self.unison.voices > 1 {
+            params.push(ParamDesc::float("detune_cents", self.detune_cents.load(), 0.0, UNISON_DETUNE_MAX_CENTS, 1.0));
+        }
+        params.extend(self.nodes.iter().flat_map(|n| n.params()));
+        params
+    }
+
+    /// the first node with a parameter called `id` gets it
+    fn set_param(&self, id: &str, value: ParamValue) -> Result<(), Box<dyn std::error::Error>> {
+        if id == "detune_cents" && self.unison.voices > 1 {
+            let desc = find_param(&self.params(), self.name(), id)?;
+            self.detune_cents.store(desc.clamp(value)?);
+            return Ok(());
+        }
+        let node = self.nodes.iter().find(|n| n.params().iter().any(|p| p.id == id));
+        match node {
+            Some(node) => node.set_param(id, value),
+            None => Err(format!("{} has no parameter {id}", self.name()).into()),
+        }
     }
 }
+
+/// sums the unison copies, scaled by 1/√n so the stack is about as loud as one copy.
+/// ends with the first copy to end
+struct UnisonMix {
+    copies: Vec<SynthSource>,
+    gain: f32,
+}
+
+impl UnisonMix {
+    fn new(copies: Vec<SynthSource>) -> Self {
+        let gain = 1.0 / (copies.len().max(1) as f32).sqrt();
+        Self { copies, gain }
+    }
+}
+
+impl Iterator for UnisonMix {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let mut sum = 0.0;
+        for c in self.copies.iter_mut() {
+            sum += c.next()?;
+        }
+        Some(sum * self.gain)
+    }
+}
+
+impl Source for UnisonMix {
+    fn current_span_len(&self) -> Option<usize> { None }
+    fn channels(&self) -> u16 { 1 }
+    fn sample_rate(&self) -> u32 { SAMPLE_RATE }
+    fn total_duration(&self) -> Option<Duration> { None }
+}
+
+/// `src` as the engine expects it: sources at another rate (samples, soundfonts) are resampled
+/// to `SAMPLE_RATE`, so they keep their pitch and everything after them keeps its timing
+pub fn at_engine_rate(src: SynthSource) -> SynthSource {
+    if src.sample_rate() == SAMPLE_RATE {
+        src
+    } else {
+        Box::new(Resample::new(src, SAMPLE_RATE))
+    }
+}
+
+/// linear interpolation from the input's rate to `out_rate`. converts patch sources to the engine
+/// rate and the engine's output to the device rate
+pub struct Resample {
+    input: SynthSource,
+    step: f64,
+    pos: f64,
+    a: f32,
+    b: f32,
+    out_rate: u32,
+}
+
+impl Resample {
+    pub fn new(mut input: SynthSource, out_rate: u32) -> Self {
+        let step = input.sample_rate() as f64 / out_rate as f64;
+        let a = input.next().unwrap_or(0.0);
+        let b = input.next().unwrap_or(0.0);
+        Self { input, step, pos: 0.0, a, b, out_rate }
+    }
+}
+
+impl Iterator for Resample {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        while self.pos >= 1.0 {
+            self.pos -= 1.0;
+            self.a = self.b;
+            self.b = self.input.next()?;
+        }
+        let t = self.pos as f32;
+        self.pos += self.step;
+        Some(self.a + (self.b - self.a) * t)
+    }
+}
+
+impl Source for Resample {
+    fn current_span_len(&self) -> Option<usize> { None }
+    fn channels(&self) -> u16 { 1 }
+    fn sample_rate(&self) -> u32 { self.out_rate }
+    fn total_duration(&self) -> Option<Duration> { self.input.total_duration() }
+}
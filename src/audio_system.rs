@@ -1,6 +1,27 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
 use tokio::sync::{mpsc, watch, OnceCell, Mutex};
-use crate::audio_patch::AudioSource;
-use crate::fx::adsr::Adsr;
+use crate::audio_patch::{AudioSource, ParamDesc, ParamValue};
+use crate::fx::adsr::{Adsr, FilterEnvelope};
+use crate::fx::chain::FxKind;
+use crate::fx::compressor::CompressorSettings;
+use crate::fx::drive::DriveSettings;
+use crate::fx::eq::EqSettings;
+use crate::fx::lfo::LfoSettings;
+use crate::fx::reverb::ReverbSettings;
+use crate::presets;
+use crate::key::{Key, Keymap, ScaleLock};
+use crate::gain_staging::GainAdvice;
+use crate::meter::to_db;
+use crate::looper::{LooperState, LooperStatus};
+use crate::echo::EchoSettings;
+use crate::chord::ChordType;
+use crate::engine::{Track, TrackSettings};
+use crate::transport::{TimeSignature, Transport, TransportStatus};
+use crate::lessons::LessonStatus;
+use crate::midi_in::CcBinding;
+use crate::config::{ADSR_ATTACK_S, ADSR_DECAY_S, ADSR_SUSTAIN, ADSR_RELEASE_S};
 
 
 /// current audio state that the UI can read (volume/mute + which source is active).
@@ -9,6 +30,141 @@ pub struct AudioSnapshot {
     pub volume: f32,
     pub muted: bool,
     pub patch_name: String,
+    /// the amp envelope new notes get
+    pub adsr: Adsr,
+    /// the envelope new notes send to their filter cutoff
+    pub filter_env: FilterEnvelope,
+    /// notes currently held down, for the on-screen keyboard
+    pub active_keys: Vec<Key>,
+    /// notes the looper is playing back
+    pub loop_keys: Vec<Key>,
+    pub presets: Vec<String>,
+    pub preset_name: Option<String>,
+    pub notice: Option<String>,
+    pub master_peak_db: f32,
+    pub master_rms_db: f32,
+    pub gain_advice: Option<GainAdvice>,
+    pub auto_gain: bool,
+    /// the LFO routing table, one entry per route
+    pub lfos: Vec<LfoSettings>,
+    pub chord_assist: bool,
+    pub chord: ChordType,
+    /// the file the sampler patch plays, if one is loaded
+    pub sample: Option<PathBuf>,
+    /// where the sample browser opens
+    pub sample_dir: Option<PathBuf>,
+    /// the running script's name
+    pub script: Option<String>,
+    pub looper: LooperStatus,
+    pub echo: EchoSettings,
+    pub transport: TransportStatus,
+    pub scale_lock: ScaleLock,
+    pub keymap: Keymap,
+    pub reverb: ReverbSettings,
+    /// tilt EQ of the current patch, -1 dark .. 1 bright
+    pub tilt: f32,
+    /// waveshaper of the current patch
+    pub drive: DriveSettings,
+    /// three-band EQ of the current patch
+    pub eq: EqSettings,
+    /// the current patch's effect chain, in order; each one's parameters are in `params`
+    pub chain: Vec<ChainSlot>,
+    pub master_drive: DriveSettings,
+    pub compressor: CompressorSettings,
+    /// most gain reduction the master compressor applied since the last snapshot, dB
+    pub gain_reduction_db: f32,
+    /// the master spectral freeze is holding a drone
+    pub freeze: bool,
+    /// sustain pedal down
+    pub sustain: bool,
+    /// keys let go keep sounding until latch is turned off
+    pub latch: bool,
+    /// pitch bend, -1..1 of the bend range
+    pub bend: f32,
+    /// most note keys the keyboard has delivered at once this session
+    pub max_rollover: usize,
+    /// voices the engine is mixing, releasing ones included
+    pub voices: usize,
+    /// share of the audio callback's time budget in use, 1.0 being all of it
+    pub audio_load: f32,
+    /// audio callbacks that missed their deadline since startup
+    pub underruns: u64,
+    /// the mixer's channel strips, in `Track::ALL` order
+    pub tracks: [TrackSettings; Track::COUNT],
+    /// a take is being logged for MIDI export
+    pub recording: bool,
+    /// the running lesson, if any
+    pub lesson: Option<LessonStatus>,
+    /// every parameter `SetParam` can reach: the current patch's first, then the master effects'
+    pub params: Vec<ParamGroup>,
+    /// MIDI controller knobs and the parameters they move
+    pub cc_bindings: Vec<CcBinding>,
+    /// the parameter (owner, id) the next knob moved gets bound to
+    pub cc_learn: Option<(String, String)>,
+}
+
+/// the parameters of the current patch, or of one master effect
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ParamGroup {
+    /// `patch`, or the effect's name
+    pub owner: String,
+    pub params: Vec<ParamDesc>,
+}
+
+/// the global transport's buttons
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransportAction {
+    Start,
+    Stop,
+    Toggle,
+    /// one tap of tap tempo
+    Tap,
+}
+
+/// where a drive goes: on each note of the current patch, or over the whole mix
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DriveTarget {
+    Patch,
+    Master,
+}
+
+/// one effect of the current patch's chain. its `SetParam` owner is `chain.{index}`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ChainSlot {
+    pub kind: FxKind,
+    pub bypassed: bool,
+}
+
+/// a change to which effects are in the current patch's chain, by position
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum ChainEdit {
+    /// a new one at the end
+    Add { kind: FxKind },
+    Remove { index: usize },
+    Move { index: usize, to: usize },
+    Bypass { index: usize, on: bool },
+}
+
+/// the looper's transport buttons
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LooperAction {
+    /// record → play → overdub → play, like a one-button loop pedal
+    Advance,
+    TogglePlay,
+    UndoLayer,
+    Clear,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LessonAction {
+    /// start the first unfinished lesson, or stop the running one
+    Toggle,
+    /// skip to the next lesson
+    Next,
 }
 
 /// cmds that the UI sends to the audio runtime to change behavior
@@ -17,7 +173,60 @@ pub enum AudioCommand {
     SetMuted(bool),
     TogglePatch(Vec<Box<dyn AudioSource>>),
     SetPatch(Box<dyn AudioSource>),
+    /// same as pressing B
+    NextPatch,
+    /// one of the built-in patches, by name
+    SelectPatch(String),
     SetAdsr(Adsr),
+    SetFilterEnv(FilterEnvelope),
+    /// the sustain pedal, like holding space
+    SetSustain(bool),
+    /// a sustain that stays down until turned off, for drones
+    SetLatch(bool),
+    /// hard stop: every voice, echo, loop playback and effect tail silenced at once
+    Panic,
+    /// pitch bend, -1..1 of the bend range, like a pitch wheel
+    SetBend(f32),
+    /// one parameter of `owner` (see `ParamGroup`), by id
+    SetParam { owner: String, id: String, value: ParamValue },
+    /// add, remove, reorder or bypass an effect in the current patch's chain
+    EditChain(ChainEdit),
+    /// a knob on a MIDI controller moved: channel 1..16, controller, value 0..127
+    MidiCc { channel: u8, cc: u8, value: u8 },
+    /// binds the next knob moved to this parameter (owner, id); None stops waiting for one
+    LearnCc(Option<(String, String)>),
+    /// unbinds every knob from this parameter
+    ForgetCc { owner: String, id: String },
+    /// a mixer channel strip
+    SetTrack(Track, TrackSettings),
+    /// start logging a take, or stop and save it as a MIDI file
+    SetRecording(bool),
+    SetTempo(f32),
+    SetTimeSignature(TimeSignature),
+    Transport(TransportAction),
+    ApplyGainAdvice,
+    SetAutoGain(bool),
+    SetLfo(usize, LfoSettings),
+    SetChordAssist(bool),
+    SetChord(ChordType),
+    StartRolloverTest,
+    Looper(LooperAction),
+    SetEcho(EchoSettings),
+    SetScaleLock(ScaleLock),
+    SetReverb(ReverbSettings),
+    /// tilt EQ for the current patch
+    SetTilt(f32),
+    SetDrive(DriveTarget, DriveSettings),
+    /// capture the master spectrum and hold it, or let it fade
+    SetFreeze(bool),
+    Lesson(LessonAction),
+    SavePreset(String),
+    LoadPreset(String),
+    /// a WAV file for the sampler patch, which becomes the current one
+    LoadSample(PathBuf),
+    /// a Rhai script, in place of the one running
+    LoadScript(PathBuf),
+    StopScript,
 }
 
 /// handle used by the UI: send commands + subscribe to live snapshots
@@ -44,10 +253,154 @@ impl AudioHandle {
         let _ = self.tx.send(AudioCommand::SetPatch(patch));
     }
 
+    pub fn next_patch(&self) {
+        let _ = self.tx.send(AudioCommand::NextPatch);
+    }
+
+    pub fn select_patch(&self, name: impl Into<String>) {
+        let _ = self.tx.send(AudioCommand::SelectPatch(name.into()));
+    }
+
     pub fn set_adsr(&self, adsr: Adsr) {
         let _ = self.tx.send(AudioCommand::SetAdsr(adsr));
     }
 
+    pub fn set_filter_env(&self, filter_env: FilterEnvelope) {
+        let _ = self.tx.send(AudioCommand::SetFilterEnv(filter_env));
+    }
+
+    pub fn set_param(&self, owner: impl Into<String>, id: impl Into<String>, value: ParamValue) {
+        let _ = self.tx.send(AudioCommand::SetParam { owner: owner.into(), id: id.into(), value });
+    }
+
+    pub fn edit_chain(&self, edit: ChainEdit) {
+        let _ = self.tx.send(AudioCommand::EditChain(edit));
+    }
+
+    pub fn midi_cc(&self, channel: u8, cc: u8, value: u8) {
+        let _ = self.tx.send(AudioCommand::MidiCc { channel, cc, value });
+    }
+
+    pub fn learn_cc(&self, param: Option<(String, String)>) {
+        let _ = self.tx.send(AudioCommand::LearnCc(param));
+    }
+
+    pub fn forget_cc(&self, owner: impl Into<String>, id: impl Into<String>) {
+        let _ = self.tx.send(AudioCommand::ForgetCc { owner: owner.into(), id: id.into() });
+    }
+
+    pub fn set_tempo(&self, bpm: f32) {
+        let _ = self.tx.send(AudioCommand::SetTempo(bpm));
+    }
+
+    pub fn set_time_signature(&self, signature: TimeSignature) {
+        let _ = self.tx.send(AudioCommand::SetTimeSignature(signature));
+    }
+
+    pub fn transport(&self, action: TransportAction) {
+        let _ = self.tx.send(AudioCommand::Transport(action));
+    }
+
+    pub fn set_recording(&self, on: bool) {
+        let _ = self.tx.send(AudioCommand::SetRecording(on));
+    }
+
+    pub fn set_track(&self, track: Track, settings: TrackSettings) {
+        let _ = self.tx.send(AudioCommand::SetTrack(track, settings));
+    }
+
+    pub fn set_bend(&self, amount: f32) {
+        let _ = self.tx.send(AudioCommand::SetBend(amount));
+    }
+
+    pub fn set_sustain(&self, on: bool) {
+        let _ = self.tx.send(AudioCommand::SetSustain(on));
+    }
+
+    pub fn set_latch(&self, on: bool) {
+        let _ = self.tx.send(AudioCommand::SetLatch(on));
+    }
+
+    pub fn panic(&self) {
+        let _ = self.tx.send(AudioCommand::Panic);
+    }
+
+    pub fn apply_gain_advice(&self) {
+        let _ = self.tx.send(AudioCommand::ApplyGainAdvice);
+    }
+
+    pub fn set_auto_gain(&self, on: bool) {
+        let _ = self.tx.send(AudioCommand::SetAutoGain(on));
+    }
+
+    pub fn set_lfo(&self, index: usize, settings: LfoSettings) {
+        let _ = self.tx.send(AudioCommand::SetLfo(index, settings));
+    }
+
+    pub fn set_chord_assist(&self, on: bool) {
+        let _ = self.tx.send(AudioCommand::SetChordAssist(on));
+    }
+
+    pub fn set_chord(&self, chord: ChordType) {
+        let _ = self.tx.send(AudioCommand::SetChord(chord));
+    }
+
+    pub fn start_rollover_test(&self) {
+        let _ = self.tx.send(AudioCommand::StartRolloverTest);
+    }
+
+    pub fn looper(&self, action: LooperAction) {
+        let _ = self.tx.send(AudioCommand::Looper(action));
+    }
+
+    pub fn set_echo(&self, echo: EchoSettings) {
+        let _ = self.tx.send(AudioCommand::SetEcho(echo));
+    }
+
+    pub fn set_scale_lock(&self, lock: ScaleLock) {
+        let _ = self.tx.send(AudioCommand::SetScaleLock(lock));
+    }
+
+    pub fn set_reverb(&self, reverb: ReverbSettings) {
+        let _ = self.tx.send(AudioCommand::SetReverb(reverb));
+    }
+
+    pub fn set_tilt(&self, tilt: f32) {
+        let _ = self.tx.send(AudioCommand::SetTilt(tilt));
+    }
+
+    pub fn set_drive(&self, target: DriveTarget, drive: DriveSettings) {
+        let _ = self.tx.send(AudioCommand::SetDrive(target, drive));
+    }
+
+    pub fn set_freeze(&self, on: bool) {
+        let _ = self.tx.send(AudioCommand::SetFreeze(on));
+    }
+
+    pub fn lesson(&self, action: LessonAction) {
+        let _ = self.tx.send(AudioCommand::Lesson(action));
+    }
+
+    pub fn save_preset(&self, name: impl Into<String>) {
+        let _ = self.tx.send(AudioCommand::SavePreset(name.into()));
+    }
+
+    pub fn load_preset(&self, name: impl Into<String>) {
+        let _ = self.tx.send(AudioCommand::LoadPreset(name.into()));
+    }
+
+    pub fn load_sample(&self, path: impl Into<PathBuf>) {
+        let _ = self.tx.send(AudioCommand::LoadSample(path.into()));
+    }
+
+    pub fn load_script(&self, path: impl Into<PathBuf>) {
+        let _ = self.tx.send(AudioCommand::LoadScript(path.into()));
+    }
+
+    pub fn stop_script(&self) {
+        let _ = self.tx.send(AudioCommand::StopScript);
+    }
+
     pub fn subscribe(&self) -> watch::Receiver<AudioSnapshot> {
         self.snapshot_rx.clone()
     }
@@ -67,12 +420,7 @@ pub async fn get_handle() -> &'static AudioHandle {
     &AUDIO
         .get_or_init(|| async {
             let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
-            let initial = AudioSnapshot {
-                volume: 1.0,
-                muted: false,
-                patch_name: "Sine".to_string(),
-            };
-            let (snapshot_tx, snapshot_rx) = watch::channel(initial);
+            let (snapshot_tx, snapshot_rx) = watch::channel(initial_snapshot());
             AudioSystem {
                 handle: AudioHandle { tx: cmd_tx, snapshot_rx },
                 cmd_rx: Mutex::new(Some(cmd_rx)),
@@ -83,6 +431,70 @@ pub async fn get_handle() -> &'static AudioHandle {
         .handle
 }
 
+/// what the handle sees before the engine publishes anything
+fn initial_snapshot() -> AudioSnapshot {
+    AudioSnapshot {
+        volume: 1.0,
+        muted: false,
+        patch_name: "Sine".to_string(),
+        adsr: Adsr::new(ADSR_ATTACK_S, ADSR_DECAY_S, ADSR_SUSTAIN, ADSR_RELEASE_S),
+        filter_env: FilterEnvelope::default(),
+        active_keys: vec![],
+        loop_keys: vec![],
+        presets: presets::list_presets(),
+        preset_name: None,
+        notice: None,
+        master_peak_db: to_db(0.0),
+        master_rms_db: to_db(0.0),
+        gain_advice: None,
+        auto_gain: false,
+        lfos: vec![],
+        chord_assist: false,
+        chord: ChordType::Off,
+        sample: None,
+        sample_dir: None,
+        script: None,
+        looper: LooperStatus { state: LooperState::Empty, length_s: None, position_s: 0.0, layers: 0 },
+        echo: EchoSettings::default(),
+        transport: Transport::default().status(std::time::Instant::now()),
+        scale_lock: ScaleLock::default(),
+        keymap: Keymap::default(),
+        reverb: ReverbSettings::default(),
+        tilt: 0.0,
+        drive: DriveSettings::default(),
+        eq: EqSettings::default(),
+        chain: vec![],
+        master_drive: DriveSettings::default(),
+        compressor: CompressorSettings::default(),
+        gain_reduction_db: 0.0,
+        freeze: false,
+        sustain: false,
+        latch: false,
+        bend: 0.0,
+        max_rollover: 0,
+        voices: 0,
+        audio_load: 0.0,
+        underruns: 0,
+        tracks: [TrackSettings::default(); Track::COUNT],
+        recording: false,
+        lesson: None,
+        params: vec![],
+        cc_bindings: vec![],
+        cc_learn: None,
+    }
+}
+
+#[cfg(test)]
+impl AudioHandle {
+    /// a handle no engine listens to: what it sends comes out of the receiver, and the sender sets
+    /// the snapshot it reads
+    pub(crate) fn detached() -> (Self, mpsc::UnboundedReceiver<AudioCommand>, watch::Sender<AudioSnapshot>) {
+        let (tx, cmd_rx) = mpsc::unbounded_channel();
+        let (snapshot_tx, snapshot_rx) = watch::channel(initial_snapshot());
+        (Self { tx, snapshot_rx }, cmd_rx, snapshot_tx)
+    }
+}
+
 pub async fn take_runtime_channels(
 ) -> (mpsc::UnboundedReceiver<AudioCommand>, watch::Sender<AudioSnapshot>, AudioSnapshot) {
     let sys = AUDIO.get_or_init(|| async { unreachable!("call get_handle() first") }).await;
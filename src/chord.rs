@@ -0,0 +1,88 @@
+//! chord mode: every note key plays a whole chord built up from its note, so one hand can play
+//! the harmony while the other tweaks. like the echo it works on notes, so the looper replays
+//! chords and every patch gets them
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::CHORD_MAX_NOTES;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChordType {
+    /// one note per key
+    #[default]
+    Off,
+    Major,
+    Minor,
+    Sus4,
+    Seventh,
+    MajorSeventh,
+    MinorSeventh,
+    /// the intervals in `input.custom_chord`
+    Custom,
+}
+
+impl ChordType {
+    const ALL: [ChordType; 8] = [
+        ChordType::Off,
+        ChordType::Major,
+        ChordType::Minor,
+        ChordType::Sus4,
+        ChordType::Seventh,
+        ChordType::MajorSeventh,
+        ChordType::MinorSeventh,
+        ChordType::Custom,
+    ];
+
+    pub fn next(self) -> Self {
+        let i = Self::ALL.iter().position(|c| *c == self).unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ChordType::Off => "off",
+            ChordType::Major => "major",
+            ChordType::Minor => "minor",
+            ChordType::Sus4 => "sus4",
+            ChordType::Seventh => "7th",
+            ChordType::MajorSeventh => "maj7",
+            ChordType::MinorSeventh => "m7",
+            ChordType::Custom => "custom",
+        }
+    }
+}
+
+/// the chord type in use and the user's own shape
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chord {
+    pub kind: ChordType,
+    custom: Vec<i32>,
+}
+
+impl Chord {
+    /// `custom` is semitones above the key; the key itself is always played
+    pub fn new(kind: ChordType, custom: &[i32]) -> Self {
+        let mut notes = vec![0];
+        for i in custom {
+            if !notes.contains(i) && notes.len() < CHORD_MAX_NOTES {
+                notes.push(*i);
+            }
+        }
+        Self { kind, custom: notes }
+    }
+
+    /// semitones above the key of every note the key plays, the key's own first
+    pub fn intervals(&self) -> &[i32] {
+        match self.kind {
+            ChordType::Off => &[0],
+            ChordType::Major => &[0, 4, 7],
+            ChordType::Minor => &[0, 3, 7],
+            ChordType::Sus4 => &[0, 5, 7],
+            ChordType::Seventh => &[0, 4, 7, 10],
+            ChordType::MajorSeventh => &[0, 4, 7, 11],
+            ChordType::MinorSeventh => &[0, 3, 7, 10],
+            ChordType::Custom => &self.custom,
+        }
+    }
+}
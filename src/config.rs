@@ -6,18 +6,279 @@ pub const TICK: u64 = 10;
 //key.rs
 pub const BASE_FREQ: f32 = 440.0;
 pub const A4_SEMITONES: i32 = 57;
+pub const MIDI_A4: i32 = 69; //MIDI note number of A4
 pub const SEMITONES_PER_OCTAVE: i32 = 12;
 pub const KEYBOARD_BASE_OCTAVE: i32 = 4;
 
-//audio_source.rs
-pub const AMP_DEFAULT:f32 = 0.1;
+//tuning.rs
+pub const TUNING_REFERENCE_MIN_HZ: f32 = 200.0;
+pub const TUNING_REFERENCE_MAX_HZ: f32 = 1_000.0;
 
 //patches
+pub const AMP_DEFAULT:f32 = 0.1;
 pub const SAMPLE_RATE: u32 = 48_000;
 pub const ENDLESS: Duration = Duration::from_secs(3600);
+pub const SUPERSAW_VOICES: u32 = 7;
+pub const SUPERSAW_DETUNE_CENTS: f32 = 20.0;
+pub const FM_RATIO_DEFAULT: f32 = 2.0; //modulator freq / carrier freq
+pub const FM_INDEX_DEFAULT: f32 = 3.0; //peak phase deviation, radians
+pub const FM_RATIO_MAX: f32 = 16.0;
+pub const FM_INDEX_MAX: f32 = 20.0;
+pub const UNISON_DETUNE_MAX_CENTS: f32 = 100.0;
+pub const SUB_OCTAVES_DEFAULT: f32 = 1.0; //how far below the note the sub-oscillator plays
+pub const SUB_OCTAVES_MAX: f32 = 2.0;
+pub const PLUCK_DAMPING_DEFAULT: f32 = 0.5; //0 bright .. 1 dull
+pub const PLUCK_DECAY_DEFAULT: f32 = 0.996; //loop gain per period
+pub const PLUCK_DECAY_MIN: f32 = 0.9;
+pub const PLUCK_BEND_HEADROOM: f32 = 4.0; //how much lower than its note a string can be bent
+pub const DRUM_AMP: f32 = 0.3;
+pub const KICK_START_HZ: f32 = 150.0;
+pub const KICK_END_HZ: f32 = 45.0;
+pub const KICK_SWEEP_S: f32 = 0.03;
+pub const KICK_DECAY_S: f32 = 0.12;
+pub const SNARE_TONE_HZ: f32 = 185.0;
+pub const SNARE_TONE_DECAY_S: f32 = 0.04;
+pub const SNARE_NOISE_CUTOFF_HZ: f32 = 1_500.0; //noise below this is cut
+pub const SNARE_NOISE_DECAY_S: f32 = 0.06;
+pub const HAT_CUTOFF_HZ: f32 = 7_000.0;
+pub const HAT_DECAY_S: f32 = 0.015;
+pub const SAMPLES_DIR: &str = "samples"; //under the data dir, where the sample browser opens
+pub const SAMPLE_EXT: &str = "wav";
+pub const SAMPLE_MAX_S: f32 = 60.0; //longer files are cut
+
+// velocity (0..1)
+pub const VELOCITY_DEFAULT: f32 = 0.7; //plain key press, plays at the patch's own level
+pub const VELOCITY_ACCENT: f32 = 1.0; //key pressed with Shift held
+pub const RELEASE_VELOCITY_CURVE: f32 = 4.0; //a Shift release cuts the release time to about a quarter
 
 // ADSR defaults
 pub const ADSR_ATTACK_S: f32  = 0.5; //sec
 pub const ADSR_DECAY_S: f32   = 0.5; //sec
 pub const ADSR_SUSTAIN: f32   = 0.4; //0..1
 pub const ADSR_RELEASE_S: f32 = 1.0; //sec
+pub const ADSR_TIME_MIN_S: f32 = 0.001; //shortest stage, from the editor or a preset
+pub const ADSR_TIME_MAX_S: f32 = 10.0;
+pub const ADSR_CURVE_STEEPNESS: f32 = 5.0; //exponential segments: the time constant is a fifth of the segment
+
+// filter envelope defaults
+pub const FILTER_ENV_ATTACK_S: f32  = 0.01; //sec
+pub const FILTER_ENV_DECAY_S: f32   = 0.4;  //sec
+pub const FILTER_ENV_SUSTAIN: f32   = 0.3;  //0..1
+pub const FILTER_ENV_RELEASE_S: f32 = 0.5;  //sec
+pub const FILTER_ENV_BASE_HZ: f32 = 200.0; //cutoff with the envelope closed
+pub const FILTER_ENV_DEPTH_MAX: f32 = 7.0; //octaves, ~25kHz at full
+pub const FILTER_ENV_DEPTH_STEP: f32 = 0.25;
+
+// LFO defaults
+pub const LFO_RATE_DEFAULT: f32 = 5.0; //Hz
+pub const LFO_RATE_MIN: f32 = 0.05;
+pub const LFO_RATE_MAX: f32 = 20.0;
+pub const LFO_RATE_STEP: f32 = 1.25; //multiplier per key press
+pub const LFO_PITCH_DEPTH_DEFAULT: f32 = 0.3; //semitones
+pub const LFO_AMP_DEPTH_DEFAULT: f32 = 0.5; //0..1
+pub const LFO_CUTOFF_DEPTH_DEFAULT: f32 = 2.0; //octaves
+pub const LFO_CUTOFF_BASE_HZ: f32 = 2000.0;
+pub const LFO_CUTOFF_Q: f32 = 0.707;
+pub const BEND_RANGE_DEFAULT: f32 = 2.0; //semitones at full bend
+pub const BEND_GLIDE_MS: f32 = 40.0; //how quickly voices follow a bend
+pub const ANALOG_CENTS_MAX: f32 = 50.0; //per-voice detune and drift
+pub const ANALOG_DRIFT_RATE_HZ: f32 = 0.4; //how often the drift picks somewhere new to wander to
+
+//input.rs
+pub const CHORD_ASSIST_WINDOW_MS: u64 = 30; //presses this close together count as one chord
+pub const ROLLOVER_STEP_TIMEOUT_S: f32 = 5.0;
+pub const KEY_REPEAT_DELAY_MS: u64 = 200; //os key repeat never starts sooner than this into a hold
+pub const KEY_REPEAT_GAP_MS: u64 = 30; //a long-held key back down within this never went up
+
+//chord.rs
+pub const CHORD_MAX_NOTES: usize = 6; //a custom chord's notes, the key's own included
+
+//looper.rs, echo.rs
+pub const SCHEDULE_TICK_MS: u64 = 5; //how often scheduled note events are checked
+
+//script.rs
+pub const SCRIPT_TICKS_PER_BEAT: u64 = 4; //on_tick runs on every sixteenth note
+pub const SCRIPT_RELOAD_CHECK_MS: u64 = 250; //how often the script file is checked for changes
+pub const SCRIPT_MAX_OPERATIONS: u64 = 500_000; //a callback still running after this many steps is stopped
+
+//render.rs
+pub const RENDER_BLOCK: usize = 4_800; //frames between checks for the end of the tail
+pub const RENDER_SILENCE: f32 = 1e-4; //peak under which the tail counts as rung out (-80 dBFS)
+pub const RENDER_TAIL_MAX_S: f32 = 30.0; //longest the render waits for notes and effects to ring out
+
+//profiler.rs
+pub const PROFILE_BLOCK: usize = 1024; //samples per voice mix reading
+pub const PROFILE_SMOOTHING: f32 = 0.1; //weight of the newest reading in the running average
+
+//engine.rs
+pub const ENGINE_CONTROL_BLOCK: usize = 16; //samples between checks for new/killed voices
+pub const ENGINE_KILL_FADE_MS: f32 = 5.0;
+pub const ENGINE_VOLUME_SMOOTHING: f32 = 0.002; //per-sample step towards a new master volume
+pub const ENGINE_LOAD_SMOOTHING: f32 = 0.05; //weight of the newest callback in the running load
+pub const TRACK_VOLUME_MAX: f32 = 2.0;
+pub const TRACK_VOLUME_STEP: f32 = 0.05;
+pub const TRACK_PAN_STEP: f32 = 0.1;
+
+//transport.rs
+pub const TEMPO_BPM_DEFAULT: f32 = 120.0;
+pub const TEMPO_BPM_MIN: f32 = 20.0;
+pub const TEMPO_BPM_MAX: f32 = 300.0;
+pub const TAP_TEMPO_TIMEOUT_S: f32 = 2.0; //a longer pause starts a new tap count
+pub const TAP_TEMPO_MAX_TAPS: usize = 5; //the tempo follows the gaps between the last few taps
+
+//echo.rs
+pub const ECHO_REPEATS_DEFAULT: u32 = 4;
+pub const ECHO_FEEDBACK_DEFAULT: f32 = 0.6; //velocity kept from one repeat to the next
+pub const ECHO_MIN_VELOCITY: f32 = 0.05; //quieter repeats are dropped
+
+//settings.rs
+pub const CONFIG_DIR: &str = "tjam";
+pub const SETTINGS_FILE: &str = "config.toml";
+
+//lessons.rs
+pub const LESSONS_FILE: &str = "lessons.toml";
+
+//crash.rs
+pub const CRASH_DIR: &str = "crashes";
+pub const CRASH_LOG_LINES: usize = 200;
+
+//recorder.rs
+pub const RECORDINGS_DIR: &str = "recordings";
+pub const MIDI_PPQ: u16 = 480; //ticks per quarter note
+pub const MIDI_DRUM_CHANNEL: u8 = 9; //channel 10, General MIDI drums
+
+//midi_in.rs
+pub const MIDI_CLIENT_NAME: &str = "tjam";
+pub const MIDI_CC_MAX: u8 = 127;
+pub const MIDI_SUSTAIN_CC: u8 = 64; //the sustain pedal
+pub const MIDI_SUSTAIN_DOWN: u8 = 64; //pedal values from here up hold notes
+pub const MIDI_BEND_CENTER: u16 = 8192; //a pitch wheel at rest, of 0..16383
+pub const MIDI_CLOCK_PPQ: usize = 24; //clock ticks per quarter note
+pub const MIDI_CLOCK_TIMEOUT_US: u64 = 500_000; //a longer gap between ticks starts a new tempo count
+pub const MIDI_CLOCK_BPM_STEP: f32 = 0.1; //smaller tempo changes are not passed on
+
+//control.rs
+pub const CONTROL_SOCKET_FILE: &str = "tjam.sock";
+
+//presets.rs
+pub const PRESETS_DIR: &str = "presets";
+pub const PRESET_EXT: &str = "toml";
+
+//ui.rs
+pub const UI_MAX_FPS: f32 = 30.0;
+pub const UI_IDLE_FPS: f32 = 4.0;
+pub const UI_SIDE_WIDTH: u16 = 34; //presets, looper, LFOs and levels column
+pub const UI_KEYBOARD_HEIGHT: u16 = 6;
+pub const FPS_WINDOW_S: f32 = 1.0;
+pub const CPU_REFRESH_S: f32 = 1.0;
+pub const UNDERRUN_WARN_S: f32 = 5.0; //how long the status bar flags an audio underrun
+pub const GR_METER_CELLS: usize = 12; //compressor gain reduction bar in the gain staging panel
+pub const GR_METER_DB_PER_CELL: f32 = 1.0;
+
+//ui/adsr_editor.rs
+pub const ADSR_TIME_STEP: f32 = 1.25; //one press scales a stage time by this
+pub const ADSR_SUSTAIN_STEP: f32 = 0.05;
+
+//ui/level_meter.rs
+pub const METER_FLOOR_DB: f32 = -60.0; //left end of the bars
+pub const METER_PEAK_HOLD_S: f32 = 1.5;
+pub const METER_CLIP_HOLD_S: f32 = 3.0;
+
+//meter.rs
+pub const METER_BLOCK: usize = 512; //samples per published reading
+pub const METER_TICK_MS: u64 = 50;
+
+//gain_staging.rs
+pub const GAIN_TARGET_PEAK_DB: f32 = -3.0;
+pub const GAIN_CLIP_DB: f32 = -0.1;
+pub const GAIN_QUIET_DB: f32 = -18.0;
+pub const GAIN_SILENCE_DB: f32 = -60.0;
+pub const GAIN_WINDOW_S: f32 = 3.0;
+
+//fx/reverb.rs
+pub const REVERB_ROOM_DEFAULT: f32 = 0.5;
+pub const REVERB_DAMPING_DEFAULT: f32 = 0.5;
+pub const REVERB_MIX_DEFAULT: f32 = 0.2;
+pub const REVERB_STEP: f32 = 0.05; //one press of the reverb keys
+
+//fx/tilt.rs
+pub const TILT_PIVOT_HZ: f32 = 800.0;
+pub const TILT_MAX_DB: f32 = 6.0; //each shelf, at full tilt
+pub const TILT_STEP: f32 = 0.1; //one press of the tilt keys
+
+//fx/freeze.rs
+pub const FREEZE_FFT_SIZE: usize = 4096; //~85ms at 48kHz, bins ~12Hz apart
+pub const FREEZE_HOP: usize = FREEZE_FFT_SIZE / 4; //the overlap-add gain assumes 75% overlap
+pub const FREEZE_FADE_MS: f32 = 300.0; //drone fade in/out
+
+//fx/bitcrush.rs
+pub const BITCRUSH_BITS_DEFAULT: f32 = 6.0;
+pub const BITCRUSH_BITS_MAX: f32 = 16.0;
+pub const BITCRUSH_DOWNSAMPLE_DEFAULT: f32 = 4.0; //48kHz down to 12kHz
+pub const BITCRUSH_DOWNSAMPLE_MAX: f32 = 32.0;
+
+//fx/drive.rs
+pub const DRIVE_DB_DEFAULT: f32 = 12.0;
+pub const DRIVE_DB_MAX: f32 = 48.0;
+pub const DRIVE_OUTPUT_DB_DEFAULT: f32 = -6.0;
+pub const DRIVE_OUTPUT_DB_MIN: f32 = -36.0;
+pub const DRIVE_OUTPUT_DB_MAX: f32 = 6.0;
+pub const DRIVE_STEP_DB: f32 = 1.0;
+
+//fx/eq.rs
+pub const EQ_LOW_HZ_DEFAULT: f32 = 200.0;
+pub const EQ_MID_HZ_DEFAULT: f32 = 1_000.0;
+pub const EQ_HIGH_HZ_DEFAULT: f32 = 5_000.0;
+pub const EQ_FREQ_MIN_HZ: f32 = 20.0;
+pub const EQ_FREQ_MAX_HZ: f32 = 20_000.0;
+pub const EQ_GAIN_DB_MAX: f32 = 15.0;
+pub const EQ_GAIN_STEP_DB: f32 = 1.0;
+pub const EQ_Q_DEFAULT: f32 = 0.7;
+pub const EQ_Q_MIN: f32 = 0.1;
+pub const EQ_Q_MAX: f32 = 10.0;
+
+//fx/ringmod.rs
+pub const RINGMOD_RATIO_DEFAULT: f32 = 1.4; //off a whole number, so the sidebands ring like a bell
+pub const RINGMOD_RATIO_MIN: f32 = 0.25;
+pub const RINGMOD_RATIO_MAX: f32 = 16.0;
+pub const RINGMOD_RATIO_STEP: f32 = 0.05;
+pub const RINGMOD_FREQ_DEFAULT_HZ: f32 = 440.0;
+pub const RINGMOD_FREQ_MIN_HZ: f32 = 1.0; //slow carriers give tremolo
+pub const RINGMOD_FREQ_MAX_HZ: f32 = 5_000.0;
+
+//fx/compressor.rs
+pub const COMPRESSOR_THRESHOLD_DB_DEFAULT: f32 = -18.0;
+pub const COMPRESSOR_THRESHOLD_DB_MIN: f32 = -60.0;
+pub const COMPRESSOR_RATIO_DEFAULT: f32 = 4.0;
+pub const COMPRESSOR_RATIO_MAX: f32 = 20.0;
+pub const COMPRESSOR_ATTACK_MS_DEFAULT: f32 = 10.0;
+pub const COMPRESSOR_RELEASE_MS_DEFAULT: f32 = 120.0;
+pub const COMPRESSOR_TIME_MS_MAX: f32 = 2_000.0;
+pub const COMPRESSOR_MAKEUP_DB_MAX: f32 = 24.0;
+
+//fx/phaser.rs
+pub const PHASER_STAGES: usize = 6; //all-pass stages, each pair adds a notch
+pub const PHASER_MIN_HZ: f32 = 200.0; //the bottom and top of the sweep at full depth
+pub const PHASER_MAX_HZ: f32 = 4_000.0;
+pub const PHASER_RATE_DEFAULT: f32 = 0.5; //Hz
+pub const PHASER_FEEDBACK_MAX: f32 = 0.9;
+
+//fx/flanger.rs
+pub const FLANGER_RATE_DEFAULT: f32 = 0.25; //Hz
+pub const FLANGER_DELAY_MS_DEFAULT: f32 = 2.0;
+pub const FLANGER_DELAY_MS_MAX: f32 = 10.0;
+pub const FLANGER_DEPTH_MS_DEFAULT: f32 = 1.5; //the delay swings this far either way
+pub const FLANGER_DEPTH_MS_MAX: f32 = 5.0;
+pub const FLANGER_FEEDBACK_MAX: f32 = 0.95; //either sign: negative feedback hollows it out
+
+//fx/tremolo.rs, fx/autopan.rs
+pub const TREMOLO_RATE_DEFAULT: f32 = 4.0; //Hz
+pub const AUTOPAN_RATE_DEFAULT: f32 = 0.5; //Hz
+
+//fx/bypass.rs
+pub const BYPASS_FADE_MS: f32 = 10.0; //in or out of the signal over this long
+
+//fx/limiter.rs
+pub const LIMITER_THRESHOLD_DB: f32 = -1.0;
+pub const LIMITER_LOOKAHEAD_MS: f32 = 5.0;
+pub const LIMITER_RELEASE_MS: f32 = 80.0;
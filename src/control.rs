@@ -0,0 +1,491 @@
+//! local control socket: newline-delimited JSON on a unix socket, so scripts and other apps can
+//! drive tjam (switch patch on a scene change, start the looper, ...). every line is one command
+//! and gets one JSON line back. commands mirror `AudioCommand`; settings commands take only the
+//! fields to change
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::watch;
+
+use crate::audio_patch::ParamValue;
+use crate::audio_system::{AudioHandle, ChainEdit, DriveTarget, LooperAction, TransportAction};
+use crate::config::{CONTROL_SOCKET_FILE, MIDI_CC_MAX};
+use crate::chord::ChordType;
+use crate::echo::Division;
+use crate::engine::Track;
+use crate::fx::adsr::Adsr;
+use crate::fx::drive::DriveCurve;
+use crate::fx::lfo::{LfoShape, ModTarget};
+use crate::key::{Note, Scale};
+use crate::presets;
+use crate::settings::config_dir;
+use crate::transport::TimeSignature;
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum ControlCommand {
+    Status,
+    SetVolume { volume: f32 },
+    SetMuted { muted: bool },
+    NextPatch,
+    SelectPatch { name: String },
+    SetAdsr { adsr: Adsr },
+    SetFilterEnv { adsr: Option<Adsr>, depth: Option<f32> },
+    ApplyGainAdvice,
+    SetAutoGain { on: bool },
+    SetChordAssist { on: bool },
+    /// what every key plays: off, major, minor, sus4, seventh, major_seventh, minor_seventh, custom
+    SetChord { chord: ChordType },
+    SetLfo {
+        index: usize,
+        enabled: Option<bool>,
+        target: Option<ModTarget>,
+        shape: Option<LfoShape>,
+        rate_hz: Option<f32>,
+        /// tempo synced rate; a `rate_hz` on its own goes back to a free rate
+        sync: Option<Division>,
+        depth: Option<f32>,
+    },
+    SetEcho {
+        enabled: Option<bool>,
+        division: Option<Division>,
+        repeats: Option<u32>,
+        feedback: Option<f32>,
+        pitch_step: Option<i32>,
+    },
+    SetReverb { room_size: Option<f32>, damping: Option<f32>, mix: Option<f32> },
+    SetTilt { tilt: f32 },
+    /// the current patch's waveshaper (`patch`) or the master bus one (`master`)
+    SetDrive {
+        target: DriveTarget,
+        enabled: Option<bool>,
+        curve: Option<DriveCurve>,
+        drive_db: Option<f32>,
+        output_db: Option<f32>,
+    },
+    SetFreeze { on: bool },
+    SetSustain { on: bool },
+    SetLatch { on: bool },
+    /// stops everything that sounds, like Ctrl+K
+    Panic,
+    /// off saves the take as a MIDI file
+    SetRecording { on: bool },
+    /// -1..1 of the bend range, like a pitch wheel
+    SetBend { amount: f32 },
+    ListParams,
+    /// `owner` is `patch` or a master effect, as `list_params` names them
+    SetParam { owner: String, id: String, value: ParamValue },
+    /// adds, removes, moves or bypasses an effect in the current patch's chain
+    EditChain { edit: ChainEdit },
+    /// binds the next MIDI knob moved to a parameter, as `set_param` names it; without one, stops
+    /// waiting
+    LearnCc { owner: Option<String>, id: Option<String> },
+    ForgetCc { owner: String, id: String },
+    /// as if a MIDI controller sent this control change: channel 1..16, value 0..127
+    MidiCc { channel: u8, cc: u8, value: u8 },
+    /// one mixer channel strip; fields left out keep their value
+    SetTrack { track: Track, volume: Option<f32>, pan: Option<f32>, muted: Option<bool>, solo: Option<bool> },
+    SetScaleLock { enabled: Option<bool>, scale: Option<Scale>, root: Option<Note> },
+    Looper { action: LooperAction },
+    /// tempo and time signature; fields left out keep their value
+    SetTempo { bpm: Option<f32>, beats: Option<u8>, unit: Option<u8> },
+    Transport { action: TransportAction },
+    ListPresets,
+    SavePreset { name: String },
+    LoadPreset { name: String },
+    /// a WAV file for the sampler patch, which becomes the current one
+    LoadSample { path: PathBuf },
+    /// a Rhai script, in place of the one running
+    LoadScript { path: PathBuf },
+    StopScript,
+}
+
+/// `$XDG_RUNTIME_DIR/tjam.sock`, falling back to the config dir
+pub fn default_socket_path() -> Option<PathBuf> {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .or_else(config_dir)
+        .map(|d| d.join(CONTROL_SOCKET_FILE))
+}
+
+/// listens until shutdown, one task per client. the socket file is removed again on the way out
+pub async fn run_control_socket(
+    handle: AudioHandle,
+    path: PathBuf,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    remove_stale(&path)?;
+    let listener = UnixListener::bind(&path)?;
+
+    loop {
+        tokio::select! {
+            _ = shutdown.changed() => {
+                if *shutdown.borrow() { break; }
+            }
+            conn = listener.accept() => {
+                let (stream, _) = conn?;
+                tokio::spawn(serve_client(stream, handle.clone()));
+            }
+        }
+    }
+
+    let _ = std::fs::remove_file(&path);
+    Ok(())
+}
+
+/// a socket left behind by a crashed run is removed; one another tjam is listening on is an error
+fn remove_stale(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    if !path.exists() {
+        return Ok(());
+    }
+    if std::os::unix::net::UnixStream::connect(path).is_ok() {
+        return Err(format!("{} is in use by another tjam", path.display()).into());
+    }
+    std::fs::remove_file(path)?;
+    Ok(())
+}
+
+async fn serve_client(stream: UnixStream, handle: AudioHandle) {
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match serde_json::from_str::<ControlCommand>(&line) {
+            Ok(cmd) => execute(&handle, cmd),
+            Err(e) => json!({ "ok": false, "error": e.to_string() }),
+        };
+        let mut out = reply.to_string();
+        out.push('\n');
+        if write.write_all(out.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
+
+fn execute(handle: &AudioHandle, cmd: ControlCommand) -> Value {
+    let snap = handle.subscribe().borrow().clone();
+    match cmd {
+        ControlCommand::Status => {
+            return json!({
+                "ok": true,
+                "patch": snap.patch_name,
+                "volume": snap.volume,
+                "muted": snap.muted,
+                "preset": snap.preset_name,
+                "octave": snap.keymap.octave(),
+                "held": snap.active_keys.iter().map(ToString::to_string).collect::<Vec<_>>(),
+                "voices": snap.voices,
+                "adsr": snap.adsr,
+                "filter_env": snap.filter_env,
+                "reverb": { "room_size": snap.reverb.room_size, "damping": snap.reverb.damping, "mix": snap.reverb.mix },
+                "peak_db": snap.master_peak_db,
+                "audio_load": snap.audio_load,
+                "underruns": snap.underruns,
+                "looper": snap.looper.state.name(),
+                "bpm": snap.transport.bpm,
+                "transport": snap.transport,
+                "echo": snap.echo.enabled,
+                "scale_lock": snap.scale_lock.enabled.then(|| snap.scale_lock.name()),
+                "tilt": snap.tilt,
+                "drive": snap.drive,
+                "eq": snap.eq,
+                "chain": snap.chain,
+                "cc_bindings": snap.cc_bindings,
+                "cc_learn": snap.cc_learn,
+                "master_drive": snap.master_drive,
+                "compressor": snap.compressor,
+                "gain_reduction_db": snap.gain_reduction_db,
+                "freeze": snap.freeze,
+                "sustain": snap.sustain,
+                "latch": snap.latch,
+                "bend": snap.bend,
+                "max_rollover": snap.max_rollover,
+                "recording": snap.recording,
+                "chord": snap.chord,
+                "sample": snap.sample,
+                "script": snap.script,
+                "tracks": Track::ALL.iter().map(|t| (t.name(), snap.tracks[t.index()])).collect::<HashMap<_, _>>(),
+            });
+        }
+        ControlCommand::SetVolume { volume } => handle.set_volume(volume),
+        ControlCommand::SetMuted { muted } => handle.set_muted(muted),
+        ControlCommand::NextPatch => handle.next_patch(),
+        ControlCommand::SelectPatch { name } => handle.select_patch(name),
+        ControlCommand::SetAdsr { adsr } => handle.set_adsr(adsr),
+        ControlCommand::SetFilterEnv { adsr, depth } => {
+            let mut env = snap.filter_env;
+            env.adsr = adsr.unwrap_or(env.adsr);
+            env.depth = depth.unwrap_or(env.depth);
+            handle.set_filter_env(env.clamped());
+        }
+        ControlCommand::ApplyGainAdvice => handle.apply_gain_advice(),
+        ControlCommand::SetAutoGain { on } => handle.set_auto_gain(on),
+        ControlCommand::SetChordAssist { on } => handle.set_chord_assist(on),
+        ControlCommand::SetChord { chord } => handle.set_chord(chord),
+        ControlCommand::SetLfo { index, enabled, target, shape, rate_hz, sync, depth } => {
+            let Some(mut lfo) = snap.lfos.get(index).copied() else {
+                return json!({ "ok": false, "error": format!("no lfo route {index}") });
+            };
+            lfo.enabled = enabled.unwrap_or(lfo.enabled);
+            lfo.target = target.unwrap_or(lfo.target);
+            lfo.shape = shape.unwrap_or(lfo.shape);
+            lfo.rate_hz = rate_hz.unwrap_or(lfo.rate_hz);
+            if sync.is_some() || rate_hz.is_some() {
+                lfo.sync = sync;
+            }
+            lfo.depth = depth.unwrap_or(lfo.depth);
+            handle.set_lfo(index, lfo.clamped());
+        }
+        ControlCommand::SetEcho { enabled, division, repeats, feedback, pitch_step } => {
+            let mut echo = snap.echo;
+            echo.enabled = enabled.unwrap_or(echo.enabled);
+            echo.division = division.unwrap_or(echo.division);
+            echo.repeats = repeats.unwrap_or(echo.repeats);
+            echo.feedback = feedback.unwrap_or(echo.feedback).clamp(0.0, 1.0);
+            echo.pitch_step = pitch_step.unwrap_or(echo.pitch_step);
+            handle.set_echo(echo);
+        }
+        ControlCommand::SetReverb { room_size, damping, mix } => {
+            let mut reverb = snap.reverb;
+            reverb.room_size = room_size.unwrap_or(reverb.room_size);
+            reverb.damping = damping.unwrap_or(reverb.damping);
+            reverb.mix = mix.unwrap_or(reverb.mix);
+            handle.set_reverb(reverb.clamped());
+        }
+        ControlCommand::SetTilt { tilt } => handle.set_tilt(tilt),
+        ControlCommand::SetDrive { target, enabled, curve, drive_db, output_db } => {
+            let mut drive = match target {
+                DriveTarget::Patch => snap.drive,
+                DriveTarget::Master => snap.master_drive,
+            };
+            drive.enabled = enabled.unwrap_or(drive.enabled);
+            drive.curve = curve.unwrap_or(drive.curve);
+            drive.drive_db = drive_db.unwrap_or(drive.drive_db);
+            drive.output_db = output_db.unwrap_or(drive.output_db);
+            handle.set_drive(target, drive.clamped());
+        }
+        ControlCommand::SetFreeze { on } => handle.set_freeze(on),
+        ControlCommand::SetSustain { on } => handle.set_sustain(on),
+        ControlCommand::SetLatch { on } => handle.set_latch(on),
+        ControlCommand::Panic => handle.panic(),
+        ControlCommand::SetRecording { on } => handle.set_recording(on),
+        ControlCommand::SetTrack { track, volume, pan, muted, solo } => {
+            let mut strip = snap.tracks[track.index()];
+            strip.volume = volume.unwrap_or(strip.volume);
+            strip.pan = pan.unwrap_or(strip.pan);
+            strip.muted = muted.unwrap_or(strip.muted);
+            strip.solo = solo.unwrap_or(strip.solo);
+            handle.set_track(track, strip.clamped());
+        }
+        ControlCommand::SetTempo { bpm, beats, unit } => {
+            if let Some(bpm) = bpm {
+                handle.set_tempo(bpm);
+            }
+            if beats.is_some() || unit.is_some() {
+                let old = snap.transport.signature;
+                handle.set_time_signature(TimeSignature { beats: beats.unwrap_or(old.beats), unit: unit.unwrap_or(old.unit) });
+            }
+        }
+        ControlCommand::Transport { action } => handle.transport(action),
+        ControlCommand::SetBend { amount } => handle.set_bend(amount),
+        ControlCommand::ListParams => {
+            return json!({ "ok": true, "params": snap.params });
+        }
+        ControlCommand::SetParam { owner, id, value } => {
+            let known = snap.params.iter().any(|g| g.owner.eq_ignore_ascii_case(&owner) && g.params.iter().any(|p| p.id == id));
+            if !known {
+                return json!({ "ok": false, "error": format!("no parameter {id} on {owner}") });
+            }
+            handle.set_param(owner, id, value);
+        }
+        ControlCommand::EditChain { edit } => handle.edit_chain(edit),
+        ControlCommand::LearnCc { owner, id } => match (owner, id) {
+            (Some(owner), Some(id)) => {
+                let known = snap.params.iter().any(|g| g.owner.eq_ignore_ascii_case(&owner) && g.params.iter().any(|p| p.id == id));
+                if !known {
+                    return json!({ "ok": false, "error": format!("no parameter {id} on {owner}") });
+                }
+                handle.learn_cc(Some((owner, id)));
+            }
+            (None, None) => handle.learn_cc(None),
+            _ => return json!({ "ok": false, "error": "learn_cc takes both owner and id, or neither" }),
+        },
+        ControlCommand::ForgetCc { owner, id } => handle.forget_cc(owner, id),
+        ControlCommand::MidiCc { channel, cc, value } => {
+            if !(1..=16).contains(&channel) || cc > MIDI_CC_MAX || value > MIDI_CC_MAX {
+                return json!({ "ok": false, "error": "channel is 1..16, cc and value 0..127" });
+            }
+            handle.midi_cc(channel, cc, value);
+        }
+        ControlCommand::SetScaleLock { enabled, scale, root } => {
+            let mut lock = snap.scale_lock;
+            lock.enabled = enabled.unwrap_or(lock.enabled);
+            lock.scale = scale.unwrap_or(lock.scale);
+            lock.root = root.unwrap_or(lock.root);
+            handle.set_scale_lock(lock);
+        }
+        ControlCommand::Looper { action } => handle.looper(action),
+        ControlCommand::ListPresets => {
+            return json!({ "ok": true, "presets": presets::list_presets() });
+        }
+        ControlCommand::SavePreset { name } => handle.save_preset(name),
+        ControlCommand::LoadPreset { name } => handle.load_preset(name),
+        ControlCommand::LoadSample { path } => handle.load_sample(path),
+        ControlCommand::LoadScript { path } => handle.load_script(path),
+        ControlCommand::StopScript => handle.stop_script(),
+    }
+    json!({ "ok": true })
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc::UnboundedReceiver;
+
+    use super::*;
+    use crate::audio_patch::ParamDesc;
+    use crate::audio_system::{AudioCommand, ParamGroup};
+    use crate::echo::EchoSettings;
+    use crate::engine::TrackSettings;
+    use crate::fx::lfo::{LfoSettings, ModTarget};
+
+    fn parse(line: &str) -> Result<ControlCommand, serde_json::Error> {
+        serde_json::from_str(line)
+    }
+
+    /// a handle whose snapshot has the three LFO routes and one known parameter
+    fn handle() -> (AudioHandle, UnboundedReceiver<AudioCommand>) {
+        let (handle, rx, snapshot_tx) = AudioHandle::detached();
+        snapshot_tx.send_modify(|s| {
+            s.lfos = [ModTarget::Pitch, ModTarget::Amplitude, ModTarget::Cutoff].map(LfoSettings::new).to_vec();
+            s.params = vec![ParamGroup { owner: "Reverb".into(), params: vec![ParamDesc::float("mix", 0.2, 0.0, 1.0, 0.05)] }];
+        });
+        (handle, rx)
+    }
+
+    fn run(handle: &AudioHandle, line: &str) -> Value {
+        execute(handle, parse(line).unwrap())
+    }
+
+    #[test]
+    fn every_command_shape_parses() {
+        let lines = [
+            r#"{"cmd":"status"}"#,
+            r#"{"cmd":"set_volume","volume":0.5}"#,
+            r#"{"cmd":"set_muted","muted":true}"#,
+            r#"{"cmd":"next_patch"}"#,
+            r#"{"cmd":"select_patch","name":"Saw"}"#,
+            r#"{"cmd":"set_adsr","adsr":{"attack_s":0.01,"decay_s":0.3,"sustain":0.6,"release_s":0.8,"release_curve":"exponential"}}"#,
+            r#"{"cmd":"set_filter_env","depth":2.0}"#,
+            r#"{"cmd":"apply_gain_advice"}"#,
+            r#"{"cmd":"set_auto_gain","on":true}"#,
+            r#"{"cmd":"set_chord_assist","on":false}"#,
+            r#"{"cmd":"set_chord","chord":"minor_seventh"}"#,
+            r#"{"cmd":"set_lfo","index":0,"target":"cutoff","shape":"square","sync":"dotted_eighth"}"#,
+            r#"{"cmd":"set_echo","division":"eighth_triplet","repeats":4}"#,
+            r#"{"cmd":"set_reverb","mix":0.3}"#,
+            r#"{"cmd":"set_tilt","tilt":-0.5}"#,
+            r#"{"cmd":"set_drive","target":"master","curve":"hard_clip"}"#,
+            r#"{"cmd":"set_freeze","on":true}"#,
+            r#"{"cmd":"set_sustain","on":true}"#,
+            r#"{"cmd":"set_latch","on":false}"#,
+            r#"{"cmd":"panic"}"#,
+            r#"{"cmd":"set_recording","on":true}"#,
+            r#"{"cmd":"set_bend","amount":-1.0}"#,
+            r#"{"cmd":"list_params"}"#,
+            r#"{"cmd":"set_param","owner":"patch","id":"index","value":6.5}"#,
+            r#"{"cmd":"set_param","owner":"Bitcrush","id":"enabled","value":true}"#,
+            r#"{"cmd":"edit_chain","edit":{"op":"add","kind":"flanger"}}"#,
+            r#"{"cmd":"edit_chain","edit":{"op":"move","index":0,"to":2}}"#,
+            r#"{"cmd":"edit_chain","edit":{"op":"bypass","index":1,"on":true}}"#,
+            r#"{"cmd":"learn_cc","owner":"Reverb","id":"mix"}"#,
+            r#"{"cmd":"learn_cc"}"#,
+            r#"{"cmd":"forget_cc","owner":"Reverb","id":"mix"}"#,
+            r#"{"cmd":"midi_cc","channel":1,"cc":74,"value":127}"#,
+            r#"{"cmd":"set_track","track":"keys","pan":-0.5}"#,
+            r#"{"cmd":"set_scale_lock","scale":"minor_pentatonic","root":"A"}"#,
+            r#"{"cmd":"looper","action":"undo_layer"}"#,
+            r#"{"cmd":"set_tempo","beats":6,"unit":8}"#,
+            r#"{"cmd":"transport","action":"tap"}"#,
+            r#"{"cmd":"list_presets"}"#,
+            r#"{"cmd":"save_preset","name":"pad"}"#,
+            r#"{"cmd":"load_preset","name":"pad"}"#,
+            r#"{"cmd":"load_sample","path":"/tmp/kick.wav"}"#,
+            r#"{"cmd":"load_script","path":"/tmp/jam.rhai"}"#,
+            r#"{"cmd":"stop_script"}"#,
+        ];
+        for line in lines {
+            assert!(parse(line).is_ok(), "{line}: {:?}", parse(line).err());
+        }
+    }
+
+    #[test]
+    fn malformed_commands_do_not_parse() {
+        for line in [
+            r#"{"cmd":"make_coffee"}"#,
+            r#"{"volume":0.5}"#,
+            r#"{"cmd":"set_volume"}"#,
+            r#"{"cmd":"set_track","track":"bass"}"#,
+            r#"{"cmd":"edit_chain","edit":{"op":"add","kind":"chorus"}}"#,
+            r#"{"cmd":"set_lfo","index":0,"target":"wobble"}"#,
+        ] {
+            assert!(parse(line).is_err(), "{line}");
+        }
+    }
+
+    #[test]
+    fn set_lfo_changes_only_the_fields_given() {
+        let (handle, mut rx) = handle();
+        let before = LfoSettings::new(ModTarget::Amplitude);
+        assert_eq!(run(&handle, r#"{"cmd":"set_lfo","index":1,"depth":0.2,"sync":"eighth"}"#)["ok"], true);
+        let Ok(AudioCommand::SetLfo(1, lfo)) = rx.try_recv() else { panic!("no set_lfo sent") };
+        assert_eq!(lfo, LfoSettings { depth: 0.2, sync: Some(Division::Eighth), ..before });
+
+        // depth is clamped to the target's range, and a route that is not there is an error
+        run(&handle, r#"{"cmd":"set_lfo","index":0,"depth":99.0}"#);
+        let Ok(AudioCommand::SetLfo(0, lfo)) = rx.try_recv() else { panic!("no set_lfo sent") };
+        assert_eq!(lfo.depth, ModTarget::Pitch.max_depth());
+        assert_eq!(run(&handle, r#"{"cmd":"set_lfo","index":7,"depth":0.2}"#)["ok"], false);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn set_echo_and_set_track_change_only_the_fields_given() {
+        let (handle, mut rx) = handle();
+        let echo = handle.subscribe().borrow().echo;
+        run(&handle, r#"{"cmd":"set_echo","repeats":5,"feedback":1.5}"#);
+        let Ok(AudioCommand::SetEcho(sent)) = rx.try_recv() else { panic!("no set_echo sent") };
+        assert_eq!(sent, EchoSettings { repeats: 5, feedback: 1.0, ..echo });
+
+        run(&handle, r#"{"cmd":"set_track","track":"drums","pan":-3.0,"solo":true}"#);
+        let Ok(AudioCommand::SetTrack(Track::Drums, strip)) = rx.try_recv() else { panic!("no set_track sent") };
+        assert_eq!(strip, TrackSettings { pan: -1.0, solo: true, ..TrackSettings::default() });
+    }
+
+    #[test]
+    fn learn_cc_takes_both_owner_and_id_or_neither() {
+        let (handle, mut rx) = handle();
+        let reply = run(&handle, r#"{"cmd":"learn_cc","owner":"Reverb"}"#);
+        assert_eq!(reply["ok"], false);
+        assert_eq!(reply["error"], "learn_cc takes both owner and id, or neither");
+        assert_eq!(run(&handle, r#"{"cmd":"learn_cc","id":"mix"}"#)["ok"], false);
+        assert_eq!(run(&handle, r#"{"cmd":"learn_cc","owner":"Reverb","id":"size"}"#)["ok"], false);
+        assert!(rx.try_recv().is_err());
+
+        assert_eq!(run(&handle, r#"{"cmd":"learn_cc","owner":"reverb","id":"mix"}"#)["ok"], true);
+        let Ok(AudioCommand::LearnCc(Some((owner, id)))) = rx.try_recv() else { panic!("no learn_cc sent") };
+        assert_eq!((owner.as_str(), id.as_str()), ("reverb", "mix"));
+        run(&handle, r#"{"cmd":"learn_cc"}"#);
+        assert!(matches!(rx.try_recv(), Ok(AudioCommand::LearnCc(None))));
+    }
+}
@@ -0,0 +1,144 @@
+//! crash reports: on a panic or an audio engine failure, a plain text bundle (version, system
+//! and audio device info, settings, the last few log lines) is written to the data dir so it can
+//! be attached to a bug report
+
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use cpal::traits::{DeviceTrait, HostTrait};
+use crossterm::{execute, terminal::{LeaveAlternateScreen, disable_raw_mode}};
+
+use crate::config::{CRASH_DIR, CRASH_LOG_LINES};
+use crate::settings::{Settings, data_dir};
+
+static LOG: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+static SETTINGS: OnceLock<String> = OnceLock::new();
+/// the report written for a runtime audio failure, waiting to be shown
+static PENDING: Mutex<Option<PathBuf>> = Mutex::new(None);
+static ENGINE_REPORTED: OnceLock<()> = OnceLock::new();
+
+/// keeps the settings for reports and installs the panic hook
+pub fn init(settings: &Settings) {
+    let _ = SETTINGS.set(toml::to_string_pretty(settings).unwrap_or_else(|e| format!("unprintable: {e}")));
+
+    std::panic::set_hook(Box::new(|info| {
+        // the TUI may still own the terminal; give it back first so the message is readable
+        let _ = disable_raw_mode();
+        let _ = execute!(io::stdout(), LeaveAlternateScreen);
+        eprintln!("tjam crashed: {info}");
+        match write_report(&format!("panic: {info}")) {
+            Ok(path) => eprintln!("crash report: {}", path.display()),
+            Err(e) => eprintln!("could not write a crash report: {e}"),
+        }
+    }));
+}
+
+/// adds a line to the log kept for reports; repeats of the last line are dropped
+pub fn note(line: impl Into<String>) {
+    let line = line.into();
+    let Ok(mut log) = LOG.lock() else { return; };
+    if log.back() == Some(&line) {
+        return;
+    }
+    if log.len() >= CRASH_LOG_LINES {
+        log.pop_front();
+    }
+    log.push_back(line);
+}
+
+/// the audio engine could not start; the returned error points at the report
+pub fn engine_failed(e: Box<dyn std::error::Error>) -> Box<dyn std::error::Error> {
+    note(format!("audio engine failed: {e}"));
+    match write_report(&format!("audio engine failed: {e}")) {
+        Ok(path) => format!("{e} (crash report: {})", path.display()).into(),
+        Err(_) => e,
+    }
+}
+
+/// the running stream reported an error. only the first one gets a report, the rest are logged
+pub fn engine_error(message: &str) {
+    note(format!("audio stream error: {message}"));
+    if ENGINE_REPORTED.set(()).is_err() {
+        return;
+    }
+    if let Ok(path) = write_report(&format!("audio stream error: {message}"))
+        && let Ok(mut pending) = PENDING.lock()
+    {
+        *pending = Some(path);
+    }
+}
+
+/// a report written since the last call, for the UI to point at
+pub fn take_pending() -> Option<PathBuf> {
+    PENDING.lock().ok()?.take()
+}
+
+pub fn write_report(reason: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let dir = data_dir().ok_or("could not resolve the data directory")?.join(CRASH_DIR);
+    fs::create_dir_all(&dir)?;
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let path = dir.join(format!("crash-{secs}.txt"));
+    fs::write(&path, report(reason, secs))?;
+    Ok(path)
+}
+
+fn report(reason: &str, secs: u64) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "tjam {} crash report", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(out, "time: {secs} (unix)");
+    let _ = writeln!(out, "reason: {reason}");
+
+    let _ = writeln!(out, "\n[system]");
+    let _ = writeln!(out, "os: {} {}", std::env::consts::OS, std::env::consts::ARCH);
+    let _ = writeln!(out, "name: {}", sysinfo::System::long_os_version().unwrap_or_else(|| "?".into()));
+    let _ = writeln!(out, "kernel: {}", sysinfo::System::kernel_version().unwrap_or_else(|| "?".into()));
+    let _ = writeln!(out, "terminal: {}", std::env::var("TERM").unwrap_or_else(|_| "?".into()));
+
+    let _ = writeln!(out, "\n[audio]");
+    out.push_str(&audio_info());
+
+    let _ = writeln!(out, "\n[settings]");
+    out.push_str(SETTINGS.get().map_or("not loaded\n", |s| s.as_str()));
+
+    let _ = writeln!(out, "\n[log]");
+    if let Ok(log) = LOG.lock() {
+        for line in log.iter() {
+            let _ = writeln!(out, "{line}");
+        }
+    }
+    out
+}
+
+fn audio_info() -> String {
+    let mut out = String::new();
+    let host = cpal::default_host();
+    let _ = writeln!(out, "host: {:?}", host.id());
+    let name = |d: &cpal::Device| d.description().map_or_else(|e| format!("? ({e})"), |d| d.name().to_string());
+    match host.default_output_device() {
+        Some(d) => {
+            let _ = writeln!(out, "default output: {}", name(&d));
+            if let Ok(c) = d.default_output_config() {
+                let _ = writeln!(out, "default config: {} Hz, {} ch, {}", c.sample_rate(), c.channels(), c.sample_format());
+            }
+        }
+        None => {
+            let _ = writeln!(out, "default output: none");
+        }
+    }
+    match host.output_devices() {
+        Ok(devices) => {
+            for d in devices {
+                let _ = writeln!(out, "output: {}", name(&d));
+            }
+        }
+        Err(e) => {
+            let _ = writeln!(out, "could not list outputs: {e}");
+        }
+    }
+    out
+}
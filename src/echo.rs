@@ -0,0 +1,247 @@
+//! note echo: repeats every played note a tempo-synced interval later, quieter each time and
+//! optionally transposed. it works on note events, before any audio is made, so every patch
+//! gets it for free
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use device_query::Keycode;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{ECHO_FEEDBACK_DEFAULT, ECHO_MIN_VELOCITY, ECHO_REPEATS_DEFAULT};
+
+/// note length as a fraction of a beat
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Division {
+    Quarter,
+    DottedEighth,
+    Eighth,
+    EighthTriplet,
+    Sixteenth,
+}
+
+impl Division {
+    /// longest first
+    pub const ALL: [Division; 5] = [
+        Division::Quarter,
+        Division::DottedEighth,
+        Division::Eighth,
+        Division::EighthTriplet,
+        Division::Sixteenth,
+    ];
+
+    pub fn next(self) -> Self {
+        let i = Self::ALL.iter().position(|d| *d == self).unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+
+    /// one step longer, stopping at the longest
+    pub fn longer(self) -> Self {
+        let i = Self::ALL.iter().position(|d| *d == self).unwrap_or(0);
+        Self::ALL[i.saturating_sub(1)]
+    }
+
+    /// one step shorter, stopping at the shortest
+    pub fn shorter(self) -> Self {
+        let i = Self::ALL.iter().position(|d| *d == self).unwrap_or(0);
+        Self::ALL[(i + 1).min(Self::ALL.len() - 1)]
+    }
+
+    pub fn beats(self) -> f32 {
+        match self {
+            Division::Quarter => 1.0,
+            Division::DottedEighth => 0.75,
+            Division::Eighth => 0.5,
+            Division::EighthTriplet => 1.0 / 3.0,
+            Division::Sixteenth => 0.25,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Division::Quarter => "1/4",
+            Division::DottedEighth => "1/8.",
+            Division::Eighth => "1/8",
+            Division::EighthTriplet => "1/8t",
+            Division::Sixteenth => "1/16",
+        }
+    }
+
+    pub fn duration(self, bpm: f32) -> Duration {
+        Duration::from_secs_f32(self.beats() * 60.0 / bpm.max(1.0))
+    }
+
+    /// one cycle per division, in Hz
+    pub fn rate_hz(self, bpm: f32) -> f32 {
+        bpm.max(1.0) / 60.0 / self.beats()
+    }
+}
+
+/// pitch shift applied per repeat, cycled from the UI
+pub const ECHO_PITCH_STEPS: [i32; 5] = [0, 12, 7, 5, -12];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EchoSettings {
+    pub enabled: bool,
+    pub division: Division,
+    pub repeats: u32,
+    /// velocity multiplier from one repeat to the next
+    pub feedback: f32,
+    /// semitones added on every repeat (so the 3rd repeat is 3 × this)
+    pub pitch_step: i32,
+}
+
+impl Default for EchoSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            division: Division::Eighth,
+            repeats: ECHO_REPEATS_DEFAULT,
+            feedback: ECHO_FEEDBACK_DEFAULT,
+            pitch_step: 0,
+        }
+    }
+}
+
+/// one echoed copy of a note, identified so its release finds the same voice
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EchoVoice {
+    pub keycode: Keycode,
+    pub repeat: u32,
+    pub semitones: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EchoEvent {
+    On(EchoVoice, f32),
+    Off(EchoVoice),
+}
+
+struct Scheduled {
+    due: Instant,
+    event: EchoEvent,
+}
+
+pub struct NoteEcho {
+    pub settings: EchoSettings,
+    queue: Vec<Scheduled>,
+    /// repeats scheduled for the last press of each key, with their delay, so the release
+    /// can be echoed with the same spacing
+    open: HashMap<Keycode, Vec<(EchoVoice, Duration)>>,
+}
+
+impl NoteEcho {
+    pub fn new(settings: EchoSettings) -> Self {
+        Self { settings, queue: vec![], open: HashMap::new() }
+    }
+
+    pub fn note_on(&mut self, keycode: Keycode, velocity: f32, bpm: f32, now: Instant) {
+        if !self.settings.enabled {
+            return;
+        }
+        let step = self.settings.division.duration(bpm);
+        let mut repeats = vec![];
+        let mut v = velocity;
+        for repeat in 1..=self.settings.repeats {
+            v *= self.settings.feedback;
+            if v < ECHO_MIN_VELOCITY {
+                break;
+            }
+            let voice = EchoVoice { keycode, repeat, semitones: self.settings.pitch_step * repeat as i32 };
+            let delay = step * repeat;
+            self.queue.push(Scheduled { due: now + delay, event: EchoEvent::On(voice, v) });
+            repeats.push((voice, delay));
+        }
+        self.open.insert(keycode, repeats);
+    }
+
+    /// echoes the release too; works even if echo was switched off while the key was down
+    pub fn note_off(&mut self, keycode: Keycode, now: Instant) {
+        let Some(repeats) = self.open.remove(&keycode) else { return; };
+        for (voice, delay) in repeats {
+            self.queue.push(Scheduled { due: now + delay, event: EchoEvent::Off(voice) });
+        }
+    }
+
+    /// forgets every repeat still to come
+    pub fn clear(&mut self) {
+        self.queue.clear();
+        self.open.clear();
+    }
+
+    /// events that are due, in time order
+    pub fn poll(&mut self, now: Instant) -> Vec<EchoEvent> {
+        if self.queue.is_empty() {
+            return vec![];
+        }
+        self.queue.sort_by_key(|s| s.due);
+        let split = self.queue.partition_point(|s| s.due <= now);
+        self.queue.drain(..split).map(|s| s.event).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(t0: Instant, ms: u64) -> Instant {
+        t0 + Duration::from_millis(ms)
+    }
+
+    fn voice(repeat: u32, semitones: i32) -> EchoVoice {
+        EchoVoice { keycode: Keycode::A, repeat, semitones }
+    }
+
+    #[test]
+    fn repeats_fall_on_the_division_quieter_and_transposed() {
+        let t0 = Instant::now();
+        let settings = EchoSettings { enabled: true, division: Division::Eighth, repeats: 3, feedback: 0.5, pitch_step: 7 };
+        let mut echo = NoteEcho::new(settings);
+        // an eighth at 120 bpm is 250 ms
+        echo.note_on(Keycode::A, 0.8, 120.0, t0);
+        echo.note_off(Keycode::A, at(t0, 100));
+
+        assert_eq!(echo.poll(at(t0, 249)), vec![]);
+        assert_eq!(echo.poll(at(t0, 250)), vec![EchoEvent::On(voice(1, 7), 0.4)]);
+        // each release is as far behind its repeat as the key's release was behind the press
+        assert_eq!(echo.poll(at(t0, 350)), vec![EchoEvent::Off(voice(1, 7))]);
+        assert_eq!(echo.poll(at(t0, 600)), vec![EchoEvent::On(voice(2, 14), 0.2), EchoEvent::Off(voice(2, 14))]);
+        assert_eq!(echo.poll(at(t0, 2000)), vec![EchoEvent::On(voice(3, 21), 0.1), EchoEvent::Off(voice(3, 21))]);
+        assert_eq!(echo.poll(at(t0, 3000)), vec![]);
+    }
+
+    #[test]
+    fn the_division_follows_the_tempo() {
+        let t0 = Instant::now();
+        let settings = EchoSettings { enabled: true, division: Division::DottedEighth, repeats: 2, feedback: 1.0, pitch_step: 0 };
+        let mut echo = NoteEcho::new(settings);
+        // a dotted eighth at 90 bpm is half a second
+        echo.note_on(Keycode::A, 0.5, 90.0, t0);
+        assert_eq!(echo.poll(at(t0, 499)), vec![]);
+        assert_eq!(echo.poll(at(t0, 500)), vec![EchoEvent::On(voice(1, 0), 0.5)]);
+        assert_eq!(echo.poll(at(t0, 999)), vec![]);
+        assert_eq!(echo.poll(at(t0, 1000)), vec![EchoEvent::On(voice(2, 0), 0.5)]);
+    }
+
+    #[test]
+    fn repeats_too_quiet_to_hear_are_dropped() {
+        let t0 = Instant::now();
+        let settings = EchoSettings { enabled: true, division: Division::Sixteenth, repeats: 10, feedback: 0.5, pitch_step: 0 };
+        let mut echo = NoteEcho::new(settings);
+        // 0.5, 0.25, 0.125, 0.0625, and then under ECHO_MIN_VELOCITY
+        echo.note_on(Keycode::A, 1.0, 120.0, t0);
+        let heard = echo.poll(at(t0, 10_000));
+        assert_eq!(heard.len(), 4, "{heard:?}");
+        assert_eq!(heard.last(), Some(&EchoEvent::On(voice(4, 0), 0.0625)));
+
+        // switched off, nothing is echoed; cleared, nothing still to come is
+        echo.settings.enabled = false;
+        echo.note_on(Keycode::A, 1.0, 120.0, t0);
+        assert_eq!(echo.poll(at(t0, 10_000)), vec![]);
+        echo.settings.enabled = true;
+        echo.note_on(Keycode::A, 1.0, 120.0, t0);
+        echo.clear();
+        assert_eq!(echo.poll(at(t0, 10_000)), vec![]);
+    }
+}
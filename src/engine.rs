@@ -0,0 +1,477 @@
+//! the audio backend: one cpal output stream whose callback mixes every sounding voice,
+//! sample by sample, onto its track (live keys, loop, echo, drums, script), pans the tracks into stereo and
+//! runs each side through the master chain. voices are handed over through a channel, so
+//! starting or stopping a note never touches the device
+
+use std::collections::VecDeque;
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    mpsc::{self, Receiver, Sender},
+};
+use std::time::{Duration, Instant};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, SizedSample, StreamConfig};
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+
+use crate::audio_patch::{AtomicF32, Resample, SynthSource};
+use crate::crash;
+use crate::config::{
+    ENGINE_CONTROL_BLOCK, ENGINE_KILL_FADE_MS, ENGINE_LOAD_SMOOTHING, ENGINE_VOLUME_SMOOTHING, SAMPLE_RATE, TRACK_VOLUME_MAX,
+};
+
+pub type VoiceId = u64;
+
+/// the bus a voice is mixed on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Track {
+    /// notes played on the keyboard
+    #[serde(rename = "keys")]
+    Live,
+    Loop,
+    Echo,
+    /// the drum keys, whoever plays them
+    Drums,
+    /// notes a script plays
+    Script,
+}
+
+impl Track {
+    pub const ALL: [Track; 5] = [Track::Live, Track::Loop, Track::Echo, Track::Drums, Track::Script];
+    pub const COUNT: usize = Self::ALL.len();
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Track::Live => "keys",
+            Track::Loop => "loop",
+            Track::Echo => "echo",
+            Track::Drums => "drums",
+            Track::Script => "script",
+        }
+    }
+
+    /// position in `ALL`
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// one channel strip. pan is a balance: -1 is hard left, and the center leaves both sides at
+/// full level, like the mono mix always was
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrackSettings {
+    pub volume: f32,
+    pub pan: f32,
+    pub muted: bool,
+    /// while any track is soloed, only soloed tracks are heard
+    pub solo: bool,
+}
+
+impl Default for TrackSettings {
+    fn default() -> Self {
+        Self { volume: 1.0, pan: 0.0, muted: false, solo: false }
+    }
+}
+
+impl TrackSettings {
+    pub fn clamped(mut self) -> Self {
+        self.volume = self.volume.clamp(0.0, TRACK_VOLUME_MAX);
+        self.pan = self.pan.clamp(-1.0, 1.0);
+        self
+    }
+}
+
+#[derive(Debug, Default)]
+struct TrackControls {
+    volume: AtomicF32,
+    pan: AtomicF32,
+    muted: AtomicBool,
+    solo: AtomicBool,
+}
+
+impl TrackControls {
+    fn load(&self) -> TrackSettings {
+        TrackSettings {
+            volume: self.volume.load(),
+            pan: self.pan.load(),
+            muted: self.muted.load(Ordering::Relaxed),
+            solo: self.solo.load(Ordering::Relaxed),
+        }
+    }
+
+    fn store(&self, s: TrackSettings) {
+        let s = s.clamped();
+        self.volume.store(s.volume);
+        self.pan.store(s.pan);
+        self.muted.store(s.muted, Ordering::Relaxed);
+        self.solo.store(s.solo, Ordering::Relaxed);
+    }
+}
+
+enum EngineMsg {
+    Add { id: VoiceId, source: SynthSource, track: Track, done: Arc<AtomicBool> },
+    /// fade out over a few ms instead of cutting, so killing a voice doesn't click
+    Kill(VoiceId),
+    KillAll,
+}
+
+/// master controls the callback reads every sample, and what it reports back
+#[derive(Debug)]
+struct MasterControls {
+    volume: AtomicF32,
+    muted: AtomicBool,
+    tracks: [TrackControls; Track::COUNT],
+    /// voices in the mix, fading ones included
+    voices: AtomicUsize,
+    /// running average of the time a callback takes over the time the audio it makes lasts
+    load: AtomicF32,
+    /// callbacks that took longer than the audio they made, so the device ran dry
+    underruns: AtomicU64,
+}
+
+/// the device stream plus the sending side of the voice channel
+pub struct Engine {
+    /// `None` when rendering offline, where the caller pulls the mix itself
+    _stream: Option<cpal::Stream>,
+    tx: Sender<EngineMsg>,
+    controls: Arc<MasterControls>,
+    next_id: VoiceId,
+    device_rate: u32,
+}
+
+impl Engine {
+    /// opens the output device whose name contains `device` (the default one when `None`) and
+    /// asks it for `sample_rate`, falling back to the engine rate. `master` builds the master
+    /// chain on top of one side of the voice mix; it is called for channel 0 (left) and 1 (right)
+    pub fn start(
+        device: Option<&str>,
+        sample_rate: Option<u32>,
+        master: impl Fn(usize, SynthSource) -> SynthSource,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let host = cpal::default_host();
+        let device = match device {
+            Some(name) => find_device(&host, name)?,
+            None => host.default_output_device().ok_or("no audio output device")?,
+        };
+        let supported = pick_config(&device, sample_rate)?;
+        let format = supported.sample_format();
+        let config: StreamConfig = supported.config();
+        let name = device.description().map_or_else(|_| "?".to_string(), |d| d.name().to_string());
+        crash::note(format!("audio: {name}, {} Hz, {} ch, {format}", config.sample_rate, config.channels));
+
+        let (tx, controls, chains) = mix_chains(master);
+        let chains = chains.map(|chain| -> SynthSource {
+            if config.sample_rate != SAMPLE_RATE { Box::new(Resample::new(chain, config.sample_rate)) } else { chain }
+        });
+
+        let stream = match format {
+            SampleFormat::F32 => build_stream::<f32>(&device, &config, chains, controls.clone())?,
+            SampleFormat::I16 => build_stream::<i16>(&device, &config, chains, controls.clone())?,
+            SampleFormat::U16 => build_stream::<u16>(&device, &config, chains, controls.clone())?,
+            SampleFormat::I32 => build_stream::<i32>(&device, &config, chains, controls.clone())?,
+            other => return Err(format!("unsupported sample format {other}").into()),
+        };
+        stream.play()?;
+
+        Ok(Self { _stream: Some(stream), tx, controls, next_id: 0, device_rate: config.sample_rate })
+    }
+
+    /// no device: the two master chains come back for the caller to pull at the engine rate,
+    /// as fast as it likes. voices are taken in between pulls, every `ENGINE_CONTROL_BLOCK` frames
+    pub fn offline(master: impl Fn(usize, SynthSource) -> SynthSource) -> (Self, [SynthSource; 2]) {
+        let (tx, controls, chains) = mix_chains(master);
+        (Self { _stream: None, tx, controls, next_id: 0, device_rate: SAMPLE_RATE }, chains)
+    }
+
+    /// starts mixing `source` on `track`; the flag goes up once it has finished or been killed
+    pub fn add_voice(&mut self, source: SynthSource, track: Track) -> (VoiceId, Arc<AtomicBool>) {
+        let id = self.next_id;
+        self.next_id += 1;
+        let done = Arc::new(AtomicBool::new(false));
+        let _ = self.tx.send(EngineMsg::Add { id, source, track, done: done.clone() });
+        (id, done)
+    }
+
+    pub fn kill(&self, id: VoiceId) {
+        let _ = self.tx.send(EngineMsg::Kill(id));
+    }
+
+    pub fn kill_all(&self) {
+        let _ = self.tx.send(EngineMsg::KillAll);
+    }
+
+    pub fn set_volume(&self, v: f32) {
+        self.controls.volume.store(v);
+    }
+
+    pub fn set_muted(&self, muted: bool) {
+        self.controls.muted.store(muted, Ordering::Relaxed);
+    }
+
+    pub fn track(&self, track: Track) -> TrackSettings {
+        self.controls.tracks[track.index()].load()
+    }
+
+    pub fn set_track(&self, track: Track, settings: TrackSettings) {
+        self.controls.tracks[track.index()].store(settings);
+    }
+
+    pub fn device_rate(&self) -> u32 { self.device_rate }
+
+    /// voices being mixed right now
+    pub fn voice_count(&self) -> usize {
+        self.controls.voices.load(Ordering::Relaxed)
+    }
+
+    /// share of the audio callback's time budget in use, 1.0 being all of it
+    pub fn load(&self) -> f32 {
+        self.controls.load.load()
+    }
+
+    /// callbacks since startup that missed their deadline
+    pub fn underruns(&self) -> u64 {
+        self.controls.underruns.load(Ordering::Relaxed)
+    }
+}
+
+/// the voice channel, the controls and the master chain over each side of the mix, at the
+/// engine rate
+fn mix_chains(
+    master: impl Fn(usize, SynthSource) -> SynthSource,
+) -> (Sender<EngineMsg>, Arc<MasterControls>, [SynthSource; 2]) {
+    let (tx, rx) = mpsc::channel();
+    let controls = Arc::new(MasterControls {
+        volume: AtomicF32::new(1.0),
+        muted: AtomicBool::new(false),
+        tracks: Default::default(),
+        voices: AtomicUsize::new(0),
+        load: AtomicF32::new(0.0),
+        underruns: AtomicU64::new(0),
+    });
+    for track in &controls.tracks {
+        track.store(TrackSettings::default());
+    }
+
+    let mix = Arc::new(Mutex::new(VoiceMix::new(rx, controls.clone())));
+    let chains = [0, 1].map(|channel| master(channel, Box::new(MixTap { mix: mix.clone(), channel })));
+    (tx, controls, chains)
+}
+
+/// first output device whose name contains `name`, ignoring case. the error lists what is there
+fn find_device(host: &cpal::Host, name: &str) -> Result<cpal::Device, Box<dyn std::error::Error>> {
+    let wanted = name.to_lowercase();
+    let mut names = vec![];
+    for device in host.output_devices()? {
+        let Ok(desc) = device.description() else { continue; };
+        if desc.name().to_lowercase().contains(&wanted) {
+            return Ok(device);
+        }
+        names.push(desc.name().to_string());
+    }
+    Err(format!("no output device matching \"{name}\" (found: {})", names.join(", ")).into())
+}
+
+/// the requested rate if the device can do it, then the engine rate, otherwise whatever it
+/// prefers. the chain is resampled to whichever rate is picked
+fn pick_config(
+    device: &cpal::Device,
+    sample_rate: Option<u32>,
+) -> Result<cpal::SupportedStreamConfig, Box<dyn std::error::Error>> {
+    for rate in sample_rate.into_iter().chain([SAMPLE_RATE]) {
+        let native = device
+            .supported_output_configs()?
+            .filter(|r| matches!(r.sample_format(), SampleFormat::F32 | SampleFormat::I16 | SampleFormat::U16 | SampleFormat::I32))
+            .filter_map(|r| r.try_with_sample_rate(rate))
+            .max_by_key(|c| c.sample_format() == SampleFormat::F32);
+        if let Some(c) = native {
+            return Ok(c);
+        }
+    }
+    Ok(device.default_output_config()?)
+}
+
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    chains: [SynthSource; 2],
+    controls: Arc<MasterControls>,
+) -> Result<cpal::Stream, Box<dyn std::error::Error>>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let channels = config.channels.max(1) as usize;
+    let rate = config.sample_rate as f32;
+    let [mut left, mut right] = chains;
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], _| {
+            let started = Instant::now();
+            // stereo chains; a mono device gets both folded together, any past two stay silent
+            for frame in data.chunks_mut(channels) {
+                let l = left.next().unwrap_or(0.0);
+                let r = right.next().unwrap_or(0.0);
+                if let [only] = frame {
+                    *only = T::from_sample((l + r) * 0.5);
+                    continue;
+                }
+                frame.fill(T::from_sample(0.0));
+                frame[0] = T::from_sample(l);
+                frame[1] = T::from_sample(r);
+            }
+
+            let budget = (data.len() / channels) as f32 / rate;
+            let load = started.elapsed().as_secs_f32() / budget.max(f32::EPSILON);
+            if load > 1.0 {
+                controls.underruns.fetch_add(1, Ordering::Relaxed);
+            }
+            let avg = controls.load.load();
+            controls.load.store(avg + (load - avg) * ENGINE_LOAD_SMOOTHING);
+        },
+        |e| crash::engine_error(&e.to_string()),
+        None,
+    )?;
+    Ok(stream)
+}
+
+struct MixVoice {
+    id: VoiceId,
+    source: SynthSource,
+    track: Track,
+    done: Arc<AtomicBool>,
+    /// 1.0 while playing; counts down once killed
+    fade: f32,
+    fading: bool,
+}
+
+/// sums the voices onto their tracks, pans the tracks into a stereo frame and applies master
+/// volume/mute. never ends, silence when nothing plays
+struct VoiceMix {
+    rx: Receiver<EngineMsg>,
+    voices: Vec<MixVoice>,
+    controls: Arc<MasterControls>,
+    gain: f32,
+    /// left and right gain of each track, smoothed towards its fader, pan, mute and solo
+    track_gains: [[f32; 2]; Track::COUNT],
+    fade_step: f32,
+    counter: usize,
+    /// frames made but not yet taken by each channel's tap
+    pending: [VecDeque<f32>; 2],
+}
+
+impl VoiceMix {
+    fn new(rx: Receiver<EngineMsg>, controls: Arc<MasterControls>) -> Self {
+        let fade_samples = (ENGINE_KILL_FADE_MS / 1000.0 * SAMPLE_RATE as f32).max(1.0);
+        Self {
+            rx,
+            voices: vec![],
+            controls,
+            gain: 1.0,
+            track_gains: [[1.0; 2]; Track::COUNT],
+            fade_step: 1.0 / fade_samples,
+            counter: 0,
+            // the chains only drift apart by what their first stages read ahead
+            pending: [VecDeque::with_capacity(64), VecDeque::with_capacity(64)],
+        }
+    }
+
+    fn drain_messages(&mut self) {
+        while let Ok(msg) = self.rx.try_recv() {
+            match msg {
+                EngineMsg::Add { id, source, track, done } => {
+                    self.voices.push(MixVoice { id, source, track, done, fade: 1.0, fading: false });
+                }
+                EngineMsg::Kill(id) => {
+                    if let Some(v) = self.voices.iter_mut().find(|v| v.id == id) {
+                        v.fading = true;
+                    }
+                }
+                EngineMsg::KillAll => {
+                    for v in self.voices.iter_mut() {
+                        v.fading = true;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl VoiceMix {
+    /// mixes one stereo frame onto the end of `pending`
+    fn mix_frame(&mut self) {
+        if self.counter == 0 {
+            self.drain_messages();
+            self.controls.voices.store(self.voices.len(), Ordering::Relaxed);
+        }
+        self.counter = (self.counter + 1) % ENGINE_CONTROL_BLOCK;
+
+        let mut sums = [0.0; Track::COUNT];
+        let mut i = 0;
+        while i < self.voices.len() {
+            let v = &mut self.voices[i];
+            if v.fading {
+                v.fade -= self.fade_step;
+            }
+            match v.source.next() {
+                Some(x) if v.fade > 0.0 => {
+                    sums[v.track.index()] += x * v.fade;
+                    i += 1;
+                }
+                _ => {
+                    v.done.store(true, Ordering::Release);
+                    self.voices.swap_remove(i);
+                }
+            }
+        }
+
+        let tracks = self.controls.tracks.each_ref().map(|t| t.load());
+        let soloing = tracks.iter().any(|t| t.solo);
+        let mut frame = [0.0; 2];
+        for (i, t) in tracks.iter().enumerate() {
+            let heard = !t.muted && (!soloing || t.solo);
+            let volume = if heard { t.volume } else { 0.0 };
+            let targets = [volume * (1.0 - t.pan).min(1.0), volume * (1.0 + t.pan).min(1.0)];
+            for side in 0..2 {
+                let g = &mut self.track_gains[i][side];
+                *g += (targets[side] - *g) * ENGINE_VOLUME_SMOOTHING;
+                frame[side] += sums[i] * *g;
+            }
+        }
+
+        let target = if self.controls.muted.load(Ordering::Relaxed) { 0.0 } else { self.controls.volume.load() };
+        self.gain += (target - self.gain) * ENGINE_VOLUME_SMOOTHING;
+        for (pending, x) in self.pending.iter_mut().zip(frame) {
+            pending.push_back(x * self.gain);
+        }
+    }
+}
+
+/// one side of the voice mix, as a source the master chain can run on. the two taps share the
+/// mix; whichever runs out first mixes the next frame and leaves the other side waiting
+struct MixTap {
+    mix: Arc<Mutex<VoiceMix>>,
+    channel: usize,
+}
+
+impl Iterator for MixTap {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        // only the audio thread takes this lock, once per sample per side
+        let mut mix = self.mix.lock().unwrap_or_else(|e| e.into_inner());
+        if mix.pending[self.channel].is_empty() {
+            mix.mix_frame();
+        }
+        mix.pending[self.channel].pop_front()
+    }
+}
+
+impl Source for MixTap {
+    fn current_span_len(&self) -> Option<usize> { None }
+    fn channels(&self) -> u16 { 1 }
+    fn sample_rate(&self) -> u32 { SAMPLE_RATE }
+    fn total_duration(&self) -> Option<Duration> { None }
+}
@@ -7,124 +7,296 @@ use std::{
     time::Duration,
 };
 
-use crate::audio_patch::Node;
+use serde::{Deserialize, Serialize};
+
+use crate::audio_patch::{AtomicF32, Node};
+use crate::config::{
+    ADSR_CURVE_STEEPNESS, ADSR_TIME_MAX_S, ADSR_TIME_MIN_S, FILTER_ENV_ATTACK_S, FILTER_ENV_DECAY_S,
+    FILTER_ENV_DEPTH_MAX, FILTER_ENV_RELEASE_S, FILTER_ENV_SUSTAIN,
+};
 
 pub type SynthSource = Box<dyn Source<Item = f32> + Send>;
 pub type Gate = Arc<AtomicBool>;
+/// per-voice multiplier on the release time, read when the gate closes (1.0 = the ADSR's own)
+pub type ReleaseScale = Arc<AtomicF32>;
+/// a voice's envelope level, published every sample so a retriggered note can carry on from it
+pub type VoiceLevel = Arc<AtomicF32>;
 
-#[derive(Clone, Copy, Debug)]
+/// how a segment moves from its start level to its target
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Curve {
+    #[default]
+    Linear,
+    /// fast at first, then easing in, like an analog envelope: attacks bow up (logarithmic),
+    /// decays and releases drop quickly and tail off (exponential)
+    Exponential,
+    /// slow at both ends
+    SCurve,
+}
+
+impl Curve {
+    pub const ALL: [Curve; 3] = [Curve::Linear, Curve::Exponential, Curve::SCurve];
+
+    /// share of the way to the target after `t` (0..1) of the segment; 0 and 1 at the ends
+    pub fn shape(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Curve::Linear => t,
+            Curve::Exponential => {
+                let k = ADSR_CURVE_STEEPNESS;
+                (1.0 - (-k * t).exp()) / (1.0 - (-k).exp())
+            }
+            Curve::SCurve => t * t * (3.0 - 2.0 * t),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Curve::Linear => "lin",
+            Curve::Exponential => "exp",
+            Curve::SCurve => "s",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        let i = Self::ALL.iter().position(|c| *c == self).unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+}
+
+/// what a key does when it is struck again while its last note still sounds
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Retrigger {
+    /// a new voice from zero; the old one rings out under it
+    #[default]
+    Overlap,
+    /// the old voice is cut and a new one attacks from zero
+    Restart,
+    /// the new voice takes over at the old one's level and moves straight to sustain, no attack
+    Legato,
+    /// the new voice takes over at the old one's level and attacks from there
+    FromCurrent,
+}
+
+impl Retrigger {
+    pub const ALL: [Retrigger; 4] = [Retrigger::Overlap, Retrigger::Restart, Retrigger::Legato, Retrigger::FromCurrent];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Retrigger::Overlap => "overlap",
+            Retrigger::Restart => "restart",
+            Retrigger::Legato => "legato",
+            Retrigger::FromCurrent => "from current",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        let i = Self::ALL.iter().position(|r| *r == self).unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+
+    /// the stage a voice that takes over from an old one starts in, for the modes that do
+    pub fn resume_stage(self) -> Option<Stage> {
+        match self {
+            Retrigger::Overlap | Retrigger::Restart => None,
+            Retrigger::Legato => Some(Stage::Decay),
+            Retrigger::FromCurrent => Some(Stage::Attack),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Adsr {
     pub attack_s: f32,
     pub decay_s: f32,
     pub sustain: f32,
     pub release_s: f32,
+    #[serde(default)]
+    pub attack_curve: Curve,
+    #[serde(default)]
+    pub decay_curve: Curve,
+    #[serde(default)]
+    pub release_curve: Curve,
+    #[serde(default)]
+    pub retrigger: Retrigger,
 }
 
+/// an `Adsr` in samples at one sample rate
 #[derive(Clone, Copy, Debug)]
 pub struct AdsrEnvelope {
     pub sustain: f32,
-    pub attack_step: f32,
-    pub decay_step: f32,
+    pub attack_samples: f32,
+    pub decay_samples: f32,
     pub release_samples: f32,
+    pub attack_curve: Curve,
+    pub decay_curve: Curve,
+    pub release_curve: Curve,
 }
 
 impl Adsr {
+    /// linear segments
     pub fn new(attack_s: f32, decay_s: f32, sustain: f32, release_s: f32) -> Self {
-        Self { attack_s, decay_s, sustain, release_s }
+        Self {
+            attack_s,
+            decay_s,
+            sustain,
+            release_s,
+            attack_curve: Curve::Linear,
+            decay_curve: Curve::Linear,
+            release_curve: Curve::Linear,
+            retrigger: Retrigger::Overlap,
+        }
+    }
+
+    /// stage times within what the editor allows and sustain 0..1; anything not a number is the
+    /// shortest time, or silence
+    pub fn clamped(self) -> Self {
+        let time = |s: f32| if s.is_nan() { ADSR_TIME_MIN_S } else { s.clamp(ADSR_TIME_MIN_S, ADSR_TIME_MAX_S) };
+        Self {
+            attack_s: time(self.attack_s),
+            decay_s: time(self.decay_s),
+            sustain: if self.sustain.is_nan() { 0.0 } else { self.sustain.clamp(0.0, 1.0) },
+            release_s: time(self.release_s),
+            ..self
+        }
     }
 
     pub fn to_envelope(&self, sample_rate: u32) -> AdsrEnvelope {
         let sr = sample_rate as f32;
-
-        let attack_samples = (self.attack_s.max(0.0) * sr).max(1.0);
-        let decay_samples = (self.decay_s.max(0.0) * sr).max(1.0);
-        let release_samples = (self.release_s.max(0.0) * sr).max(1.0);
-
-        let sustain = self.sustain.clamp(0.0, 1.0);
+        // whole samples, so a segment lands on its target exactly on its last sample
+        let samples = |s: f32| (s.max(0.0) * sr).round().max(1.0);
 
         AdsrEnvelope {
-            sustain,
-            attack_step: 1.0 / attack_samples,
-            decay_step: (1.0 - sustain) / decay_samples,
-            release_samples,
+            sustain: self.sustain.clamp(0.0, 1.0),
+            attack_samples: samples(self.attack_s),
+            decay_samples: samples(self.decay_s),
+            release_samples: samples(self.release_s),
+            attack_curve: self.attack_curve,
+            decay_curve: self.decay_curve,
+            release_curve: self.release_curve,
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum Stage { Attack, Decay, Sustain, Release, Done }
-
-pub struct AdsrNode {
+/// the second envelope of every voice, sent to the filter cutoff: it opens the lowpass `depth`
+/// octaves above `FILTER_ENV_BASE_HZ` at its peak. depth 0 leaves the filter out
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FilterEnvelope {
     pub adsr: Adsr,
-    pub sample_rate: u32,
-    pub gate: Gate,
+    pub depth: f32,
 }
 
-impl AdsrNode {
-    pub fn new(adsr: Adsr, sample_rate: u32, gate: Gate) -> Self {
-        Self { adsr, sample_rate, gate }
+impl Default for FilterEnvelope {
+    fn default() -> Self {
+        Self {
+            adsr: Adsr::new(FILTER_ENV_ATTACK_S, FILTER_ENV_DECAY_S, FILTER_ENV_SUSTAIN, FILTER_ENV_RELEASE_S),
+            depth: 0.0,
+        }
     }
 }
 
-pub struct AdsrSource {
-    input: SynthSource,
-    envelope: AdsrEnvelope,
-    gate: Gate,
-    sample_rate: u32,
+impl FilterEnvelope {
+    pub fn clamped(mut self) -> Self {
+        self.adsr = self.adsr.clamped();
+        self.depth = self.depth.clamp(0.0, FILTER_ENV_DEPTH_MAX);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage { Attack, Decay, Sustain, Release, Done }
+
+/// where a voice is on its envelope; stepped one sample at a time with the gate value passed in,
+/// so the stage logic is deterministic and can be driven without a real source or thread
+#[derive(Debug, Clone, Copy)]
+pub struct EnvelopeState {
     stage: Stage,
     current_amp: f32,
-    release_step: f32,
+    /// samples into the current stage
+    pos: f32,
+    /// level the current stage started from
+    from: f32,
 }
 
-impl AdsrSource {
-    pub fn new(input: SynthSource, adsr: Adsr, sample_rate: u32, gate: Gate) -> Self {
-        Self {
-            input,
-            envelope: adsr.to_envelope(sample_rate),
-            gate,
-            sample_rate,
-            stage: Stage::Attack,
-            current_amp: 0.0,
-            release_step: 0.0,
+impl Default for EnvelopeState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EnvelopeState {
+    pub fn new() -> Self {
+        Self { stage: Stage::Attack, current_amp: 0.0, pos: 0.0, from: 0.0 }
+    }
+
+    /// picks up at `level` at the start of `stage` (attack or decay), as a voice taking over from
+    /// a retriggered one does; from nothing it is a fresh start
+    pub fn resume(level: f32, stage: Stage) -> Self {
+        if level <= 0.0 {
+            return Self::new();
         }
+        Self { stage, current_amp: level, pos: 0.0, from: level }
+    }
+
+    pub fn stage(&self) -> Stage { self.stage }
+    pub fn level(&self) -> f32 { self.current_amp }
+
+    fn enter(&mut self, stage: Stage) {
+        self.stage = stage;
+        self.pos = 0.0;
+        self.from = self.current_amp;
     }
 
     fn enter_release(&mut self) {
-        self.stage = Stage::Release;
-        self.release_step = self.current_amp / self.envelope.release_samples.max(1.0);
+        // nothing to fade out when the gate closes before the attack got anywhere
+        if self.current_amp <= 0.0 {
+            self.enter(Stage::Done);
+            return;
+        }
+        self.enter(Stage::Release);
     }
 
-    fn step_envelope(&mut self) -> f32 {
-        if !self.gate.load(Ordering::Relaxed)
-            && self.stage != Stage::Release
-            && self.stage != Stage::Done
-        {
+    /// advance one sample. a closed gate always wins over the stage transitions below it,
+    /// whatever stage the voice is in, so a voice can never stay in Attack/Decay/Sustain once released
+    pub fn step(&mut self, envelope: &AdsrEnvelope, gate_open: bool) -> f32 {
+        if !gate_open && matches!(self.stage, Stage::Attack | Stage::Decay | Stage::Sustain) {
             self.enter_release();
         }
 
+        // levels come from the position in the stage rather than adding up per-sample steps,
+        // so the end of each segment is hit exactly instead of a float error away
+        self.pos += 1.0;
         match self.stage {
             Stage::Attack => {
-                self.current_amp += self.envelope.attack_step;
-                if self.current_amp >= 1.0 {
+                let t = self.pos / envelope.attack_samples;
+                if t >= 1.0 {
                     self.current_amp = 1.0;
-                    self.stage = Stage::Decay;
+                    self.enter(Stage::Decay);
+                } else {
+                    self.current_amp = self.from + (1.0 - self.from) * envelope.attack_curve.shape(t);
                 }
             }
             Stage::Decay => {
-                self.current_amp -= self.envelope.decay_step;
-                if self.current_amp <= self.envelope.sustain {
-                    self.current_amp = self.envelope.sustain;
-                    self.stage = Stage::Sustain;
+                let t = self.pos / envelope.decay_samples;
+                if t >= 1.0 {
+                    self.current_amp = envelope.sustain;
+                    self.enter(Stage::Sustain);
+                } else {
+                    self.current_amp = self.from - (self.from - envelope.sustain) * envelope.decay_curve.shape(t);
                 }
             }
             Stage::Sustain => {
-                self.current_amp = self.envelope.sustain;
+                self.current_amp = envelope.sustain;
             }
             Stage::Release => {
-                self.current_amp -= self.release_step;
-                if self.current_amp <= 0.0 {
+                let t = self.pos / envelope.release_samples.max(1.0);
+                if t >= 1.0 {
                     self.current_amp = 0.0;
-                    self.stage = Stage::Done;
+                    self.enter(Stage::Done);
+                } else {
+                    self.current_amp = self.from * (1.0 - envelope.release_curve.shape(t));
                 }
             }
             Stage::Done => {
@@ -136,18 +308,95 @@ impl AdsrSource {
     }
 }
 
+pub struct AdsrNode {
+    pub adsr: Adsr,
+    pub sample_rate: u32,
+    pub gate: Gate,
+    pub release_scale: Option<ReleaseScale>,
+    pub level: Option<VoiceLevel>,
+    pub carry_from: Option<VoiceLevel>,
+}
+
+impl AdsrNode {
+    pub fn new(adsr: Adsr, sample_rate: u32, gate: Gate) -> Self {
+        Self { adsr, sample_rate, gate, release_scale: None, level: None, carry_from: None }
+    }
+
+    /// lets the release time be decided when the note is let go rather than when it starts
+    pub fn with_release_scale(mut self, scale: ReleaseScale) -> Self {
+        self.release_scale = Some(scale);
+        self
+    }
+
+    /// publishes the envelope level into `level` every sample
+    pub fn with_level(mut self, level: VoiceLevel) -> Self {
+        self.level = Some(level);
+        self
+    }
+
+    /// starts at the level in `from`, read on the first sample, when `adsr.retrigger` carries on
+    /// from the voice it replaces
+    pub fn carrying_on_from(mut self, from: VoiceLevel) -> Self {
+        self.carry_from = Some(from);
+        self
+    }
+}
+
+pub struct AdsrSource {
+    input: SynthSource,
+    envelope: AdsrEnvelope,
+    gate: Gate,
+    release_scale: Option<ReleaseScale>,
+    level: Option<VoiceLevel>,
+    /// the old voice's level and the stage to resume in, until the first sample
+    carry_from: Option<(VoiceLevel, Stage)>,
+    sample_rate: u32,
+    state: EnvelopeState,
+}
+
+impl AdsrSource {
+    pub fn new(input: SynthSource, adsr: Adsr, sample_rate: u32, gate: Gate) -> Self {
+        Self {
+            input,
+            envelope: adsr.to_envelope(sample_rate),
+            gate,
+            release_scale: None,
+            level: None,
+            carry_from: None,
+            sample_rate,
+            state: EnvelopeState::new(),
+        }
+    }
+
+    pub fn stage(&self) -> Stage { self.state.stage() }
+}
+
 impl Iterator for AdsrSource {
     type Item = f32;
 
     fn next(&mut self) -> Option<f32> {
-        if self.stage == Stage::Done {
+        if self.state.stage() == Stage::Done {
             return None;
         }
 
         let x = self.input.next()?;
-        let env = self.step_envelope();
+        if let Some((from, stage)) = self.carry_from.take() {
+            self.state = EnvelopeState::resume(from.load(), stage);
+        }
+        // the gate is read exactly once per sample, before any stage logic runs
+        let gate_open = self.gate.load(Ordering::Acquire);
+        if !gate_open
+            && matches!(self.state.stage(), Stage::Attack | Stage::Decay | Stage::Sustain)
+            && let Some(scale) = &self.release_scale
+        {
+            self.envelope.release_samples = (self.envelope.release_samples * scale.load()).max(1.0);
+        }
+        let env = self.state.step(&self.envelope, gate_open);
+        if let Some(level) = &self.level {
+            level.store(env);
+        }
 
-        if self.stage == Stage::Done {
+        if self.state.stage() == Stage::Done {
             return None;
         }
 
@@ -164,7 +413,11 @@ impl Source for AdsrSource {
 
 impl Node for AdsrNode {
     fn apply(&self, input: SynthSource) -> SynthSource {
-        Box::new(AdsrSource::new(input, self.adsr, self.sample_rate, self.gate.clone()))
+        let mut src = AdsrSource::new(input, self.adsr, self.sample_rate, self.gate.clone());
+        src.release_scale = self.release_scale.clone();
+        src.level = self.level.clone();
+        src.carry_from = self.carry_from.clone().zip(self.adsr.retrigger.resume_stage());
+        Box::new(src)
     }
     fn name(&self) -> &'static str { "ADSR" }
 }
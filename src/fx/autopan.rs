@@ -0,0 +1,144 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+use std::time::Duration;
+
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+
+use crate::audio_patch::{AtomicF32, Node, ParamDesc, ParamValue, SynthSource, find_param};
+use crate::config::{AUTOPAN_RATE_DEFAULT, LFO_RATE_MAX, LFO_RATE_MIN};
+use crate::echo::Division;
+use crate::fx::lfo::{Lfo, LfoShape, SyncedRate};
+
+/// plain copy of the auto-pan controls
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoPanSettings {
+    pub enabled: bool,
+    pub rate_hz: f32,
+    /// when set, one sweep per division of the transport tempo and `rate_hz` is ignored
+    pub sync: Option<Division>,
+    /// how far it swings either way, 0..1; 1 goes hard left and hard right
+    pub depth: f32,
+}
+
+impl Default for AutoPanSettings {
+    fn default() -> Self {
+        Self { enabled: false, rate_hz: AUTOPAN_RATE_DEFAULT, sync: None, depth: 0.8 }
+    }
+}
+
+impl AutoPanSettings {
+    pub fn clamped(self) -> Self {
+        Self { rate_hz: self.rate_hz.clamp(LFO_RATE_MIN, LFO_RATE_MAX), depth: self.depth.clamp(0.0, 1.0), ..self }
+    }
+}
+
+#[derive(Debug)]
+struct AutoPanParams {
+    enabled: AtomicBool,
+    rate: SyncedRate,
+    depth: AtomicF32,
+}
+
+/// moves the whole mix between the speakers with an LFO. it needs to know which side it is on,
+/// so the master chain uses `apply_side`; `apply` on its own pans as the left side would
+#[derive(Debug, Clone)]
+pub struct AutoPan {
+    params: Arc<AutoPanParams>,
+}
+
+impl AutoPan {
+    pub fn new(settings: AutoPanSettings) -> Self {
+        let s = settings.clamped();
+        Self {
+            params: Arc::new(AutoPanParams {
+                enabled: AtomicBool::new(s.enabled),
+                rate: SyncedRate::new(s.rate_hz, s.sync),
+                depth: AtomicF32::new(s.depth),
+            }),
+        }
+    }
+
+    pub fn settings(&self) -> AutoPanSettings {
+        AutoPanSettings {
+            enabled: self.params.enabled.load(Ordering::Relaxed),
+            rate_hz: self.params.rate.rate_hz(),
+            sync: self.params.rate.sync(),
+            depth: self.params.depth.load(),
+        }
+    }
+
+    /// pans one side (0 left, 1 right) of the mix. both sides' LFOs start together and are pulled
+    /// in step, so they agree on where the sound is
+    pub fn apply_side(&self, side: usize, input: SynthSource) -> SynthSource {
+        Box::new(AutoPanSource { input, params: self.params.clone(), lfo: Lfo::new(), right: side == 1 })
+    }
+}
+
+impl Node for AutoPan {
+    fn apply(&self, input: SynthSource) -> SynthSource {
+        self.apply_side(0, input)
+    }
+
+    fn name(&self) -> &'static str { "AutoPan" }
+
+    fn params(&self) -> Vec<ParamDesc> {
+        let s = self.settings();
+        vec![
+            ParamDesc::switch("enabled", s.enabled),
+            ParamDesc::float("rate_hz", s.rate_hz, LFO_RATE_MIN, LFO_RATE_MAX, 0.25),
+            ParamDesc::float("sync", self.params.rate.sync_index(), 0.0, Division::ALL.len() as f32, 1.0),
+            ParamDesc::float("depth", s.depth, 0.0, 1.0, 0.05),
+        ]
+    }
+
+    fn set_param(&self, id: &str, value: ParamValue) -> Result<(), Box<dyn std::error::Error>> {
+        let desc = find_param(&self.params(), self.name(), id)?;
+        let p = &self.params;
+        match id {
+            "enabled" => p.enabled.store(value.bool()?, Ordering::Relaxed),
+            "rate_hz" => p.rate.set_rate_hz(desc.clamp(value)?),
+            "sync" => p.rate.set_sync_index(desc.clamp(value)?),
+            _ => p.depth.store(desc.clamp(value)?),
+        }
+        Ok(())
+    }
+
+    fn set_bpm(&self, bpm: f32) {
+        self.params.rate.set_bpm(bpm);
+    }
+}
+
+pub struct AutoPanSource {
+    input: SynthSource,
+    params: Arc<AutoPanParams>,
+    lfo: Lfo,
+    right: bool,
+}
+
+impl Iterator for AutoPanSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let x = self.input.next()?;
+        // kept running while off, so the two sides stay in step whenever it comes back
+        let v = self.lfo.step(LfoShape::Sine, self.params.rate.hz(), self.input.sample_rate());
+        if !self.params.enabled.load(Ordering::Relaxed) {
+            return Some(x);
+        }
+        // the same balance law as the track pan: the centre leaves both sides at full level
+        let pan = v * self.params.depth.load();
+        let gain = if self.right { 1.0 + pan } else { 1.0 - pan };
+        Some(x * gain.min(1.0))
+    }
+}
+
+impl Source for AutoPanSource {
+    fn current_span_len(&self) -> Option<usize> { self.input.current_span_len() }
+    fn channels(&self) -> u16 { self.input.channels() }
+    fn sample_rate(&self) -> u32 { self.input.sample_rate() }
+    fn total_duration(&self) -> Option<Duration> { self.input.total_duration() }
+}
@@ -0,0 +1,108 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+use std::time::Duration;
+
+use rodio::Source;
+
+use crate::audio_patch::{AtomicF32, Node, ParamDesc, ParamValue, SynthSource, find_param};
+use crate::config::{BITCRUSH_BITS_DEFAULT, BITCRUSH_BITS_MAX, BITCRUSH_DOWNSAMPLE_DEFAULT, BITCRUSH_DOWNSAMPLE_MAX};
+
+#[derive(Debug)]
+struct BitcrushParams {
+    enabled: AtomicBool,
+    /// levels each side of zero are 2^(bits-1); fractional depths sweep smoothly
+    bits: AtomicF32,
+    /// each sample is held this many output samples, so the rate drops by this factor
+    downsample: AtomicF32,
+}
+
+/// lo-fi crusher for the master bus: fewer bits for quantization grit, a lower sample rate (held
+/// samples, no filtering) for the aliased chiptune edge. off passes the sound through untouched;
+/// the controls are shared with the running source, so it can be flipped while playing
+#[derive(Debug, Clone)]
+pub struct Bitcrush {
+    params: Arc<BitcrushParams>,
+}
+
+impl Default for Bitcrush {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bitcrush {
+    pub fn new() -> Self {
+        Self {
+            params: Arc::new(BitcrushParams {
+                enabled: AtomicBool::new(false),
+                bits: AtomicF32::new(BITCRUSH_BITS_DEFAULT),
+                downsample: AtomicF32::new(BITCRUSH_DOWNSAMPLE_DEFAULT),
+            }),
+        }
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.params.enabled.load(Ordering::Relaxed)
+    }
+}
+
+impl Node for Bitcrush {
+    fn apply(&self, input: SynthSource) -> SynthSource {
+        Box::new(BitcrushSource { input, params: self.params.clone(), held: 0.0, phase: 1.0 })
+    }
+
+    fn name(&self) -> &'static str { "Bitcrush" }
+
+    fn params(&self) -> Vec<ParamDesc> {
+        vec![
+            ParamDesc::switch("enabled", self.is_on()),
+            ParamDesc::float("bits", self.params.bits.load(), 1.0, BITCRUSH_BITS_MAX, 1.0),
+            ParamDesc::float("downsample", self.params.downsample.load(), 1.0, BITCRUSH_DOWNSAMPLE_MAX, 1.0),
+        ]
+    }
+
+    fn set_param(&self, id: &str, value: ParamValue) -> Result<(), Box<dyn std::error::Error>> {
+        let desc = find_param(&self.params(), self.name(), id)?;
+        match id {
+            "enabled" => self.params.enabled.store(value.bool()?, Ordering::Relaxed),
+            "bits" => self.params.bits.store(desc.clamp(value)?),
+            _ => self.params.downsample.store(desc.clamp(value)?),
+        }
+        Ok(())
+    }
+}
+
+pub struct BitcrushSource {
+    input: SynthSource,
+    params: Arc<BitcrushParams>,
+    held: f32,
+    /// output samples since the held one was taken, in input samples; a new one is taken at 1
+    phase: f32,
+}
+
+impl Iterator for BitcrushSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let x = self.input.next()?;
+        if !self.params.enabled.load(Ordering::Relaxed) {
+            return Some(x);
+        }
+        self.phase += 1.0 / self.params.downsample.load().max(1.0);
+        if self.phase >= 1.0 {
+            self.phase -= self.phase.floor();
+            let levels = 2f32.powf(self.params.bits.load() - 1.0);
+            self.held = (x * levels).round() / levels;
+        }
+        Some(self.held)
+    }
+}
+
+impl Source for BitcrushSource {
+    fn current_span_len(&self) -> Option<usize> { self.input.current_span_len() }
+    fn channels(&self) -> u16 { self.input.channels() }
+    fn sample_rate(&self) -> u32 { self.input.sample_rate() }
+    fn total_duration(&self) -> Option<Duration> { self.input.total_duration() }
+}
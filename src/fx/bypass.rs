@@ -0,0 +1,96 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+use std::time::Duration;
+
+use rodio::Source;
+
+use crate::audio_patch::{AtomicF32, Node, SynthSource};
+use crate::config::BYPASS_FADE_MS;
+
+/// a switch that takes a node out of the signal without rebuilding what runs through it. sources
+/// check it every sample and fade between the node's output and the untouched input, so flipping
+/// it under a held note neither restrikes nor clicks
+#[derive(Debug, Clone, Default)]
+pub struct Bypass {
+    on: Arc<AtomicBool>,
+}
+
+impl Bypass {
+    pub fn is_on(&self) -> bool {
+        self.on.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, on: bool) {
+        self.on.store(on, Ordering::Relaxed);
+    }
+
+    /// `node` on `input`, behind this switch. the node has to give one sample out for each one in,
+    /// as every effect does
+    pub fn apply(&self, node: &dyn Node, input: SynthSource) -> SynthSource {
+        let tap = Arc::new(AtomicF32::new(0.0));
+        let channels = input.channels();
+        let sample_rate = input.sample_rate();
+        let wet = node.apply(Box::new(TapSource { tap: tap.clone(), channels, sample_rate }));
+        let step = 1.0 / (BYPASS_FADE_MS / 1000.0 * sample_rate as f32 * channels as f32).max(1.0);
+        let dry = if self.is_on() { 1.0 } else { 0.0 };
+        Box::new(BypassSource { input, tap, wet, on: self.on.clone(), dry, step })
+    }
+}
+
+/// hands the node whatever sample `BypassSource` last put in
+struct TapSource {
+    tap: Arc<AtomicF32>,
+    channels: u16,
+    sample_rate: u32,
+}
+
+impl Iterator for TapSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        Some(self.tap.load())
+    }
+}
+
+impl Source for TapSource {
+    fn current_span_len(&self) -> Option<usize> { None }
+    fn channels(&self) -> u16 { self.channels }
+    fn sample_rate(&self) -> u32 { self.sample_rate }
+    fn total_duration(&self) -> Option<Duration> { None }
+}
+
+pub struct BypassSource {
+    input: SynthSource,
+    tap: Arc<AtomicF32>,
+    wet: SynthSource,
+    on: Arc<AtomicBool>,
+    /// how much of the untouched input is heard, 0..1
+    dry: f32,
+    step: f32,
+}
+
+impl Iterator for BypassSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let x = self.input.next()?;
+        let target = if self.on.load(Ordering::Relaxed) { 1.0 } else { 0.0 };
+        self.dry = if self.dry < target { (self.dry + self.step).min(target) } else { (self.dry - self.step).max(target) };
+        if self.dry >= 1.0 {
+            // the node is not run at all; it picks up where it left off when it comes back in
+            return Some(x);
+        }
+        self.tap.store(x);
+        let y = self.wet.next().unwrap_or(x);
+        Some(y + (x - y) * self.dry)
+    }
+}
+
+impl Source for BypassSource {
+    fn current_span_len(&self) -> Option<usize> { self.input.current_span_len() }
+    fn channels(&self) -> u16 { self.input.channels() }
+    fn sample_rate(&self) -> u32 { self.input.sample_rate() }
+    fn total_duration(&self) -> Option<Duration> { self.input.total_duration() }
+}
@@ -0,0 +1,196 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::audio_patch::{Node, ParamValue, ParamValues, SynthSource, param_values, set_param_values};
+use crate::fx::bitcrush::Bitcrush;
+use crate::fx::bypass::Bypass;
+use crate::fx::compressor::{Compressor, CompressorSettings};
+use crate::fx::drive::{Drive, DriveSettings};
+use crate::fx::eq::ThreeBandEq;
+use crate::fx::flanger::{Flanger, FlangerSettings};
+use crate::fx::phaser::{Phaser, PhaserSettings};
+use crate::fx::tremolo::{Tremolo, TremoloSettings};
+
+/// the effects a patch's chain can hold
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FxKind {
+    Drive,
+    Bitcrush,
+    Compressor,
+    Eq,
+    Phaser,
+    Flanger,
+    Tremolo,
+}
+
+impl FxKind {
+    pub const ALL: [FxKind; 7] = [
+        FxKind::Drive,
+        FxKind::Bitcrush,
+        FxKind::Compressor,
+        FxKind::Eq,
+        FxKind::Phaser,
+        FxKind::Flanger,
+        FxKind::Tremolo,
+    ];
+
+    pub fn next(self) -> Self {
+        let i = Self::ALL.iter().position(|k| *k == self).unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+
+    /// the node's own name
+    pub fn name(self) -> &'static str {
+        match self {
+            FxKind::Drive => "Drive",
+            FxKind::Bitcrush => "Bitcrush",
+            FxKind::Compressor => "Compressor",
+            FxKind::Eq => "EQ",
+            FxKind::Phaser => "Phaser",
+            FxKind::Flanger => "Flanger",
+            FxKind::Tremolo => "Tremolo",
+        }
+    }
+
+    /// a fresh one at its defaults, switched on: in a chain, bypass is what turns it off
+    fn make(self) -> Arc<dyn Node> {
+        let node: Arc<dyn Node> = match self {
+            FxKind::Drive => Arc::new(Drive::new(DriveSettings::default())),
+            FxKind::Bitcrush => Arc::new(Bitcrush::new()),
+            FxKind::Compressor => Arc::new(Compressor::new(CompressorSettings::default())),
+            FxKind::Eq => Arc::new(ThreeBandEq::default()),
+            FxKind::Phaser => Arc::new(Phaser::new(PhaserSettings::default())),
+            FxKind::Flanger => Arc::new(Flanger::new(FlangerSettings::default())),
+            FxKind::Tremolo => Arc::new(Tremolo::new(TremoloSettings::default())),
+        };
+        if node.params().iter().any(|p| p.id == "enabled") {
+            let _ = node.set_param("enabled", ParamValue::Bool(true));
+        }
+        node
+    }
+}
+
+/// one effect in a chain. the node and its bypass are shared with every note it made, so both can
+/// be turned while they sound
+#[derive(Clone)]
+pub struct FxSlot {
+    pub kind: FxKind,
+    bypass: Bypass,
+    node: Arc<dyn Node>,
+}
+
+impl FxSlot {
+    pub fn new(kind: FxKind) -> Self {
+        Self { kind, bypass: Bypass::default(), node: kind.make() }
+    }
+
+    pub fn bypassed(&self) -> bool {
+        self.bypass.is_on()
+    }
+
+    pub fn node(&self) -> &dyn Node {
+        self.node.as_ref()
+    }
+}
+
+/// one effect of a chain as presets keep it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedSlot {
+    pub kind: FxKind,
+    #[serde(default)]
+    pub bypassed: bool,
+    #[serde(default)]
+    pub params: ParamValues,
+}
+
+/// a patch's own effects, run in order on each of its notes. changes to which effects there are
+/// only reach notes struck afterwards; bypassing one reaches the notes already sounding
+#[derive(Clone, Default)]
+pub struct FxChain {
+    slots: Vec<FxSlot>,
+}
+
+impl FxChain {
+    pub fn slots(&self) -> &[FxSlot] {
+        &self.slots
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn saved(&self) -> Vec<SavedSlot> {
+        self.slots
+            .iter()
+            .map(|s| SavedSlot { kind: s.kind, bypassed: s.bypassed(), params: param_values(s.node()) })
+            .collect()
+    }
+
+    /// fresh effects, set up as `saved` has them, with synced rates following `bpm`
+    pub fn from_saved(saved: &[SavedSlot], bpm: f32) -> Self {
+        let slots = saved
+            .iter()
+            .map(|s| {
+                let slot = FxSlot::new(s.kind);
+                slot.bypass.set(s.bypassed);
+                set_param_values(slot.node(), &s.params);
+                slot.node.set_bpm(bpm);
+                slot
+            })
+            .collect();
+        Self { slots }
+    }
+
+    /// a fresh effect at the end, its synced rate following `bpm`
+    pub fn push(&mut self, kind: FxKind, bpm: f32) {
+        let slot = FxSlot::new(kind);
+        slot.node.set_bpm(bpm);
+        self.slots.push(slot);
+    }
+
+    /// the transport's tempo, passed on to every effect with a rate synced to it
+    pub fn set_bpm(&self, bpm: f32) {
+        for s in &self.slots {
+            s.node.set_bpm(bpm);
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) -> Result<(), Box<dyn std::error::Error>> {
+        self.check(index)?;
+        self.slots.remove(index);
+        Ok(())
+    }
+
+    /// takes the effect at `index` out and puts it back in at `to`
+    pub fn move_to(&mut self, index: usize, to: usize) -> Result<(), Box<dyn std::error::Error>> {
+        self.check(index)?;
+        let slot = self.slots.remove(index);
+        self.slots.insert(to.min(self.slots.len()), slot);
+        Ok(())
+    }
+
+    pub fn set_bypassed(&self, index: usize, bypassed: bool) -> Result<(), Box<dyn std::error::Error>> {
+        self.check(index)?;
+        self.slots[index].bypass.set(bypassed);
+        Ok(())
+    }
+
+    pub fn set_param(&self, index: usize, id: &str, value: ParamValue) -> Result<(), Box<dyn std::error::Error>> {
+        self.check(index)?;
+        self.slots[index].node.set_param(id, value)
+    }
+
+    fn check(&self, index: usize) -> Result<(), Box<dyn std::error::Error>> {
+        if index < self.slots.len() { Ok(()) } else { Err(format!("no effect {index} in the chain").into()) }
+    }
+}
+
+impl Node for FxChain {
+    fn apply(&self, input: SynthSource) -> SynthSource {
+        self.slots.iter().fold(input, |src, s| s.bypass.apply(s.node.as_ref(), src))
+    }
+
+    fn name(&self) -> &'static str { "Chain" }
+}
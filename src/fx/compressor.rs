@@ -0,0 +1,210 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+use std::time::Duration;
+
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+
+use crate::audio_patch::{AtomicF32, Node, ParamDesc, ParamValue, SynthSource, find_param};
+use crate::config::{
+    COMPRESSOR_ATTACK_MS_DEFAULT, COMPRESSOR_MAKEUP_DB_MAX, COMPRESSOR_RATIO_DEFAULT, COMPRESSOR_RATIO_MAX,
+    COMPRESSOR_RELEASE_MS_DEFAULT, COMPRESSOR_THRESHOLD_DB_DEFAULT, COMPRESSOR_THRESHOLD_DB_MIN, COMPRESSOR_TIME_MS_MAX,
+    METER_BLOCK,
+};
+use crate::meter::{from_db, to_db};
+
+/// plain copy of the compressor controls
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompressorSettings {
+    pub enabled: bool,
+    /// level above which it starts turning down, dBFS
+    pub threshold_db: f32,
+    /// dB in over the threshold per dB out; 20 and up is as good as a limiter
+    pub ratio: f32,
+    /// how fast the gain reduction comes in and goes away again
+    pub attack_ms: f32,
+    pub release_ms: f32,
+    /// gain after it, to win back the level it took
+    pub makeup_db: f32,
+}
+
+impl Default for CompressorSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold_db: COMPRESSOR_THRESHOLD_DB_DEFAULT,
+            ratio: COMPRESSOR_RATIO_DEFAULT,
+            attack_ms: COMPRESSOR_ATTACK_MS_DEFAULT,
+            release_ms: COMPRESSOR_RELEASE_MS_DEFAULT,
+            makeup_db: 0.0,
+        }
+    }
+}
+
+impl CompressorSettings {
+    pub fn clamped(self) -> Self {
+        Self {
+            threshold_db: self.threshold_db.clamp(COMPRESSOR_THRESHOLD_DB_MIN, 0.0),
+            ratio: self.ratio.clamp(1.0, COMPRESSOR_RATIO_MAX),
+            attack_ms: self.attack_ms.clamp(0.1, COMPRESSOR_TIME_MS_MAX),
+            release_ms: self.release_ms.clamp(1.0, COMPRESSOR_TIME_MS_MAX),
+            makeup_db: self.makeup_db.clamp(0.0, COMPRESSOR_MAKEUP_DB_MAX),
+            ..self
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CompressorParams {
+    enabled: AtomicBool,
+    threshold_db: AtomicF32,
+    ratio: AtomicF32,
+    attack_ms: AtomicF32,
+    release_ms: AtomicF32,
+    makeup_db: AtomicF32,
+    /// most gain reduction since it was last taken, dB (positive)
+    gain_reduction: AtomicF32,
+}
+
+/// feed-forward peak compressor. on the master bus it evens out the whole mix; on a patch each
+/// note gets its own, and all of them report their gain reduction to the same meter
+#[derive(Debug, Clone)]
+pub struct Compressor {
+    params: Arc<CompressorParams>,
+}
+
+impl Compressor {
+    pub fn new(settings: CompressorSettings) -> Self {
+        let s = settings.clamped();
+        Self {
+            params: Arc::new(CompressorParams {
+                enabled: AtomicBool::new(s.enabled),
+                threshold_db: AtomicF32::new(s.threshold_db),
+                ratio: AtomicF32::new(s.ratio),
+                attack_ms: AtomicF32::new(s.attack_ms),
+                release_ms: AtomicF32::new(s.release_ms),
+                makeup_db: AtomicF32::new(s.makeup_db),
+                gain_reduction: AtomicF32::new(0.0),
+            }),
+        }
+    }
+
+    pub fn settings(&self) -> CompressorSettings {
+        CompressorSettings {
+            enabled: self.params.enabled.load(Ordering::Relaxed),
+            threshold_db: self.params.threshold_db.load(),
+            ratio: self.params.ratio.load(),
+            attack_ms: self.params.attack_ms.load(),
+            release_ms: self.params.release_ms.load(),
+            makeup_db: self.params.makeup_db.load(),
+        }
+    }
+
+    /// the most gain reduction since the previous call, in dB (0 for none)
+    pub fn take_gain_reduction(&self) -> f32 {
+        self.params.gain_reduction.swap(0.0)
+    }
+}
+
+impl Node for Compressor {
+    fn apply(&self, input: SynthSource) -> SynthSource {
+        Box::new(CompressorSource {
+            input,
+            params: self.params.clone(),
+            gr_db: 0.0,
+            times: (f32::NAN, f32::NAN, 0.0, 0.0),
+            block_gr: 0.0,
+            block_len: 0,
+        })
+    }
+
+    fn name(&self) -> &'static str { "Compressor" }
+
+    fn params(&self) -> Vec<ParamDesc> {
+        let s = self.settings();
+        vec![
+            ParamDesc::switch("enabled", s.enabled),
+            ParamDesc::float("threshold_db", s.threshold_db, COMPRESSOR_THRESHOLD_DB_MIN, 0.0, 1.0),
+            ParamDesc::float("ratio", s.ratio, 1.0, COMPRESSOR_RATIO_MAX, 0.5),
+            ParamDesc::float("attack_ms", s.attack_ms, 0.1, COMPRESSOR_TIME_MS_MAX, 1.0),
+            ParamDesc::float("release_ms", s.release_ms, 1.0, COMPRESSOR_TIME_MS_MAX, 10.0),
+            ParamDesc::float("makeup_db", s.makeup_db, 0.0, COMPRESSOR_MAKEUP_DB_MAX, 1.0),
+        ]
+    }
+
+    fn set_param(&self, id: &str, value: ParamValue) -> Result<(), Box<dyn std::error::Error>> {
+        let desc = find_param(&self.params(), self.name(), id)?;
+        let p = &self.params;
+        match id {
+            "enabled" => p.enabled.store(value.bool()?, Ordering::Relaxed),
+            "threshold_db" => p.threshold_db.store(desc.clamp(value)?),
+            "ratio" => p.ratio.store(desc.clamp(value)?),
+            "attack_ms" => p.attack_ms.store(desc.clamp(value)?),
+            "release_ms" => p.release_ms.store(desc.clamp(value)?),
+            _ => p.makeup_db.store(desc.clamp(value)?),
+        }
+        Ok(())
+    }
+}
+
+pub struct CompressorSource {
+    input: SynthSource,
+    params: Arc<CompressorParams>,
+    /// gain reduction now, dB
+    gr_db: f32,
+    /// attack and release ms last seen, and their smoothing coefficients
+    times: (f32, f32, f32, f32),
+    block_gr: f32,
+    block_len: usize,
+}
+
+impl CompressorSource {
+    fn coefficients(&mut self) -> (f32, f32) {
+        let (attack, release) = (self.params.attack_ms.load(), self.params.release_ms.load());
+        if (attack, release) != (self.times.0, self.times.1) {
+            let sr = self.input.sample_rate() as f32;
+            let coef = |ms: f32| (-1.0 / (ms / 1000.0 * sr)).exp();
+            self.times = (attack, release, coef(attack), coef(release));
+        }
+        (self.times.2, self.times.3)
+    }
+
+    fn publish(&mut self) {
+        self.block_gr = self.block_gr.max(self.gr_db);
+        self.block_len += 1;
+        if self.block_len >= METER_BLOCK {
+            self.params.gain_reduction.fetch_max(self.block_gr);
+            self.block_gr = 0.0;
+            self.block_len = 0;
+        }
+    }
+}
+
+impl Iterator for CompressorSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let x = self.input.next()?;
+        if !self.params.enabled.load(Ordering::Relaxed) {
+            self.gr_db = 0.0;
+            return Some(x);
+        }
+        let over = to_db(x.abs()) - self.params.threshold_db.load();
+        let target = if over > 0.0 { over * (1.0 - 1.0 / self.params.ratio.load()) } else { 0.0 };
+        let (attack, release) = self.coefficients();
+        let coef = if target > self.gr_db { attack } else { release };
+        self.gr_db = target + (self.gr_db - target) * coef;
+        self.publish();
+        Some(x * from_db(self.params.makeup_db.load() - self.gr_db))
+    }
+}
+
+impl Source for CompressorSource {
+    fn current_span_len(&self) -> Option<usize> { self.input.current_span_len() }
+    fn channels(&self) -> u16 { self.input.channels() }
+    fn sample_rate(&self) -> u32 { self.input.sample_rate() }
+    fn total_duration(&self) -> Option<Duration> { self.input.total_duration() }
+}
@@ -0,0 +1,203 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, AtomicU8, Ordering},
+};
+use std::time::Duration;
+
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+
+use crate::audio_patch::{AtomicF32, Node, ParamDesc, ParamValue, SynthSource, find_param};
+use crate::config::{
+    DRIVE_DB_DEFAULT, DRIVE_DB_MAX, DRIVE_OUTPUT_DB_DEFAULT, DRIVE_OUTPUT_DB_MAX, DRIVE_OUTPUT_DB_MIN, DRIVE_STEP_DB,
+};
+use crate::meter::from_db;
+
+/// the waveshaper's transfer curve, all flat at ±1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DriveCurve {
+    /// cubic, rounds off gently before it flattens
+    #[default]
+    SoftClip,
+    /// cut flat, buzzy
+    HardClip,
+    /// tube-like, smooth all the way
+    Tanh,
+    /// folds peaks back down instead of flattening them, so more drive adds more overtones
+    Foldback,
+}
+
+impl DriveCurve {
+    const ALL: [DriveCurve; 4] = [DriveCurve::SoftClip, DriveCurve::HardClip, DriveCurve::Tanh, DriveCurve::Foldback];
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self.index() + 1) % Self::ALL.len()]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DriveCurve::SoftClip => "soft clip",
+            DriveCurve::HardClip => "hard clip",
+            DriveCurve::Tanh => "tanh",
+            DriveCurve::Foldback => "foldback",
+        }
+    }
+
+    fn index(self) -> usize {
+        Self::ALL.iter().position(|c| *c == self).unwrap_or(0)
+    }
+
+    fn from_index(i: usize) -> Self {
+        Self::ALL[i.min(Self::ALL.len() - 1)]
+    }
+
+    #[inline]
+    fn shape(self, x: f32) -> f32 {
+        match self {
+            DriveCurve::SoftClip => {
+                let x = x.clamp(-1.0, 1.0);
+                1.5 * x - 0.5 * x * x * x
+            }
+            DriveCurve::HardClip => x.clamp(-1.0, 1.0),
+            DriveCurve::Tanh => x.tanh(),
+            DriveCurve::Foldback => ((x - 1.0).rem_euclid(4.0) - 2.0).abs() - 1.0,
+        }
+    }
+}
+
+/// plain copy of the drive controls
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DriveSettings {
+    pub enabled: bool,
+    pub curve: DriveCurve,
+    /// gain into the curve
+    pub drive_db: f32,
+    /// gain after it, to bring the level back down
+    pub output_db: f32,
+}
+
+impl Default for DriveSettings {
+    fn default() -> Self {
+        Self { enabled: false, curve: DriveCurve::default(), drive_db: DRIVE_DB_DEFAULT, output_db: DRIVE_OUTPUT_DB_DEFAULT }
+    }
+}
+
+impl DriveSettings {
+    pub fn clamped(self) -> Self {
+        Self {
+            drive_db: self.drive_db.clamp(0.0, DRIVE_DB_MAX),
+            output_db: self.output_db.clamp(DRIVE_OUTPUT_DB_MIN, DRIVE_OUTPUT_DB_MAX),
+            ..self
+        }
+    }
+}
+
+#[derive(Debug)]
+struct DriveParams {
+    enabled: AtomicBool,
+    curve: AtomicU8,
+    drive_db: AtomicF32,
+    output_db: AtomicF32,
+}
+
+/// waveshaping distortion. on a patch each note gets its own (so chords stay clean of
+/// intermodulation), on the master bus it drives the whole mix. the controls are shared with
+/// every source it made, so they can be turned while it runs
+#[derive(Debug, Clone)]
+pub struct Drive {
+    params: Arc<DriveParams>,
+}
+
+impl Drive {
+    pub fn new(settings: DriveSettings) -> Self {
+        let s = settings.clamped();
+        Self {
+            params: Arc::new(DriveParams {
+                enabled: AtomicBool::new(s.enabled),
+                curve: AtomicU8::new(s.curve.index() as u8),
+                drive_db: AtomicF32::new(s.drive_db),
+                output_db: AtomicF32::new(s.output_db),
+            }),
+        }
+    }
+
+    pub fn settings(&self) -> DriveSettings {
+        DriveSettings {
+            enabled: self.params.enabled.load(Ordering::Relaxed),
+            curve: DriveCurve::from_index(self.params.curve.load(Ordering::Relaxed) as usize),
+            drive_db: self.params.drive_db.load(),
+            output_db: self.params.output_db.load(),
+        }
+    }
+
+    pub fn set(&self, settings: DriveSettings) {
+        let s = settings.clamped();
+        self.params.enabled.store(s.enabled, Ordering::Relaxed);
+        self.params.curve.store(s.curve.index() as u8, Ordering::Relaxed);
+        self.params.drive_db.store(s.drive_db);
+        self.params.output_db.store(s.output_db);
+    }
+}
+
+impl Node for Drive {
+    fn apply(&self, input: SynthSource) -> SynthSource {
+        Box::new(DriveSource { input, params: self.params.clone(), gains: (f32::NAN, f32::NAN, 1.0, 1.0) })
+    }
+
+    fn name(&self) -> &'static str { "Drive" }
+
+    /// the curve is its index: 0 soft clip, 1 hard clip, 2 tanh, 3 foldback
+    fn params(&self) -> Vec<ParamDesc> {
+        let s = self.settings();
+        vec![
+            ParamDesc::switch("enabled", s.enabled),
+            ParamDesc::float("curve", s.curve.index() as f32, 0.0, (DriveCurve::ALL.len() - 1) as f32, 1.0),
+            ParamDesc::float("drive_db", s.drive_db, 0.0, DRIVE_DB_MAX, DRIVE_STEP_DB),
+            ParamDesc::float("output_db", s.output_db, DRIVE_OUTPUT_DB_MIN, DRIVE_OUTPUT_DB_MAX, DRIVE_STEP_DB),
+        ]
+    }
+
+    fn set_param(&self, id: &str, value: ParamValue) -> Result<(), Box<dyn std::error::Error>> {
+        let desc = find_param(&self.params(), self.name(), id)?;
+        match id {
+            "enabled" => self.params.enabled.store(value.bool()?, Ordering::Relaxed),
+            "curve" => self.params.curve.store(desc.clamp(value)?.round() as u8, Ordering::Relaxed),
+            "drive_db" => self.params.drive_db.store(desc.clamp(value)?),
+            _ => self.params.output_db.store(desc.clamp(value)?),
+        }
+        Ok(())
+    }
+}
+
+pub struct DriveSource {
+    input: SynthSource,
+    params: Arc<DriveParams>,
+    /// drive and output dB last seen, and their gains, so the powf only runs when they move
+    gains: (f32, f32, f32, f32),
+}
+
+impl Iterator for DriveSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let x = self.input.next()?;
+        if !self.params.enabled.load(Ordering::Relaxed) {
+            return Some(x);
+        }
+        let (drive_db, output_db) = (self.params.drive_db.load(), self.params.output_db.load());
+        if (drive_db, output_db) != (self.gains.0, self.gains.1) {
+            self.gains = (drive_db, output_db, from_db(drive_db), from_db(output_db));
+        }
+        let curve = DriveCurve::from_index(self.params.curve.load(Ordering::Relaxed) as usize);
+        Some(curve.shape(x * self.gains.2) * self.gains.3)
+    }
+}
+
+impl Source for DriveSource {
+    fn current_span_len(&self) -> Option<usize> { self.input.current_span_len() }
+    fn channels(&self) -> u16 { self.input.channels() }
+    fn sample_rate(&self) -> u32 { self.input.sample_rate() }
+    fn total_duration(&self) -> Option<Duration> { self.input.total_duration() }
+}
@@ -0,0 +1,267 @@
+use std::f32::consts::{FRAC_1_SQRT_2, TAU};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+
+use crate::audio_patch::{AtomicF32, Node, ParamDesc, ParamValue, SynthSource, find_param};
+use crate::config::{
+    EQ_FREQ_MAX_HZ, EQ_FREQ_MIN_HZ, EQ_GAIN_DB_MAX, EQ_GAIN_STEP_DB, EQ_HIGH_HZ_DEFAULT, EQ_LOW_HZ_DEFAULT,
+    EQ_MID_HZ_DEFAULT, EQ_Q_DEFAULT, EQ_Q_MAX, EQ_Q_MIN,
+};
+
+/// plain copy of the EQ controls; gains in dB, 0 leaves a band alone
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EqSettings {
+    /// low shelf corner
+    pub low_hz: f32,
+    pub low_db: f32,
+    /// centre of the mid bell, and how narrow it is
+    pub mid_hz: f32,
+    pub mid_db: f32,
+    pub mid_q: f32,
+    /// high shelf corner
+    pub high_hz: f32,
+    pub high_db: f32,
+}
+
+impl Default for EqSettings {
+    fn default() -> Self {
+        Self {
+            low_hz: EQ_LOW_HZ_DEFAULT,
+            low_db: 0.0,
+            mid_hz: EQ_MID_HZ_DEFAULT,
+            mid_db: 0.0,
+            mid_q: EQ_Q_DEFAULT,
+            high_hz: EQ_HIGH_HZ_DEFAULT,
+            high_db: 0.0,
+        }
+    }
+}
+
+impl EqSettings {
+    pub fn clamped(self) -> Self {
+        let hz = |f: f32| f.clamp(EQ_FREQ_MIN_HZ, EQ_FREQ_MAX_HZ);
+        let db = |g: f32| g.clamp(-EQ_GAIN_DB_MAX, EQ_GAIN_DB_MAX);
+        Self {
+            low_hz: hz(self.low_hz),
+            low_db: db(self.low_db),
+            mid_hz: hz(self.mid_hz),
+            mid_db: db(self.mid_db),
+            mid_q: self.mid_q.clamp(EQ_Q_MIN, EQ_Q_MAX),
+            high_hz: hz(self.high_hz),
+            high_db: db(self.high_db),
+        }
+    }
+
+    /// every band at 0 dB: the sound goes through untouched
+    pub fn is_flat(&self) -> bool {
+        self.low_db == 0.0 && self.mid_db == 0.0 && self.high_db == 0.0
+    }
+}
+
+#[derive(Debug)]
+struct EqParams {
+    low_hz: AtomicF32,
+    low_db: AtomicF32,
+    mid_hz: AtomicF32,
+    mid_db: AtomicF32,
+    mid_q: AtomicF32,
+    high_hz: AtomicF32,
+    high_db: AtomicF32,
+}
+
+impl EqParams {
+    fn settings(&self) -> EqSettings {
+        EqSettings {
+            low_hz: self.low_hz.load(),
+            low_db: self.low_db.load(),
+            mid_hz: self.mid_hz.load(),
+            mid_db: self.mid_db.load(),
+            mid_q: self.mid_q.load(),
+            high_hz: self.high_hz.load(),
+            high_db: self.high_db.load(),
+        }
+    }
+}
+
+/// three-band EQ: a low shelf, a bell in the middle and a high shelf. each note gets its own
+/// filters, which work out their coefficients again when a control moves
+#[derive(Debug, Clone)]
+pub struct ThreeBandEq {
+    params: Arc<EqParams>,
+}
+
+impl Default for ThreeBandEq {
+    fn default() -> Self {
+        Self::new(EqSettings::default())
+    }
+}
+
+impl ThreeBandEq {
+    pub fn new(settings: EqSettings) -> Self {
+        let s = settings.clamped();
+        Self {
+            params: Arc::new(EqParams {
+                low_hz: AtomicF32::new(s.low_hz),
+                low_db: AtomicF32::new(s.low_db),
+                mid_hz: AtomicF32::new(s.mid_hz),
+                mid_db: AtomicF32::new(s.mid_db),
+                mid_q: AtomicF32::new(s.mid_q),
+                high_hz: AtomicF32::new(s.high_hz),
+                high_db: AtomicF32::new(s.high_db),
+            }),
+        }
+    }
+
+    pub fn settings(&self) -> EqSettings {
+        self.params.settings()
+    }
+
+    pub fn set(&self, settings: EqSettings) {
+        let s = settings.clamped();
+        let p = &self.params;
+        p.low_hz.store(s.low_hz);
+        p.low_db.store(s.low_db);
+        p.mid_hz.store(s.mid_hz);
+        p.mid_db.store(s.mid_db);
+        p.mid_q.store(s.mid_q);
+        p.high_hz.store(s.high_hz);
+        p.high_db.store(s.high_db);
+    }
+}
+
+impl Node for ThreeBandEq {
+    fn apply(&self, input: SynthSource) -> SynthSource {
+        Box::new(EqSource { input, params: self.params.clone(), seen: None, bands: [Biquad::default(); 3] })
+    }
+
+    fn name(&self) -> &'static str { "EQ" }
+
+    fn params(&self) -> Vec<ParamDesc> {
+        let s = self.settings();
+        let (lo, hi, g) = (EQ_FREQ_MIN_HZ, EQ_FREQ_MAX_HZ, EQ_GAIN_DB_MAX);
+        vec![
+            ParamDesc::float("low_hz", s.low_hz, lo, hi, 10.0),
+            ParamDesc::float("low_db", s.low_db, -g, g, EQ_GAIN_STEP_DB),
+            ParamDesc::float("mid_hz", s.mid_hz, lo, hi, 50.0),
+            ParamDesc::float("mid_db", s.mid_db, -g, g, EQ_GAIN_STEP_DB),
+            ParamDesc::float("mid_q", s.mid_q, EQ_Q_MIN, EQ_Q_MAX, 0.1),
+            ParamDesc::float("high_hz", s.high_hz, lo, hi, 250.0),
+            ParamDesc::float("high_db", s.high_db, -g, g, EQ_GAIN_STEP_DB),
+        ]
+    }
+
+    fn set_param(&self, id: &str, value: ParamValue) -> Result<(), Box<dyn std::error::Error>> {
+        let v = find_param(&self.params(), self.name(), id)?.clamp(value)?;
+        let p = &self.params;
+        match id {
+            "low_hz" => p.low_hz.store(v),
+            "low_db" => p.low_db.store(v),
+            "mid_hz" => p.mid_hz.store(v),
+            "mid_db" => p.mid_db.store(v),
+            "mid_q" => p.mid_q.store(v),
+            "high_hz" => p.high_hz.store(v),
+            _ => p.high_db.store(v),
+        }
+        Ok(())
+    }
+}
+
+/// one second-order section, transposed direct form II, with RBJ cookbook coefficients
+#[derive(Debug, Default, Clone, Copy)]
+struct Biquad {
+    b: [f32; 3],
+    a: [f32; 2],
+    z: [f32; 2],
+}
+
+enum Band {
+    LowShelf,
+    Bell,
+    HighShelf,
+}
+
+impl Biquad {
+    /// new coefficients, keeping the state so a moving control doesn't click
+    fn set(&mut self, band: Band, hz: f32, db: f32, q: f32, sample_rate: f32) {
+        let a = 10f32.powf(db / 40.0);
+        let w = TAU * hz.min(sample_rate * 0.45) / sample_rate;
+        let (sin, cos) = w.sin_cos();
+        let alpha = sin / (2.0 * q);
+        let k = 2.0 * a.sqrt() * alpha;
+        let (b, den) = match band {
+            Band::LowShelf => (
+                [
+                    a * ((a + 1.0) - (a - 1.0) * cos + k),
+                    2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                    a * ((a + 1.0) - (a - 1.0) * cos - k),
+                ],
+                [(a + 1.0) + (a - 1.0) * cos + k, -2.0 * ((a - 1.0) + (a + 1.0) * cos), (a + 1.0) + (a - 1.0) * cos - k],
+            ),
+            Band::Bell => (
+                [1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a],
+                [1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a],
+            ),
+            Band::HighShelf => (
+                [
+                    a * ((a + 1.0) + (a - 1.0) * cos + k),
+                    -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                    a * ((a + 1.0) + (a - 1.0) * cos - k),
+                ],
+                [(a + 1.0) - (a - 1.0) * cos + k, 2.0 * ((a - 1.0) - (a + 1.0) * cos), (a + 1.0) - (a - 1.0) * cos - k],
+            ),
+        };
+        let a0 = den[0];
+        self.b = [b[0] / a0, b[1] / a0, b[2] / a0];
+        self.a = [den[1] / a0, den[2] / a0];
+    }
+
+    #[inline]
+    fn process(&mut self, x: f32) -> f32 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+pub struct EqSource {
+    input: SynthSource,
+    params: Arc<EqParams>,
+    /// the settings the coefficients were worked out for
+    seen: Option<EqSettings>,
+    bands: [Biquad; 3],
+}
+
+impl Iterator for EqSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let x = self.input.next()?;
+        let s = self.params.settings();
+        if s.is_flat() {
+            self.seen = None;
+            self.bands = [Biquad::default(); 3];
+            return Some(x);
+        }
+        if self.seen != Some(s) {
+            let sr = self.input.sample_rate() as f32;
+            let [low, mid, high] = &mut self.bands;
+            low.set(Band::LowShelf, s.low_hz, s.low_db, FRAC_1_SQRT_2, sr);
+            mid.set(Band::Bell, s.mid_hz, s.mid_db, s.mid_q, sr);
+            high.set(Band::HighShelf, s.high_hz, s.high_db, FRAC_1_SQRT_2, sr);
+            self.seen = Some(s);
+        }
+        Some(self.bands.iter_mut().fold(x, |y, band| band.process(y)))
+    }
+}
+
+impl Source for EqSource {
+    fn current_span_len(&self) -> Option<usize> { self.input.current_span_len() }
+    fn channels(&self) -> u16 { self.input.channels() }
+    fn sample_rate(&self) -> u32 { self.input.sample_rate() }
+    fn total_duration(&self) -> Option<Duration> { self.input.total_duration() }
+}
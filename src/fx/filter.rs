@@ -0,0 +1,82 @@
+use std::f32::consts::PI;
+use std::time::Duration;
+
+use rodio::Source;
+
+use crate::audio_patch::{Node, SynthSource};
+
+/// state of a 2-pole state variable filter (trapezoidal, so the cutoff can move every sample
+/// without blowing up)
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Svf {
+    ic1eq: f32,
+    ic2eq: f32,
+}
+
+impl Svf {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn lowpass(&mut self, x: f32, cutoff_hz: f32, q: f32, sample_rate: u32) -> f32 {
+        let sr = sample_rate as f32;
+        let fc = cutoff_hz.clamp(20.0, sr * 0.45);
+        let g = (PI * fc / sr).tan();
+        let k = 1.0 / q.max(0.1);
+        let a1 = 1.0 / (1.0 + g * (g + k));
+        let a2 = g * a1;
+        let a3 = g * a2;
+
+        let v3 = x - self.ic2eq;
+        let v1 = a1 * self.ic1eq + a2 * v3;
+        let v2 = self.ic2eq + a2 * self.ic1eq + a3 * v3;
+        self.ic1eq = 2.0 * v1 - self.ic1eq;
+        self.ic2eq = 2.0 * v2 - self.ic2eq;
+        v2
+    }
+}
+
+/// fixed lowpass node
+#[derive(Debug, Clone, Copy)]
+pub struct LowPass {
+    pub cutoff_hz: f32,
+    pub q: f32,
+    pub sample_rate: u32,
+}
+
+impl LowPass {
+    pub fn new(cutoff_hz: f32, q: f32, sample_rate: u32) -> Self {
+        Self { cutoff_hz, q, sample_rate }
+    }
+}
+
+impl Node for LowPass {
+    fn apply(&self, input: SynthSource) -> SynthSource {
+        Box::new(LowPassSource { input, params: *self, svf: Svf::new() })
+    }
+
+    fn name(&self) -> &'static str { "LowPass" }
+}
+
+struct LowPassSource {
+    input: SynthSource,
+    params: LowPass,
+    svf: Svf,
+}
+
+impl Iterator for LowPassSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let x = self.input.next()?;
+        let p = self.params;
+        Some(self.svf.lowpass(x, p.cutoff_hz, p.q, p.sample_rate))
+    }
+}
+
+impl Source for LowPassSource {
+    fn current_span_len(&self) -> Option<usize> { self.input.current_span_len() }
+    fn channels(&self) -> u16 { self.input.channels() }
+    fn sample_rate(&self) -> u32 { self.input.sample_rate() }
+    fn total_duration(&self) -> Option<Duration> { self.input.total_duration() }
+}
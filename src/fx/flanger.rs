@@ -0,0 +1,190 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+use std::time::Duration;
+
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+
+use crate::audio_patch::{AtomicF32, Node, ParamDesc, ParamValue, SynthSource, find_param};
+use crate::config::{
+    FLANGER_DELAY_MS_DEFAULT, FLANGER_DELAY_MS_MAX, FLANGER_DEPTH_MS_DEFAULT, FLANGER_DEPTH_MS_MAX,
+    FLANGER_FEEDBACK_MAX, FLANGER_RATE_DEFAULT, LFO_RATE_MAX, LFO_RATE_MIN,
+};
+use crate::fx::lfo::{Lfo, LfoShape};
+
+/// plain copy of the flanger controls
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FlangerSettings {
+    pub enabled: bool,
+    /// sweeps per second
+    pub rate_hz: f32,
+    /// the delay the sweep swings around, and how far either way
+    pub delay_ms: f32,
+    pub depth_ms: f32,
+    /// how much of the delayed sound goes round again; negative flips it for a hollower sound
+    pub feedback: f32,
+    /// 0 dry, 1 only the delayed sound; the comb is deepest at 0.5
+    pub mix: f32,
+}
+
+impl Default for FlangerSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            rate_hz: FLANGER_RATE_DEFAULT,
+            delay_ms: FLANGER_DELAY_MS_DEFAULT,
+            depth_ms: FLANGER_DEPTH_MS_DEFAULT,
+            feedback: 0.5,
+            mix: 0.5,
+        }
+    }
+}
+
+impl FlangerSettings {
+    pub fn clamped(self) -> Self {
+        Self {
+            rate_hz: self.rate_hz.clamp(LFO_RATE_MIN, LFO_RATE_MAX),
+            delay_ms: self.delay_ms.clamp(0.1, FLANGER_DELAY_MS_MAX),
+            depth_ms: self.depth_ms.clamp(0.0, FLANGER_DEPTH_MS_MAX),
+            feedback: self.feedback.clamp(-FLANGER_FEEDBACK_MAX, FLANGER_FEEDBACK_MAX),
+            mix: self.mix.clamp(0.0, 1.0),
+            ..self
+        }
+    }
+}
+
+#[derive(Debug)]
+struct FlangerParams {
+    enabled: AtomicBool,
+    rate_hz: AtomicF32,
+    delay_ms: AtomicF32,
+    depth_ms: AtomicF32,
+    feedback: AtomicF32,
+    mix: AtomicF32,
+}
+
+/// a very short delay an LFO swings back and forth, mixed with the dry sound so the comb it makes
+/// sweeps up and down like a jet
+#[derive(Debug, Clone)]
+pub struct Flanger {
+    params: Arc<FlangerParams>,
+}
+
+impl Flanger {
+    pub fn new(settings: FlangerSettings) -> Self {
+        let s = settings.clamped();
+        Self {
+            params: Arc::new(FlangerParams {
+                enabled: AtomicBool::new(s.enabled),
+                rate_hz: AtomicF32::new(s.rate_hz),
+                delay_ms: AtomicF32::new(s.delay_ms),
+                depth_ms: AtomicF32::new(s.depth_ms),
+                feedback: AtomicF32::new(s.feedback),
+                mix: AtomicF32::new(s.mix),
+            }),
+        }
+    }
+
+    pub fn settings(&self) -> FlangerSettings {
+        FlangerSettings {
+            enabled: self.params.enabled.load(Ordering::Relaxed),
+            rate_hz: self.params.rate_hz.load(),
+            delay_ms: self.params.delay_ms.load(),
+            depth_ms: self.params.depth_ms.load(),
+            feedback: self.params.feedback.load(),
+            mix: self.params.mix.load(),
+        }
+    }
+}
+
+impl Node for Flanger {
+    fn apply(&self, input: SynthSource) -> SynthSource {
+        // room for the longest delay the controls allow, plus a sample to interpolate towards
+        let max_ms = FLANGER_DELAY_MS_MAX + FLANGER_DEPTH_MS_MAX;
+        let len = (max_ms / 1000.0 * input.sample_rate() as f32).ceil() as usize + 2;
+        Box::new(FlangerSource { input, params: self.params.clone(), lfo: Lfo::new(), line: vec![0.0; len], pos: 0 })
+    }
+
+    fn name(&self) -> &'static str { "Flanger" }
+
+    fn params(&self) -> Vec<ParamDesc> {
+        let s = self.settings();
+        vec![
+            ParamDesc::switch("enabled", s.enabled),
+            ParamDesc::float("rate_hz", s.rate_hz, LFO_RATE_MIN, LFO_RATE_MAX, 0.05),
+            ParamDesc::float("delay_ms", s.delay_ms, 0.1, FLANGER_DELAY_MS_MAX, 0.1),
+            ParamDesc::float("depth_ms", s.depth_ms, 0.0, FLANGER_DEPTH_MS_MAX, 0.1),
+            ParamDesc::float("feedback", s.feedback, -FLANGER_FEEDBACK_MAX, FLANGER_FEEDBACK_MAX, 0.05),
+            ParamDesc::float("mix", s.mix, 0.0, 1.0, 0.05),
+        ]
+    }
+
+    fn set_param(&self, id: &str, value: ParamValue) -> Result<(), Box<dyn std::error::Error>> {
+        let desc = find_param(&self.params(), self.name(), id)?;
+        let p = &self.params;
+        match id {
+            "enabled" => p.enabled.store(value.bool()?, Ordering::Relaxed),
+            "rate_hz" => p.rate_hz.store(desc.clamp(value)?),
+            "delay_ms" => p.delay_ms.store(desc.clamp(value)?),
+            "depth_ms" => p.depth_ms.store(desc.clamp(value)?),
+            "feedback" => p.feedback.store(desc.clamp(value)?),
+            _ => p.mix.store(desc.clamp(value)?),
+        }
+        Ok(())
+    }
+}
+
+pub struct FlangerSource {
+    input: SynthSource,
+    params: Arc<FlangerParams>,
+    lfo: Lfo,
+    /// ring buffer of what went into the delay; `pos` is where the next sample goes
+    line: Vec<f32>,
+    pos: usize,
+}
+
+impl FlangerSource {
+    /// the line `delay` samples back, between samples by linear interpolation
+    fn tap(&self, delay: f32) -> f32 {
+        let len = self.line.len();
+        let back = delay.clamp(1.0, (len - 2) as f32);
+        let whole = back as usize;
+        let frac = back - whole as f32;
+        let a = self.line[(self.pos + len - whole) % len];
+        let b = self.line[(self.pos + len - whole - 1) % len];
+        a + (b - a) * frac
+    }
+}
+
+impl Iterator for FlangerSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let x = self.input.next()?;
+        if !self.params.enabled.load(Ordering::Relaxed) {
+            // keeps filling the line, so nothing stale rings out when it is switched back on
+            self.line[self.pos] = x;
+            self.pos = (self.pos + 1) % self.line.len();
+            return Some(x);
+        }
+        let sr = self.input.sample_rate();
+        let v = self.lfo.step(LfoShape::Triangle, self.params.rate_hz.load(), sr);
+        let ms = self.params.delay_ms.load() + v * self.params.depth_ms.load();
+        let delayed = self.tap(ms / 1000.0 * sr as f32);
+
+        self.line[self.pos] = x + delayed * self.params.feedback.load();
+        self.pos = (self.pos + 1) % self.line.len();
+        let mix = self.params.mix.load();
+        Some(x * (1.0 - mix) + delayed * mix)
+    }
+}
+
+impl Source for FlangerSource {
+    fn current_span_len(&self) -> Option<usize> { self.input.current_span_len() }
+    fn channels(&self) -> u16 { self.input.channels() }
+    fn sample_rate(&self) -> u32 { self.input.sample_rate() }
+    fn total_duration(&self) -> Option<Duration> { self.input.total_duration() }
+}
@@ -0,0 +1,225 @@
+use std::f32::consts::{PI, TAU};
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, AtomicU32, Ordering},
+};
+use std::time::Duration;
+
+use fundsp::fft::{fix_negative, inverse_fft, real_fft};
+use num_complex::Complex32;
+use rodio::Source;
+
+use crate::audio_patch::{Node, ParamDesc, ParamValue, SynthSource, find_param};
+use crate::config::{FREEZE_FADE_MS, FREEZE_FFT_SIZE, FREEZE_HOP};
+
+/// spectral freeze for the master bus: turning it on captures the spectrum of the last moment
+/// and keeps resynthesizing it as a drone under whatever is played next. each bin keeps the
+/// frequency measured from two frames a hop apart (phase vocoder), so the drone holds its pitch
+/// instead of smearing. the switch is shared with the running source
+#[derive(Debug, Clone)]
+pub struct Freeze {
+    on: Arc<AtomicBool>,
+    /// bumped to drop the drone without fading
+    flushes: Arc<AtomicU32>,
+}
+
+impl Default for Freeze {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Freeze {
+    pub fn new() -> Self {
+        Self { on: Arc::new(AtomicBool::new(false)), flushes: Arc::new(AtomicU32::new(0)) }
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.on.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, on: bool) {
+        self.on.store(on, Ordering::Relaxed);
+    }
+
+    /// switches off and cuts the drone at once instead of fading it out
+    pub fn flush(&self) {
+        self.set(false);
+        self.flushes.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Node for Freeze {
+    fn apply(&self, input: SynthSource) -> SynthSource {
+        let n = FREEZE_FFT_SIZE;
+        let bins = n / 2 + 1;
+        let fade_samples = (FREEZE_FADE_MS / 1000.0 * input.sample_rate() as f32).max(1.0);
+        Box::new(FreezeSource {
+            input,
+            on: self.on.clone(),
+            flushed: self.flushes.load(Ordering::Relaxed),
+            flushes: self.flushes.clone(),
+            history: vec![0.0; n + FREEZE_HOP],
+            history_pos: 0,
+            window: (0..n).map(|i| 0.5 - 0.5 * (TAU * i as f32 / n as f32).cos()).collect(),
+            mags: vec![0.0; bins],
+            phases: vec![0.0; bins],
+            advance: vec![0.0; bins],
+            frame: vec![0.0; n],
+            spectrum: vec![Complex32::new(0.0, 0.0); n],
+            ola: vec![0.0; n],
+            ola_pos: FREEZE_HOP,
+            frozen: false,
+            level: 0.0,
+            fade_step: 1.0 / fade_samples,
+        })
+    }
+
+    fn name(&self) -> &'static str { "Freeze" }
+
+    fn params(&self) -> Vec<ParamDesc> {
+        vec![ParamDesc::switch("hold", self.is_on())]
+    }
+
+    fn set_param(&self, id: &str, value: ParamValue) -> Result<(), Box<dyn std::error::Error>> {
+        find_param(&self.params(), self.name(), id)?;
+        self.set(value.bool()?);
+        Ok(())
+    }
+}
+
+pub struct FreezeSource {
+    input: SynthSource,
+    on: Arc<AtomicBool>,
+    flushes: Arc<AtomicU32>,
+    /// the last flush it acted on
+    flushed: u32,
+    /// the last `FREEZE_FFT_SIZE + FREEZE_HOP` input samples, a ring
+    history: Vec<f32>,
+    history_pos: usize,
+    window: Vec<f32>,
+    mags: Vec<f32>,
+    phases: Vec<f32>,
+    /// phase each bin moves per hop
+    advance: Vec<f32>,
+    frame: Vec<f32>,
+    spectrum: Vec<Complex32>,
+    /// overlap-add output; the first `FREEZE_HOP` samples are ready to play
+    ola: Vec<f32>,
+    ola_pos: usize,
+    frozen: bool,
+    level: f32,
+    fade_step: f32,
+}
+
+impl FreezeSource {
+    /// windowed spectrum of the `FREEZE_FFT_SIZE` samples ending `ago` samples back, into `spectrum`
+    fn analyze(&mut self, ago: usize) {
+        let n = FREEZE_FFT_SIZE;
+        let len = self.history.len();
+        let start = self.history_pos + len - ago - n;
+        for i in 0..n {
+            self.frame[i] = self.history[(start + i) % len] * self.window[i];
+        }
+        let bins = real_fft(&mut self.frame);
+        self.spectrum[..n / 2].copy_from_slice(bins);
+    }
+
+    fn capture(&mut self) {
+        let n = FREEZE_FFT_SIZE;
+        // the frame a hop earlier only lends its phases, to measure how far each bin turns
+        self.analyze(FREEZE_HOP);
+        for k in 1..n / 2 {
+            self.phases[k] = self.spectrum[k].arg();
+        }
+        self.analyze(0);
+
+        // DC and Nyquist are dropped; a drone has no use for either
+        self.mags.fill(0.0);
+        for k in 1..n / 2 {
+            let c = self.spectrum[k];
+            let expected = TAU * k as f32 * FREEZE_HOP as f32 / n as f32;
+            let deviation = wrap_phase(c.arg() - self.phases[k] - expected);
+            self.mags[k] = c.norm();
+            self.phases[k] = c.arg();
+            self.advance[k] = expected + deviation;
+        }
+        self.ola.fill(0.0);
+        self.ola_pos = FREEZE_HOP;
+    }
+
+    /// adds the next frame of the frozen spectrum onto the overlap-add buffer
+    fn synthesize(&mut self) {
+        let n = FREEZE_FFT_SIZE;
+        for k in 0..=n / 2 {
+            self.phases[k] = wrap_phase(self.phases[k] + self.advance[k]);
+            self.spectrum[k] = Complex32::from_polar(self.mags[k], self.phases[k]);
+        }
+        fix_negative(&mut self.spectrum);
+        inverse_fft(&mut self.spectrum);
+
+        // microfft's inverse is already normalized; hann in and out at 75% overlap sums to 1.5
+        let scale = 1.0 / 1.5;
+        self.ola.copy_within(FREEZE_HOP.., 0);
+        self.ola[n - FREEZE_HOP..].fill(0.0);
+        for i in 0..n {
+            self.ola[i] += self.spectrum[i].re * self.window[i] * scale;
+        }
+        self.ola_pos = 0;
+    }
+}
+
+fn wrap_phase(p: f32) -> f32 {
+    p - TAU * ((p + PI) / TAU).floor()
+}
+
+impl Iterator for FreezeSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let x = self.input.next()?;
+        self.history[self.history_pos] = x;
+        self.history_pos = (self.history_pos + 1) % self.history.len();
+
+        let flushes = self.flushes.load(Ordering::Relaxed);
+        if flushes != self.flushed {
+            self.flushed = flushes;
+            self.frozen = false;
+            self.level = 0.0;
+        }
+
+        let on = self.on.load(Ordering::Relaxed);
+        if on && !self.frozen {
+            self.capture();
+            self.frozen = true;
+        }
+        if !self.frozen {
+            return Some(x);
+        }
+
+        self.level = if on {
+            (self.level + self.fade_step).min(1.0)
+        } else {
+            (self.level - self.fade_step).max(0.0)
+        };
+        if !on && self.level == 0.0 {
+            // faded out; the next switch-on captures afresh
+            self.frozen = false;
+            return Some(x);
+        }
+
+        if self.ola_pos == FREEZE_HOP {
+            self.synthesize();
+        }
+        let drone = self.ola[self.ola_pos];
+        self.ola_pos += 1;
+        Some(x + drone * self.level)
+    }
+}
+
+impl Source for FreezeSource {
+    fn current_span_len(&self) -> Option<usize> { self.input.current_span_len() }
+    fn channels(&self) -> u16 { self.input.channels() }
+    fn sample_rate(&self) -> u32 { self.input.sample_rate() }
+    fn total_duration(&self) -> Option<Duration> { self.input.total_duration() }
+}
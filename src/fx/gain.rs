@@ -6,8 +6,8 @@ pub struct Gain {
 }
 
 impl Gain {
-    fn new(gain:f32) -> Gain {
-        return Gain { gain };
+    pub fn new(gain:f32) -> Gain {
+        Gain { gain }
     }
 }
 
@@ -0,0 +1,500 @@
+use std::f32::consts::TAU;
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering},
+};
+use std::time::Duration;
+
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+
+use crate::audio_patch::{AtomicF32, FreqMul, SynthSource};
+use crate::config::{
+    LFO_AMP_DEPTH_DEFAULT, LFO_CUTOFF_BASE_HZ, LFO_CUTOFF_DEPTH_DEFAULT, LFO_CUTOFF_Q,
+    LFO_PITCH_DEPTH_DEFAULT, LFO_RATE_DEFAULT, LFO_RATE_MAX, LFO_RATE_MIN, TEMPO_BPM_DEFAULT,
+    FILTER_ENV_BASE_HZ, BEND_GLIDE_MS, ANALOG_CENTS_MAX, ANALOG_DRIFT_RATE_HZ,
+};
+use crate::fx::adsr::{AdsrEnvelope, EnvelopeState, FilterEnvelope, Gate};
+use crate::echo::Division;
+use crate::fx::filter::Svf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LfoShape {
+    Sine,
+    Triangle,
+    Square,
+    SampleHold,
+}
+
+impl LfoShape {
+    const ALL: [LfoShape; 4] = [LfoShape::Sine, LfoShape::Triangle, LfoShape::Square, LfoShape::SampleHold];
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LfoShape::Sine => "sine",
+            LfoShape::Triangle => "tri",
+            LfoShape::Square => "square",
+            LfoShape::SampleHold => "s&h",
+        }
+    }
+
+    fn from_u8(v: u8) -> Self {
+        Self::ALL[v as usize % Self::ALL.len()]
+    }
+}
+
+/// what an LFO route moves. depth is in semitones for pitch, 0..1 for amplitude and octaves for cutoff
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModTarget {
+    Pitch,
+    Amplitude,
+    Cutoff,
+}
+
+impl ModTarget {
+    const ALL: [ModTarget; 3] = [ModTarget::Pitch, ModTarget::Amplitude, ModTarget::Cutoff];
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ModTarget::Pitch => "vibrato",
+            ModTarget::Amplitude => "tremolo",
+            ModTarget::Cutoff => "cutoff",
+        }
+    }
+
+    pub fn unit(self) -> &'static str {
+        match self {
+            ModTarget::Pitch => "st",
+            ModTarget::Amplitude => "",
+            ModTarget::Cutoff => "oct",
+        }
+    }
+
+    pub fn max_depth(self) -> f32 {
+        match self {
+            ModTarget::Pitch => 12.0,
+            ModTarget::Amplitude => 1.0,
+            ModTarget::Cutoff => 6.0,
+        }
+    }
+
+    /// one press of the depth keys
+    pub fn depth_step(self) -> f32 {
+        match self {
+            ModTarget::Pitch => 0.1,
+            ModTarget::Amplitude => 0.05,
+            ModTarget::Cutoff => 0.25,
+        }
+    }
+
+    fn default_depth(self) -> f32 {
+        match self {
+            ModTarget::Pitch => LFO_PITCH_DEPTH_DEFAULT,
+            ModTarget::Amplitude => LFO_AMP_DEPTH_DEFAULT,
+            ModTarget::Cutoff => LFO_CUTOFF_DEPTH_DEFAULT,
+        }
+    }
+
+    fn from_u8(v: u8) -> Self {
+        Self::ALL[v as usize % Self::ALL.len()]
+    }
+}
+
+/// plain copy of one route, for the UI, for commands and for presets
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LfoSettings {
+    pub target: ModTarget,
+    pub shape: LfoShape,
+    pub rate_hz: f32,
+    /// when set, the rate follows the tempo (one cycle per division) and `rate_hz` is ignored
+    pub sync: Option<Division>,
+    pub depth: f32,
+    pub enabled: bool,
+}
+
+impl LfoSettings {
+    pub fn new(target: ModTarget) -> Self {
+        Self {
+            target,
+            shape: LfoShape::Sine,
+            rate_hz: LFO_RATE_DEFAULT,
+            sync: None,
+            depth: target.default_depth(),
+            enabled: false,
+        }
+    }
+
+    pub fn clamped(mut self) -> Self {
+        self.rate_hz = self.rate_hz.clamp(LFO_RATE_MIN, LFO_RATE_MAX);
+        self.depth = self.depth.clamp(0.0, self.target.max_depth());
+        self
+    }
+
+    /// the rate it runs at, in Hz
+    pub fn rate_at(&self, bpm: f32) -> f32 {
+        match self.sync {
+            Some(division) => division.rate_hz(bpm),
+            None => self.rate_hz,
+        }
+    }
+
+    /// switches between a free rate and the division nearest to it
+    pub fn toggle_sync(&mut self, bpm: f32) {
+        self.sync = match self.sync {
+            Some(_) => None,
+            None => Division::ALL.iter().copied().min_by(|a, b| {
+                let off = |d: &Division| (d.rate_hz(bpm) / self.rate_hz).ln().abs();
+                off(a).total_cmp(&off(b))
+            }),
+        };
+    }
+}
+
+/// one row of the routing table. every field is atomic so sounding voices follow edits live
+#[derive(Debug)]
+struct LfoParams {
+    target: AtomicU8,
+    shape: AtomicU8,
+    rate_hz: AtomicF32,
+    /// 0 for a free rate, otherwise 1 + the index into `Division::ALL`
+    sync: AtomicU8,
+    depth: AtomicF32,
+    enabled: AtomicBool,
+}
+
+impl LfoParams {
+    fn new(s: LfoSettings) -> Self {
+        let p = Self {
+            target: AtomicU8::new(0),
+            shape: AtomicU8::new(0),
+            rate_hz: AtomicF32::new(0.0),
+            sync: AtomicU8::new(0),
+            depth: AtomicF32::new(0.0),
+            enabled: AtomicBool::new(false),
+        };
+        p.store(s);
+        p
+    }
+
+    fn load(&self) -> LfoSettings {
+        LfoSettings {
+            target: ModTarget::from_u8(self.target.load(Ordering::Relaxed)),
+            shape: LfoShape::from_u8(self.shape.load(Ordering::Relaxed)),
+            rate_hz: self.rate_hz.load(),
+            sync: sync_from_u8(self.sync.load(Ordering::Relaxed)),
+            depth: self.depth.load(),
+            enabled: self.enabled.load(Ordering::Relaxed),
+        }
+    }
+
+    fn store(&self, s: LfoSettings) {
+        self.target.store(s.target as u8, Ordering::Relaxed);
+        self.shape.store(s.shape as u8, Ordering::Relaxed);
+        self.rate_hz.store(s.rate_hz);
+        self.sync.store(sync_to_u8(s.sync), Ordering::Relaxed);
+        self.depth.store(s.depth);
+        self.enabled.store(s.enabled, Ordering::Relaxed);
+    }
+}
+
+/// 0 for a free rate, otherwise 1 + the index into `Division::ALL`
+fn sync_to_u8(sync: Option<Division>) -> u8 {
+    sync.and_then(|d| Division::ALL.iter().position(|a| *a == d)).map_or(0, |i| i as u8 + 1)
+}
+
+fn sync_from_u8(v: u8) -> Option<Division> {
+    match v {
+        0 => None,
+        i => Division::ALL.get(i as usize - 1).copied(),
+    }
+}
+
+/// an LFO rate for the master effects: free, or one cycle per division of the transport tempo.
+/// atomic so running sources follow edits and tempo changes
+#[derive(Debug)]
+pub(crate) struct SyncedRate {
+    rate_hz: AtomicF32,
+    sync: AtomicU8,
+    bpm: AtomicF32,
+}
+
+impl SyncedRate {
+    pub(crate) fn new(rate_hz: f32, sync: Option<Division>) -> Self {
+        Self { rate_hz: AtomicF32::new(rate_hz), sync: AtomicU8::new(sync_to_u8(sync)), bpm: AtomicF32::new(TEMPO_BPM_DEFAULT) }
+    }
+
+    pub(crate) fn rate_hz(&self) -> f32 {
+        self.rate_hz.load()
+    }
+
+    pub(crate) fn sync(&self) -> Option<Division> {
+        sync_from_u8(self.sync.load(Ordering::Relaxed))
+    }
+
+    /// a free rate, which ends any sync
+    pub(crate) fn set_rate_hz(&self, hz: f32) {
+        self.rate_hz.store(hz);
+        self.sync.store(0, Ordering::Relaxed);
+    }
+
+    /// 0 goes back to the free rate, 1.. picks from `Division::ALL`, longest first
+    pub(crate) fn set_sync_index(&self, index: f32) {
+        self.sync.store(index.round().clamp(0.0, Division::ALL.len() as f32) as u8, Ordering::Relaxed);
+    }
+
+    pub(crate) fn sync_index(&self) -> f32 {
+        self.sync.load(Ordering::Relaxed) as f32
+    }
+
+    pub(crate) fn set_bpm(&self, bpm: f32) {
+        self.bpm.store(bpm);
+    }
+
+    /// the rate it runs at now, in Hz
+    pub(crate) fn hz(&self) -> f32 {
+        match self.sync() {
+            Some(division) => division.rate_hz(self.bpm.load()),
+            None => self.rate_hz(),
+        }
+    }
+}
+
+/// small modulation routing table: each route is an LFO sent to one target
+pub struct ModMatrix {
+    routes: Vec<Arc<LfoParams>>,
+    /// tempo synced routes follow; shared so sounding voices pick up tempo changes
+    bpm: Arc<AtomicF32>,
+    /// pitch bend in semitones, on every voice; they glide to it rather than jump
+    bend: Arc<AtomicF32>,
+    /// how far off pitch each voice starts and wanders, in cents; 0 keeps them exact
+    analog_cents: Arc<AtomicF32>,
+    /// notes struck so far, which seeds each one's drift so no two wander alike
+    voices: AtomicU32,
+}
+
+impl Default for ModMatrix {
+    /// one route per target, all off
+    fn default() -> Self {
+        Self {
+            routes: ModTarget::ALL
+                .iter()
+                .map(|t| Arc::new(LfoParams::new(LfoSettings::new(*t))))
+                .collect(),
+            bpm: Arc::new(AtomicF32::new(TEMPO_BPM_DEFAULT)),
+            bend: Arc::new(AtomicF32::new(0.0)),
+            analog_cents: Arc::new(AtomicF32::new(0.0)),
+            voices: AtomicU32::new(0),
+        }
+    }
+}
+
+impl ModMatrix {
+    pub fn settings(&self) -> Vec<LfoSettings> {
+        self.routes.iter().map(|r| r.load()).collect()
+    }
+
+    pub fn set(&self, index: usize, settings: LfoSettings) {
+        if let Some(route) = self.routes.get(index) {
+            route.store(settings.clamped());
+        }
+    }
+
+    pub fn set_bpm(&self, bpm: f32) {
+        self.bpm.store(bpm);
+    }
+
+    pub fn set_bend(&self, semitones: f32) {
+        self.bend.store(semitones);
+    }
+
+    pub fn analog_cents(&self) -> f32 {
+        self.analog_cents.load()
+    }
+
+    pub fn set_analog_cents(&self, cents: f32) {
+        self.analog_cents.store(cents.clamp(0.0, ANALOG_CENTS_MAX));
+    }
+
+    /// wrap one voice; pitch routes drive the voice's `freq_mul`, the rest process its samples.
+    /// the filter envelope follows the voice's `gate` like its amp envelope does
+    pub fn apply(&self, input: SynthSource, freq_mul: FreqMul, gate: Gate, filter_env: FilterEnvelope) -> SynthSource {
+        let sample_rate = input.sample_rate();
+        let filter_env = (filter_env.depth > 0.0)
+            .then(|| (filter_env.adsr.to_envelope(sample_rate), EnvelopeState::new(), filter_env.depth));
+        Box::new(ModSource {
+            input,
+            freq_mul,
+            gate,
+            filter_env,
+            lfos: self.routes.iter().map(|r| (r.clone(), Lfo::new())).collect(),
+            svf: Svf::new(),
+            bpm: self.bpm.clone(),
+            // a note struck mid-bend starts where the others are
+            bent: self.bend.load(),
+            bend: self.bend.clone(),
+            bend_coeff: 1.0 - (-1000.0 / (BEND_GLIDE_MS * sample_rate as f32)).exp(),
+            analog_cents: self.analog_cents.clone(),
+            drift: Drift::new(self.voices.fetch_add(1, Ordering::Relaxed), sample_rate),
+            sample_rate,
+        })
+    }
+}
+
+/// a free-running low frequency oscillator; the modulation effects on the master bus use it too
+pub(crate) struct Lfo {
+    phase: f32,
+    held: f32,
+    rng: u32,
+}
+
+impl Lfo {
+    pub(crate) fn new() -> Self {
+        Self::seeded(0)
+    }
+
+    fn seeded(seed: u32) -> Self {
+        Self { phase: 0.0, held: 0.0, rng: 0x9E37_79B9 ^ seed.wrapping_mul(0x85EB_CA6B) }
+    }
+
+    /// next value in -1..1
+    pub(crate) fn step(&mut self, shape: LfoShape, rate_hz: f32, sample_rate: u32) -> f32 {
+        let p = self.phase;
+        let y = match shape {
+            LfoShape::Sine => (TAU * p).sin(),
+            LfoShape::Triangle => 1.0 - 4.0 * (p - 0.5).abs(),
+            LfoShape::Square => if p < 0.5 { 1.0 } else { -1.0 },
+            LfoShape::SampleHold => {
+                if p == 0.0 {
+                    self.held = self.random();
+                }
+                self.held
+            }
+        };
+
+        self.phase += rate_hz / sample_rate as f32;
+        if self.phase >= 1.0 {
+            self.phase = 0.0;
+        }
+        y
+    }
+
+    fn random(&mut self) -> f32 {
+        let mut x = self.rng;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.rng = x;
+        (x as f32 / u32::MAX as f32) * 2.0 - 1.0
+    }
+}
+
+struct ModSource {
+    input: SynthSource,
+    freq_mul: FreqMul,
+    gate: Gate,
+    /// envelope, its state and its depth in octaves
+    filter_env: Option<(AdsrEnvelope, EnvelopeState, f32)>,
+    lfos: Vec<(Arc<LfoParams>, Lfo)>,
+    svf: Svf,
+    bpm: Arc<AtomicF32>,
+    bend: Arc<AtomicF32>,
+    /// where this voice's bend is on its way to `bend`
+    bent: f32,
+    bend_coeff: f32,
+    analog_cents: Arc<AtomicF32>,
+    drift: Drift,
+    sample_rate: u32,
+}
+
+impl Iterator for ModSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        self.bent += (self.bend.load() - self.bent) * self.bend_coeff;
+        let mut semitones = self.bent;
+        let analog_cents = self.analog_cents.load();
+        if analog_cents > 0.0 {
+            semitones += analog_cents / 100.0 * self.drift.step(self.sample_rate);
+        }
+        let mut gain = 1.0;
+        let mut octaves = 0.0;
+        let mut filtered = false;
+        let bpm = self.bpm.load();
+
+        for (params, lfo) in self.lfos.iter_mut() {
+            let s = params.load();
+            if !s.enabled {
+                continue;
+            }
+            let v = lfo.step(s.shape, s.rate_at(bpm), self.sample_rate);
+            match s.target {
+                ModTarget::Pitch => semitones += s.depth * v,
+                // swings between full level and 1 - depth
+                ModTarget::Amplitude => gain *= 1.0 - s.depth * (1.0 - v) * 0.5,
+                ModTarget::Cutoff => {
+                    octaves += s.depth * v;
+                    filtered = true;
+                }
+            }
+        }
+
+        // with the filter envelope on, it decides where the filter sits and cutoff LFOs swing
+        // around that instead of the fixed base
+        let mut base = LFO_CUTOFF_BASE_HZ;
+        if let Some((envelope, state, depth)) = &mut self.filter_env {
+            octaves += *depth * state.step(envelope, self.gate.load(Ordering::Acquire));
+            base = FILTER_ENV_BASE_HZ;
+            filtered = true;
+        }
+
+        // written before pulling the input so the oscillator upstream sees it this sample
+        self.freq_mul.store(2f32.powf(semitones / 12.0));
+
+        let mut x = self.input.next()?;
+        if filtered {
+            let cutoff = (base * 2f32.powf(octaves)).min(self.sample_rate as f32 * 0.45);
+            x = self.svf.lowpass(x, cutoff, LFO_CUTOFF_Q, self.sample_rate);
+        }
+        Some(x * gain)
+    }
+}
+
+/// analog instability for one voice: a fixed random detune plus a slow wander, a sample and hold
+/// smoothed out so it glides from one spot to the next. both stay within -1..1 of the amount
+struct Drift {
+    detune: f32,
+    wander: Lfo,
+    at: f32,
+    coeff: f32,
+}
+
+impl Drift {
+    fn new(seed: u32, sample_rate: u32) -> Self {
+        let mut wander = Lfo::seeded(seed);
+        let detune = wander.random();
+        Self { detune, wander, at: 0.0, coeff: 1.0 - (-2.0 * ANALOG_DRIFT_RATE_HZ / sample_rate as f32).exp() }
+    }
+
+    fn step(&mut self, sample_rate: u32) -> f32 {
+        let target = self.wander.step(LfoShape::SampleHold, ANALOG_DRIFT_RATE_HZ, sample_rate);
+        self.at += (target - self.at) * self.coeff;
+        (self.detune + self.at) * 0.5
+    }
+}
+
+impl Source for ModSource {
+    fn current_span_len(&self) -> Option<usize> { self.input.current_span_len() }
+    fn channels(&self) -> u16 { self.input.channels() }
+    fn sample_rate(&self) -> u32 { self.sample_rate }
+    fn total_duration(&self) -> Option<Duration> { self.input.total_duration() }
+}
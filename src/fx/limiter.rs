@@ -0,0 +1,128 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use rodio::Source;
+
+use crate::audio_patch::{Node, SynthSource};
+use crate::meter::from_db;
+
+/// lookahead peak limiter: the signal is delayed by `lookahead_ms` so gain reduction can start
+/// before a peak arrives instead of clipping it, then released smoothly. anything still over the
+/// threshold after that (attack not fully converged) is soft clipped
+#[derive(Debug, Clone, Copy)]
+pub struct Limiter {
+    pub threshold_db: f32,
+    pub lookahead_ms: f32,
+    pub release_ms: f32,
+    pub sample_rate: u32,
+}
+
+impl Limiter {
+    pub fn new(threshold_db: f32, lookahead_ms: f32, release_ms: f32, sample_rate: u32) -> Self {
+        Self { threshold_db, lookahead_ms, release_ms, sample_rate }
+    }
+}
+
+impl Node for Limiter {
+    fn apply(&self, input: SynthSource) -> SynthSource {
+        Box::new(LimiterSource::new(input, *self))
+    }
+
+    fn name(&self) -> &'static str { "Limiter" }
+}
+
+pub struct LimiterSource {
+    input: SynthSource,
+    threshold: f32,
+    delay: VecDeque<f32>,
+    lookahead: usize,
+    /// (sample index, |x|) with decreasing |x|, front is the max of the lookahead window
+    window: VecDeque<(u64, f32)>,
+    index: u64,
+    gain: f32,
+    attack_coef: f32,
+    release_coef: f32,
+    drained: usize,
+}
+
+impl LimiterSource {
+    pub fn new(input: SynthSource, params: Limiter) -> Self {
+        let sr = params.sample_rate as f32;
+        let lookahead = ((params.lookahead_ms.max(0.0) / 1000.0 * sr) as usize).max(1);
+        // reach ~99% of the target gain within the lookahead window
+        let attack_coef = (-5.0 / lookahead as f32).exp();
+        let release_coef = (-1.0 / (params.release_ms.max(1.0) / 1000.0 * sr)).exp();
+
+        Self {
+            input,
+            threshold: from_db(params.threshold_db.min(0.0)),
+            delay: VecDeque::with_capacity(lookahead + 1),
+            lookahead,
+            window: VecDeque::with_capacity(lookahead + 1),
+            index: 0,
+            gain: 1.0,
+            attack_coef,
+            release_coef,
+            drained: 0,
+        }
+    }
+
+    fn window_peak(&mut self, x: f32) -> f32 {
+        let a = x.abs();
+        while self.window.back().is_some_and(|(_, v)| *v <= a) {
+            self.window.pop_back();
+        }
+        self.window.push_back((self.index, a));
+        while self.window.front().is_some_and(|(i, _)| self.index - i >= self.lookahead as u64) {
+            self.window.pop_front();
+        }
+        self.index += 1;
+        self.window.front().map_or(0.0, |(_, v)| *v)
+    }
+
+    /// linear up to the threshold, then a tanh knee that never goes past full scale
+    fn soft_clip(&self, y: f32) -> f32 {
+        let t = self.threshold;
+        let a = y.abs();
+        if a <= t || t >= 1.0 {
+            return y.clamp(-1.0, 1.0);
+        }
+        let knee = 1.0 - t;
+        y.signum() * (t + knee * ((a - t) / knee).tanh())
+    }
+}
+
+impl Iterator for LimiterSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        // once the input ends, flush what is still sitting in the delay line
+        let x = match self.input.next() {
+            Some(x) => x,
+            None if self.drained < self.delay.len() => {
+                self.drained += 1;
+                0.0
+            }
+            None => return None,
+        };
+
+        let peak = self.window_peak(x);
+        let target = if peak > self.threshold { self.threshold / peak } else { 1.0 };
+        let coef = if target < self.gain { self.attack_coef } else { self.release_coef };
+        self.gain = target + (self.gain - target) * coef;
+
+        self.delay.push_back(x);
+        if self.delay.len() <= self.lookahead {
+            return Some(0.0);
+        }
+        let delayed = self.delay.pop_front().unwrap_or(0.0);
+        Some(self.soft_clip(delayed * self.gain))
+    }
+}
+
+impl Source for LimiterSource {
+    fn current_span_len(&self) -> Option<usize> { None }
+    fn channels(&self) -> u16 { self.input.channels() }
+    fn sample_rate(&self) -> u32 { self.input.sample_rate() }
+    fn total_duration(&self) -> Option<Duration> { None }
+}
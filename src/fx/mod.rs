@@ -1,2 +1,25 @@
+//! the effects. the ones with controls are built alike: a plain `...Settings` copy of them
+//! (`clamped()` keeps it in range) for the config, presets and the snapshot, and a `...Params` of
+//! atomics behind an `Arc`, which the effect shares with every source it made. `set` and
+//! `set_param` store into those, so a control turned while notes are sounding is heard in them
+//! at once, without rebuilding anything
+
 pub mod gain;
 pub mod adsr;
+pub mod limiter;
+pub mod filter;
+pub mod lfo;
+pub mod reverb;
+pub mod freeze;
+pub mod tilt;
+pub mod bitcrush;
+pub mod drive;
+pub mod compressor;
+pub mod eq;
+pub mod phaser;
+pub mod flanger;
+pub mod tremolo;
+pub mod autopan;
+pub mod bypass;
+pub mod chain;
+pub mod ringmod;
@@ -0,0 +1,175 @@
+use std::f32::consts::PI;
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+use std::time::Duration;
+
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+
+use crate::audio_patch::{AtomicF32, Node, ParamDesc, ParamValue, SynthSource, find_param};
+use crate::config::{
+    LFO_RATE_MAX, LFO_RATE_MIN, PHASER_FEEDBACK_MAX, PHASER_MAX_HZ, PHASER_MIN_HZ, PHASER_RATE_DEFAULT, PHASER_STAGES,
+};
+use crate::fx::lfo::{Lfo, LfoShape};
+
+/// plain copy of the phaser controls
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PhaserSettings {
+    pub enabled: bool,
+    /// sweeps per second
+    pub rate_hz: f32,
+    /// how much of the sweep range it covers, 0..1
+    pub depth: f32,
+    /// how much of the shifted sound goes round again; deepens the notches
+    pub feedback: f32,
+    /// 0 dry, 1 only the shifted sound; the notches are deepest at 0.5
+    pub mix: f32,
+}
+
+impl Default for PhaserSettings {
+    fn default() -> Self {
+        Self { enabled: false, rate_hz: PHASER_RATE_DEFAULT, depth: 0.8, feedback: 0.5, mix: 0.5 }
+    }
+}
+
+impl PhaserSettings {
+    pub fn clamped(self) -> Self {
+        Self {
+            rate_hz: self.rate_hz.clamp(LFO_RATE_MIN, LFO_RATE_MAX),
+            depth: self.depth.clamp(0.0, 1.0),
+            feedback: self.feedback.clamp(0.0, PHASER_FEEDBACK_MAX),
+            mix: self.mix.clamp(0.0, 1.0),
+            ..self
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PhaserParams {
+    enabled: AtomicBool,
+    rate_hz: AtomicF32,
+    depth: AtomicF32,
+    feedback: AtomicF32,
+    mix: AtomicF32,
+}
+
+/// a chain of all-pass stages whose corner an LFO sweeps up and down; mixed with the dry sound
+/// their phase shift cuts moving notches into it
+#[derive(Debug, Clone)]
+pub struct Phaser {
+    params: Arc<PhaserParams>,
+}
+
+impl Phaser {
+    pub fn new(settings: PhaserSettings) -> Self {
+        let s = settings.clamped();
+        Self {
+            params: Arc::new(PhaserParams {
+                enabled: AtomicBool::new(s.enabled),
+                rate_hz: AtomicF32::new(s.rate_hz),
+                depth: AtomicF32::new(s.depth),
+                feedback: AtomicF32::new(s.feedback),
+                mix: AtomicF32::new(s.mix),
+            }),
+        }
+    }
+
+    pub fn settings(&self) -> PhaserSettings {
+        PhaserSettings {
+            enabled: self.params.enabled.load(Ordering::Relaxed),
+            rate_hz: self.params.rate_hz.load(),
+            depth: self.params.depth.load(),
+            feedback: self.params.feedback.load(),
+            mix: self.params.mix.load(),
+        }
+    }
+}
+
+impl Node for Phaser {
+    fn apply(&self, input: SynthSource) -> SynthSource {
+        Box::new(PhaserSource {
+            input,
+            params: self.params.clone(),
+            lfo: Lfo::new(),
+            stages: [0.0; PHASER_STAGES],
+            last: 0.0,
+        })
+    }
+
+    fn name(&self) -> &'static str { "Phaser" }
+
+    fn params(&self) -> Vec<ParamDesc> {
+        let s = self.settings();
+        vec![
+            ParamDesc::switch("enabled", s.enabled),
+            ParamDesc::float("rate_hz", s.rate_hz, LFO_RATE_MIN, LFO_RATE_MAX, 0.05),
+            ParamDesc::float("depth", s.depth, 0.0, 1.0, 0.05),
+            ParamDesc::float("feedback", s.feedback, 0.0, PHASER_FEEDBACK_MAX, 0.05),
+            ParamDesc::float("mix", s.mix, 0.0, 1.0, 0.05),
+        ]
+    }
+
+    fn set_param(&self, id: &str, value: ParamValue) -> Result<(), Box<dyn std::error::Error>> {
+        let desc = find_param(&self.params(), self.name(), id)?;
+        let p = &self.params;
+        match id {
+            "enabled" => p.enabled.store(value.bool()?, Ordering::Relaxed),
+            "rate_hz" => p.rate_hz.store(desc.clamp(value)?),
+            "depth" => p.depth.store(desc.clamp(value)?),
+            "feedback" => p.feedback.store(desc.clamp(value)?),
+            _ => p.mix.store(desc.clamp(value)?),
+        }
+        Ok(())
+    }
+}
+
+pub struct PhaserSource {
+    input: SynthSource,
+    params: Arc<PhaserParams>,
+    lfo: Lfo,
+    /// each all-pass stage's one sample of state
+    stages: [f32; PHASER_STAGES],
+    /// the chain's previous output, fed back in
+    last: f32,
+}
+
+impl Iterator for PhaserSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let x = self.input.next()?;
+        if !self.params.enabled.load(Ordering::Relaxed) {
+            self.stages = [0.0; PHASER_STAGES];
+            self.last = 0.0;
+            return Some(x);
+        }
+        let sr = self.input.sample_rate();
+        let v = self.lfo.step(LfoShape::Sine, self.params.rate_hz.load(), sr);
+
+        // swept on a log scale around the middle of the range, so it moves evenly by ear
+        let centre = (PHASER_MIN_HZ * PHASER_MAX_HZ).sqrt();
+        let hz = centre * (PHASER_MAX_HZ / centre).powf(v * self.params.depth.load());
+        let t = (PI * hz / sr as f32).tan();
+        let a = (t - 1.0) / (t + 1.0);
+
+        let mut y = x + self.last * self.params.feedback.load();
+        for z in self.stages.iter_mut() {
+            let out = a * y + *z;
+            *z = y - a * out;
+            y = out;
+        }
+        self.last = y;
+        let mix = self.params.mix.load();
+        Some(x * (1.0 - mix) + y * mix)
+    }
+}
+
+impl Source for PhaserSource {
+    fn current_span_len(&self) -> Option<usize> { self.input.current_span_len() }
+    fn channels(&self) -> u16 { self.input.channels() }
+    fn sample_rate(&self) -> u32 { self.input.sample_rate() }
+    fn total_duration(&self) -> Option<Duration> { self.input.total_duration() }
+}
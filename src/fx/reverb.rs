@@ -0,0 +1,223 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicU32, Ordering},
+};
+use std::time::Duration;
+
+use rodio::Source;
+
+use crate::audio_patch::{AtomicF32, Node, ParamDesc, ParamValue, SynthSource, find_param};
+use crate::config::{REVERB_DAMPING_DEFAULT, REVERB_MIX_DEFAULT, REVERB_ROOM_DEFAULT, REVERB_STEP};
+
+// freeverb tuning, in samples at 44.1kHz
+const COMB_TUNING: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const ALLPASS_TUNING: [usize; 4] = [556, 441, 341, 225];
+const TUNING_RATE: f32 = 44_100.0;
+const FIXED_GAIN: f32 = 0.015;
+const SCALE_WET: f32 = 3.0;
+const SCALE_ROOM: f32 = 0.28;
+const OFFSET_ROOM: f32 = 0.7;
+const SCALE_DAMP: f32 = 0.4;
+const ALLPASS_FEEDBACK: f32 = 0.5;
+
+/// plain copy of the reverb controls, all 0..1. `mix` 0 is fully dry
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReverbSettings {
+    pub room_size: f32,
+    pub damping: f32,
+    pub mix: f32,
+}
+
+impl Default for ReverbSettings {
+    fn default() -> Self {
+        Self { room_size: REVERB_ROOM_DEFAULT, damping: REVERB_DAMPING_DEFAULT, mix: REVERB_MIX_DEFAULT }
+    }
+}
+
+impl ReverbSettings {
+    pub fn clamped(self) -> Self {
+        Self {
+            room_size: self.room_size.clamp(0.0, 1.0),
+            damping: self.damping.clamp(0.0, 1.0),
+            mix: self.mix.clamp(0.0, 1.0),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ReverbParams {
+    room_size: AtomicF32,
+    damping: AtomicF32,
+    mix: AtomicF32,
+    /// bumped to empty the tail
+    flushes: AtomicU32,
+}
+
+/// freeverb-style algorithmic reverb (8 damped combs into 4 allpasses, mono). `flush` empties
+/// the tail of every source it made
+#[derive(Debug, Clone)]
+pub struct Reverb {
+    params: Arc<ReverbParams>,
+}
+
+impl Reverb {
+    pub fn new(settings: ReverbSettings) -> Self {
+        let s = settings.clamped();
+        Self {
+            params: Arc::new(ReverbParams {
+                room_size: AtomicF32::new(s.room_size),
+                damping: AtomicF32::new(s.damping),
+                mix: AtomicF32::new(s.mix),
+                flushes: AtomicU32::new(0),
+            }),
+        }
+    }
+
+    pub fn settings(&self) -> ReverbSettings {
+        ReverbSettings {
+            room_size: self.params.room_size.load(),
+            damping: self.params.damping.load(),
+            mix: self.params.mix.load(),
+        }
+    }
+
+    pub fn set(&self, settings: ReverbSettings) {
+        let s = settings.clamped();
+        self.params.room_size.store(s.room_size);
+        self.params.damping.store(s.damping);
+        self.params.mix.store(s.mix);
+    }
+
+    /// silences the tail at once; the room starts empty again
+    pub fn flush(&self) {
+        self.params.flushes.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Node for Reverb {
+    fn apply(&self, input: SynthSource) -> SynthSource {
+        let scale = input.sample_rate() as f32 / TUNING_RATE;
+        let len = |n: usize| ((n as f32 * scale) as usize).max(1);
+        Box::new(ReverbSource {
+            input,
+            flushed: self.params.flushes.load(Ordering::Relaxed),
+            params: self.params.clone(),
+            combs: COMB_TUNING.iter().map(|n| Comb::new(len(*n))).collect(),
+            allpasses: ALLPASS_TUNING.iter().map(|n| Allpass::new(len(*n))).collect(),
+        })
+    }
+
+    fn name(&self) -> &'static str { "Reverb" }
+
+    fn params(&self) -> Vec<ParamDesc> {
+        let s = self.settings();
+        vec![
+            ParamDesc::float("room_size", s.room_size, 0.0, 1.0, REVERB_STEP),
+            ParamDesc::float("damping", s.damping, 0.0, 1.0, REVERB_STEP),
+            ParamDesc::float("mix", s.mix, 0.0, 1.0, REVERB_STEP),
+        ]
+    }
+
+    fn set_param(&self, id: &str, value: ParamValue) -> Result<(), Box<dyn std::error::Error>> {
+        let v = find_param(&self.params(), self.name(), id)?.clamp(value)?;
+        match id {
+            "room_size" => self.params.room_size.store(v),
+            "damping" => self.params.damping.store(v),
+            _ => self.params.mix.store(v),
+        }
+        Ok(())
+    }
+}
+
+/// feedback comb with a one-pole lowpass in the loop (the damping)
+struct Comb {
+    buf: Vec<f32>,
+    pos: usize,
+    store: f32,
+}
+
+impl Comb {
+    fn new(len: usize) -> Self {
+        Self { buf: vec![0.0; len], pos: 0, store: 0.0 }
+    }
+
+    fn clear(&mut self) {
+        self.buf.fill(0.0);
+        self.store = 0.0;
+    }
+
+    #[inline]
+    fn process(&mut self, x: f32, feedback: f32, damp: f32) -> f32 {
+        let y = self.buf[self.pos];
+        self.store = y * (1.0 - damp) + self.store * damp;
+        self.buf[self.pos] = x + self.store * feedback;
+        self.pos = (self.pos + 1) % self.buf.len();
+        y
+    }
+}
+
+struct Allpass {
+    buf: Vec<f32>,
+    pos: usize,
+}
+
+impl Allpass {
+    fn new(len: usize) -> Self {
+        Self { buf: vec![0.0; len], pos: 0 }
+    }
+
+    fn clear(&mut self) {
+        self.buf.fill(0.0);
+    }
+
+    #[inline]
+    fn process(&mut self, x: f32) -> f32 {
+        let delayed = self.buf[self.pos];
+        self.buf[self.pos] = x + delayed * ALLPASS_FEEDBACK;
+        self.pos = (self.pos + 1) % self.buf.len();
+        delayed - x
+    }
+}
+
+pub struct ReverbSource {
+    input: SynthSource,
+    params: Arc<ReverbParams>,
+    /// the last flush it acted on
+    flushed: u32,
+    combs: Vec<Comb>,
+    allpasses: Vec<Allpass>,
+}
+
+impl Iterator for ReverbSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let x = self.input.next()?;
+        let flushes = self.params.flushes.load(Ordering::Relaxed);
+        if flushes != self.flushed {
+            self.flushed = flushes;
+            self.combs.iter_mut().for_each(Comb::clear);
+            self.allpasses.iter_mut().for_each(Allpass::clear);
+        }
+        let feedback = self.params.room_size.load() * SCALE_ROOM + OFFSET_ROOM;
+        let damp = self.params.damping.load() * SCALE_DAMP;
+        let mix = self.params.mix.load();
+
+        let fed = x * FIXED_GAIN;
+        let mut wet = 0.0;
+        for c in self.combs.iter_mut() {
+            wet += c.process(fed, feedback, damp);
+        }
+        for a in self.allpasses.iter_mut() {
+            wet = a.process(wet);
+        }
+        Some(x * (1.0 - mix) + wet * SCALE_WET * mix)
+    }
+}
+
+impl Source for ReverbSource {
+    fn current_span_len(&self) -> Option<usize> { self.input.current_span_len() }
+    fn channels(&self) -> u16 { self.input.channels() }
+    fn sample_rate(&self) -> u32 { self.input.sample_rate() }
+    fn total_duration(&self) -> Option<Duration> { self.input.total_duration() }
+}
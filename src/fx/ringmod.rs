@@ -0,0 +1,135 @@
+use std::f32::consts::TAU;
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+use std::time::Duration;
+
+use rodio::Source;
+
+use crate::audio_patch::{AtomicF32, FreqMul, Node, ParamDesc, ParamValue, SynthSource, find_param, new_freq_mul};
+use crate::config::{
+    RINGMOD_FREQ_DEFAULT_HZ, RINGMOD_FREQ_MAX_HZ, RINGMOD_FREQ_MIN_HZ, RINGMOD_RATIO_DEFAULT, RINGMOD_RATIO_MAX,
+    RINGMOD_RATIO_MIN, RINGMOD_RATIO_STEP,
+};
+
+#[derive(Debug)]
+struct RingModParams {
+    enabled: AtomicBool,
+    /// carrier at `ratio` times the note (and bent with it) rather than at `freq_hz`
+    keyed: AtomicBool,
+    ratio: AtomicF32,
+    freq_hz: AtomicF32,
+    /// 1 multiplies outright (ring mod, the note itself is gone), less keeps some of the dry
+    /// note under the sidebands (amplitude modulation)
+    depth: AtomicF32,
+}
+
+/// ring/amplitude modulator: each note is multiplied by a sine carrier, leaving the sum and
+/// difference frequencies. off a whole-number ratio they are inharmonic, hence the bells. one
+/// set of controls for every note, shared with the sounding ones so it can be tuned while they play
+#[derive(Debug, Clone)]
+pub struct RingMod {
+    params: Arc<RingModParams>,
+}
+
+impl Default for RingMod {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RingMod {
+    pub fn new() -> Self {
+        Self {
+            params: Arc::new(RingModParams {
+                enabled: AtomicBool::new(false),
+                keyed: AtomicBool::new(true),
+                ratio: AtomicF32::new(RINGMOD_RATIO_DEFAULT),
+                freq_hz: AtomicF32::new(RINGMOD_FREQ_DEFAULT_HZ),
+                depth: AtomicF32::new(1.0),
+            }),
+        }
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.params.enabled.load(Ordering::Relaxed)
+    }
+
+    /// modulates one note; a keyed carrier follows `note_hz` and the note's bends
+    pub fn apply_to_note(&self, input: SynthSource, note_hz: f32, freq_mul: FreqMul) -> SynthSource {
+        let dt = 1.0 / input.sample_rate() as f32;
+        Box::new(RingModSource { input, params: self.params.clone(), note_hz, freq_mul, phase: 0.0, dt })
+    }
+}
+
+impl Node for RingMod {
+    /// without a note to key to, the carrier stays on `freq_hz`
+    fn apply(&self, input: SynthSource) -> SynthSource {
+        self.apply_to_note(input, 0.0, new_freq_mul())
+    }
+
+    fn name(&self) -> &'static str { "RingMod" }
+
+    fn params(&self) -> Vec<ParamDesc> {
+        let p = &self.params;
+        vec![
+            ParamDesc::switch("enabled", self.is_on()),
+            ParamDesc::switch("keyed", p.keyed.load(Ordering::Relaxed)),
+            ParamDesc::float("ratio", p.ratio.load(), RINGMOD_RATIO_MIN, RINGMOD_RATIO_MAX, RINGMOD_RATIO_STEP),
+            ParamDesc::float("freq_hz", p.freq_hz.load(), RINGMOD_FREQ_MIN_HZ, RINGMOD_FREQ_MAX_HZ, 10.0),
+            ParamDesc::float("depth", p.depth.load(), 0.0, 1.0, 0.05),
+        ]
+    }
+
+    fn set_param(&self, id: &str, value: ParamValue) -> Result<(), Box<dyn std::error::Error>> {
+        let desc = find_param(&self.params(), self.name(), id)?;
+        let p = &self.params;
+        match id {
+            "enabled" => p.enabled.store(value.bool()?, Ordering::Relaxed),
+            "keyed" => p.keyed.store(value.bool()?, Ordering::Relaxed),
+            "ratio" => p.ratio.store(desc.clamp(value)?),
+            "freq_hz" => p.freq_hz.store(desc.clamp(value)?),
+            _ => p.depth.store(desc.clamp(value)?),
+        }
+        Ok(())
+    }
+}
+
+pub struct RingModSource {
+    input: SynthSource,
+    params: Arc<RingModParams>,
+    note_hz: f32,
+    freq_mul: FreqMul,
+    /// carrier phase, 0..1
+    phase: f32,
+    dt: f32,
+}
+
+impl Iterator for RingModSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let x = self.input.next()?;
+        let p = &self.params;
+        if !p.enabled.load(Ordering::Relaxed) {
+            return Some(x);
+        }
+        let carrier_hz = if p.keyed.load(Ordering::Relaxed) && self.note_hz > 0.0 {
+            self.note_hz * self.freq_mul.load() * p.ratio.load()
+        } else {
+            p.freq_hz.load()
+        };
+        let carrier = (TAU * self.phase).sin();
+        self.phase = (self.phase + carrier_hz * self.dt).fract();
+        let depth = p.depth.load();
+        Some(x * (1.0 - depth + depth * carrier))
+    }
+}
+
+impl Source for RingModSource {
+    fn current_span_len(&self) -> Option<usize> { self.input.current_span_len() }
+    fn channels(&self) -> u16 { self.input.channels() }
+    fn sample_rate(&self) -> u32 { self.input.sample_rate() }
+    fn total_duration(&self) -> Option<Duration> { self.input.total_duration() }
+}
@@ -0,0 +1,61 @@
+use std::f32::consts::TAU;
+use std::time::Duration;
+
+use rodio::Source;
+
+use crate::audio_patch::{Node, SynthSource};
+use crate::config::{TILT_MAX_DB, TILT_PIVOT_HZ};
+
+/// tilt EQ: a one-pole split at `TILT_PIVOT_HZ` into a low and a high shelf, one turned down
+/// while the other goes up. `tilt` runs -1 (dark) .. 1 (bright), ±`TILT_MAX_DB` at the ends
+#[derive(Debug, Clone, Copy)]
+pub struct TiltEq {
+    pub tilt: f32,
+}
+
+impl TiltEq {
+    pub fn new(tilt: f32) -> Self {
+        Self { tilt: tilt.clamp(-1.0, 1.0) }
+    }
+}
+
+impl Node for TiltEq {
+    fn apply(&self, input: SynthSource) -> SynthSource {
+        let sr = input.sample_rate() as f32;
+        let db = self.tilt * TILT_MAX_DB;
+        Box::new(TiltSource {
+            input,
+            coeff: 1.0 - (-TAU * TILT_PIVOT_HZ / sr).exp(),
+            low_gain: 10f32.powf(-db / 20.0),
+            high_gain: 10f32.powf(db / 20.0),
+            low: 0.0,
+        })
+    }
+
+    fn name(&self) -> &'static str { "Tilt" }
+}
+
+pub struct TiltSource {
+    input: SynthSource,
+    coeff: f32,
+    low_gain: f32,
+    high_gain: f32,
+    low: f32,
+}
+
+impl Iterator for TiltSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let x = self.input.next()?;
+        self.low += (x - self.low) * self.coeff;
+        Some(self.low * self.low_gain + (x - self.low) * self.high_gain)
+    }
+}
+
+impl Source for TiltSource {
+    fn current_span_len(&self) -> Option<usize> { self.input.current_span_len() }
+    fn channels(&self) -> u16 { self.input.channels() }
+    fn sample_rate(&self) -> u32 { self.input.sample_rate() }
+    fn total_duration(&self) -> Option<Duration> { self.input.total_duration() }
+}
@@ -0,0 +1,134 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+use std::time::Duration;
+
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+
+use crate::audio_patch::{AtomicF32, Node, ParamDesc, ParamValue, SynthSource, find_param};
+use crate::config::{LFO_RATE_MAX, LFO_RATE_MIN, TREMOLO_RATE_DEFAULT};
+use crate::echo::Division;
+use crate::fx::lfo::{Lfo, LfoShape, SyncedRate};
+
+/// plain copy of the tremolo controls
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TremoloSettings {
+    pub enabled: bool,
+    pub rate_hz: f32,
+    /// when set, one cycle per division of the transport tempo and `rate_hz` is ignored
+    pub sync: Option<Division>,
+    /// how far down it dips, 0..1; 1 goes all the way to silence
+    pub depth: f32,
+}
+
+impl Default for TremoloSettings {
+    fn default() -> Self {
+        Self { enabled: false, rate_hz: TREMOLO_RATE_DEFAULT, sync: None, depth: 0.5 }
+    }
+}
+
+impl TremoloSettings {
+    pub fn clamped(self) -> Self {
+        Self { rate_hz: self.rate_hz.clamp(LFO_RATE_MIN, LFO_RATE_MAX), depth: self.depth.clamp(0.0, 1.0), ..self }
+    }
+}
+
+#[derive(Debug)]
+struct TremoloParams {
+    enabled: AtomicBool,
+    rate: SyncedRate,
+    depth: AtomicF32,
+}
+
+/// an LFO on the level of the whole mix, at a free rate or synced to the tempo
+#[derive(Debug, Clone)]
+pub struct Tremolo {
+    params: Arc<TremoloParams>,
+}
+
+impl Tremolo {
+    pub fn new(settings: TremoloSettings) -> Self {
+        let s = settings.clamped();
+        Self {
+            params: Arc::new(TremoloParams {
+                enabled: AtomicBool::new(s.enabled),
+                rate: SyncedRate::new(s.rate_hz, s.sync),
+                depth: AtomicF32::new(s.depth),
+            }),
+        }
+    }
+
+    pub fn settings(&self) -> TremoloSettings {
+        TremoloSettings {
+            enabled: self.params.enabled.load(Ordering::Relaxed),
+            rate_hz: self.params.rate.rate_hz(),
+            sync: self.params.rate.sync(),
+            depth: self.params.depth.load(),
+        }
+    }
+}
+
+impl Node for Tremolo {
+    fn apply(&self, input: SynthSource) -> SynthSource {
+        Box::new(TremoloSource { input, params: self.params.clone(), lfo: Lfo::new() })
+    }
+
+    fn name(&self) -> &'static str { "Tremolo" }
+
+    fn params(&self) -> Vec<ParamDesc> {
+        let s = self.settings();
+        vec![
+            ParamDesc::switch("enabled", s.enabled),
+            ParamDesc::float("rate_hz", s.rate_hz, LFO_RATE_MIN, LFO_RATE_MAX, 0.25),
+            ParamDesc::float("sync", self.params.rate.sync_index(), 0.0, Division::ALL.len() as f32, 1.0),
+            ParamDesc::float("depth", s.depth, 0.0, 1.0, 0.05),
+        ]
+    }
+
+    fn set_param(&self, id: &str, value: ParamValue) -> Result<(), Box<dyn std::error::Error>> {
+        let desc = find_param(&self.params(), self.name(), id)?;
+        let p = &self.params;
+        match id {
+            "enabled" => p.enabled.store(value.bool()?, Ordering::Relaxed),
+            "rate_hz" => p.rate.set_rate_hz(desc.clamp(value)?),
+            "sync" => p.rate.set_sync_index(desc.clamp(value)?),
+            _ => p.depth.store(desc.clamp(value)?),
+        }
+        Ok(())
+    }
+
+    fn set_bpm(&self, bpm: f32) {
+        self.params.rate.set_bpm(bpm);
+    }
+}
+
+pub struct TremoloSource {
+    input: SynthSource,
+    params: Arc<TremoloParams>,
+    lfo: Lfo,
+}
+
+impl Iterator for TremoloSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let x = self.input.next()?;
+        // kept running while off, so both sides stay in step whenever it comes back
+        let v = self.lfo.step(LfoShape::Sine, self.params.rate.hz(), self.input.sample_rate());
+        if !self.params.enabled.load(Ordering::Relaxed) {
+            return Some(x);
+        }
+        // swings between full level and 1 - depth
+        Some(x * (1.0 - self.params.depth.load() * (1.0 - v) * 0.5))
+    }
+}
+
+impl Source for TremoloSource {
+    fn current_span_len(&self) -> Option<usize> { self.input.current_span_len() }
+    fn channels(&self) -> u16 { self.input.channels() }
+    fn sample_rate(&self) -> u32 { self.input.sample_rate() }
+    fn total_duration(&self) -> Option<Duration> { self.input.total_duration() }
+}
@@ -0,0 +1,72 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::config::{GAIN_CLIP_DB, GAIN_QUIET_DB, GAIN_SILENCE_DB, GAIN_TARGET_PEAK_DB, GAIN_WINDOW_S};
+use crate::meter::{from_db, to_db};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AdviceKind {
+    Clipping,
+    TooQuiet,
+}
+
+/// a suggested master volume that would put the recent peak at `GAIN_TARGET_PEAK_DB`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GainAdvice {
+    pub kind: AdviceKind,
+    pub peak_db: f32,
+    pub suggested_volume: f32,
+}
+
+/// watches master peaks over a sliding window and suggests volume changes that avoid clipping
+/// while keeping as much level as the headroom target allows
+pub struct GainAdvisor {
+    window: VecDeque<(Instant, f32)>,
+}
+
+impl Default for GainAdvisor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GainAdvisor {
+    pub fn new() -> Self {
+        Self { window: VecDeque::new() }
+    }
+
+    pub fn observe(&mut self, peak: f32, now: Instant) {
+        self.window.push_back((now, peak));
+        let span = Duration::from_secs_f32(GAIN_WINDOW_S);
+        while self.window.front().is_some_and(|(t, _)| now.duration_since(*t) > span) {
+            self.window.pop_front();
+        }
+    }
+
+    /// forget the history, e.g. after the volume was changed so old peaks no longer apply
+    pub fn reset(&mut self) {
+        self.window.clear();
+    }
+
+    pub fn window_peak_db(&self) -> f32 {
+        to_db(self.window.iter().map(|(_, p)| *p).fold(0.0, f32::max))
+    }
+
+    pub fn advise(&self, volume: f32) -> Option<GainAdvice> {
+        let peak_db = self.window_peak_db();
+
+        let kind = if peak_db >= GAIN_CLIP_DB {
+            AdviceKind::Clipping
+        } else if peak_db < GAIN_QUIET_DB && peak_db > GAIN_SILENCE_DB {
+            AdviceKind::TooQuiet
+        } else {
+            return None;
+        };
+
+        let suggested_volume = (volume * from_db(GAIN_TARGET_PEAK_DB - peak_db)).clamp(0.0, 2.0);
+        if (suggested_volume - volume).abs() < 0.01 {
+            return None;
+        }
+        Some(GainAdvice { kind, peak_db, suggested_volume })
+    }
+}
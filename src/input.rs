@@ -0,0 +1,265 @@
+//! what happens to the polled keyboard state before it reaches the note engine: the press/release
+//! state machine, chord assist for staggered presses, and a guided rollover test for keyboards
+//! that drop keys in chords
+
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use device_query::Keycode;
+
+use crate::config::{KEY_REPEAT_DELAY_MS, KEY_REPEAT_GAP_MS, ROLLOVER_STEP_TIMEOUT_S};
+use crate::key::{Key, Keymap};
+
+/// what changed in one poll, in the order the note engine should apply it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyUpdate {
+    /// every key down after this poll
+    pub held: HashSet<Keycode>,
+    pub pressed: Vec<Keycode>,
+    pub released: Vec<Keycode>,
+    /// most note keys seen down at once so far
+    pub max_held: usize,
+}
+
+impl KeyUpdate {
+    pub fn is_empty(&self) -> bool {
+        self.pressed.is_empty() && self.released.is_empty()
+    }
+}
+
+/// press/release tracking between the keyboard poll and the notes. each key is up, down, or
+/// lifting: a key held past the OS repeat delay that goes up is only released once it stays up
+/// for `KEY_REPEAT_GAP_MS`, so the release/press blips key repeat can leave in the poll never
+/// retrigger a held note. quick taps release right away. it also keeps the largest number of
+/// note keys seen down at once, the keyboard's rollover as far as we can tell
+#[derive(Debug, Default)]
+pub struct KeyState {
+    down_since: HashMap<Keycode, Instant>,
+    lifting: HashMap<Keycode, Instant>,
+    max_held: usize,
+    suppressed: u64,
+}
+
+impl KeyState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// feed one poll of the keys that are down
+    pub fn update(&mut self, raw: &HashSet<Keycode>, now: Instant) -> KeyUpdate {
+        let mut pressed = vec![];
+        for k in raw {
+            if self.lifting.remove(k).is_some() {
+                self.suppressed += 1;
+            } else if !self.down_since.contains_key(k) {
+                self.down_since.insert(*k, now);
+                pressed.push(*k);
+            }
+        }
+
+        let repeat_delay = Duration::from_millis(KEY_REPEAT_DELAY_MS);
+        let mut released = vec![];
+        for (k, since) in &self.down_since {
+            if raw.contains(k) || self.lifting.contains_key(k) {
+                continue;
+            }
+            if now.duration_since(*since) >= repeat_delay {
+                self.lifting.insert(*k, now);
+            } else {
+                released.push(*k);
+            }
+        }
+        let gap = Duration::from_millis(KEY_REPEAT_GAP_MS);
+        self.lifting.retain(|k, since| {
+            let gone = now.duration_since(*since) >= gap;
+            if gone {
+                released.push(*k);
+            }
+            !gone
+        });
+        for k in &released {
+            self.down_since.remove(k);
+        }
+
+        let notes = raw.iter().filter(|k| Key::from_keycode(**k).is_some()).count();
+        self.max_held = self.max_held.max(notes);
+        self.changes(pressed, released)
+    }
+
+    /// lets go of everything at once, for when the window loses focus
+    pub fn release_all(&mut self) -> KeyUpdate {
+        self.lifting.clear();
+        let released = self.down_since.drain().map(|(k, _)| k).collect();
+        self.changes(vec![], released)
+    }
+
+    /// takes `raw` as already held without reporting presses, so keys still down when focus
+    /// comes back don't sound
+    pub fn resync(&mut self, raw: &HashSet<Keycode>, now: Instant) {
+        self.lifting.clear();
+        self.down_since = raw.iter().map(|k| (*k, now)).collect();
+    }
+
+    fn changes(&self, pressed: Vec<Keycode>, released: Vec<Keycode>) -> KeyUpdate {
+        KeyUpdate { held: self.down_since.keys().copied().collect(), pressed, released, max_held: self.max_held }
+    }
+
+    pub fn max_held(&self) -> usize {
+        self.max_held
+    }
+
+    /// repeat blips swallowed so far
+    pub fn suppressed(&self) -> u64 {
+        self.suppressed
+    }
+}
+
+/// makes a slightly staggered chord land as one. a press is held back until the first press of
+/// its group is `window` old, and a release only goes through once the key has stayed up for
+/// `window`, which also hides the short dropouts cheap keyboards produce while ghosting
+#[derive(Debug)]
+pub struct ChordAssist {
+    window: Duration,
+    pending: HashMap<Keycode, Instant>,
+    releasing: HashMap<Keycode, Instant>,
+    held: HashSet<Keycode>,
+}
+
+impl ChordAssist {
+    pub fn new(window: Duration) -> Self {
+        Self { window, pending: HashMap::new(), releasing: HashMap::new(), held: HashSet::new() }
+    }
+
+    /// the keys to treat as held, given the raw poll
+    pub fn filter(&mut self, raw: &HashSet<Keycode>, now: Instant) -> HashSet<Keycode> {
+        for k in raw {
+            if !self.held.contains(k) {
+                self.pending.entry(*k).or_insert(now);
+            }
+            self.releasing.remove(k);
+        }
+        for k in &self.held {
+            if !raw.contains(k) {
+                self.releasing.entry(*k).or_insert(now);
+            }
+        }
+
+        // a quick tap still plays: it is flushed with its group and released a window later
+        if let Some(first) = self.pending.values().min()
+            && now.duration_since(*first) >= self.window
+        {
+            self.held.extend(self.pending.drain().map(|(k, _)| k));
+        }
+
+        let window = self.window;
+        let held = &mut self.held;
+        self.releasing.retain(|k, since| {
+            if now.duration_since(*since) >= window {
+                held.remove(k);
+                false
+            } else {
+                true
+            }
+        });
+
+        self.held.clone()
+    }
+
+    pub fn reset(&mut self) {
+        self.pending.clear();
+        self.releasing.clear();
+        self.held.clear();
+    }
+}
+
+/// combinations the rollover test asks for: growing home-row chords, then mixed rows,
+/// which is where matrix keyboards usually ghost
+const ROLLOVER_COMBOS: &[&[Keycode]] = &[
+    &[Keycode::A, Keycode::S, Keycode::D],
+    &[Keycode::A, Keycode::S, Keycode::D, Keycode::F],
+    &[Keycode::A, Keycode::S, Keycode::D, Keycode::F, Keycode::G, Keycode::H],
+    &[Keycode::W, Keycode::E, Keycode::T, Keycode::Y],
+    &[Keycode::A, Keycode::W, Keycode::S, Keycode::E],
+    &[Keycode::H, Keycode::J, Keycode::U, Keycode::K, Keycode::O],
+];
+
+struct ComboMiss {
+    combo: &'static [Keycode],
+    missing: Vec<Keycode>,
+}
+
+/// guided ghosting/rollover diagnostic: asks for each combination in turn and notes the ones
+/// that never fully arrive within the timeout
+pub struct RolloverTest {
+    step: usize,
+    started: Instant,
+    /// largest part of the current combination seen held at once
+    best: Vec<Keycode>,
+    most_held: usize,
+    misses: Vec<ComboMiss>,
+}
+
+impl RolloverTest {
+    pub fn new(now: Instant) -> Self {
+        Self { step: 0, started: now, best: vec![], most_held: 0, misses: vec![] }
+    }
+
+    /// `keymap` names the keys as they are printed
+    pub fn prompt(&self, keymap: &Keymap) -> String {
+        let combo = ROLLOVER_COMBOS[self.step.min(ROLLOVER_COMBOS.len() - 1)];
+        format!("rollover test {}/{}: hold {}", self.step + 1, ROLLOVER_COMBOS.len(), key_list(combo, keymap))
+    }
+
+    /// feed the currently held keys (also on a timer, so timeouts fire without key events).
+    /// returns the report once every combination has been tried
+    pub fn observe(&mut self, held: &HashSet<Keycode>, now: Instant, keymap: &Keymap) -> Option<String> {
+        let notes = held.iter().filter(|k| Key::from_keycode(**k).is_some()).count();
+        self.most_held = self.most_held.max(notes);
+
+        let combo = ROLLOVER_COMBOS[self.step];
+        let hit: Vec<Keycode> = combo.iter().copied().filter(|k| held.contains(k)).collect();
+        if hit.len() > self.best.len() {
+            self.best = hit;
+        }
+
+        if self.best.len() == combo.len() {
+            self.advance(now);
+        } else if now.duration_since(self.started) >= Duration::from_secs_f32(ROLLOVER_STEP_TIMEOUT_S) {
+            let missing = combo.iter().copied().filter(|k| !self.best.contains(k)).collect();
+            self.misses.push(ComboMiss { combo, missing });
+            self.advance(now);
+        }
+
+        (self.step >= ROLLOVER_COMBOS.len()).then(|| self.report(keymap))
+    }
+
+    fn advance(&mut self, now: Instant) {
+        self.step += 1;
+        self.started = now;
+        self.best.clear();
+    }
+
+    fn report(&self, keymap: &Keymap) -> String {
+        if self.misses.is_empty() {
+            return format!(
+                "rollover ok: all {} combinations arrived, up to {} keys at once",
+                ROLLOVER_COMBOS.len(),
+                self.most_held
+            );
+        }
+        let misses: Vec<String> = self
+            .misses
+            .iter()
+            .map(|m| format!("{} (short {})", key_list(m.combo, keymap), key_list(&m.missing, keymap)))
+            .collect();
+        format!(
+            "rollover: max {} keys at once, never arrived: {}. try chord assist (c)",
+            self.most_held,
+            misses.join(", ")
+        )
+    }
+}
+
+fn key_list(keys: &[Keycode], keymap: &Keymap) -> String {
+    keys.iter().map(|k| keymap.label(*k)).collect::<Vec<_>>().join(" ")
+}
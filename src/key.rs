@@ -1,7 +1,11 @@
+use std::sync::Arc;
+
 use device_query::Keycode;
-use crate::config::{BASE_FREQ, A4_SEMITONES, SEMITONES_PER_OCTAVE, KEYBOARD_BASE_OCTAVE};
+use serde::{Deserialize, Serialize};
+use crate::config::{A4_SEMITONES, MIDI_A4, SEMITONES_PER_OCTAVE, KEYBOARD_BASE_OCTAVE};
+use crate::keymap::KeyBindings;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum Note {
     C = 0,
@@ -71,11 +75,6 @@ pub const fn key_absolute_semitone(key: Key) -> i32 {
     key.octave * SEMITONES_PER_OCTAVE + note_semitone(key.note)
 }
 
-pub fn key_frequency(key: Key) -> f32 {
-    let semitone_diff = key_absolute_semitone(key) - A4_SEMITONES;
-    BASE_FREQ * 2.0f32.powf(semitone_diff as f32 / 12.0)
-}
-
 pub const fn key_transpose(key: Key, semitones: i32) -> Key {
     let new_absolute = key_absolute_semitone(key) + semitones;
     let new_octave = new_absolute.div_euclid(SEMITONES_PER_OCTAVE);
@@ -100,6 +99,7 @@ pub const fn key_transpose(key: Key, semitones: i32) -> Key {
     create_key(new_note, new_octave)
 }
 
+/// the built-in layout. physical keys are translated onto it by the keymap before they get here
 pub fn key_from_keycode(keycode: Keycode) -> Option<Key> {
     let base = KEYBOARD_BASE_OCTAVE;
     match keycode {
@@ -125,6 +125,291 @@ pub fn key_from_keycode(keycode: Keycode) -> Option<Key> {
     }
 }
 
+/// home row, left to right; with a scale lock these play consecutive scale degrees
+pub const HOME_ROW: [Keycode; 11] = [
+    Keycode::A, Keycode::S, Keycode::D, Keycode::F, Keycode::G, Keycode::H,
+    Keycode::J, Keycode::K, Keycode::L, Keycode::Semicolon, Keycode::Apostrophe,
+];
+/// top row keys and the home row degree each one doubles an octave up (the home key to its left)
+pub const TOP_ROW: [(Keycode, usize); 7] = [
+    (Keycode::W, 0), (Keycode::E, 1), (Keycode::T, 3), (Keycode::Y, 4),
+    (Keycode::U, 5), (Keycode::O, 7), (Keycode::P, 8),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scale {
+    Major,
+    Minor,
+    Dorian,
+    Mixolydian,
+    MajorPentatonic,
+    MinorPentatonic,
+    Blues,
+}
+
+impl Scale {
+    const ALL: [Scale; 7] = [
+        Scale::Major,
+        Scale::Minor,
+        Scale::Dorian,
+        Scale::Mixolydian,
+        Scale::MajorPentatonic,
+        Scale::MinorPentatonic,
+        Scale::Blues,
+    ];
+
+    pub fn next(self) -> Self {
+        let i = Self::ALL.iter().position(|s| *s == self).unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Scale::Major => "major",
+            Scale::Minor => "minor",
+            Scale::Dorian => "dorian",
+            Scale::Mixolydian => "mixolydian",
+            Scale::MajorPentatonic => "major pentatonic",
+            Scale::MinorPentatonic => "minor pentatonic",
+            Scale::Blues => "blues",
+        }
+    }
+
+    /// semitones above the root, one octave's worth
+    pub const fn intervals(self) -> &'static [i32] {
+        match self {
+            Scale::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Scale::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            Scale::Dorian => &[0, 2, 3, 5, 7, 9, 10],
+            Scale::Mixolydian => &[0, 2, 4, 5, 7, 9, 10],
+            Scale::MajorPentatonic => &[0, 2, 4, 7, 9],
+            Scale::MinorPentatonic => &[0, 3, 5, 7, 10],
+            Scale::Blues => &[0, 3, 5, 6, 7, 10],
+        }
+    }
+}
+
+/// remaps the playing keys to a scale so every key is a right note: the home row walks up the
+/// scale from the root and the top row repeats it an octave higher
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScaleLock {
+    pub enabled: bool,
+    pub scale: Scale,
+    pub root: Note,
+}
+
+impl Default for ScaleLock {
+    fn default() -> Self {
+        Self { enabled: false, scale: Scale::Major, root: Note::C }
+    }
+}
+
+impl ScaleLock {
+    pub fn name(self) -> String {
+        format!("{} {}", note_name(self.root), self.scale.name())
+    }
+
+    /// the key a physical key plays, honouring the lock when it is on
+    pub fn key(self, keycode: Keycode) -> Option<Key> {
+        if !self.enabled {
+            return key_from_keycode(keycode);
+        }
+        let degree = match HOME_ROW.iter().position(|k| *k == keycode) {
+            Some(i) => i,
+            None => {
+                let (_, i) = TOP_ROW.iter().find(|(k, _)| *k == keycode)?;
+                i + self.scale.intervals().len()
+            }
+        };
+        let intervals = self.scale.intervals();
+        let octave = (degree / intervals.len()) as i32;
+        let semitones = octave * SEMITONES_PER_OCTAVE + intervals[degree % intervals.len()];
+        Some(create_key(self.root, KEYBOARD_BASE_OCTAVE).transpose(semitones))
+    }
+}
+
+/// every physical key that plays a note, in the order they are listed and labelled
+pub const PLAY_KEYS: [Keycode; 18] = [
+    Keycode::A, Keycode::W, Keycode::S, Keycode::E, Keycode::D, Keycode::F,
+    Keycode::T, Keycode::G, Keycode::Y, Keycode::H, Keycode::U, Keycode::J,
+    Keycode::K, Keycode::O, Keycode::L, Keycode::P, Keycode::Semicolon, Keycode::Apostrophe,
+];
+
+/// names for keys by their place on a US layout, which is how the poller reports them
+const KEY_NAMES: &[(Keycode, &str)] = &[
+    (Keycode::A, "a"), (Keycode::B, "b"), (Keycode::C, "c"), (Keycode::D, "d"), (Keycode::E, "e"),
+    (Keycode::F, "f"), (Keycode::G, "g"), (Keycode::H, "h"), (Keycode::I, "i"), (Keycode::J, "j"),
+    (Keycode::K, "k"), (Keycode::L, "l"), (Keycode::M, "m"), (Keycode::N, "n"), (Keycode::O, "o"),
+    (Keycode::P, "p"), (Keycode::Q, "q"), (Keycode::R, "r"), (Keycode::S, "s"), (Keycode::T, "t"),
+    (Keycode::U, "u"), (Keycode::V, "v"), (Keycode::W, "w"), (Keycode::X, "x"), (Keycode::Y, "y"),
+    (Keycode::Z, "z"),
+    (Keycode::Key0, "0"), (Keycode::Key1, "1"), (Keycode::Key2, "2"), (Keycode::Key3, "3"), (Keycode::Key4, "4"),
+    (Keycode::Key5, "5"), (Keycode::Key6, "6"), (Keycode::Key7, "7"), (Keycode::Key8, "8"), (Keycode::Key9, "9"),
+    (Keycode::Semicolon, ";"), (Keycode::Apostrophe, "'"), (Keycode::Comma, ","), (Keycode::Dot, "."),
+    (Keycode::Slash, "/"), (Keycode::LeftBracket, "["), (Keycode::RightBracket, "]"), (Keycode::Minus, "-"),
+    (Keycode::Equal, "="), (Keycode::Grave, "`"), (Keycode::BackSlash, "\\"),
+    (Keycode::Space, "space"), (Keycode::Left, "left"), (Keycode::Right, "right"), (Keycode::Up, "up"),
+    (Keycode::Down, "down"), (Keycode::Tab, "tab"), (Keycode::Enter, "enter"), (Keycode::Backspace, "backspace"),
+];
+
+/// what is printed on the physical key of a US layout
+pub fn keycode_label(keycode: Keycode) -> &'static str {
+    KEY_NAMES.iter().find(|(k, _)| *k == keycode).map_or("?", |(_, name)| name)
+}
+
+/// a key named as `keycode_label` prints it, or as device_query spells it (`F5`, `Numpad1`)
+pub fn keycode_from_name(name: &str) -> Option<Keycode> {
+    let lower = name.to_lowercase();
+    KEY_NAMES.iter().find(|(_, n)| *n == lower).map(|(k, _)| *k).or_else(|| name.parse().ok())
+}
+
+/// the character keys in US order, row by row, for the layout tables below
+const LAYOUT_KEYS: [Keycode; 47] = [
+    Keycode::Grave, Keycode::Key1, Keycode::Key2, Keycode::Key3, Keycode::Key4, Keycode::Key5, Keycode::Key6,
+    Keycode::Key7, Keycode::Key8, Keycode::Key9, Keycode::Key0, Keycode::Minus, Keycode::Equal,
+    Keycode::Q, Keycode::W, Keycode::E, Keycode::R, Keycode::T, Keycode::Y, Keycode::U, Keycode::I, Keycode::O,
+    Keycode::P, Keycode::LeftBracket, Keycode::RightBracket, Keycode::BackSlash,
+    Keycode::A, Keycode::S, Keycode::D, Keycode::F, Keycode::G, Keycode::H, Keycode::J, Keycode::K, Keycode::L,
+    Keycode::Semicolon, Keycode::Apostrophe,
+    Keycode::Z, Keycode::X, Keycode::C, Keycode::V, Keycode::B, Keycode::N, Keycode::M, Keycode::Comma,
+    Keycode::Dot, Keycode::Slash,
+];
+
+/// what a key types unshifted on each layout, in `LAYOUT_KEYS` order
+const QWERTY_CHARS: [char; 47] = [
+    '`', '1', '2', '3', '4', '5', '6', '7', '8', '9', '0', '-', '=',
+    'q', 'w', 'e', 'r', 't', 'y', 'u', 'i', 'o', 'p', '[', ']', '\\',
+    'a', 's', 'd', 'f', 'g', 'h', 'j', 'k', 'l', ';', '\'',
+    'z', 'x', 'c', 'v', 'b', 'n', 'm', ',', '.', '/',
+];
+const AZERTY_CHARS: [char; 47] = [
+    '²', '&', 'é', '"', '\'', '(', '-', 'è', '_', 'ç', 'à', ')', '=',
+    'a', 'z', 'e', 'r', 't', 'y', 'u', 'i', 'o', 'p', '^', '$', '*',
+    'q', 's', 'd', 'f', 'g', 'h', 'j', 'k', 'l', 'm', 'ù',
+    'w', 'x', 'c', 'v', 'b', 'n', ',', ';', ':', '!',
+];
+const QWERTZ_CHARS: [char; 47] = [
+    '^', '1', '2', '3', '4', '5', '6', '7', '8', '9', '0', 'ß', '´',
+    'q', 'w', 'e', 'r', 't', 'z', 'u', 'i', 'o', 'p', 'ü', '+', '#',
+    'a', 's', 'd', 'f', 'g', 'h', 'j', 'k', 'l', 'ö', 'ä',
+    'y', 'x', 'c', 'v', 'b', 'n', 'm', ',', '.', '-',
+];
+const DVORAK_CHARS: [char; 47] = [
+    '`', '1', '2', '3', '4', '5', '6', '7', '8', '9', '0', '[', ']',
+    '\'', ',', '.', 'p', 'y', 'f', 'g', 'c', 'r', 'l', '/', '=', '\\',
+    'a', 'o', 'e', 'u', 'i', 'd', 'h', 't', 'n', 's', '-',
+    ';', 'q', 'j', 'k', 'x', 'b', 'm', 'w', 'v', 'z',
+];
+
+/// the system keyboard layout. notes are polled by position so they sit in the same place on
+/// every layout; the layout decides what the labels say and lets the terminal shortcuts keep
+/// their place too, instead of landing on note keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyboardLayout {
+    #[default]
+    Qwerty,
+    Azerty,
+    Qwertz,
+    Dvorak,
+}
+
+impl KeyboardLayout {
+    const ALL: [KeyboardLayout; 4] =
+        [KeyboardLayout::Qwerty, KeyboardLayout::Azerty, KeyboardLayout::Qwertz, KeyboardLayout::Dvorak];
+
+    pub fn next(self) -> Self {
+        let i = Self::ALL.iter().position(|l| *l == self).unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyboardLayout::Qwerty => "qwerty",
+            KeyboardLayout::Azerty => "azerty",
+            KeyboardLayout::Qwertz => "qwertz",
+            KeyboardLayout::Dvorak => "dvorak",
+        }
+    }
+
+    fn chars(self) -> &'static [char; 47] {
+        match self {
+            KeyboardLayout::Qwerty => &QWERTY_CHARS,
+            KeyboardLayout::Azerty => &AZERTY_CHARS,
+            KeyboardLayout::Qwertz => &QWERTZ_CHARS,
+            KeyboardLayout::Dvorak => &DVORAK_CHARS,
+        }
+    }
+
+    /// what is printed on the key at a US position. the number row is labelled with its digits,
+    /// which AZERTY prints shifted
+    pub fn printed(self, keycode: Keycode) -> Option<char> {
+        let i = LAYOUT_KEYS.iter().position(|k| *k == keycode)?;
+        let c = self.chars()[i];
+        Some(if (1..=10).contains(&i) { QWERTY_CHARS[i] } else { c })
+    }
+
+    /// the US character at the place a typed character sits on this layout, so a shortcut is
+    /// pressed in the same place whatever the layout. characters it doesn't know pass through
+    pub fn to_us(self, typed: char) -> char {
+        let lower = typed.to_lowercase().next().unwrap_or(typed);
+        let Some(i) = self.chars().iter().position(|c| *c == lower) else { return typed; };
+        let us = QWERTY_CHARS[i];
+        if typed.is_uppercase() { us.to_ascii_uppercase() } else { us }
+    }
+}
+
+/// the resolved physical key → sounding note table. the audio side plays from it and the UI
+/// draws from the same copy, so the keyboard widget always shows what a key really plays
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keymap {
+    entries: Vec<(Keycode, Key)>,
+    octave: i32,
+    /// where the keys really are, for labels and the UI's shortcuts
+    bindings: Arc<KeyBindings>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::new(ScaleLock::default(), KEYBOARD_BASE_OCTAVE)
+    }
+}
+
+impl Keymap {
+    /// `octave` is where the lowest key (`a`, or the lock's root) sits
+    pub fn new(lock: ScaleLock, octave: i32) -> Self {
+        let shift = (octave - KEYBOARD_BASE_OCTAVE) * SEMITONES_PER_OCTAVE;
+        let entries = PLAY_KEYS.iter().filter_map(|k| Some((*k, lock.key(*k)?.transpose(shift)))).collect();
+        Self { entries, octave, bindings: Arc::default() }
+    }
+
+    pub fn with_bindings(self, bindings: Arc<KeyBindings>) -> Self {
+        Self { bindings, ..self }
+    }
+
+    pub fn octave(&self) -> i32 { self.octave }
+    pub fn bindings(&self) -> &Arc<KeyBindings> { &self.bindings }
+
+    /// what is printed on the physical key that plays as `keycode`
+    pub fn label(&self, keycode: Keycode) -> &str {
+        self.bindings.label(keycode)
+    }
+
+    pub fn key(&self, keycode: Keycode) -> Option<Key> {
+        self.entries.iter().find(|(k, _)| *k == keycode).map(|(_, key)| *key)
+    }
+
+    /// physical keys that play `key`; more than one when a scale lock doubles a note
+    pub fn keycodes_for(&self, key: Key) -> impl Iterator<Item = Keycode> + '_ {
+        self.entries.iter().filter(move |(_, k)| *k == key).map(|(kc, _)| *kc)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Keycode, Key)> + '_ {
+        self.entries.iter().copied()
+    }
+}
+
 pub fn key_to_string(key: Key) -> String {
     format!("{}{}", note_name(key.note), key.octave)
 }
@@ -140,20 +425,50 @@ impl Key {
         key_absolute_semitone(self)
     }
 
-    #[inline]
-    pub fn frequency(self) -> f32 {
-        key_frequency(self)
-    }
-
     pub const fn transpose(self, semitones: i32) -> Self {
         key_transpose(self, semitones)
     }
 
+    /// MIDI note number, A4 = 69
+    pub fn midi_note(self) -> u8 {
+        (self.absolute_semitone() - A4_SEMITONES + MIDI_A4).clamp(0, 127) as u8
+    }
+
+    /// the key a MIDI note number sounds
+    pub fn from_midi(note: u8) -> Self {
+        create_key(Note::C, 0).transpose(note as i32 - MIDI_A4 + A4_SEMITONES)
+    }
+
+    /// a note name and octave, like `C4`, `F#3` or `Bb2`
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.trim();
+        let mut chars = name.chars();
+        let semitone = match chars.next()?.to_ascii_uppercase() {
+            'C' => 0,
+            'D' => 2,
+            'E' => 4,
+            'F' => 5,
+            'G' => 7,
+            'A' => 9,
+            'B' => 11,
+            _ => return None,
+        };
+        let rest = chars.as_str();
+        let (shift, octave) = match rest.chars().next()? {
+            '#' => (1, &rest[1..]),
+            'b' => (-1, &rest[1..]),
+            _ => (0, rest),
+        };
+        Some(create_key(Note::C, octave.parse().ok()?).transpose(semitone + shift))
+    }
+
     pub fn from_keycode(keycode: Keycode) -> Option<Self> {
         key_from_keycode(keycode)
     }
+}
 
-    pub fn to_string(self) -> String {
-        key_to_string(self)
+impl std::fmt::Display for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&key_to_string(*self))
     }
 }
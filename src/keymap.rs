@@ -0,0 +1,163 @@
+//! user key bindings from the `[keymap]` config section: which physical keys play the notes, the
+//! drums and the polled controls, what is printed on them, and which keys the terminal shortcuts
+//! sit on. the poller translates physical keys onto the built-in layout before anything else sees
+//! them, so the note table, the looper and the lessons work on one set of keycodes whatever the
+//! keyboard
+
+use std::collections::{HashMap, HashSet};
+
+use device_query::Keycode;
+use serde::{Deserialize, Serialize};
+
+use crate::key::{HOME_ROW, KeyboardLayout, TOP_ROW, keycode_from_name, keycode_label};
+
+/// the built-in drum keys: kick, snare, hat
+const DRUM_KEYS: [Keycode; 3] = [Keycode::Key1, Keycode::Key2, Keycode::Key3];
+
+/// keys are named by their place on a US layout (`a`, `;`, `1`, `space`, `left`, `F5`), which is
+/// how the keyboard is polled whatever layout the system uses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeymapSettings {
+    /// the system layout, for the labels and where the shortcuts are
+    pub layout: KeyboardLayout,
+    /// the white notes from C up, left to right; at most 11
+    pub home_row: Vec<String>,
+    /// the black notes from C# up, left to right; at most 7
+    pub top_row: Vec<String>,
+    /// kick, snare, hi-hat
+    pub drums: Vec<String>,
+    pub next_patch: String,
+    pub sustain: String,
+    pub bend_down: String,
+    pub bend_up: String,
+    /// what is printed on a key, for the on-screen labels, where the layout gets it wrong
+    pub labels: HashMap<String, String>,
+    /// terminal shortcuts: a typed character = the default shortcut it should act as, over
+    /// the layout's. a character mapped to "" does nothing, so a note key can't also quit
+    pub shortcuts: HashMap<String, String>,
+}
+
+impl Default for KeymapSettings {
+    fn default() -> Self {
+        let name = |k: &Keycode| keycode_label(*k).to_string();
+        Self {
+            layout: KeyboardLayout::default(),
+            home_row: HOME_ROW.iter().map(name).collect(),
+            top_row: TOP_ROW.iter().map(|(k, _)| name(k)).collect(),
+            drums: DRUM_KEYS.iter().map(name).collect(),
+            next_patch: "b".into(),
+            sustain: "space".into(),
+            bend_down: "left".into(),
+            bend_up: "right".into(),
+            labels: HashMap::new(),
+            shortcuts: HashMap::new(),
+        }
+    }
+}
+
+/// the resolved bindings. the default is the built-in layout, translating every key to itself
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyBindings {
+    /// physical key → the built-in key it stands for
+    to_builtin: HashMap<Keycode, Keycode>,
+    /// the other way round, for labels
+    to_physical: HashMap<Keycode, Keycode>,
+    /// built-in keys that were moved away or left unbound, so they don't play as themselves
+    moved: HashSet<Keycode>,
+    /// by physical key
+    labels: HashMap<Keycode, String>,
+    layout: KeyboardLayout,
+    shortcuts: HashMap<char, Option<char>>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self::from_settings(&KeymapSettings::default()).expect("the default keymap resolves")
+    }
+}
+
+impl KeyBindings {
+    pub fn from_settings(s: &KeymapSettings) -> Result<Self, Box<dyn std::error::Error>> {
+        let builtin_top: Vec<Keycode> = TOP_ROW.iter().map(|(k, _)| *k).collect();
+        let controls = [Keycode::B, Keycode::Space, Keycode::Left, Keycode::Right];
+        let rows: [(&str, &[String], &[Keycode]); 4] = [
+            ("home_row", &s.home_row, &HOME_ROW),
+            ("top_row", &s.top_row, &builtin_top),
+            ("drums", &s.drums, &DRUM_KEYS),
+            ("controls", &[s.next_patch.clone(), s.sustain.clone(), s.bend_down.clone(), s.bend_up.clone()], &controls),
+        ];
+
+        let mut bindings = Self {
+            to_builtin: HashMap::new(),
+            to_physical: HashMap::new(),
+            moved: HashSet::new(),
+            labels: HashMap::new(),
+            layout: s.layout,
+            shortcuts: HashMap::new(),
+        };
+        for (field, names, builtin) in rows {
+            if names.len() > builtin.len() {
+                return Err(format!("keymap {field} has {} keys, at most {} fit", names.len(), builtin.len()).into());
+            }
+            bindings.moved.extend(builtin.iter().copied());
+            for (name, target) in names.iter().zip(builtin) {
+                let physical = parse_key(name)?;
+                if bindings.to_builtin.insert(physical, *target).is_some() {
+                    return Err(format!("keymap binds {name} twice").into());
+                }
+                bindings.to_physical.insert(*target, physical);
+            }
+        }
+
+        for physical in bindings.to_physical.values() {
+            let label = s.layout.printed(*physical).map_or_else(|| keycode_label(*physical).to_string(), String::from);
+            bindings.labels.insert(*physical, label);
+        }
+        for (name, label) in &s.labels {
+            bindings.labels.insert(parse_key(name)?, label.clone());
+        }
+        for (typed, acts_as) in &s.shortcuts {
+            let typed = single_char(typed).ok_or_else(|| format!("keymap shortcut {typed:?} is not one character"))?;
+            let acts_as = match acts_as.as_str() {
+                "" => None,
+                c => Some(single_char(c).ok_or_else(|| format!("keymap shortcut {c:?} is not one character"))?),
+            };
+            bindings.shortcuts.insert(typed, acts_as);
+        }
+        Ok(bindings)
+    }
+
+    /// one poll of physical keys, as the built-in keys they play. keys nothing is bound to pass
+    /// through (shift, escape, ...)
+    pub fn translate(&self, raw: &HashSet<Keycode>) -> HashSet<Keycode> {
+        raw.iter()
+            .filter_map(|k| match self.to_builtin.get(k) {
+                Some(builtin) => Some(*builtin),
+                None if self.moved.contains(k) => None,
+                None => Some(*k),
+            })
+            .collect()
+    }
+
+    /// what is printed on the key that plays as the built-in `keycode`; empty when it is unbound
+    pub fn label(&self, keycode: Keycode) -> &str {
+        let Some(physical) = self.to_physical.get(&keycode) else { return ""; };
+        self.labels.get(physical).map_or("", String::as_str)
+    }
+
+    /// the shortcut a typed character acts as, `None` when it was turned off
+    pub fn shortcut(&self, typed: char) -> Option<char> {
+        self.shortcuts.get(&typed).copied().unwrap_or_else(|| Some(self.layout.to_us(typed)))
+    }
+}
+
+fn parse_key(name: &str) -> Result<Keycode, Box<dyn std::error::Error>> {
+    keycode_from_name(name).ok_or_else(|| format!("keymap has an unknown key {name:?}").into())
+}
+
+fn single_char(s: &str) -> Option<char> {
+    let mut chars = s.chars();
+    let c = chars.next()?;
+    chars.next().is_none().then_some(c)
+}
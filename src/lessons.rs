@@ -0,0 +1,197 @@
+//! built-in lessons for learning the keyboard layout: a scale or a short tune, one note at a
+//! time. the next note is highlighted on the piano and progress is kept between sessions in the
+//! user data dir
+
+use std::collections::BTreeMap;
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::LESSONS_FILE;
+use crate::key::{Key, Keymap, Note};
+use crate::settings::data_dir;
+
+use Note::*;
+
+pub struct Lesson {
+    pub name: &'static str,
+    notes: &'static [(Note, i32)],
+}
+
+impl Lesson {
+    pub fn len(&self) -> usize {
+        self.notes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.notes.is_empty()
+    }
+
+    /// step `i` as a key; octaves are relative to `base`, the octave the keyboard starts on
+    pub fn key(&self, i: usize, base: i32) -> Option<Key> {
+        let (note, octave) = self.notes.get(i)?;
+        Some(Key::new(*note, base + octave))
+    }
+}
+
+pub const LESSONS: &[Lesson] = &[
+    Lesson {
+        name: "C major scale",
+        notes: &[(C, 0), (D, 0), (E, 0), (F, 0), (G, 0), (A, 0), (B, 0), (C, 1),
+                 (B, 0), (A, 0), (G, 0), (F, 0), (E, 0), (D, 0), (C, 0)],
+    },
+    Lesson {
+        name: "chromatic run",
+        notes: &[(C, 0), (Db, 0), (D, 0), (Eb, 0), (E, 0), (F, 0), (Gb, 0), (G, 0),
+                 (Ab, 0), (A, 0), (Bb, 0), (B, 0), (C, 1)],
+    },
+    Lesson {
+        name: "Mary had a little lamb",
+        notes: &[(E, 0), (D, 0), (C, 0), (D, 0), (E, 0), (E, 0), (E, 0),
+                 (D, 0), (D, 0), (D, 0), (E, 0), (G, 0), (G, 0),
+                 (E, 0), (D, 0), (C, 0), (D, 0), (E, 0), (E, 0), (E, 0),
+                 (E, 0), (D, 0), (D, 0), (E, 0), (D, 0), (C, 0)],
+    },
+    Lesson {
+        name: "Twinkle twinkle",
+        notes: &[(C, 0), (C, 0), (G, 0), (G, 0), (A, 0), (A, 0), (G, 0),
+                 (F, 0), (F, 0), (E, 0), (E, 0), (D, 0), (D, 0), (C, 0)],
+    },
+    Lesson {
+        name: "Ode to joy",
+        notes: &[(E, 0), (E, 0), (F, 0), (G, 0), (G, 0), (F, 0), (E, 0), (D, 0),
+                 (C, 0), (C, 0), (D, 0), (E, 0), (E, 0), (D, 0), (D, 0)],
+    },
+    Lesson {
+        name: "Für Elise (opening)",
+        notes: &[(E, 1), (Eb, 1), (E, 1), (Eb, 1), (E, 1), (B, 0), (D, 1), (C, 1), (A, 0)],
+    },
+];
+
+/// what the UI shows about the running lesson
+#[derive(Debug, Clone, PartialEq)]
+pub struct LessonStatus {
+    pub name: &'static str,
+    pub step: usize,
+    pub total: usize,
+    pub next: Option<Key>,
+    pub misses: u32,
+}
+
+impl LessonStatus {
+    /// "Ode to joy 3/15 · next E4 (d) · 1 miss"
+    pub fn describe(&self, keymap: &Keymap) -> String {
+        let next = match self.next {
+            Some(key) => {
+                let keys: Vec<&str> = keymap.keycodes_for(key).map(|k| keymap.label(k)).collect();
+                if keys.is_empty() {
+                    format!("next {key} (not on the keyboard, switch the scale lock off)")
+                } else {
+                    format!("next {key} ({})", keys.join("/"))
+                }
+            }
+            None => "done".to_string(),
+        };
+        let misses = if self.misses == 1 { "1 miss".to_string() } else { format!("{} misses", self.misses) };
+        format!("{} {}/{} · {next} · {misses}", self.name, self.step, self.total)
+    }
+}
+
+/// result of one played note
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LessonStep {
+    Correct,
+    Missed,
+    Finished { misses: u32 },
+}
+
+pub struct LessonRun {
+    index: usize,
+    octave: i32,
+    step: usize,
+    misses: u32,
+}
+
+impl LessonRun {
+    /// `octave` is the keyboard's, so lessons stay playable when it is moved
+    pub fn new(index: usize, octave: i32) -> Self {
+        Self { index: index % LESSONS.len(), octave, step: 0, misses: 0 }
+    }
+
+    pub fn index(&self) -> usize { self.index }
+
+    pub fn lesson(&self) -> &'static Lesson {
+        &LESSONS[self.index]
+    }
+
+    pub fn status(&self) -> LessonStatus {
+        let lesson = self.lesson();
+        LessonStatus {
+            name: lesson.name,
+            step: self.step,
+            total: lesson.len(),
+            next: lesson.key(self.step, self.octave),
+            misses: self.misses,
+        }
+    }
+
+    /// a note was played; the lesson only moves on when it was the right one
+    pub fn observe(&mut self, key: Key) -> LessonStep {
+        let Some(expected) = self.lesson().key(self.step, self.octave) else {
+            return LessonStep::Finished { misses: self.misses };
+        };
+        if key != expected {
+            self.misses += 1;
+            return LessonStep::Missed;
+        }
+        self.step += 1;
+        if self.step >= self.lesson().len() {
+            LessonStep::Finished { misses: self.misses }
+        } else {
+            LessonStep::Correct
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct LessonRecord {
+    pub completed: u32,
+    pub best_misses: u32,
+}
+
+/// per-lesson results, keyed by lesson name
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LessonProgress {
+    pub lessons: BTreeMap<String, LessonRecord>,
+}
+
+impl LessonProgress {
+    pub fn record(&mut self, name: &str, misses: u32) {
+        let entry = self.lessons.entry(name.to_string()).or_default();
+        entry.best_misses = if entry.completed == 0 { misses } else { entry.best_misses.min(misses) };
+        entry.completed += 1;
+    }
+
+    pub fn get(&self, name: &str) -> Option<LessonRecord> {
+        self.lessons.get(name).copied()
+    }
+
+    /// first lesson never finished, or the first one if all are
+    pub fn first_unfinished(&self) -> usize {
+        LESSONS.iter().position(|l| self.get(l.name).is_none()).unwrap_or(0)
+    }
+}
+
+/// missing or unreadable progress starts fresh
+pub fn load_progress() -> LessonProgress {
+    let Some(path) = data_dir().map(|d| d.join(LESSONS_FILE)) else { return LessonProgress::default(); };
+    fs::read_to_string(path).ok().and_then(|t| toml::from_str(&t).ok()).unwrap_or_default()
+}
+
+pub fn save_progress(progress: &LessonProgress) -> Result<(), Box<dyn std::error::Error>> {
+    let dir = data_dir().ok_or("could not resolve the data directory")?;
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join(LESSONS_FILE), toml::to_string_pretty(progress)?)?;
+    Ok(())
+}
@@ -1,8 +1,85 @@
+//! the tjam engine without its terminal UI, which lives in the binary: the patches and effects,
+//! the mixer and master chain, the key mapping, looper, echo, transport and recorder, and the
+//! command/snapshot channel everything is driven through.
+//!
+//! to embed it, run [`play::run_audio`] with a [`settings::Settings`] and a shutdown channel, and
+//! talk to it through the [`audio_system::AudioHandle`] from [`audio_system::get_handle`]: every
+//! command the TUI and the control socket send is a method on it, and `subscribe` gives the latest
+//! [`audio_system::AudioSnapshot`]. `focused` decides whether the engine polls the computer keyboard
+//! for notes; leave it false to drive it only through the handle.
+//!
+//! ```no_run
+//! use std::sync::{Arc, atomic::AtomicBool};
+//!
+//! # async fn demo() -> Result<(), Box<dyn std::error::Error>> {
+//! let settings = synth_rs::settings::load_settings()?;
+//! let handle = synth_rs::audio_system::get_handle().await.clone();
+//! let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+//! let audio = synth_rs::play::run_audio(shutdown_rx, Arc::new(AtomicBool::new(false)), settings);
+//!
+//! let play = async {
+//!     handle.select_patch("Supersaw");
+//!     tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+//!     println!("{}", handle.subscribe().borrow().patch_name);
+//!     let _ = shutdown_tx.send(true);
+//! };
+//! let (res, ()) = tokio::join!(audio, play);
+//! res
+//! # }
+//! ```
+
+/// notes, keys and the keyboard layouts
 pub mod key;
+/// user key bindings from the `[keymap]` config section
+pub mod keymap;
+/// the keyboard state machine, chord assist and the rollover test
+pub mod input;
+/// the performance looper: record, overdub, fixed lengths and undo
+pub mod looper;
+/// the global clock: tempo, time signature and bar/beat position
+pub mod transport;
+/// what frequency each key plays: equal temperament or a Scala scale
+pub mod tuning;
+/// logs a take and writes it out as a MIDI file
+pub mod recorder;
+/// MIDI controllers: learned knobs, and an external clock driving the transport
+pub mod midi_in;
+/// the MIDI file reader and WAV writer of offline rendering
+pub mod render;
+/// the tempo-synced note echo
+pub mod echo;
+/// Rhai scripts that play along with the transport
+pub mod script;
+/// chord mode: one key plays a whole chord
+pub mod chord;
+/// built-in lessons for learning the keyboard layout
+pub mod lessons;
+/// the JSON control socket
+#[cfg(unix)]
+pub mod control;
+/// timings for the profiling overlay
+pub mod profiler;
+/// crash reports written to the data dir
+pub mod crash;
+/// the audio task: turns key events and commands into voices
 pub mod play;
+/// every tuning constant, grouped by the module that uses it
 pub mod config;
+/// the command channel into the audio task and the snapshot it publishes
 pub mod audio_system;
+/// the audio backend: the output stream, the mixer tracks and the master chain
+pub mod engine;
+/// the patch and effect traits, and their parameters
 pub mod audio_patch;
-pub mod ui;
+/// the sound sources a key can play
 pub mod patches;
+/// per-voice and master effects
 pub mod fx;
+/// saved sound settings
+pub mod presets;
+/// `config.toml`
+pub mod settings;
+/// master level metering
+pub mod meter;
+/// master volume advice from recent peaks
+pub mod gain_staging;
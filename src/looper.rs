@@ -0,0 +1,306 @@
+//! performance looper: records note on/off events with their timing, replays them in a loop and
+//! lets more takes be layered on top. it only produces note events; the voices are played by
+//! `play.rs` like any other key
+
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use device_query::Keycode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LooperState {
+    Empty,
+    Recording,
+    Playing,
+    Overdubbing,
+    Stopped,
+}
+
+impl LooperState {
+    pub fn name(self) -> &'static str {
+        match self {
+            LooperState::Empty => "empty",
+            LooperState::Recording => "rec",
+            LooperState::Playing => "play",
+            LooperState::Overdubbing => "dub",
+            LooperState::Stopped => "stop",
+        }
+    }
+}
+
+/// what the UI shows about the looper
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LooperStatus {
+    pub state: LooperState,
+    pub length_s: Option<f32>,
+    pub position_s: f32,
+    pub layers: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LoopEvent {
+    /// offset from the start of the loop
+    at: Duration,
+    keycode: Keycode,
+    on: bool,
+    velocity: f32,
+    layer: usize,
+    /// pass it was recorded in; it is already sounding live then, so playback skips it once
+    pass: u64,
+}
+
+/// a note event the looper wants played (`on`) or released
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoopNote {
+    pub keycode: Keycode,
+    pub on: bool,
+    pub velocity: f32,
+}
+
+pub struct Looper {
+    state: LooperState,
+    /// fixed length from the config; when `None` the first take decides it
+    fixed_length: Option<Duration>,
+    length: Option<Duration>,
+    events: Vec<LoopEvent>,
+    layers: usize,
+    started: Instant,
+    pass: u64,
+    /// where the last poll stopped; `None` right after a (re)start, so events at zero still fire
+    last_pos: Option<Duration>,
+    /// keys held by playback right now, so stopping can release them
+    sounding: HashSet<Keycode>,
+    /// keys held live while recording, closed off when the take ends
+    recording_held: HashSet<Keycode>,
+}
+
+impl Looper {
+    pub fn new(fixed_length: Option<Duration>) -> Self {
+        Self {
+            state: LooperState::Empty,
+            fixed_length,
+            length: None,
+            events: vec![],
+            layers: 0,
+            started: Instant::now(),
+            pass: 0,
+            last_pos: None,
+            sounding: HashSet::new(),
+            recording_held: HashSet::new(),
+        }
+    }
+
+    pub fn state(&self) -> LooperState { self.state }
+
+    /// keys the loop is holding down right now
+    pub fn sounding(&self) -> impl Iterator<Item = Keycode> + '_ {
+        self.sounding.iter().copied()
+    }
+
+    pub fn status(&self, now: Instant) -> LooperStatus {
+        let position = match self.state {
+            LooperState::Recording => now.duration_since(self.started),
+            LooperState::Playing | LooperState::Overdubbing => self.position(now),
+            _ => Duration::ZERO,
+        };
+        LooperStatus {
+            state: self.state,
+            length_s: self.length.map(|l| l.as_secs_f32()),
+            position_s: position.as_secs_f32(),
+            layers: self.layers,
+        }
+    }
+
+    fn position(&self, now: Instant) -> Duration {
+        let Some(length) = self.length.filter(|l| !l.is_zero()) else { return Duration::ZERO; };
+        let elapsed = now.duration_since(self.started).as_nanos() % length.as_nanos();
+        Duration::from_nanos(elapsed as u64)
+    }
+
+    fn pass_at(&self, now: Instant) -> u64 {
+        let Some(length) = self.length.filter(|l| !l.is_zero()) else { return 0; };
+        (now.duration_since(self.started).as_nanos() / length.as_nanos()) as u64
+    }
+
+    /// the one-button loop pedal flow: record → play → overdub → play → ...
+    pub fn advance(&mut self, now: Instant) {
+        match self.state {
+            LooperState::Empty => {
+                self.state = LooperState::Recording;
+                self.started = now;
+                self.layers = 1;
+            }
+            LooperState::Recording => self.finish_first_take(now),
+            LooperState::Playing => {
+                self.state = LooperState::Overdubbing;
+                self.layers += 1;
+            }
+            LooperState::Overdubbing => {
+                self.close_held(self.position(now));
+                self.state = LooperState::Playing;
+                // nothing played during the dub, don't keep an empty layer around
+                if !self.events.iter().any(|e| e.layer == self.layers) {
+                    self.layers -= 1;
+                }
+            }
+            LooperState::Stopped => {
+                self.state = LooperState::Playing;
+                self.restart(now);
+            }
+        }
+    }
+
+    /// stop playback, or start it again from the top
+    pub fn toggle_play(&mut self, now: Instant) -> Vec<LoopNote> {
+        match self.state {
+            LooperState::Playing | LooperState::Overdubbing => {
+                self.close_held(self.position(now));
+                self.state = LooperState::Stopped;
+                self.release_all()
+            }
+            LooperState::Stopped => {
+                self.state = LooperState::Playing;
+                self.restart(now);
+                vec![]
+            }
+            LooperState::Recording => {
+                self.finish_first_take(now);
+                vec![]
+            }
+            LooperState::Empty => vec![],
+        }
+    }
+
+    /// stops playback and overdubbing; the loop itself is kept
+    pub fn stop(&mut self, now: Instant) -> Vec<LoopNote> {
+        match self.state {
+            LooperState::Playing | LooperState::Overdubbing => self.toggle_play(now),
+            _ => vec![],
+        }
+    }
+
+    /// drop the most recent layer; undoing the only one clears the loop
+    pub fn undo_layer(&mut self, now: Instant) -> Vec<LoopNote> {
+        if self.layers <= 1 {
+            return self.clear();
+        }
+        if self.state == LooperState::Overdubbing {
+            self.state = LooperState::Playing;
+        }
+        let layer = self.layers;
+        self.events.retain(|e| e.layer != layer);
+        self.layers -= 1;
+        self.recording_held.clear();
+        let released = self.release_all();
+        self.last_pos = Some(self.position(now));
+        released
+    }
+
+    /// the length the next first take gets; a loop already recorded keeps its own
+    pub fn set_fixed_length(&mut self, fixed_length: Option<Duration>) {
+        self.fixed_length = fixed_length;
+    }
+
+    pub fn clear(&mut self) -> Vec<LoopNote> {
+        let released = self.release_all();
+        *self = Self::new(self.fixed_length);
+        released
+    }
+
+    /// note a live key event; ignored unless a take is being recorded
+    pub fn record(&mut self, keycode: Keycode, on: bool, velocity: f32, now: Instant) {
+        let (at, pass) = match self.state {
+            LooperState::Recording => (now.duration_since(self.started), self.pass),
+            LooperState::Overdubbing => (self.position(now), self.pass_at(now)),
+            _ => return,
+        };
+        if on {
+            self.recording_held.insert(keycode);
+        } else if !self.recording_held.remove(&keycode) {
+            // released a key that was pressed before the take began
+            return;
+        }
+        self.events.push(LoopEvent { at, keycode, on, velocity, layer: self.layers, pass });
+    }
+
+    /// events due since the last call. also ends a first take that reached the fixed length
+    pub fn poll(&mut self, now: Instant) -> Vec<LoopNote> {
+        if self.state == LooperState::Recording {
+            if let Some(fixed) = self.fixed_length
+                && now.duration_since(self.started) >= fixed
+            {
+                self.finish_first_take(self.started + fixed);
+            }
+            return vec![];
+        }
+        if !matches!(self.state, LooperState::Playing | LooperState::Overdubbing) {
+            return vec![];
+        }
+        let Some(length) = self.length else { return vec![]; };
+
+        let pass = self.pass_at(now);
+        let pos = self.position(now);
+        let mut due = vec![];
+
+        if pass != self.pass {
+            // wrapped: finish the old pass, then start the new one from zero
+            self.collect(self.last_pos, length, &mut due);
+            self.pass = pass;
+            self.collect(None, pos, &mut due);
+        } else {
+            self.collect(self.last_pos, pos, &mut due);
+        }
+        self.last_pos = Some(pos);
+        due
+    }
+
+    /// events in `(from, to]`, or `[0, to]` when `from` is `None`, in time order
+    fn collect(&mut self, from: Option<Duration>, to: Duration, due: &mut Vec<LoopNote>) {
+        let mut hits: Vec<&LoopEvent> = self
+            .events
+            .iter()
+            .filter(|e| from.is_none_or(|f| e.at > f) && e.at <= to && e.pass != self.pass)
+            .collect();
+        hits.sort_by_key(|e| e.at);
+
+        for e in hits {
+            if e.on {
+                self.sounding.insert(e.keycode);
+            } else {
+                self.sounding.remove(&e.keycode);
+            }
+            due.push(LoopNote { keycode: e.keycode, on: e.on, velocity: e.velocity });
+        }
+    }
+
+    fn finish_first_take(&mut self, end: Instant) {
+        let length = self.fixed_length.unwrap_or_else(|| end.duration_since(self.started));
+        self.length = Some(length);
+        self.close_held(length);
+        self.state = LooperState::Playing;
+        self.restart(end);
+    }
+
+    fn restart(&mut self, now: Instant) {
+        self.started = now;
+        self.pass = 0;
+        self.last_pos = None;
+        // every event is fair game again from the top
+        for e in self.events.iter_mut() {
+            e.pass = u64::MAX;
+        }
+    }
+
+    /// keys still held when a take ends get their release at `at`
+    fn close_held(&mut self, at: Duration) {
+        let layer = self.layers;
+        let pass = self.pass;
+        for keycode in self.recording_held.drain() {
+            self.events.push(LoopEvent { at, keycode, on: false, velocity: 0.0, layer, pass });
+        }
+    }
+
+    fn release_all(&mut self) -> Vec<LoopNote> {
+        self.sounding.drain().map(|keycode| LoopNote { keycode, on: false, velocity: 0.0 }).collect()
+    }
+}
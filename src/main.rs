@@ -1,13 +1,115 @@
+use std::path::PathBuf;
 use std::sync::{
     Arc,
     atomic::{AtomicBool, Ordering},
 };
 
-use synth_rs::{play::run_audio, audio_system::get_handle, ui::run_ui};
+use clap::{Parser, Subcommand};
+use synth_rs::{
+    play::{render, run_audio},
+    audio_system::get_handle,
+    settings::{Settings, load_settings, load_settings_from},
+};
 use tokio::sync::watch;
 
+mod ui;
+
+use ui::run_ui;
+use ui::text_mode::{run_status_lines, run_text_ui};
+
+/// terminal synth played from the computer keyboard. options override `config.toml`
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    /// output device, matched against part of its name
+    #[arg(long)]
+    device: Option<String>,
+    /// rate to open the output device at, in Hz
+    #[arg(long)]
+    sample_rate: Option<u32>,
+    /// patch to start on (sine, saw, square, triangle, noise, supersaw, fm, pluck)
+    #[arg(long)]
+    patch: Option<String>,
+    /// octave the `a` key plays
+    #[arg(long, allow_negative_numbers = true)]
+    octave: Option<i32>,
+    /// settings file to use instead of the one in the config dir
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// Rhai script to run with the transport (reloaded whenever it is saved)
+    #[arg(long, value_name = "FILE")]
+    script: Option<PathBuf>,
+    /// leave out the piano and level bars
+    #[arg(long)]
+    no_visualizer: bool,
+    /// screen-reader friendly line output instead of the full-screen TUI
+    #[arg(long, conflicts_with = "headless")]
+    text: bool,
+    /// no terminal UI at all; runs until ctrl-c
+    #[arg(long)]
+    headless: bool,
+    /// with --headless, print a line to stdout whenever the patch, notes, looper... change
+    #[arg(long, requires = "headless")]
+    status: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// play a MIDI file into a WAV file, faster than realtime, with the starting patch and effects
+    Render {
+        /// standard MIDI file; channel 10 plays the drums
+        midi: PathBuf,
+        /// where to write the WAV; next to the MIDI file by default
+        #[arg(long, value_name = "FILE")]
+        out: Option<PathBuf>,
+        /// saved preset to render with, over the config file's sound
+        #[arg(long)]
+        preset: Option<String>,
+    },
+}
+
+impl Cli {
+    fn settings(&self) -> Result<Settings, Box<dyn std::error::Error>> {
+        let mut settings = match &self.config {
+            Some(path) => load_settings_from(path)?,
+            None => load_settings()?,
+        };
+        if self.device.is_some() {
+            settings.audio.device = self.device.clone();
+        }
+        if self.sample_rate.is_some() {
+            settings.audio.sample_rate = self.sample_rate;
+        }
+        if self.patch.is_some() {
+            settings.audio.patch = self.patch.clone();
+        }
+        if let Some(octave) = self.octave {
+            settings.input.octave = octave;
+        }
+        if self.script.is_some() {
+            settings.script.file = self.script.clone();
+        }
+        if self.no_visualizer {
+            settings.ui.visualizer = false;
+        }
+        Ok(settings)
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+    let settings = cli.settings()?;
+    synth_rs::crash::init(&settings);
+    synth_rs::crash::note(format!("args: {:?}", std::env::args().skip(1).collect::<Vec<_>>()));
+    if let Some(Command::Render { midi, out, preset }) = &cli.command {
+        let out = out.clone().unwrap_or_else(|| midi.with_extension("wav"));
+        let length = render(&settings, preset.as_deref(), midi, &out).await?;
+        println!("wrote {} ({:.1} s)", out.display(), length.as_secs_f32());
+        return Ok(());
+    }
     let handle = get_handle().await.clone();
     let (shutdown_tx, shutdown_rx) = watch::channel(false);
 
@@ -18,22 +120,58 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         let shutdown_tx = shutdown_tx.clone();
         let handle = handle.clone();
         let focused = focused.clone();
+        let ui_settings = settings.ui.clone();
+        let config = cli.config.clone();
 
         async move {
-            let res = run_ui(handle, shutdown_tx.clone(), focused).await;
+            if cli.headless {
+                // nothing to draw; ctrl-c below ends the run
+                eprintln!("tjam running headless, ctrl-c to stop");
+                if cli.status {
+                    run_status_lines(handle.clone()).await;
+                }
+                std::future::pending::<()>().await;
+            }
+            let res = if cli.text {
+                run_text_ui(handle, shutdown_tx.clone()).await
+            } else {
+                run_ui(handle, shutdown_tx.clone(), focused, ui_settings, config).await
+            };
             let _ = shutdown_tx.send(true);
 
             res
         }
     };
 
-    let audio = run_audio(shutdown_rx, focused.clone());
+    #[cfg(unix)]
+    if settings.control.socket {
+        let path = settings.control.path.clone().or_else(synth_rs::control::default_socket_path);
+        if let Some(path) = path {
+            let control = synth_rs::control::run_control_socket(handle.clone(), path, shutdown_rx.clone());
+            tokio::spawn(async move {
+                if let Err(e) = control.await {
+                    synth_rs::crash::note(format!("control socket: {e}"));
+                    eprintln!("control socket: {e}");
+                }
+            });
+        }
+    }
+
+    let audio = run_audio(shutdown_rx, focused.clone(), settings);
+    tokio::pin!(audio);
 
     tokio::select! {
         _ = tokio::signal::ctrl_c() => {
             let _ = shutdown_tx.send(true);
+            // let it stop the keyboard poller, or the runtime waits on that thread forever
+            audio.await?;
+        }
+        // audio only stops first when it could not start (bad device, unknown patch)
+        res = &mut audio => res?,
+        res = ui => {
+            res?;
+            audio.await?;
         }
-        _ = async { tokio::join!(audio, ui) } => {}
     }
 
     Ok(())
@@ -0,0 +1,82 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use rodio::Source;
+
+use crate::audio_patch::{AtomicF32, SynthSource};
+use crate::config::METER_BLOCK;
+
+/// level readings published by a `LevelMeter`, shared with the control side
+#[derive(Debug, Default)]
+pub struct MeterTap {
+    peak: AtomicF32,
+    rms: AtomicF32,
+}
+
+impl MeterTap {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// highest absolute sample since the previous call (linear, 1.0 = full scale)
+    pub fn take_peak(&self) -> f32 {
+        self.peak.swap(0.0)
+    }
+
+    /// rms of the most recent block (linear)
+    pub fn rms(&self) -> f32 {
+        self.rms.load()
+    }
+}
+
+pub fn to_db(linear: f32) -> f32 {
+    20.0 * linear.max(1e-6).log10()
+}
+
+pub fn from_db(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// pass-through source that measures what flows through it, publishing once per `METER_BLOCK` samples
+pub struct LevelMeter {
+    input: SynthSource,
+    tap: Arc<MeterTap>,
+    block_peak: f32,
+    block_sum_sq: f32,
+    block_len: usize,
+}
+
+impl LevelMeter {
+    pub fn new(input: SynthSource, tap: Arc<MeterTap>) -> Self {
+        Self { input, tap, block_peak: 0.0, block_sum_sq: 0.0, block_len: 0 }
+    }
+}
+
+impl Iterator for LevelMeter {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let x = self.input.next()?;
+
+        self.block_peak = self.block_peak.max(x.abs());
+        self.block_sum_sq += x * x;
+        self.block_len += 1;
+
+        if self.block_len >= METER_BLOCK {
+            self.tap.peak.fetch_max(self.block_peak);
+            self.tap.rms.store((self.block_sum_sq / self.block_len as f32).sqrt());
+            self.block_peak = 0.0;
+            self.block_sum_sq = 0.0;
+            self.block_len = 0;
+        }
+
+        Some(x)
+    }
+}
+
+impl Source for LevelMeter {
+    fn current_span_len(&self) -> Option<usize> { self.input.current_span_len() }
+    fn channels(&self) -> u16 { self.input.channels() }
+    fn sample_rate(&self) -> u32 { self.input.sample_rate() }
+    fn total_duration(&self) -> Option<Duration> { self.input.total_duration() }
+}
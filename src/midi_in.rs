@@ -0,0 +1,177 @@
+//! MIDI controllers: their knobs (control changes) move whatever parameter MIDI learn bound them
+//! to, a sustain pedal (CC 64) holds notes like space does, the pitch wheel bends like the arrow
+//! keys, and a drum machine's or DAW's clock, start and stop drive the transport. notes from the
+//! controller are not played
+
+use std::collections::VecDeque;
+
+use midir::{Ignore, MidiInput, MidiInputConnection};
+use serde::{Deserialize, Serialize};
+
+use crate::audio_patch::{ParamDesc, ParamValue};
+use crate::audio_system::{AudioHandle, TransportAction};
+use crate::config::{
+    MIDI_BEND_CENTER, MIDI_CLIENT_NAME, MIDI_CC_MAX, MIDI_CLOCK_BPM_STEP, MIDI_CLOCK_PPQ, MIDI_CLOCK_TIMEOUT_US, MIDI_SUSTAIN_CC,
+    MIDI_SUSTAIN_DOWN,
+};
+use crate::settings::MidiSettings;
+
+/// one knob bound to one parameter, named as `SetParam` names it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CcBinding {
+    /// 1..16
+    pub channel: u8,
+    pub cc: u8,
+    pub owner: String,
+    pub id: String,
+}
+
+impl CcBinding {
+    pub fn is_knob(&self, channel: u8, cc: u8) -> bool {
+        self.channel == channel && self.cc == cc
+    }
+
+    pub fn is_param(&self, owner: &str, id: &str) -> bool {
+        self.owner.eq_ignore_ascii_case(owner) && self.id == id
+    }
+}
+
+/// a control change's channel (1..16), controller and value; anything else is None
+pub fn parse_cc(msg: &[u8]) -> Option<(u8, u8, u8)> {
+    match *msg {
+        [status, cc, value, ..] if status & 0xF0 == 0xB0 => Some(((status & 0x0F) + 1, cc & 0x7F, value & 0x7F)),
+        _ => None,
+    }
+}
+
+/// a pitch wheel's position as -1..1, 0 at rest; anything else is None
+pub fn parse_bend(msg: &[u8]) -> Option<f32> {
+    match *msg {
+        [status, lsb, msb, ..] if status & 0xF0 == 0xE0 => {
+            let value = u16::from(lsb & 0x7F) | u16::from(msb & 0x7F) << 7;
+            let offset = f32::from(value) - f32::from(MIDI_BEND_CENTER);
+            // the wheel goes one step further down than up
+            Some((offset / f32::from(MIDI_BEND_CENTER - 1)).clamp(-1.0, 1.0))
+        }
+        _ => None,
+    }
+}
+
+/// a knob's 0..127 across the parameter's range; a switch flips at the halfway point
+pub fn cc_value(desc: &ParamDesc, value: u8) -> ParamValue {
+    let t = f32::from(value.min(MIDI_CC_MAX)) / f32::from(MIDI_CC_MAX);
+    match desc.value {
+        ParamValue::Bool(_) => ParamValue::Bool(t >= 0.5),
+        ParamValue::Float(_) => ParamValue::Float(desc.min + (desc.max - desc.min) * t),
+    }
+}
+
+/// works out the tempo of an external MIDI clock from the gaps between its ticks, averaged over
+/// the last beat so a jittery cable does not make it wobble
+#[derive(Debug, Default)]
+pub struct ClockFollower {
+    /// when the last few ticks came, in microseconds
+    ticks: VecDeque<u64>,
+    bpm: Option<f32>,
+}
+
+impl ClockFollower {
+    /// one tick at `micros`. gives the tempo once a whole beat has been heard, then again
+    /// whenever it moves by `MIDI_CLOCK_BPM_STEP` or more
+    pub fn tick(&mut self, micros: u64) -> Option<f32> {
+        // a clock that stopped and came back starts a fresh count
+        if self.ticks.back().is_some_and(|last| micros.saturating_sub(*last) > MIDI_CLOCK_TIMEOUT_US) {
+            self.ticks.clear();
+        }
+        self.ticks.push_back(micros);
+        if self.ticks.len() > MIDI_CLOCK_PPQ + 1 {
+            self.ticks.pop_front();
+        }
+        let (first, last) = (*self.ticks.front()?, *self.ticks.back()?);
+        if self.ticks.len() <= MIDI_CLOCK_PPQ || last <= first {
+            return None;
+        }
+        let beat_us = (last - first) as f64 * MIDI_CLOCK_PPQ as f64 / (self.ticks.len() - 1) as f64;
+        let bpm = (60e6 / beat_us) as f32;
+        if self.bpm.is_some_and(|old| (bpm - old).abs() < MIDI_CLOCK_BPM_STEP) {
+            return None;
+        }
+        self.bpm = Some(bpm);
+        Some(bpm)
+    }
+}
+
+/// opens every input port whose name contains `settings.port` (all of them when unset, ignoring
+/// case) and hands their control changes to the engine, and with `settings.clock` their clock,
+/// start and stop to the transport. they stay open until the connections are dropped
+pub fn listen(settings: &MidiSettings, handle: &AudioHandle) -> Result<Vec<MidiInputConnection<()>>, Box<dyn std::error::Error>> {
+    let probe = MidiInput::new(MIDI_CLIENT_NAME)?;
+    let wanted = settings.port.as_deref().map(str::to_lowercase);
+    let mut connections = vec![];
+    for port in probe.ports() {
+        let name = probe.port_name(&port)?;
+        if wanted.as_ref().is_some_and(|w| !name.to_lowercase().contains(w)) {
+            continue;
+        }
+        // one client per port: connecting uses it up
+        let mut input = MidiInput::new(MIDI_CLIENT_NAME)?;
+        input.ignore(if settings.clock { Ignore::SysexAndActiveSense } else { Ignore::All });
+        let handle = handle.clone();
+        let mut clock = ClockFollower::default();
+        let on_message = move |micros: u64, msg: &[u8], _: &mut ()| match msg.first() {
+            Some(0xF8) => {
+                if let Some(bpm) = clock.tick(micros) {
+                    handle.set_tempo(bpm);
+                }
+            }
+            // the transport has no position to carry on from, so continue starts it as well
+            Some(0xFA | 0xFB) => handle.transport(TransportAction::Start),
+            Some(0xFC) => handle.transport(TransportAction::Stop),
+            Some(status) if status & 0xF0 == 0xE0 => {
+                if let Some(amount) = parse_bend(msg) {
+                    handle.set_bend(amount);
+                }
+            }
+            _ => match parse_cc(msg) {
+                // the pedal is the pedal on every channel, never a knob to learn
+                Some((_, MIDI_SUSTAIN_CC, value)) => handle.set_sustain(value >= MIDI_SUSTAIN_DOWN),
+                Some((channel, cc, value)) => handle.midi_cc(channel, cc, value),
+                None => {}
+            },
+        };
+        connections.push(input.connect(&port, &name, on_message, ())?);
+    }
+    Ok(connections)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bend_is_centered_at_8192() {
+        assert_eq!(parse_bend(&[0xE0, 0x00, 0x40]), Some(0.0));
+        assert_eq!(parse_bend(&[0xE3, 0x7F, 0x7F]), Some(1.0));
+        assert_eq!(parse_bend(&[0xE0, 0x00, 0x00]), Some(-1.0));
+        let quarter = parse_bend(&[0xE0, 0x00, 0x50]).unwrap();
+        assert!((quarter - 2048.0 / 8191.0).abs() < 1e-6, "{quarter}");
+        assert_eq!(parse_bend(&[0xB0, 0x40, 0x7F]), None);
+        assert_eq!(parse_bend(&[0xE0, 0x00]), None);
+    }
+
+    #[test]
+    fn clock_tempo_is_averaged_over_a_beat() {
+        let mut clock = ClockFollower::default();
+        // 120 bpm is a tick every 20833 µs; every other one comes 500 µs late
+        let at = |i: u64| i * 20_833 + if i % 2 == 1 { 500 } else { 0 };
+        let heard: Vec<f32> = (0..=48).filter_map(|i| clock.tick(at(i))).collect();
+        assert_eq!(heard.len(), 1, "{heard:?}");
+        assert!((heard[0] - 120.0).abs() < 0.1, "{heard:?}");
+
+        // a pause longer than the timeout starts over, at the new tempo
+        let restart = at(48) + 1_000_000;
+        let heard: Vec<f32> = (0..=24).filter_map(|i| clock.tick(restart + i * 25_000)).collect();
+        assert_eq!(heard.len(), 1, "{heard:?}");
+        assert!((heard[0] - 100.0).abs() < 0.1, "{heard:?}");
+    }
+}
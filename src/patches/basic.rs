@@ -1,10 +1,15 @@
+use std::f32::consts::TAU;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
 use std::time::Duration;
 
 use rodio::Source;
-use rodio::source::{SineWave, SquareWave, TriangleWave, SawtoothWave};
 
-use crate::audio_patch::{AudioSource, SynthSource};
-use crate::config::{AMP_DEFAULT, ENDLESS, SAMPLE_RATE};
+use crate::audio_patch::{AtomicF32, AudioSource, FreqMul, Generator, ParamDesc, ParamValue, PatchSource, SynthSource, Unison, find_param};
+use crate::patches::polyblep;
+use crate::config::{
+    AMP_DEFAULT, ENDLESS, SAMPLE_RATE, SUB_OCTAVES_DEFAULT, SUB_OCTAVES_MAX, SUPERSAW_DETUNE_CENTS, SUPERSAW_VOICES,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BasicKind {
@@ -37,17 +42,68 @@ impl BasicKind {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+/// the noise patch's spectrum
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NoiseColor {
+    /// flat, hissy
+    #[default]
+    White,
+    /// -3 dB an octave, equal energy per octave: rain, surf
+    Pink,
+    /// -6 dB an octave, a random walk: rumble, wind
+    Brown,
+}
+
+impl NoiseColor {
+    const ALL: [NoiseColor; 3] = [NoiseColor::White, NoiseColor::Pink, NoiseColor::Brown];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            NoiseColor::White => "white",
+            NoiseColor::Pink => "pink",
+            NoiseColor::Brown => "brown",
+        }
+    }
+
+    fn index(self) -> usize {
+        Self::ALL.iter().position(|c| *c == self).unwrap_or(0)
+    }
+
+    fn from_index(i: usize) -> Self {
+        Self::ALL[i.min(Self::ALL.len() - 1)]
+    }
+}
+
+#[derive(Debug, Clone)]
 struct NoiseParams {
     seed: u64,
     sample_rate: u32,
+    /// `NoiseColor` index, shared so new notes pick up a change
+    color: Arc<AtomicU8>,
+}
+
+impl NoiseParams {
+    fn color(&self) -> NoiseColor {
+        NoiseColor::from_index(self.color.load(Ordering::Relaxed) as usize)
+    }
+}
+
+/// the oscillators' sub-oscillator: the same wave one or two octaves down, mixed in under the note
+#[derive(Debug)]
+struct SubParams {
+    octaves: AtomicF32,
+    /// 0 is off
+    level: AtomicF32,
 }
 
 pub fn basic_source(kind: BasicKind) -> Box<dyn AudioSource> {
+    let sub = (kind != BasicKind::Noise)
+        .then(|| Arc::new(SubParams { octaves: AtomicF32::new(SUB_OCTAVES_DEFAULT), level: AtomicF32::new(0.0) }));
     let noise = if kind == BasicKind::Noise {
         Some(NoiseParams {
             seed: 0x1234_5678_9ABC_DEF0,
             sample_rate: SAMPLE_RATE,
+            color: Arc::new(AtomicU8::new(NoiseColor::White.index() as u8)),
         })
     } else {
         None
@@ -58,68 +114,166 @@ pub fn basic_source(kind: BasicKind) -> Box<dyn AudioSource> {
         amplitude: AMP_DEFAULT,
         duration: ENDLESS,
         noise,
+        sub,
     })
 }
 
+/// detuned saw stack, the classic trance lead/pad
+pub fn supersaw_source() -> Box<dyn AudioSource> {
+    let patch = PatchSource::new(Box::new(BasicGenerator(BasicKind::Saw)))
+        .with_unison(Unison { voices: SUPERSAW_VOICES, detune_cents: SUPERSAW_DETUNE_CENTS })
+        .named("Supersaw");
+    Box::new(patch)
+}
+
+/// the basic waveforms as a patch generator
+pub struct BasicGenerator(pub BasicKind);
+
+impl Generator for BasicGenerator {
+    fn create(&self, frequency: f32, freq_mul: FreqMul) -> SynthSource {
+        basic_source(self.0).create_source(frequency, freq_mul)
+    }
+
+    fn name(&self) -> &'static str {
+        self.0.name()
+    }
+}
+
 struct BasicSource {
     kind: BasicKind,
     amplitude: f32,
     duration: Duration,
     noise: Option<NoiseParams>,
+    sub: Option<Arc<SubParams>>,
 }
 
 impl AudioSource for BasicSource {
-    fn create_source(&self, frequency: f32) -> SynthSource {
+    fn create_source(&self, frequency: f32, freq_mul: FreqMul) -> SynthSource {
         match self.kind {
-            BasicKind::Sine => Box::new(
-                SineWave::new(frequency)
-                    .amplify(self.amplitude)
-                    .take_duration(self.duration),
-            ),
-
-            BasicKind::Square => Box::new(
-                SquareWave::new(frequency)
-                    .amplify(self.amplitude)
-                    .take_duration(self.duration),
-            ),
-
-            BasicKind::Triangle => Box::new(
-                TriangleWave::new(frequency)
-                    .amplify(self.amplitude)
-                    .take_duration(self.duration),
-            ),
-
-            BasicKind::Saw => Box::new(
-                SawtoothWave::new(frequency)
-                    .amplify(self.amplitude)
-                    .take_duration(self.duration),
-            ),
-
             BasicKind::Noise => {
-                let p = self.noise.expect("Noise params missing for BasicKind::Noise");
+                let p = self.noise.as_ref().expect("Noise params missing for BasicKind::Noise");
 
                 Box::new(
-                    NoiseGen::new(p.seed, p.sample_rate)
+                    NoiseGen::new(p.seed, p.sample_rate, p.color())
                         .amplify(self.amplitude)
                         .take_duration(self.duration),
                 )
             }
+
+            kind => Box::new(
+                Oscillator::new(kind, frequency, freq_mul, SAMPLE_RATE)
+                    .with_sub(self.sub.as_deref())
+                    .amplify(self.amplitude)
+                    .take_duration(self.duration),
+            ),
         }
     }
 
     fn name(&self) -> &'static str {
         self.kind.name()
     }
+
+    /// the oscillators' sub, which new notes pick up; the noise patch's color, as its index:
+    /// 0 white, 1 pink, 2 brown
+    fn params(&self) -> Vec<ParamDesc> {
+        if let Some(sub) = &self.sub {
+            return vec![
+                ParamDesc::float("sub_octaves", sub.octaves.load(), 1.0, SUB_OCTAVES_MAX, 1.0),
+                ParamDesc::float("sub_level", sub.level.load(), 0.0, 1.0, 0.05),
+            ];
+        }
+        let Some(noise) = &self.noise else { return vec![]; };
+        vec![ParamDesc::float("color", noise.color().index() as f32, 0.0, (NoiseColor::ALL.len() - 1) as f32, 1.0)]
+    }
+
+    fn set_param(&self, id: &str, value: ParamValue) -> Result<(), Box<dyn std::error::Error>> {
+        let v = find_param(&self.params(), self.name(), id)?.clamp(value)?;
+        match (id, &self.sub, &self.noise) {
+            ("sub_octaves", Some(sub), _) => sub.octaves.store(v.round()),
+            ("sub_level", Some(sub), _) => sub.level.store(v),
+            (_, _, Some(noise)) => noise.color.store(v.round() as u8, Ordering::Relaxed),
+            _ => {}
+        }
+        Ok(())
+    }
 }
 
+/// periodic waveform whose pitch is `frequency * freq_mul`, re-read every sample.
+/// saw/square/triangle are band-limited with polyBLEP so high notes don't alias
+struct Oscillator {
+    kind: BasicKind,
+    frequency: f32,
+    freq_mul: FreqMul,
+    phase: f32,
+    sr: u32,
+    /// the sub's frequency as a fraction of the note's, its level on top of the note's, its phase
+    sub_ratio: f32,
+    sub_level: f32,
+    sub_phase: f32,
+}
+
+impl Oscillator {
+    fn new(kind: BasicKind, frequency: f32, freq_mul: FreqMul, sr: u32) -> Self {
+        Self { kind, frequency, freq_mul, phase: 0.0, sr, sub_ratio: 0.5, sub_level: 0.0, sub_phase: 0.0 }
+    }
+
+    /// takes the sub's settings as they are now
+    fn with_sub(mut self, sub: Option<&SubParams>) -> Self {
+        if let Some(sub) = sub {
+            self.sub_ratio = 0.5f32.powf(sub.octaves.load());
+            self.sub_level = sub.level.load();
+        }
+        self
+    }
+
+    fn wave(&self, p: f32, dt: f32) -> f32 {
+        match self.kind {
+            BasicKind::Sine => (TAU * p).sin(),
+            BasicKind::Saw => polyblep::saw(p, dt),
+            BasicKind::Square => polyblep::square(p, dt),
+            BasicKind::Triangle => polyblep::triangle(p, dt),
+            BasicKind::Noise => 0.0,
+        }
+    }
+}
+
+impl Iterator for Oscillator {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let dt = self.frequency * self.freq_mul.load() / self.sr as f32;
+        let mut y = self.wave(self.phase, dt);
+        self.phase = (self.phase + dt).rem_euclid(1.0);
+        if self.sub_level > 0.0 {
+            let sub_dt = dt * self.sub_ratio;
+            y += self.sub_level * self.wave(self.sub_phase, sub_dt);
+            self.sub_phase = (self.sub_phase + sub_dt).rem_euclid(1.0);
+        }
+        Some(y)
+    }
+}
+
+impl Source for Oscillator {
+    fn current_span_len(&self) -> Option<usize> { None }
+    fn channels(&self) -> u16 { 1 }
+    fn sample_rate(&self) -> u32 { self.sr }
+    fn total_duration(&self) -> Option<Duration> { None }
+}
+
+/// white noise from a xorshift, optionally filtered: pink through Paul Kellet's refined
+/// filter (seven one-poles summed, within 0.05 dB of -3 dB/octave above 9 Hz), brown through a
+/// leaky integrator so it wanders without drifting off. all three come out about as loud (same RMS)
 struct NoiseGen {
     rng: u64,
     sr: u32,
+    color: NoiseColor,
+    pink: [f32; 7],
+    brown: f32,
 }
 
 impl NoiseGen {
-    fn new(seed: u64, sr: u32) -> Self {
-        Self { rng: seed, sr }
+    fn new(seed: u64, sr: u32, color: NoiseColor) -> Self {
+        Self { rng: seed, sr, color, pink: [0.0; 7], brown: 0.0 }
     }
 
     fn next_noise(&mut self) -> f32 {
@@ -140,7 +294,26 @@ impl Iterator for NoiseGen {
     type Item = f32;
 
     fn next(&mut self) -> Option<f32> {
-        Some(self.next_noise())
+        let white = self.next_noise();
+        Some(match self.color {
+            NoiseColor::White => white,
+            NoiseColor::Pink => {
+                let b = &mut self.pink;
+                b[0] = 0.99886 * b[0] + white * 0.0555179;
+                b[1] = 0.99332 * b[1] + white * 0.0750759;
+                b[2] = 0.96900 * b[2] + white * 0.153852;
+                b[3] = 0.86650 * b[3] + white * 0.3104856;
+                b[4] = 0.55000 * b[4] + white * 0.5329522;
+                b[5] = -0.7616 * b[5] - white * 0.0168980;
+                let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+                b[6] = white * 0.115926;
+                pink * 0.32
+            }
+            NoiseColor::Brown => {
+                self.brown = (self.brown + 0.02 * white) / 1.02;
+                self.brown * 10.0
+            }
+        })
     }
 }
 
@@ -0,0 +1,158 @@
+use std::f32::consts::TAU;
+use std::time::Duration;
+
+use device_query::Keycode;
+use rodio::Source;
+
+use crate::audio_patch::SynthSource;
+use crate::config::{
+    DRUM_AMP, HAT_CUTOFF_HZ, HAT_DECAY_S, KICK_DECAY_S, KICK_END_HZ, KICK_START_HZ, KICK_SWEEP_S, SAMPLE_RATE,
+    SNARE_NOISE_CUTOFF_HZ, SNARE_NOISE_DECAY_S, SNARE_TONE_DECAY_S, SNARE_TONE_HZ, VELOCITY_DEFAULT,
+};
+
+/// a synthesized drum. each one is a one-shot with its own short envelope: the global ADSR,
+/// the patch and the gate don't touch it, it rings out and ends by itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DrumKind {
+    /// pitch-swept sine
+    Kick,
+    /// a short tone under a burst of bright noise
+    Snare,
+    /// highpassed noise, very short
+    Hat,
+}
+
+impl DrumKind {
+    /// the drum row: 1, 2 and 3 above the keyboard
+    pub fn from_keycode(k: Keycode) -> Option<Self> {
+        match k {
+            Keycode::Key1 => Some(DrumKind::Kick),
+            Keycode::Key2 => Some(DrumKind::Snare),
+            Keycode::Key3 => Some(DrumKind::Hat),
+            _ => None,
+        }
+    }
+
+    /// General MIDI drum note, for recordings
+    pub fn midi_note(self) -> u8 {
+        match self {
+            DrumKind::Kick => 36,
+            DrumKind::Snare => 38,
+            DrumKind::Hat => 42,
+        }
+    }
+
+    /// the drum a General MIDI note is closest to: kicks, snares and rims, the rest as hats
+    pub fn from_midi_note(note: u8) -> Option<Self> {
+        match note {
+            35 | 36 => Some(DrumKind::Kick),
+            37..=40 => Some(DrumKind::Snare),
+            41..=81 => Some(DrumKind::Hat),
+            _ => None,
+        }
+    }
+
+    /// the longest of its envelopes, which sets how long it rings
+    fn decay_s(self) -> f32 {
+        match self {
+            DrumKind::Kick => KICK_DECAY_S,
+            DrumKind::Snare => SNARE_NOISE_DECAY_S.max(SNARE_TONE_DECAY_S),
+            DrumKind::Hat => HAT_DECAY_S,
+        }
+    }
+}
+
+/// one hit at `velocity` (0..1), ending once it has decayed below -80 dB
+pub fn drum_source(kind: DrumKind, velocity: f32) -> SynthSource {
+    let sr = SAMPLE_RATE;
+    // e^-9.2 is -80 dB
+    let len = (kind.decay_s() * 9.2 * sr as f32) as usize;
+    let lowpass = |hz: f32| 1.0 - (-TAU * hz / sr as f32).exp();
+    Box::new(DrumVoice {
+        kind,
+        gain: DRUM_AMP * velocity / VELOCITY_DEFAULT,
+        n: 0,
+        len,
+        phase: 0.0,
+        rng: 0x2545_F491_4F6C_DD1D,
+        low: 0.0,
+        coeff: match kind {
+            DrumKind::Snare => lowpass(SNARE_NOISE_CUTOFF_HZ),
+            _ => lowpass(HAT_CUTOFF_HZ),
+        },
+        sr,
+    })
+}
+
+struct DrumVoice {
+    kind: DrumKind,
+    gain: f32,
+    n: usize,
+    len: usize,
+    phase: f32,
+    rng: u64,
+    /// one-pole lowpass of the noise; noise minus this is the highpassed part
+    low: f32,
+    coeff: f32,
+    sr: u32,
+}
+
+impl DrumVoice {
+    fn noise(&mut self) -> f32 {
+        let mut x = self.rng;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.rng = x;
+        let u = (x.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 40) as u32;
+        2.0 * (u as f32 / (1u32 << 24) as f32) - 1.0
+    }
+
+    fn highpassed_noise(&mut self) -> f32 {
+        let x = self.noise();
+        self.low += (x - self.low) * self.coeff;
+        x - self.low
+    }
+
+    fn sine(&mut self, hz: f32) -> f32 {
+        let y = (TAU * self.phase).sin();
+        self.phase = (self.phase + hz / self.sr as f32).fract();
+        y
+    }
+}
+
+impl Iterator for DrumVoice {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.n >= self.len {
+            return None;
+        }
+        let t = self.n as f32 / self.sr as f32;
+        self.n += 1;
+        let env = |decay_s: f32| (-t / decay_s).exp();
+
+        let y = match self.kind {
+            DrumKind::Kick => {
+                let hz = KICK_END_HZ + (KICK_START_HZ - KICK_END_HZ) * env(KICK_SWEEP_S);
+                self.sine(hz) * env(KICK_DECAY_S)
+            }
+            DrumKind::Snare => {
+                let tone = self.sine(SNARE_TONE_HZ) * env(SNARE_TONE_DECAY_S);
+                let noise = self.highpassed_noise() * env(SNARE_NOISE_DECAY_S);
+                0.5 * tone + noise
+            }
+            DrumKind::Hat => self.highpassed_noise() * env(HAT_DECAY_S),
+        };
+        Some(y * self.gain)
+    }
+}
+
+impl Source for DrumVoice {
+    fn current_span_len(&self) -> Option<usize> { None }
+    fn channels(&self) -> u16 { 1 }
+    fn sample_rate(&self) -> u32 { self.sr }
+    fn total_duration(&self) -> Option<Duration> {
+        Some(Duration::from_secs_f32(self.len as f32 / self.sr as f32))
+    }
+}
@@ -0,0 +1,109 @@
+use std::f32::consts::TAU;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rodio::Source;
+
+use crate::audio_patch::{AtomicF32, AudioSource, FreqMul, ParamDesc, ParamValue, SynthSource, find_param};
+use crate::config::{AMP_DEFAULT, ENDLESS, FM_INDEX_DEFAULT, FM_INDEX_MAX, FM_RATIO_DEFAULT, FM_RATIO_MAX, SAMPLE_RATE};
+
+/// two-operator FM: a sine modulator at `frequency * ratio` bending the phase of a sine carrier.
+/// `index` is the peak phase deviation in radians, the higher it is the brighter the tone
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FmParams {
+    pub ratio: f32,
+    pub index: f32,
+}
+
+impl Default for FmParams {
+    fn default() -> Self {
+        Self { ratio: FM_RATIO_DEFAULT, index: FM_INDEX_DEFAULT }
+    }
+}
+
+pub fn fm_source(params: FmParams) -> Box<dyn AudioSource> {
+    Box::new(FmSource {
+        params: Arc::new(FmShared { ratio: AtomicF32::new(params.ratio), index: AtomicF32::new(params.index) }),
+        amplitude: AMP_DEFAULT,
+        duration: ENDLESS,
+    })
+}
+
+/// the patch's ratio and index, read by every sounding voice
+#[derive(Debug)]
+struct FmShared {
+    ratio: AtomicF32,
+    index: AtomicF32,
+}
+
+struct FmSource {
+    params: Arc<FmShared>,
+    amplitude: f32,
+    duration: Duration,
+}
+
+impl AudioSource for FmSource {
+    fn create_source(&self, frequency: f32, freq_mul: FreqMul) -> SynthSource {
+        Box::new(
+            FmOsc::new(self.params.clone(), frequency, freq_mul, SAMPLE_RATE)
+                .amplify(self.amplitude)
+                .take_duration(self.duration),
+        )
+    }
+
+    fn name(&self) -> &'static str {
+        "FM"
+    }
+
+    fn params(&self) -> Vec<ParamDesc> {
+        vec![
+            ParamDesc::float("ratio", self.params.ratio.load(), 0.25, FM_RATIO_MAX, 0.25),
+            ParamDesc::float("index", self.params.index.load(), 0.0, FM_INDEX_MAX, 0.25),
+        ]
+    }
+
+    fn set_param(&self, id: &str, value: ParamValue) -> Result<(), Box<dyn std::error::Error>> {
+        let v = find_param(&self.params(), self.name(), id)?.clamp(value)?;
+        match id {
+            "ratio" => self.params.ratio.store(v),
+            _ => self.params.index.store(v),
+        }
+        Ok(())
+    }
+}
+
+struct FmOsc {
+    params: Arc<FmShared>,
+    frequency: f32,
+    freq_mul: FreqMul,
+    carrier_phase: f32,
+    mod_phase: f32,
+    sr: u32,
+}
+
+impl FmOsc {
+    fn new(params: Arc<FmShared>, frequency: f32, freq_mul: FreqMul, sr: u32) -> Self {
+        Self { params, frequency, freq_mul, carrier_phase: 0.0, mod_phase: 0.0, sr }
+    }
+}
+
+impl Iterator for FmOsc {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let modulator = (TAU * self.mod_phase).sin();
+        let y = (TAU * self.carrier_phase + self.params.index.load() * modulator).sin();
+
+        let step = self.frequency * self.freq_mul.load() / self.sr as f32;
+        self.carrier_phase = (self.carrier_phase + step).rem_euclid(1.0);
+        self.mod_phase = (self.mod_phase + step * self.params.ratio.load()).rem_euclid(1.0);
+        Some(y)
+    }
+}
+
+impl Source for FmOsc {
+    fn current_span_len(&self) -> Option<usize> { None }
+    fn channels(&self) -> u16 { 1 }
+    fn sample_rate(&self) -> u32 { self.sr }
+    fn total_duration(&self) -> Option<Duration> { None }
+}
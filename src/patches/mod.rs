@@ -1 +1,7 @@
 pub mod basic;
+pub mod drums;
+pub mod fm;
+pub mod pluck;
+pub mod polyblep;
+pub mod soundfont;
+pub mod sampler;
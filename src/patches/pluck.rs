@@ -0,0 +1,148 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use rodio::Source;
+
+use crate::audio_patch::{AtomicF32, AudioSource, FreqMul, ParamDesc, ParamValue, SynthSource, find_param};
+use crate::config::{
+    AMP_DEFAULT, ENDLESS, PLUCK_BEND_HEADROOM, PLUCK_DAMPING_DEFAULT, PLUCK_DECAY_DEFAULT, PLUCK_DECAY_MIN, SAMPLE_RATE,
+    VELOCITY_DEFAULT,
+};
+
+/// Karplus–Strong plucked string: a burst of noise circulating in a delay line one period long,
+/// averaged with its neighbour on every pass so the highs die first, like a guitar or harp string
+pub fn pluck_source() -> Box<dyn AudioSource> {
+    Box::new(PluckSource {
+        params: Arc::new(PluckShared {
+            damping: AtomicF32::new(PLUCK_DAMPING_DEFAULT),
+            decay: AtomicF32::new(PLUCK_DECAY_DEFAULT),
+            plucks: AtomicU64::new(0),
+        }),
+        amplitude: AMP_DEFAULT,
+        duration: ENDLESS,
+    })
+}
+
+/// the patch's settings, read by every sounding string
+#[derive(Debug)]
+struct PluckShared {
+    /// 0 rings bright, 1 loses its highs within a few periods
+    damping: AtomicF32,
+    /// gain on every pass round the loop, so lower notes ring longer
+    decay: AtomicF32,
+    /// seeds each note's burst, so repeated notes don't sound sampled
+    plucks: AtomicU64,
+}
+
+struct PluckSource {
+    params: Arc<PluckShared>,
+    amplitude: f32,
+    duration: Duration,
+}
+
+impl AudioSource for PluckSource {
+    fn create_source(&self, frequency: f32, freq_mul: FreqMul) -> SynthSource {
+        self.create_source_with_velocity(frequency, freq_mul, VELOCITY_DEFAULT)
+    }
+
+    /// harder plucks are louder and brighter: the burst is filtered less
+    fn create_source_with_velocity(&self, frequency: f32, freq_mul: FreqMul, velocity: f32) -> SynthSource {
+        let seed = self.params.plucks.fetch_add(1, Ordering::Relaxed);
+        let string = PluckString::new(self.params.clone(), frequency, freq_mul, velocity.clamp(0.05, 1.0), seed);
+        Box::new(
+            string
+                .amplify(self.amplitude * velocity / VELOCITY_DEFAULT)
+                .take_duration(self.duration),
+        )
+    }
+
+    fn name(&self) -> &'static str {
+        "Pluck"
+    }
+
+    fn params(&self) -> Vec<ParamDesc> {
+        vec![
+            ParamDesc::float("damping", self.params.damping.load(), 0.0, 1.0, 0.05),
+            ParamDesc::float("decay", self.params.decay.load(), PLUCK_DECAY_MIN, 1.0, 0.001),
+        ]
+    }
+
+    fn set_param(&self, id: &str, value: ParamValue) -> Result<(), Box<dyn std::error::Error>> {
+        let v = find_param(&self.params(), self.name(), id)?.clamp(value)?;
+        match id {
+            "damping" => self.params.damping.store(v),
+            _ => self.params.decay.store(v),
+        }
+        Ok(())
+    }
+}
+
+struct PluckString {
+    params: Arc<PluckShared>,
+    frequency: f32,
+    freq_mul: FreqMul,
+    /// the delay line, a ring; long enough for the note bent `PLUCK_BEND_HEADROOM` times lower
+    line: Vec<f32>,
+    pos: usize,
+    sr: u32,
+}
+
+impl PluckString {
+    fn new(params: Arc<PluckShared>, frequency: f32, freq_mul: FreqMul, velocity: f32, seed: u64) -> Self {
+        let sr = SAMPLE_RATE;
+        let period = sr as f32 / frequency.max(1.0);
+        let mut line = vec![0.0; (period * PLUCK_BEND_HEADROOM) as usize + 2];
+
+        // the burst fills the last period of the line, lowpassed more for softer plucks
+        let burst = (period.ceil() as usize).min(line.len());
+        let start = line.len() - burst;
+        let mut rng = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+        let mut smooth = 0.0;
+        for x in &mut line[start..] {
+            rng ^= rng >> 12;
+            rng ^= rng << 25;
+            rng ^= rng >> 27;
+            let noise = (rng.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 40) as f32 / (1u32 << 24) as f32 * 2.0 - 1.0;
+            smooth += (noise - smooth) * velocity;
+            *x = smooth;
+        }
+        // no DC, or the string would ring around an offset
+        let mean = line[start..].iter().sum::<f32>() / burst as f32;
+        line[start..].iter_mut().for_each(|x| *x -= mean);
+
+        Self { params, frequency, freq_mul, line, pos: 0, sr }
+    }
+}
+
+impl Iterator for PluckString {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let len = self.line.len();
+        // the averaging lowpass delays the loop by `toward` samples; damping 1 is the classic
+        // two-point mean
+        let toward = 0.5 * self.params.damping.load();
+        // one period back less that, fractional so the tuning holds on high notes and bends glide
+        let period = self.sr as f32 / (self.frequency * self.freq_mul.load()).max(1.0) - toward;
+        let period = period.clamp(2.0, (len - 3) as f32);
+        let whole = period as usize;
+        let frac = period - whole as f32;
+        let a = self.line[(self.pos + len - whole) % len];
+        let b = self.line[(self.pos + len - whole - 1) % len];
+        let c = self.line[(self.pos + len - whole - 2) % len];
+        let y = a + (b - a) * frac;
+        let fed = (y + ((b + (c - b) * frac) - y) * toward) * self.params.decay.load();
+
+        self.line[self.pos] = fed;
+        self.pos = (self.pos + 1) % len;
+        Some(y)
+    }
+}
+
+impl Source for PluckString {
+    fn current_span_len(&self) -> Option<usize> { None }
+    fn channels(&self) -> u16 { 1 }
+    fn sample_rate(&self) -> u32 { self.sr }
+    fn total_duration(&self) -> Option<Duration> { None }
+}
@@ -0,0 +1,144 @@
+//! WAV sample playback. every key plays the whole sample, resampled so the root note sounds as
+//! recorded and the others move up or down from it. there is no time-stretch, so higher notes are
+//! shorter, like tape. the note ends with the sample or with the ADSR's release, whichever is first
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rodio::{Decoder, Source};
+use serde::{Deserialize, Serialize};
+
+use crate::audio_patch::{AudioSource, FreqMul, SynthSource};
+use crate::config::{AMP_DEFAULT, KEYBOARD_BASE_OCTAVE, SAMPLE_MAX_S, SAMPLE_RATE, SAMPLES_DIR, VELOCITY_DEFAULT};
+use crate::key::{Key, Note};
+use crate::settings::data_dir;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SamplerSettings {
+    /// a WAV file to load at startup; the sampler patch is only there once a sample is loaded
+    pub file: Option<PathBuf>,
+    /// where the sample browser opens; `samples` in the data dir when unset
+    pub dir: Option<PathBuf>,
+    /// the key the sample sounds unshifted on
+    pub root: Note,
+    pub root_octave: i32,
+}
+
+impl Default for SamplerSettings {
+    fn default() -> Self {
+        Self { file: None, dir: None, root: Note::C, root_octave: KEYBOARD_BASE_OCTAVE }
+    }
+}
+
+impl SamplerSettings {
+    pub fn root_key(&self) -> Key {
+        Key::new(self.root, self.root_octave)
+    }
+
+    pub fn samples_dir(&self) -> Option<PathBuf> {
+        self.dir.clone().or_else(|| data_dir().map(|d| d.join(SAMPLES_DIR)))
+    }
+}
+
+/// a decoded file, mixed down to mono at its own rate
+#[derive(Debug)]
+pub struct Sample {
+    path: PathBuf,
+    frames: Arc<[f32]>,
+    sample_rate: u32,
+}
+
+impl Sample {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let bad = |e: &dyn std::fmt::Display| format!("{}: {e}", path.display());
+        let file = File::open(path).map_err(|e| bad(&e))?;
+        let decoder = Decoder::try_from(file).map_err(|e| bad(&e))?;
+        let channels = decoder.channels().max(1) as usize;
+        let sample_rate = decoder.sample_rate();
+        let longest = (SAMPLE_MAX_S * sample_rate as f32) as usize * channels;
+        let raw: Vec<f32> = decoder.take(longest).collect();
+        let frames: Arc<[f32]> = raw.chunks_exact(channels).map(|c| c.iter().sum::<f32>() / channels as f32).collect();
+        if frames.len() < 2 || sample_rate == 0 {
+            return Err(bad(&"no audio in the file").into());
+        }
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        Ok(Self { path, frames, sample_rate })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// the file name without its extension
+    pub fn name(&self) -> String {
+        self.path.file_stem().map_or_else(|| self.path.display().to_string(), |s| s.to_string_lossy().into_owned())
+    }
+}
+
+/// the sample as a patch, with the root key's frequency from the tuning in use
+pub struct SamplerPatch {
+    sample: Arc<Sample>,
+    root_hz: f32,
+}
+
+impl SamplerPatch {
+    pub fn new(sample: Arc<Sample>, root_hz: f32) -> Self {
+        Self { sample, root_hz }
+    }
+}
+
+impl AudioSource for SamplerPatch {
+    fn create_source(&self, frequency: f32, freq_mul: FreqMul) -> SynthSource {
+        self.create_source_with_velocity(frequency, freq_mul, VELOCITY_DEFAULT)
+    }
+
+    fn create_source_with_velocity(&self, frequency: f32, freq_mul: FreqMul, velocity: f32) -> SynthSource {
+        let step = (frequency / self.root_hz) as f64 * self.sample.sample_rate as f64 / SAMPLE_RATE as f64;
+        Box::new(SampleVoice {
+            frames: self.sample.frames.clone(),
+            pos: 0.0,
+            step,
+            freq_mul,
+            gain: AMP_DEFAULT * velocity / VELOCITY_DEFAULT,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "Sampler"
+    }
+}
+
+/// one pass through the sample at the note's speed. linear interpolation
+struct SampleVoice {
+    frames: Arc<[f32]>,
+    pos: f64,
+    /// frames to advance per output sample at freq_mul 1.0
+    step: f64,
+    freq_mul: FreqMul,
+    gain: f32,
+}
+
+impl Iterator for SampleVoice {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let i = self.pos as usize;
+        if i + 1 >= self.frames.len() {
+            return None;
+        }
+        let t = (self.pos - i as f64) as f32;
+        let (a, b) = (self.frames[i], self.frames[i + 1]);
+        self.pos += self.step * self.freq_mul.load() as f64;
+        Some((a + (b - a) * t) * self.gain)
+    }
+}
+
+impl Source for SampleVoice {
+    fn current_span_len(&self) -> Option<usize> { None }
+    fn channels(&self) -> u16 { 1 }
+    fn sample_rate(&self) -> u32 { SAMPLE_RATE }
+    fn total_duration(&self) -> Option<Duration> { None }
+}
@@ -0,0 +1,444 @@
+//! SF2 soundfont patches. the file is parsed once at startup into flat zones (key/velocity
+//! range → sample, loop, tuning, level); a note plays every zone that covers it through the
+//! usual voice chain. the soundfont's own envelopes, filters and modulators are ignored, tjam's
+//! ADSR shapes the note instead
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rodio::Source;
+
+use crate::audio_patch::{AudioSource, FreqMul, SynthSource};
+use crate::config::{AMP_DEFAULT, SAMPLE_RATE, VELOCITY_DEFAULT};
+
+// generator operators this loader understands, numbered as in the SF2 spec
+const GEN_START_OFFSET: usize = 0;
+const GEN_END_OFFSET: usize = 1;
+const GEN_LOOP_START_OFFSET: usize = 2;
+const GEN_LOOP_END_OFFSET: usize = 3;
+const GEN_START_COARSE: usize = 4;
+const GEN_END_COARSE: usize = 12;
+const GEN_INSTRUMENT: usize = 41;
+const GEN_KEY_RANGE: usize = 43;
+const GEN_VEL_RANGE: usize = 44;
+const GEN_LOOP_START_COARSE: usize = 45;
+const GEN_ATTENUATION: usize = 48;
+const GEN_LOOP_END_COARSE: usize = 50;
+const GEN_COARSE_TUNE: usize = 51;
+const GEN_FINE_TUNE: usize = 52;
+const GEN_SAMPLE_ID: usize = 53;
+const GEN_SAMPLE_MODES: usize = 54;
+const GEN_SCALE_TUNING: usize = 56;
+const GEN_ROOT_KEY: usize = 58;
+const GEN_COUNT: usize = 61;
+
+/// one sample mapped onto a key/velocity range, with everything needed to play it
+#[derive(Debug, Clone)]
+struct Zone {
+    keys: (u8, u8),
+    vels: (u8, u8),
+    start: usize,
+    end: usize,
+    loop_start: usize,
+    loop_end: usize,
+    looped: bool,
+    sample_rate: u32,
+    /// key the sample sounds at unshifted
+    root: f32,
+    /// semitones moved per key, 1.0 normally, 0 for drum-style zones
+    key_scale: f32,
+    /// zone and sample tuning on top, in semitones
+    tune: f32,
+    gain: f32,
+}
+
+impl Zone {
+    fn covers(&self, key: u8, vel: u8) -> bool {
+        (self.keys.0..=self.keys.1).contains(&key) && (self.vels.0..=self.vels.1).contains(&vel)
+    }
+}
+
+/// a preset from the file, ready to play
+pub struct SoundFontPatch {
+    name: &'static str,
+    samples: Arc<[i16]>,
+    zones: Vec<Zone>,
+}
+
+struct Preset {
+    name: String,
+    bank: u16,
+    program: u16,
+    zones: Vec<Zone>,
+}
+
+/// a parsed soundfont: the raw sample pool plus every preset's flattened zones
+pub struct SoundFont {
+    samples: Arc<[i16]>,
+    presets: Vec<Preset>,
+}
+
+impl SoundFont {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let data = fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        parse(&data).map_err(|e| format!("{}: {e}", path.display()).into())
+    }
+
+    /// preset names, in bank/program order
+    pub fn preset_names(&self) -> Vec<&str> {
+        self.presets.iter().map(|p| p.name.as_str()).collect()
+    }
+
+    /// the preset called `name` (ignoring case), or the first one when `None`
+    pub fn patch(&self, name: Option<&str>) -> Result<SoundFontPatch, Box<dyn std::error::Error>> {
+        let found = match name {
+            Some(name) => self.presets.iter().find(|p| p.name.eq_ignore_ascii_case(name.trim())),
+            None => self.presets.first(),
+        };
+        let Some(preset) = found else {
+            let wanted = name.unwrap_or("any preset");
+            return Err(format!("no {wanted} in the soundfont (presets: {})", self.preset_names().join(", ")).into());
+        };
+        Ok(SoundFontPatch {
+            // patch names are 'static; a handful of presets loaded once at startup
+            name: Box::leak(preset.name.clone().into_boxed_str()),
+            samples: self.samples.clone(),
+            zones: preset.zones.clone(),
+        })
+    }
+}
+
+impl AudioSource for SoundFontPatch {
+    fn create_source(&self, frequency: f32, freq_mul: FreqMul) -> SynthSource {
+        self.create_source_with_velocity(frequency, freq_mul, VELOCITY_DEFAULT)
+    }
+
+    fn create_source_with_velocity(&self, frequency: f32, freq_mul: FreqMul, velocity: f32) -> SynthSource {
+        let note = 69.0 + 12.0 * (frequency / 440.0).log2();
+        let key = note.round().clamp(0.0, 127.0) as u8;
+        let vel = (velocity.clamp(0.0, 1.0) * 127.0).round() as u8;
+        let players: Vec<SamplePlayer> = self
+            .zones
+            .iter()
+            .filter(|z| z.covers(key, vel))
+            .map(|z| SamplePlayer::new(self.samples.clone(), z, note, freq_mul.clone()))
+            .collect();
+        let gain = AMP_DEFAULT * velocity / VELOCITY_DEFAULT / (players.len().max(1) as f32).sqrt();
+        Box::new(ZoneMix { players, gain })
+    }
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+/// plays one zone's sample at the note's pitch, looping if the zone says so. linear interpolation
+struct SamplePlayer {
+    samples: Arc<[i16]>,
+    pos: f64,
+    end: usize,
+    loop_range: Option<(usize, usize)>,
+    /// samples to advance per output sample at freq_mul 1.0
+    step: f64,
+    freq_mul: FreqMul,
+    gain: f32,
+}
+
+impl SamplePlayer {
+    fn new(samples: Arc<[i16]>, zone: &Zone, note: f32, freq_mul: FreqMul) -> Self {
+        let semitones = (note - zone.root) * zone.key_scale + zone.tune;
+        let step = 2f64.powf(semitones as f64 / 12.0) * zone.sample_rate as f64 / SAMPLE_RATE as f64;
+        let loop_range = (zone.looped && zone.loop_end > zone.loop_start + 1).then_some((zone.loop_start, zone.loop_end));
+        Self { samples, pos: zone.start as f64, end: zone.end, loop_range, step, freq_mul, gain: zone.gain }
+    }
+
+    fn next_sample(&mut self) -> Option<f32> {
+        match self.loop_range {
+            Some((start, end)) => {
+                while self.pos >= end as f64 {
+                    self.pos -= (end - start) as f64;
+                }
+            }
+            None if self.pos >= (self.end - 1) as f64 => return None,
+            None => {}
+        }
+        let i = self.pos as usize;
+        let t = (self.pos - i as f64) as f32;
+        let next = match self.loop_range {
+            Some((start, end)) if i + 1 >= end => start,
+            _ => i + 1,
+        };
+        let a = self.samples[i] as f32;
+        let b = self.samples[next] as f32;
+        self.pos += self.step * self.freq_mul.load() as f64;
+        Some((a + (b - a) * t) / 32768.0 * self.gain)
+    }
+}
+
+/// sums the zones a note plays (layers, stereo halves). ends when they all have
+struct ZoneMix {
+    players: Vec<SamplePlayer>,
+    gain: f32,
+}
+
+impl Iterator for ZoneMix {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let mut sum = 0.0;
+        self.players.retain_mut(|p| match p.next_sample() {
+            Some(x) => {
+                sum += x;
+                true
+            }
+            None => false,
+        });
+        if self.players.is_empty() {
+            return None;
+        }
+        Some(sum * self.gain)
+    }
+}
+
+impl Source for ZoneMix {
+    fn current_span_len(&self) -> Option<usize> { None }
+    fn channels(&self) -> u16 { 1 }
+    fn sample_rate(&self) -> u32 { SAMPLE_RATE }
+    fn total_duration(&self) -> Option<Duration> { None }
+}
+
+type Gens = [Option<i16>; GEN_COUNT];
+type Chunk<'a> = ([u8; 4], &'a [u8]);
+
+struct SampleHeader {
+    start: u32,
+    end: u32,
+    loop_start: u32,
+    loop_end: u32,
+    sample_rate: u32,
+    pitch: u8,
+    correction: i8,
+}
+
+fn u16_at(d: &[u8], i: usize) -> u16 {
+    u16::from_le_bytes([d[i], d[i + 1]])
+}
+
+fn u32_at(d: &[u8], i: usize) -> u32 {
+    u32::from_le_bytes([d[i], d[i + 1], d[i + 2], d[i + 3]])
+}
+
+fn name_at(d: &[u8], i: usize) -> String {
+    let raw = &d[i..i + 20];
+    let len = raw.iter().position(|b| *b == 0).unwrap_or(raw.len());
+    String::from_utf8_lossy(&raw[..len]).trim().to_string()
+}
+
+/// (id, body) of each chunk in `d`; bodies are word aligned
+fn chunks(d: &[u8]) -> Vec<Chunk<'_>> {
+    let mut out = vec![];
+    let mut i = 0;
+    while i + 8 <= d.len() {
+        let id = [d[i], d[i + 1], d[i + 2], d[i + 3]];
+        let len = u32_at(d, i + 4) as usize;
+        let body = &d[i + 8..(i + 8 + len).min(d.len())];
+        out.push((id, body));
+        i += 8 + len + (len & 1);
+    }
+    out
+}
+
+/// the subchunks of the LIST chunk of type `kind`
+fn list<'a>(top: &[Chunk<'a>], kind: &[u8; 4]) -> Result<Vec<Chunk<'a>>, Box<dyn std::error::Error>> {
+    top.iter()
+        .find(|(id, body)| id == b"LIST" && body.len() >= 4 && &body[..4] == kind)
+        .map(|(_, body)| chunks(&body[4..]))
+        .ok_or_else(|| format!("missing {} list", String::from_utf8_lossy(kind)).into())
+}
+
+fn find<'a>(list: &[Chunk<'a>], id: &[u8; 4]) -> Result<&'a [u8], Box<dyn std::error::Error>> {
+    list.iter()
+        .find(|(i, _)| i == id)
+        .map(|(_, body)| *body)
+        .ok_or_else(|| format!("missing {} chunk", String::from_utf8_lossy(id)).into())
+}
+
+/// generator lists of each zone. `bags` is a pbag/ibag chunk, `gens` the matching pgen/igen
+fn zone_gens(bags: &[u8], gens: &[u8], first_bag: usize, last_bag: usize) -> Vec<Gens> {
+    let bag_count = bags.len() / 4;
+    let gen_count = gens.len() / 4;
+    (first_bag..last_bag.min(bag_count.saturating_sub(1)))
+        .map(|b| {
+            let mut out: Gens = [None; GEN_COUNT];
+            let from = u16_at(bags, b * 4) as usize;
+            let to = (u16_at(bags, (b + 1) * 4) as usize).min(gen_count);
+            for g in from..to {
+                let op = u16_at(gens, g * 4) as usize;
+                if op < GEN_COUNT {
+                    out[op] = Some(u16_at(gens, g * 4 + 2) as i16);
+                }
+            }
+            out
+        })
+        .collect()
+}
+
+/// splits zones into the optional global one (no instrument/sample link, listed first) and the rest
+fn split_global(mut zones: Vec<Gens>, link: usize) -> (Gens, Vec<Gens>) {
+    if zones.first().is_some_and(|z| z[link].is_none()) {
+        let global = zones.remove(0);
+        (global, zones)
+    } else {
+        ([None; GEN_COUNT], zones)
+    }
+}
+
+fn merged(global: &Gens, local: &Gens) -> Gens {
+    let mut out = *global;
+    for (o, l) in out.iter_mut().zip(local) {
+        if l.is_some() {
+            *o = *l;
+        }
+    }
+    out
+}
+
+fn range(gens: &Gens, op: usize) -> (u8, u8) {
+    match gens[op] {
+        Some(v) => {
+            let [lo, hi] = (v as u16).to_le_bytes();
+            (lo, hi)
+        }
+        None => (0, 127),
+    }
+}
+
+fn intersect(a: (u8, u8), b: (u8, u8)) -> Option<(u8, u8)> {
+    let r = (a.0.max(b.0), a.1.min(b.1));
+    (r.0 <= r.1).then_some(r)
+}
+
+fn amount(gens: &Gens, op: usize) -> i32 {
+    gens[op].unwrap_or(0) as i32
+}
+
+fn parse(data: &[u8]) -> Result<SoundFont, Box<dyn std::error::Error>> {
+    if data.len() < 12 || &data[..4] != b"RIFF" || &data[8..12] != b"sfbk" {
+        return Err("not an SF2 soundfont".into());
+    }
+    let top = chunks(&data[12..]);
+    let sdta = list(&top, b"sdta")?;
+    let pdta = list(&top, b"pdta")?;
+
+    let smpl = find(&sdta, b"smpl")?;
+    let samples: Arc<[i16]> = smpl.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect();
+
+    let (phdr, pbag, pgen) = (find(&pdta, b"phdr")?, find(&pdta, b"pbag")?, find(&pdta, b"pgen")?);
+    let (inst, ibag, igen) = (find(&pdta, b"inst")?, find(&pdta, b"ibag")?, find(&pdta, b"igen")?);
+    let shdr = find(&pdta, b"shdr")?;
+
+    let headers: Vec<SampleHeader> = shdr
+        .chunks_exact(46)
+        .map(|h| SampleHeader {
+            start: u32_at(h, 20),
+            end: u32_at(h, 24),
+            loop_start: u32_at(h, 28),
+            loop_end: u32_at(h, 32),
+            sample_rate: u32_at(h, 36),
+            pitch: h[40],
+            correction: h[41] as i8,
+        })
+        .collect();
+
+    // every list ends with a terminal record, so record i spans bags i..i+1
+    let inst_count = (inst.len() / 22).saturating_sub(1);
+    let instruments: Vec<(Gens, Vec<Gens>)> = (0..inst_count)
+        .map(|i| {
+            let first = u16_at(inst, i * 22 + 20) as usize;
+            let last = u16_at(inst, (i + 1) * 22 + 20) as usize;
+            split_global(zone_gens(ibag, igen, first, last), GEN_SAMPLE_ID)
+        })
+        .collect();
+
+    let preset_count = (phdr.len() / 38).saturating_sub(1);
+    let mut presets = vec![];
+    for p in 0..preset_count {
+        let rec = &phdr[p * 38..];
+        let name = name_at(rec, 0);
+        let (program, bank) = (u16_at(rec, 20), u16_at(rec, 22));
+        let first = u16_at(rec, 24) as usize;
+        let last = u16_at(phdr, (p + 1) * 38 + 24) as usize;
+        let (pglobal, pzones) = split_global(zone_gens(pbag, pgen, first, last), GEN_INSTRUMENT);
+
+        let mut zones = vec![];
+        for pz in &pzones {
+            let pz = merged(&pglobal, pz);
+            let Some((iglobal, izones)) = pz[GEN_INSTRUMENT].and_then(|i| instruments.get(i as u16 as usize)) else {
+                continue;
+            };
+            for iz in izones {
+                let iz = merged(iglobal, iz);
+                if let Some(zone) = flatten(&pz, &iz, &headers, samples.len()) {
+                    zones.push(zone);
+                }
+            }
+        }
+        if !zones.is_empty() {
+            presets.push(Preset { name, bank, program, zones });
+        }
+    }
+    presets.sort_by_key(|p| (p.bank, p.program));
+    if presets.is_empty() {
+        return Err("the soundfont has no playable presets".into());
+    }
+
+    Ok(SoundFont { samples, presets })
+}
+
+/// one instrument zone under one preset zone. preset tuning and attenuation add to the
+/// instrument's, ranges intersect
+fn flatten(pz: &Gens, iz: &Gens, headers: &[SampleHeader], pool: usize) -> Option<Zone> {
+    let h = headers.get(iz[GEN_SAMPLE_ID]? as u16 as usize)?;
+    let keys = intersect(range(pz, GEN_KEY_RANGE), range(iz, GEN_KEY_RANGE))?;
+    let vels = intersect(range(pz, GEN_VEL_RANGE), range(iz, GEN_VEL_RANGE))?;
+
+    let offset = |base: u32, fine: usize, coarse: usize| {
+        let at = base as i64 + amount(iz, fine) as i64 + amount(iz, coarse) as i64 * 32768;
+        at.clamp(0, pool as i64) as usize
+    };
+    let start = offset(h.start, GEN_START_OFFSET, GEN_START_COARSE);
+    let end = offset(h.end, GEN_END_OFFSET, GEN_END_COARSE);
+    let loop_start = offset(h.loop_start, GEN_LOOP_START_OFFSET, GEN_LOOP_START_COARSE);
+    let loop_end = offset(h.loop_end, GEN_LOOP_END_OFFSET, GEN_LOOP_END_COARSE);
+    if end <= start + 1 || h.sample_rate == 0 {
+        return None;
+    }
+
+    let root_key = match iz[GEN_ROOT_KEY] {
+        Some(k) if (0..=127).contains(&k) => k as f32,
+        _ => h.pitch.min(127) as f32,
+    };
+    let tune_cents = (amount(pz, GEN_COARSE_TUNE) + amount(iz, GEN_COARSE_TUNE)) * 100
+        + amount(pz, GEN_FINE_TUNE)
+        + amount(iz, GEN_FINE_TUNE)
+        + h.correction as i32;
+    let attenuation_cb = (amount(pz, GEN_ATTENUATION) + amount(iz, GEN_ATTENUATION)).max(0);
+
+    Some(Zone {
+        keys,
+        vels,
+        start,
+        end,
+        loop_start: loop_start.clamp(start, end),
+        loop_end: loop_end.clamp(start, end),
+        // modes 1 and 3 loop; 3 would play on past the loop after release, which ADSR covers
+        looped: amount(iz, GEN_SAMPLE_MODES) & 1 == 1,
+        sample_rate: h.sample_rate,
+        root: root_key,
+        key_scale: iz[GEN_SCALE_TUNING].map_or(1.0, |c| c as f32 / 100.0),
+        tune: tune_cents as f32 / 100.0,
+        gain: 10f32.powf(-(attenuation_cb as f32) / 200.0),
+    })
+}
@@ -1,92 +1,351 @@
-use crate::audio_patch::Node;
+use crate::audio_patch::{AtomicF32, Node, param_values, set_param_values};
 use device_query::{DeviceQuery, DeviceState, Keycode};
 use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::{
     Arc,
     atomic::{AtomicBool, Ordering},
 };
-use std::time::Duration;
-
-use rodio::stream::{OutputStream, OutputStreamBuilder};
-use rodio::Sink;
+use std::time::{Duration, Instant};
 
 use tokio::{signal::ctrl_c, task};
 
-use crate::config::{TICK, SAMPLE_RATE, ADSR_ATTACK_S, ADSR_DECAY_S, ADSR_SUSTAIN, ADSR_RELEASE_S};
-use crate::key::Key;
-use crate::patches::basic::{basic_source, BasicKind};
-use crate::fx::adsr::{Adsr, AdsrNode, Gate};
-use crate::audio_system;
-use crate::audio_patch::AudioSource;
+use crate::config::{MIDI_DRUM_CHANNEL, RENDER_BLOCK, RENDER_SILENCE, RENDER_TAIL_MAX_S, TICK, SAMPLE_RATE, VELOCITY_DEFAULT, VELOCITY_ACCENT, RELEASE_VELOCITY_CURVE, METER_TICK_MS, SCHEDULE_TICK_MS, ADSR_ATTACK_S, ADSR_DECAY_S, ADSR_SUSTAIN, ADSR_RELEASE_S};
+use crate::key::{Key, Keymap, ScaleLock};
+use crate::chord::Chord;
+use crate::keymap::KeyBindings;
+use crate::patches::basic::{basic_source, supersaw_source, BasicKind};
+use crate::patches::drums::{DrumKind, drum_source};
+use crate::patches::fm::{fm_source, FmParams};
+use crate::patches::pluck::pluck_source;
+use crate::patches::sampler::{Sample, SamplerPatch, SamplerSettings};
+use crate::patches::soundfont::SoundFont;
+use crate::fx::adsr::{Adsr, AdsrNode, FilterEnvelope, Gate, ReleaseScale, Retrigger, VoiceLevel};
+use crate::audio_system::{self, ChainEdit, DriveTarget, LessonAction, LooperAction, TransportAction};
+use crate::audio_patch::{AudioSource, FreqMul, ParamValue, SynthSource, at_engine_rate, new_freq_mul};
+use crate::presets::{self, Preset};
+use crate::meter::{LevelMeter, MeterTap, to_db};
+use crate::gain_staging::{AdviceKind, GainAdvice, GainAdvisor};
+use crate::fx::limiter::Limiter;
+use crate::fx::lfo::ModMatrix;
+use crate::fx::bitcrush::Bitcrush;
+use crate::fx::compressor::{Compressor, CompressorSettings};
+use crate::fx::autopan::AutoPan;
+use crate::fx::chain::FxChain;
+use crate::fx::flanger::Flanger;
+use crate::fx::phaser::Phaser;
+use crate::fx::tremolo::Tremolo;
+use crate::fx::drive::{Drive, DriveSettings};
+use crate::fx::eq::{EqSettings, ThreeBandEq};
+use crate::fx::freeze::Freeze;
+use crate::fx::ringmod::RingMod;
+use crate::fx::reverb::{Reverb, ReverbSettings};
+use crate::fx::tilt::TiltEq;
+use crate::settings::{AudioSettings, Settings, settings_path, update_settings_file};
+use crate::midi_in::{self, CcBinding, cc_value};
+use crate::input::{ChordAssist, KeyState, KeyUpdate, RolloverTest};
+use crate::looper::{Looper, LoopNote};
+use crate::recorder::Recorder;
+use crate::render::{self, WavWriter};
+use crate::script::{Script, ScriptEvent};
+use crate::transport::Transport;
+use crate::tuning::Tuning;
+use crate::lessons::{self, LessonProgress, LessonRun, LessonStep};
+use crate::echo::{EchoEvent, EchoVoice, NoteEcho, EchoSettings};
+use crate::profiler::{self, PROFILER, ProfiledSource};
+use crate::crash;
+use crate::engine::{Engine, Track, TrackSettings, VoiceId};
+
+/// one sounding note: its voice in the engine plus the handles used to release it and move its pitch
+pub struct ActiveNote {
+    pub id: VoiceId,
+    pub gate: Gate,
+    pub freq_mul: FreqMul,
+    pub release_scale: ReleaseScale,
+    pub velocity: f32,
+    /// its envelope level, for a retrigger to carry on from
+    pub level: VoiceLevel,
+    /// set by the engine once the voice has finished its release
+    pub done: Arc<AtomicBool>,
+}
+
+/// who a voice belongs to: a key held right now, or the looper replaying one.
+/// kept apart so the loop releasing a note never cuts the same note played live
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VoiceKey {
+    Live(Keycode),
+    Loop(Keycode),
+    Echo(EchoVoice),
+    /// a note with no key on the keyboard: a script's, or one read from a MIDI file
+    Note(Track, Key),
+}
+
+impl VoiceKey {
+    pub fn keycode(self) -> Option<Keycode> {
+        match self {
+            VoiceKey::Live(k) | VoiceKey::Loop(k) => Some(k),
+            VoiceKey::Echo(e) => Some(e.keycode),
+            VoiceKey::Note(..) => None,
+        }
+    }
+
+    /// the key it sounds, before any echo shift
+    pub fn key(self, keymap: &Keymap) -> Option<Key> {
+        match self {
+            VoiceKey::Note(_, key) => Some(key),
+            _ => keymap.key(self.keycode()?),
+        }
+    }
+
+    pub fn drum(self) -> Option<DrumKind> {
+        match self {
+            VoiceKey::Note(Track::Drums, key) => DrumKind::from_midi_note(key.midi_note()),
+            _ => self.keycode().and_then(DrumKind::from_keycode),
+        }
+    }
+
+    /// pitch offset from the key's own note
+    pub fn semitones(self) -> i32 {
+        match self {
+            VoiceKey::Echo(e) => e.semitones,
+            _ => 0,
+        }
+    }
+
+    /// the mixer track the voice plays on
+    pub fn track(self) -> Track {
+        if self.drum().is_some() {
+            return Track::Drums;
+        }
+        match self {
+            VoiceKey::Live(_) => Track::Live,
+            VoiceKey::Loop(_) => Track::Loop,
+            VoiceKey::Echo(_) => Track::Echo,
+            VoiceKey::Note(track, _) => track,
+        }
+    }
+}
 
-pub type ActiveNote = (Sink, Gate);
+/// builds the master chain over one side (0 left, 1 right) of the voice mix
+type MasterChain = Box<dyn Fn(usize, SynthSource) -> SynthSource>;
 
 pub struct PlayState {
-    pub stream: OutputStream,
-    pub active_sinks: HashMap<Keycode, Vec<ActiveNote>>,
+    pub engine: Engine,
+    pub master_meter: Arc<MeterTap>,
+    pub reverb: Reverb,
+    pub freeze: Freeze,
+    pub bitcrush: Bitcrush,
+    pub drive: Drive,
+    pub compressor: Compressor,
+    pub phaser: Phaser,
+    pub flanger: Flanger,
+    pub tremolo: Tremolo,
+    pub autopan: AutoPan,
+    /// voices whose key is held
+    pub active_voices: HashMap<VoiceKey, Vec<ActiveNote>>,
+    /// voices let go of, ringing out through their release. kept apart from the held ones so a
+    /// patch change, which restarts what is held, leaves their tails alone
+    pub releasing: Vec<(VoiceKey, ActiveNote)>,
+    /// polyphony limit, 0 for none
+    max_voices: usize,
 }
 
 impl PlayState {
-    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        let stream = OutputStreamBuilder::open_default_stream()?;
-        Ok(Self { stream, active_sinks: HashMap::new() })
+    /// handles on the master effects with parameters, sharing their controls
+    fn master_nodes(&self) -> Vec<Box<dyn Node>> {
+        vec![
+            Box::new(self.reverb.clone()),
+            Box::new(self.freeze.clone()),
+            Box::new(self.bitcrush.clone()),
+            Box::new(self.drive.clone()),
+            Box::new(self.compressor.clone()),
+            Box::new(self.phaser.clone()),
+            Box::new(self.flanger.clone()),
+            Box::new(self.tremolo.clone()),
+            Box::new(self.autopan.clone()),
+        ]
     }
 
-    fn stop_note(&mut self, keycode: Keycode) {
-        if let Some(voices) = self.active_sinks.get_mut(&keycode) {
-            for (_sink, gate) in voices.iter_mut() {
-                gate.store(false, Ordering::Relaxed);
-            }
+    pub fn new(settings: &AudioSettings) -> Result<Self, Box<dyn std::error::Error>> {
+        let (play_state, ()) = Self::build(settings, |master| {
+            Ok((Engine::start(settings.device.as_deref(), settings.sample_rate, master)?, ()))
+        })?;
+        Ok(play_state)
+    }
+
+    /// with no device, for rendering: the master chain's two sides come back to be pulled
+    pub fn offline(settings: &AudioSettings) -> Result<(Self, [SynthSource; 2]), Box<dyn std::error::Error>> {
+        Self::build(settings, |master| Ok(Engine::offline(master)))
+    }
+
+    /// the master effects, and the engine `open` makes around them
+    fn build<T>(
+        settings: &AudioSettings,
+        open: impl FnOnce(MasterChain) -> Result<(Engine, T), Box<dyn std::error::Error>>,
+    ) -> Result<(Self, T), Box<dyn std::error::Error>> {
+        let master_meter = MeterTap::new();
+        let limiter = Limiter::new(
+            settings.limiter_threshold_db,
+            settings.limiter_lookahead_ms,
+            settings.limiter_release_ms,
+            SAMPLE_RATE,
+        );
+
+        let reverb = Reverb::new(ReverbSettings {
+            room_size: settings.reverb_room_size,
+            damping: settings.reverb_damping,
+            mix: settings.reverb_mix,
+        });
+
+        let freeze = Freeze::new();
+        let bitcrush = Bitcrush::new();
+        let drive = Drive::new(settings.master_drive);
+        let compressor = Compressor::new(settings.master_compressor);
+        let phaser = Phaser::new(settings.master_phaser);
+        let flanger = Flanger::new(settings.master_flanger);
+        let tremolo = Tremolo::new(settings.master_tremolo);
+        let autopan = AutoPan::new(settings.master_autopan);
+
+        // metered before the limiter, so gain staging still sees what would have clipped.
+        // the freeze goes before the reverb so the drone gets the same room, and the drive and
+        // crusher before both so the tails stay smooth. the compressor evens out what those two
+        // leave, and the phaser, flanger, tremolo and auto-pan move what it hands on, ahead of the
+        // room. each side gets its own chain, so the limiter and compressor work on the two
+        // unlinked; only the left one is profiled
+        let meter = master_meter.clone();
+        let master_reverb = reverb.clone();
+        let master_freeze = freeze.clone();
+        let master_crush = bitcrush.clone();
+        let master_drive = drive.clone();
+        let master_compressor = compressor.clone();
+        let master_phaser = phaser.clone();
+        let master_flanger = flanger.clone();
+        let master_tremolo = tremolo.clone();
+        let master_autopan = autopan.clone();
+        let (engine, out) = open(Box::new(move |channel, mix| {
+            let mix: SynthSource = if channel == 0 { Box::new(ProfiledSource::new(mix)) } else { mix };
+            let dry = master_compressor.apply(master_crush.apply(master_drive.apply(mix)));
+            let dry = master_tremolo.apply(master_flanger.apply(master_phaser.apply(dry)));
+            let dry = master_autopan.apply_side(channel, dry);
+            let wet = master_reverb.apply(master_freeze.apply(dry));
+            limiter.apply(Box::new(LevelMeter::new(wet, meter.clone())))
+        }))?;
+
+        let play_state = Self {
+            engine,
+            master_meter,
+            reverb,
+            freeze,
+            bitcrush,
+            drive,
+            compressor,
+            phaser,
+            flanger,
+            tremolo,
+            autopan,
+            active_voices: HashMap::new(),
+            releasing: vec![],
+            max_voices: settings.max_voices,
+        };
+        Ok((play_state, out))
+    }
+
+    fn stop_note(&mut self, voice_key: VoiceKey) {
+        self.release_note(voice_key, 1.0);
+    }
+
+    /// closes the gate with the release time scaled by `release_scale`
+    fn release_note(&mut self, voice_key: VoiceKey, release_scale: f32) {
+        let Some(voices) = self.active_voices.remove(&voice_key) else { return; };
+        for voice in voices {
+            voice.release_scale.store(release_scale);
+            voice.gate.store(false, Ordering::Release);
+            self.releasing.push((voice_key, voice));
         }
     }
 
-    fn kill_note(&mut self, keycode: Keycode) {
-        if let Some(mut voices) = self.active_sinks.remove(&keycode) {
-            for (sink, gate) in voices.drain(..) {
-                gate.store(false, Ordering::Relaxed);
-                sink.stop();
+    /// the key's voices, held or releasing, taken out of the books
+    fn take_voices(&mut self, voice_key: VoiceKey) -> Vec<ActiveNote> {
+        let mut voices = self.active_voices.remove(&voice_key).unwrap_or_default();
+        for (k, voice) in std::mem::take(&mut self.releasing) {
+            if k == voice_key {
+                voices.push(voice);
+            } else {
+                self.releasing.push((k, voice));
             }
         }
+        voices
     }
 
-    fn stop_all(&mut self) {
-        for (_k, voices) in self.active_sinks.iter_mut() {
-            for (_sink, gate) in voices.iter_mut() {
-                gate.store(false, Ordering::Relaxed);
+    pub fn kill_note(&mut self, voice_key: VoiceKey) {
+        for voice in self.take_voices(voice_key) {
+            voice.gate.store(false, Ordering::Release);
+            self.engine.kill(voice.id);
+        }
+    }
+
+    fn voice_count(&self) -> usize {
+        self.active_voices.values().map(Vec::len).sum::<usize>() + self.releasing.len()
+    }
+
+    /// under the polyphony limit, cuts voices until `n` more fit: the oldest ones already
+    /// releasing, then the oldest held
+    fn make_room(&mut self, n: usize) {
+        if self.max_voices == 0 {
+            return;
+        }
+        self.cleanup_finished();
+        while self.voice_count() + n > self.max_voices {
+            if let Some(i) = (0..self.releasing.len()).min_by_key(|&i| self.releasing[i].1.id) {
+                let (_, voice) = self.releasing.swap_remove(i);
+                self.engine.kill(voice.id);
+                continue;
+            }
+            let oldest = self
+                .active_voices
+                .iter()
+                .flat_map(|(k, voices)| voices.iter().map(move |v| (*k, v.id)))
+                .min_by_key(|(_, id)| *id);
+            let Some((voice_key, id)) = oldest else { break; };
+            if let Some(voices) = self.active_voices.get_mut(&voice_key) {
+                voices.retain(|v| v.id != id);
+                if voices.is_empty() {
+                    self.active_voices.remove(&voice_key);
+                }
             }
+            self.engine.kill(id);
+        }
+    }
+
+    pub fn stop_all(&mut self) {
+        let held: Vec<VoiceKey> = self.active_voices.keys().copied().collect();
+        for voice_key in held {
+            self.stop_note(voice_key);
         }
     }
 
     fn kill_all(&mut self) {
-        for (_k, mut voices) in self.active_sinks.drain() {
-            for (sink, gate) in voices.drain(..) {
-                gate.store(false, Ordering::Relaxed);
-                sink.stop();
-            }
+        let voices = self.active_voices.drain().flat_map(|(_, v)| v).chain(self.releasing.drain(..).map(|(_, v)| v));
+        for voice in voices {
+            voice.gate.store(false, Ordering::Release);
         }
+        self.engine.kill_all();
     }
 
     fn cleanup_finished(&mut self) {
-        self.active_sinks.retain(|_, voices| {
-            voices.retain(|(sink, _)| !sink.empty());
+        self.releasing.retain(|(_, voice)| !voice.done.load(Ordering::Acquire));
+        self.active_voices.retain(|_, voices| {
+            voices.retain(|voice| !voice.done.load(Ordering::Acquire));
             !voices.is_empty()
         });
     }
 
-    fn set_all_volume(&mut self, v: f32) {
-        for (_k, voices) in self.active_sinks.iter_mut() {
-            for (sink, _gate) in voices.iter_mut() {
-                sink.set_volume(v);
-            }
-        }
+    fn set_volume(&mut self, v: f32) {
+        self.engine.set_volume(v);
     }
 
-    fn set_all_muted(&mut self, muted: bool) {
-        for (_k, voices) in self.active_sinks.iter_mut() {
-            for (sink, _gate) in voices.iter_mut() {
-                if muted { sink.pause(); } else { sink.play(); }
-            }
-        }
+    fn set_muted(&mut self, muted: bool) {
+        self.engine.set_muted(muted);
     }
 }
 
@@ -94,98 +353,1098 @@ struct RuntimeState {
     volume: f32,
     muted: bool,
     adsr: Adsr,
-    current_patch: Box<dyn AudioSource>,
-    avaliable_patches: Vec<Box<dyn AudioSource>>,
+    filter_env: FilterEnvelope,
+    current_patch: Arc<dyn AudioSource>,
+    avaliable_patches: Vec<Arc<dyn AudioSource>>,
     toggle_index: usize,
     held_keys: HashSet<Keycode>,
+    /// sustain pedal down (space, or the control socket)
+    sustain: bool,
+    /// latch mode: like the pedal, but toggled, so a drone keeps going with both hands free
+    latch: bool,
+    /// keys let go while the pedal was down; their voices ring until it comes up
+    sustained: HashSet<Keycode>,
+    /// pitch bend, -1..1 of `bend_range` semitones
+    bend: f32,
+    bend_range: f32,
+    presets: Vec<String>,
+    preset_name: Option<String>,
+    notice: Option<String>,
+    master_peak_db: f32,
+    master_rms_db: f32,
+    /// the engine's voice count, callback load and missed deadlines, as of the last meter tick
+    voices: usize,
+    audio_load: f32,
+    underruns: u64,
+    gain_advisor: GainAdvisor,
+    gain_advice: Option<GainAdvice>,
+    auto_gain: bool,
+    mod_matrix: ModMatrix,
+    /// shared with the poll thread, which applies it
+    chord_assist: Arc<AtomicBool>,
+    chord: Chord,
+    /// the MIDI notes each sounding voice was struck with, so its release lets go of exactly those
+    /// even if the chord or the scale lock changed while it was held
+    chord_held: HashMap<VoiceKey, Vec<u8>>,
+    rollover_test: Option<RolloverTest>,
+    /// most note keys the keyboard has delivered at once
+    max_rollover: usize,
+    looper: Looper,
+    /// the take being logged for MIDI export, if any
+    recorder: Recorder,
+    velocity_attack: bool,
+    release_velocity: bool,
+    echo: NoteEcho,
+    transport: Transport,
+    /// loop length in bars, following the tempo; 0 leaves it to the seconds setting
+    loop_bars: u32,
+    scale_lock: ScaleLock,
+    keymap: Keymap,
+    tuning: Tuning,
+    sampler: SamplerSettings,
+    /// the sample the sampler patch plays, once one is loaded
+    sample: Option<Arc<Sample>>,
+    reverb: ReverbSettings,
+    /// tilt EQ per patch name; patches not in here are flat
+    tilt: HashMap<&'static str, f32>,
+    /// waveshaper per patch name; patches not in here have it off
+    drive: HashMap<&'static str, DriveSettings>,
+    /// three-band EQ per patch name, made the first time one is set; patches not in here are flat
+    eq: HashMap<&'static str, ThreeBandEq>,
+    /// effect chain per patch name, after its EQ; patches not in here have none
+    chains: HashMap<&'static str, FxChain>,
+    /// mirrors the master bus drive
+    master_drive: DriveSettings,
+    /// mirrors the master bus compressor, and how far it turned down since the last meter tick
+    compressor: CompressorSettings,
+    gain_reduction_db: f32,
+    freeze: bool,
+    /// every note goes through it, after the patch's own filter
+    ringmod: RingMod,
+    /// handles on the master tremolo and auto-pan, so they follow the tempo
+    tremolo: Tremolo,
+    autopan: AutoPan,
+    /// the effects with live controls, for `SetParam`: the master bus's, then the ring modulator
+    master: Vec<Box<dyn Node>>,
+    /// mirrors the engine's channel strips, in `Track::ALL` order
+    tracks: [TrackSettings; Track::COUNT],
+    lesson: Option<LessonRun>,
+    lesson_progress: LessonProgress,
+    /// the generative script, if one is loaded
+    script: Option<Script>,
+    /// MIDI controller knobs and the parameters they move
+    cc_bindings: Vec<CcBinding>,
+    /// the parameter (owner, id) waiting for a knob to be moved, in MIDI learn
+    cc_learn: Option<(String, String)>,
+    /// the settings file the engine was loaded from, which bindings are saved to
+    settings_file: Option<PathBuf>,
+}
+
+impl RuntimeState {
+    fn tilt(&self) -> f32 {
+        self.tilt.get(self.current_patch.name()).copied().unwrap_or(0.0)
+    }
+
+    fn drive(&self) -> DriveSettings {
+        self.drive.get(self.current_patch.name()).copied().unwrap_or_default()
+    }
+
+    fn eq_settings(&self) -> EqSettings {
+        self.eq.get(self.current_patch.name()).map(ThreeBandEq::settings).unwrap_or_default()
+    }
+
+    fn chain(&self) -> Option<&FxChain> {
+        self.chains.get(self.current_patch.name())
+    }
+
+    /// release time multiplier for a key let go at `velocity`; 1.0 unless release velocity is on
+    fn release_scale(&self, velocity: f32) -> f32 {
+        if !self.release_velocity {
+            return 1.0;
+        }
+        (VELOCITY_DEFAULT / velocity.max(0.05)).powf(RELEASE_VELOCITY_CURVE)
+    }
+}
+
+/// the keys held keycodes sound, every note of their chord included
+fn sounding_keys(rt: &RuntimeState, keycodes: impl Iterator<Item = Keycode>) -> Vec<Key> {
+    keycodes
+        .filter_map(|k| rt.keymap.key(k))
+        .flat_map(|key| rt.chord.intervals().iter().map(move |i| key.transpose(*i)))
+        .collect()
 }
 
 fn publish_snapshot(tx: &tokio::sync::watch::Sender<audio_system::AudioSnapshot>, rt: &RuntimeState) {
+    if let Some(notice) = &rt.notice {
+        crash::note(notice.clone());
+    }
     let _ = tx.send(audio_system::AudioSnapshot {
         volume: rt.volume,
         muted: rt.muted,
         patch_name: rt.current_patch.name().to_string(),
+        adsr: rt.adsr,
+        filter_env: rt.filter_env,
+        active_keys: sounding_keys(rt, rt.held_keys.union(&rt.sustained).copied()),
+        loop_keys: sounding_keys(rt, rt.looper.sounding()),
+        presets: rt.presets.clone(),
+        preset_name: rt.preset_name.clone(),
+        notice: rt.notice.clone(),
+        master_peak_db: rt.master_peak_db,
+        master_rms_db: rt.master_rms_db,
+        gain_advice: rt.gain_advice,
+        auto_gain: rt.auto_gain,
+        lfos: rt.mod_matrix.settings(),
+        chord_assist: rt.chord_assist.load(Ordering::Relaxed),
+        chord: rt.chord.kind,
+        sample: rt.sample.as_ref().map(|s| s.path().to_path_buf()),
+        script: rt.script.as_ref().map(|s| s.name()),
+        sample_dir: rt.sampler.samples_dir(),
+        looper: rt.looper.status(Instant::now()),
+        echo: rt.echo.settings,
+        transport: rt.transport.status(Instant::now()),
+        scale_lock: rt.scale_lock,
+        keymap: rt.keymap.clone(),
+        reverb: rt.reverb,
+        tilt: rt.tilt(),
+        drive: rt.drive(),
+        eq: rt.eq_settings(),
+        chain: rt.chain().map_or(vec![], |c| {
+            c.slots().iter().map(|s| audio_system::ChainSlot { kind: s.kind, bypassed: s.bypassed() }).collect()
+        }),
+        master_drive: rt.master_drive,
+        compressor: rt.compressor,
+        gain_reduction_db: rt.gain_reduction_db,
+        freeze: rt.freeze,
+        sustain: rt.sustain,
+        latch: rt.latch,
+        tracks: rt.tracks,
+        recording: rt.recorder.is_recording(),
+        bend: rt.bend,
+        max_rollover: rt.max_rollover,
+        voices: rt.voices,
+        audio_load: rt.audio_load,
+        underruns: rt.underruns,
+        lesson: rt.lesson.as_ref().map(|l| l.status()),
+        params: param_groups(rt),
+        cc_bindings: rt.cc_bindings.clone(),
+        cc_learn: rt.cc_learn.clone(),
     });
 }
 
-async fn play_note(play_state: &mut PlayState, rt: &RuntimeState, keycode: Keycode) {
-    let Some(key) = Key::from_keycode(keycode) else { return; };
-    let freq = key.frequency();
+fn param_groups(rt: &RuntimeState) -> Vec<audio_system::ParamGroup> {
+    let patch = audio_system::ParamGroup { owner: "patch".to_string(), params: rt.current_patch.params() };
+    let eq = rt.eq.get(rt.current_patch.name()).cloned().unwrap_or_default();
+    let eq = audio_system::ParamGroup { owner: eq.name().to_string(), params: eq.params() };
+    let slots = rt.chain().map_or(&[][..], FxChain::slots);
+    let chain = slots
+        .iter()
+        .enumerate()
+        .map(|(i, s)| audio_system::ParamGroup { owner: format!("chain.{i}"), params: s.node().params() });
+    let master = rt.master.iter().map(|n| audio_system::ParamGroup { owner: n.name().to_string(), params: n.params() });
+    [patch, eq].into_iter().chain(chain).chain(master).filter(|g| !g.params.is_empty()).collect()
+}
 
-    let gate: Gate = Arc::new(AtomicBool::new(true));
+/// `set_param` with its outcome as the notice
+fn apply_param(play_state: &PlayState, rt: &mut RuntimeState, owner: &str, id: &str, value: ParamValue) {
+    match set_param(rt, owner, id, value) {
+        Ok(()) => rt.notice = Some(format!("{owner} {id} {value}")),
+        Err(e) => rt.notice = Some(e.to_string()),
+    }
+    read_back_master(play_state, rt);
+}
 
-    let sink = Sink::connect_new(&play_state.stream.mixer());
-    sink.set_volume(rt.volume);
-    if rt.muted { sink.pause(); }
+/// the master effects as the keys and the snapshot see them, after their parameters were set
+fn read_back_master(play_state: &PlayState, rt: &mut RuntimeState) {
+    // the reverb and freeze keys read these back
+    rt.reverb = play_state.reverb.settings();
+    rt.freeze = play_state.freeze.is_on();
+    rt.master_drive = play_state.drive.settings();
+    rt.compressor = play_state.compressor.settings();
+}
+
+/// `SetParam`: `patch` means the current one, `eq` the current one's EQ, `chain.{index}` an effect
+/// in its chain, anything else names a master effect
+fn set_param(rt: &mut RuntimeState, owner: &str, id: &str, value: ParamValue) -> Result<(), Box<dyn std::error::Error>> {
+    if owner == "patch" {
+        return rt.current_patch.set_param(id, value);
+    }
+    if owner.eq_ignore_ascii_case("eq") {
+        return rt.eq.entry(rt.current_patch.name()).or_default().set_param(id, value);
+    }
+    if let Some(index) = owner.strip_prefix("chain.") {
+        let index = index.parse().map_err(|_| format!("no effect called {owner}"))?;
+        return rt.chain().ok_or("the chain is empty")?.set_param(index, id, value);
+    }
+    let node = rt.master.iter().find(|n| n.name().eq_ignore_ascii_case(owner));
+    node.ok_or_else(|| format!("no effect called {owner}"))?.set_param(id, value)
+}
+
+/// `EditChain` on the current patch's chain, with its outcome as the notice. true when which
+/// effects there are changed, so the held notes are struck again through it; a bypass reaches them
+/// as they are
+fn edit_chain(rt: &mut RuntimeState, edit: ChainEdit) -> bool {
+    let restrike = !matches!(edit, ChainEdit::Bypass { .. });
+    let bpm = rt.transport.bpm();
+    let chain = rt.chains.entry(rt.current_patch.name()).or_default();
+    let name = |chain: &FxChain, index: usize| chain.slots().get(index).map_or("", |s| s.kind.name());
+    let done = match edit {
+        ChainEdit::Add { kind } => {
+            chain.push(kind, bpm);
+            Ok(format!("chain: {} added", kind.name()))
+        }
+        ChainEdit::Remove { index } => {
+            let removed = name(chain, index);
+            chain.remove(index).map(|()| format!("chain: {removed} removed"))
+        }
+        ChainEdit::Move { index, to } => {
+            let moved = name(chain, index);
+            chain.move_to(index, to).map(|()| format!("chain: {moved} moved to {}", to.min(chain.slots().len() - 1)))
+        }
+        ChainEdit::Bypass { index, on } => {
+            let state = if on { "bypassed" } else { "back in" };
+            chain.set_bypassed(index, on).map(|()| format!("chain: {} {state}", name(chain, index)))
+        }
+    };
+    match done {
+        Ok(notice) => {
+            rt.notice = Some(notice);
+            restrike
+        }
+        Err(e) => {
+            rt.notice = Some(e.to_string());
+            false
+        }
+    }
+}
+
+/// a knob moved. with MIDI learn waiting, it is bound to that parameter first (and to nothing
+/// else); then every parameter bound to it follows it across its range
+fn midi_cc(play_state: &PlayState, rt: &mut RuntimeState, channel: u8, cc: u8, value: u8) {
+    let learned = rt.cc_learn.take().map(|(owner, id)| {
+        rt.cc_bindings.retain(|b| !(b.is_knob(channel, cc) || b.is_param(&owner, &id)));
+        rt.cc_bindings.push(CcBinding { channel, cc, owner: owner.clone(), id: id.clone() });
+        match save_cc_bindings(rt) {
+            Ok(()) => format!("CC {cc} on channel {channel} moves {owner} {id}"),
+            Err(e) => format!("CC {cc} on channel {channel} moves {owner} {id}, not saved: {e}"),
+        }
+    });
+
+    let groups = param_groups(rt);
+    let moved: Vec<(String, String, ParamValue)> = rt
+        .cc_bindings
+        .iter()
+        .filter(|b| b.is_knob(channel, cc))
+        .filter_map(|b| {
+            // a chain slot the current patch does not have is left alone
+            let group = groups.iter().find(|g| g.owner.eq_ignore_ascii_case(&b.owner))?;
+            let desc = group.params.iter().find(|p| p.id == b.id)?;
+            Some((b.owner.clone(), b.id.clone(), cc_value(desc, value)))
+        })
+        .collect();
+    for (owner, id, value) in moved {
+        apply_param(play_state, rt, &owner, &id, value);
+    }
+    if learned.is_some() {
+        rt.notice = learned;
+    }
+}
 
-    let raw_src = rt.current_patch.create_source(freq);
-    let adsr_node = AdsrNode::new(rt.adsr, SAMPLE_RATE, gate.clone());
+/// `LearnCc`: the next knob moved is bound to `param`, which has to be one `SetParam` can reach
+fn learn_cc(rt: &mut RuntimeState, param: Option<(String, String)>) {
+    let Some((owner, id)) = param else {
+        if rt.cc_learn.take().is_some() {
+            rt.notice = Some("MIDI learn off".into());
+        }
+        return;
+    };
+    let known = param_groups(rt).iter().any(|g| g.owner.eq_ignore_ascii_case(&owner) && g.params.iter().any(|p| p.id == id));
+    if !known {
+        rt.notice = Some(format!("no parameter {id} on {owner}"));
+        return;
+    }
+    rt.notice = Some(format!("MIDI learn: move a knob for {owner} {id}"));
+    rt.cc_learn = Some((owner, id));
+}
+
+/// `ForgetCc`: no knob moves the parameter any more
+fn forget_cc(rt: &mut RuntimeState, owner: &str, id: &str) {
+    let before = rt.cc_bindings.len();
+    rt.cc_bindings.retain(|b| !b.is_param(owner, id));
+    rt.notice = Some(if rt.cc_bindings.len() == before {
+        format!("no knob moves {owner} {id}")
+    } else {
+        match save_cc_bindings(rt) {
+            Ok(()) => format!("{owner} {id} unbound"),
+            Err(e) => format!("{owner} {id} unbound, not saved: {e}"),
+        }
+    });
+}
+
+/// writes the knob bindings into the settings file the engine was started with (the default one
+/// when it was given settings of its own), leaving the rest of it as it is now
+fn save_cc_bindings(rt: &RuntimeState) -> Result<(), Box<dyn std::error::Error>> {
+    let path = rt.settings_file.clone().or_else(settings_path).ok_or("could not resolve the config directory")?;
+    let cc = rt.cc_bindings.clone();
+    update_settings_file(&path, |s| s.midi.cc = cc)
+}
+
+/// pulls the latest master levels into the advisor; returns true when the volume was changed
+fn update_gain_staging(play_state: &PlayState, rt: &mut RuntimeState) -> bool {
+    let peak = play_state.master_meter.take_peak();
+    rt.master_peak_db = to_db(peak);
+    rt.master_rms_db = to_db(play_state.master_meter.rms());
+    rt.gain_reduction_db = play_state.compressor.take_gain_reduction();
+
+    rt.gain_advisor.observe(peak, Instant::now());
+    let advice = rt.gain_advisor.advise(rt.volume);
+
+    if let Some(a) = advice
+        && Some(a.kind) != rt.gain_advice.map(|a| a.kind)
+    {
+        rt.notice = Some(match a.kind {
+            AdviceKind::Clipping => format!(
+                "clipping at {:+.1} dBFS, suggest volume {:.2} (n to apply)",
+                a.peak_db, a.suggested_volume
+            ),
+            AdviceKind::TooQuiet => format!(
+                "peaking at {:.1} dBFS, room to raise volume to {:.2} (n to apply)",
+                a.peak_db, a.suggested_volume
+            ),
+        });
+    }
+    rt.gain_advice = advice;
+
+    if rt.auto_gain && rt.gain_advice.is_some() {
+        return apply_gain_advice(rt);
+    }
+    false
+}
+
+/// steps the rollover test, if one is running, and keeps its prompt in the notice line
+fn update_rollover_test(rt: &mut RuntimeState) {
+    let Some(test) = rt.rollover_test.as_mut() else { return; };
+    match test.observe(&rt.held_keys, Instant::now(), &rt.keymap) {
+        Some(report) => {
+            rt.notice = Some(report);
+            rt.rollover_test = None;
+        }
+        None => rt.notice = Some(test.prompt(&rt.keymap)),
+    }
+}
+
+fn apply_gain_advice(rt: &mut RuntimeState) -> bool {
+    let Some(advice) = rt.gain_advice.take() else { return false; };
+    rt.volume = advice.suggested_volume;
+    rt.gain_advisor.reset();
+    rt.notice = Some(format!("volume set to {:.2} by gain staging", rt.volume));
+    true
+}
+
+async fn play_note(play_state: &mut PlayState, rt: &RuntimeState, voice_key: VoiceKey, velocity: f32) {
+    if let Some(kind) = voice_key.drum() {
+        play_drum(play_state, voice_key, kind, velocity);
+        return;
+    }
+    let from = retrigger(play_state, rt.adsr.retrigger, voice_key);
+    play_state.make_room(rt.chord.intervals().len());
+    play_chord(play_state, rt, voice_key, velocity, &from);
+}
+
+/// the key's note, or chord, on the current patch; `from` as `play_voice` takes it, per note.
+/// it makes no room under the polyphony limit itself: a patch change restarting held notes adds
+/// none, and must not cut the tails ringing out to fit them
+fn play_chord(play_state: &mut PlayState, rt: &RuntimeState, voice_key: VoiceKey, velocity: f32, from: &[VoiceLevel]) {
+    let Some(key) = voice_key.key(&rt.keymap) else { return; };
+    for (i, interval) in rt.chord.intervals().iter().enumerate() {
+        let freq = rt.tuning.frequency(key.transpose(voice_key.semitones() + interval));
+        play_voice(play_state, rt, voice_key, freq, velocity, from.get(i).cloned());
+    }
+}
+
+/// a key struck again while its voices still sound: unless they overlap, they are cut, and their
+/// levels are handed back, one per note of the chord, for the new voices to carry on from
+fn retrigger(play_state: &mut PlayState, mode: Retrigger, voice_key: VoiceKey) -> Vec<VoiceLevel> {
+    if mode == Retrigger::Overlap {
+        return vec![];
+    }
+    play_state.cleanup_finished();
+    let old = play_state.take_voices(voice_key);
+    for voice in &old {
+        voice.gate.store(false, Ordering::Release);
+        play_state.engine.kill(voice.id);
+    }
+    match mode.resume_stage() {
+        Some(_) => old.into_iter().map(|v| v.level).collect(),
+        None => vec![],
+    }
+}
+
+/// one note of a key's chord, through the patch, the mod matrix and the envelope; `from` is the
+/// level of the voice it takes over from on a retrigger
+fn play_voice(
+    play_state: &mut PlayState,
+    rt: &RuntimeState,
+    voice_key: VoiceKey,
+    freq: f32,
+    velocity: f32,
+    from: Option<VoiceLevel>,
+) {
+    let gate: Gate = Arc::new(AtomicBool::new(true));
+    let freq_mul = new_freq_mul();
+
+    let raw_src = at_engine_rate(rt.current_patch.create_source_with_velocity(freq, freq_mul.clone(), velocity));
+    let raw_src = rt.mod_matrix.apply(raw_src, freq_mul.clone(), gate.clone(), rt.filter_env);
+    let raw_src = rt.ringmod.apply_to_note(raw_src, freq, freq_mul.clone());
+    let drive = rt.drive();
+    let raw_src = if drive.enabled { Drive::new(drive).apply(raw_src) } else { raw_src };
+    let tilt = rt.tilt();
+    let raw_src = if tilt != 0.0 { TiltEq::new(tilt).apply(raw_src) } else { raw_src };
+    let raw_src = match rt.eq.get(rt.current_patch.name()) {
+        Some(eq) => eq.apply(raw_src),
+        None => raw_src,
+    };
+    let raw_src = match rt.chain() {
+        Some(chain) => chain.apply(raw_src),
+        None => raw_src,
+    };
+    let mut adsr = rt.adsr;
+    if rt.velocity_attack {
+        adsr.attack_s *= VELOCITY_DEFAULT / velocity.max(0.05);
+    }
+    let release_scale: ReleaseScale = Arc::new(AtomicF32::new(1.0));
+    let level: VoiceLevel = Arc::new(AtomicF32::new(0.0));
+    let mut adsr_node =
+        AdsrNode::new(adsr, SAMPLE_RATE, gate.clone()).with_release_scale(release_scale.clone()).with_level(level.clone());
+    if let Some(from) = from {
+        adsr_node = adsr_node.carrying_on_from(from);
+    }
     let src = adsr_node.apply(raw_src);
-    sink.append(src);
+    let (id, done) = play_state.engine.add_voice(src, voice_key.track());
 
-    play_state.active_sinks.entry(keycode).or_default().push((sink, gate));
+    let note = ActiveNote { id, gate, freq_mul, release_scale, velocity, level, done };
+    play_state.active_voices.entry(voice_key).or_default().push(note);
 }
 
-async fn restart_active_notes(play_state: &mut PlayState, rt: &RuntimeState) {
-    play_state.kill_all();
-    for &k in rt.held_keys.iter() {
-        play_note(play_state, rt, k).await;
+/// a one-shot with its own envelope. its gate starts closed and it goes straight to the
+/// releasing voices: there is nothing to release, and a patch change must not strike it again
+fn play_drum(play_state: &mut PlayState, voice_key: VoiceKey, kind: DrumKind, velocity: f32) {
+    play_state.make_room(1);
+    let (id, done) = play_state.engine.add_voice(drum_source(kind, velocity), voice_key.track());
+    let note = ActiveNote {
+        id,
+        gate: Arc::new(AtomicBool::new(false)),
+        freq_mul: new_freq_mul(),
+        release_scale: Arc::new(AtomicF32::new(1.0)),
+        velocity,
+        level: Arc::new(AtomicF32::new(0.0)),
+        done,
+    };
+    play_state.releasing.push((voice_key, note));
+}
+
+/// moves every held note over to the current patch/envelope without cutting anything: the old
+/// voices have their gate closed and ring out through their own release while the new ones
+/// come in through their attack, so the change is a crossfade rather than a click. notes already
+/// releasing are left to finish as they were
+async fn crossfade_active_notes(play_state: &mut PlayState, rt: &RuntimeState) {
+    let held: Vec<(VoiceKey, f32)> = play_state
+        .active_voices
+        .iter()
+        .filter_map(|(k, voices)| Some((*k, voices.first()?.velocity)))
+        .collect();
+
+    // no retrigger here: the old voices are meant to ring out under the new ones
+    play_state.stop_all();
+    for (k, velocity) in held {
+        play_chord(play_state, rt, k, velocity, &[]);
+    }
+}
+
+async fn play_loop_notes(play_state: &mut PlayState, rt: &mut RuntimeState, notes: Vec<LoopNote>) {
+    let at = Instant::now();
+    for n in notes {
+        let voice_key = VoiceKey::Loop(n.keycode);
+        if n.on {
+            play_note(play_state, rt, voice_key, n.velocity).await;
+            record(rt, voice_key, Some(n.velocity), at);
+        } else {
+            // off events carry the release velocity; ones the looper adds itself have none
+            let release = if n.velocity > 0.0 { n.velocity } else { VELOCITY_DEFAULT };
+            play_state.release_note(voice_key, rt.release_scale(release));
+            record(rt, voice_key, None, at);
+        }
+    }
+}
+
+async fn play_echoes(play_state: &mut PlayState, rt: &mut RuntimeState, events: Vec<EchoEvent>) {
+    let at = Instant::now();
+    for e in events {
+        match e {
+            EchoEvent::On(voice, velocity) => {
+                play_note(play_state, rt, VoiceKey::Echo(voice), velocity).await;
+                record(rt, VoiceKey::Echo(voice), Some(velocity), at);
+            }
+            EchoEvent::Off(voice) => {
+                play_state.stop_note(VoiceKey::Echo(voice));
+                record(rt, VoiceKey::Echo(voice), None, at);
+            }
+        }
+    }
+}
+
+/// the MIDI notes a voice sounds: its key's chord, or the General MIDI one for a drum
+fn midi_notes(rt: &RuntimeState, voice_key: VoiceKey, intervals: &[i32]) -> Vec<u8> {
+    if let Some(kind) = voice_key.drum() {
+        return vec![kind.midi_note()];
+    }
+    let Some(key) = voice_key.key(&rt.keymap) else { return vec![]; };
+    intervals.iter().map(|i| key.transpose(voice_key.semitones() + i).midi_note()).collect()
+}
+
+/// logs a voice starting (`Some(velocity)`) or being let go, while a take is recording
+fn record(rt: &mut RuntimeState, voice_key: VoiceKey, velocity: Option<f32>, at: Instant) {
+    let notes = match velocity {
+        Some(_) => {
+            let notes = midi_notes(rt, voice_key, rt.chord.intervals());
+            rt.chord_held.insert(voice_key, notes.clone());
+            notes
+        }
+        None => rt.chord_held.remove(&voice_key).unwrap_or_else(|| midi_notes(rt, voice_key, &[0])),
+    };
+    if !rt.recorder.is_recording() {
+        return;
+    }
+    for note in notes {
+        match velocity {
+            Some(v) => rt.recorder.note_on(voice_key.track(), note, v, at),
+            None => rt.recorder.note_off(voice_key.track(), note, at),
+        }
     }
 }
 
+/// passes the transport's tempo on to what is synced to it
+fn follow_tempo(rt: &mut RuntimeState) {
+    let bpm = rt.transport.bpm();
+    rt.mod_matrix.set_bpm(bpm);
+    rt.tremolo.set_bpm(bpm);
+    rt.autopan.set_bpm(bpm);
+    for chain in rt.chains.values() {
+        chain.set_bpm(bpm);
+    }
+    if rt.loop_bars > 0 {
+        rt.looper.set_fixed_length(Some(rt.transport.bar_duration() * rt.loop_bars));
+    }
+}
+
+fn transport_action(rt: &mut RuntimeState, action: TransportAction) {
+    let now = Instant::now();
+    match action {
+        TransportAction::Start => rt.transport.start(now),
+        TransportAction::Stop => rt.transport.stop(),
+        TransportAction::Toggle if rt.transport.is_running() => rt.transport.stop(),
+        TransportAction::Toggle => rt.transport.start(now),
+        TransportAction::Tap => {
+            if let Some(bpm) = rt.transport.tap(now) {
+                follow_tempo(rt);
+                rt.notice = Some(format!("tempo {bpm:.0} bpm"));
+            }
+        }
+    }
+}
+
+/// starts a take, or ends it and writes it out as a MIDI file
+fn set_recording(rt: &mut RuntimeState, on: bool) {
+    if on == rt.recorder.is_recording() {
+        return;
+    }
+    let now = Instant::now();
+    if on {
+        rt.recorder.start(now);
+        rt.notice = Some("recording, R again to save it as a MIDI file".into());
+        return;
+    }
+    rt.notice = Some(match rt.recorder.stop(now, &rt.transport) {
+        None => "recording stopped, nothing was played".into(),
+        Some(take) => match take.save() {
+            Ok(path) => format!("saved {} notes to {}", take.notes(), path.display()),
+            Err(e) => format!("could not save the recording: {e}"),
+        },
+    });
+}
+
+/// checks a played note against the running lesson; a finished lesson is saved and the next
+/// one starts
+fn observe_lesson(rt: &mut RuntimeState, key: Key) {
+    let Some(run) = rt.lesson.as_mut() else { return; };
+    let LessonStep::Finished { misses } = run.observe(key) else { return; };
+
+    let name = run.lesson().name;
+    let next = LessonRun::new(run.index() + 1, rt.keymap.octave());
+    rt.lesson_progress.record(name, misses);
+    rt.notice = Some(match lessons::save_progress(&rt.lesson_progress) {
+        Ok(()) => format!("finished {name} with {misses} misses · next: {}", next.lesson().name),
+        Err(e) => format!("finished {name}, but could not save progress: {e}"),
+    });
+    rt.lesson = Some(next);
+}
+
+/// note and drum keys: what the looper and echo follow
+fn is_played_key(k: Keycode) -> bool {
+    Key::from_keycode(k).is_some() || DrumKind::from_keycode(k).is_some()
+}
+
+/// keys the poller sees that never play a note: B cycles the patch, space is the sustain pedal
+/// and the left/right arrows bend
+fn is_control_key(k: Keycode) -> bool {
+    matches!(k, Keycode::B | Keycode::Space | Keycode::Left | Keycode::Right)
+}
+
+fn set_bend(rt: &mut RuntimeState, amount: f32) {
+    rt.bend = amount.clamp(-1.0, 1.0);
+    rt.mod_matrix.set_bend(rt.bend * rt.bend_range);
+}
+
+/// closes a live key's gate and tells the looper and echo it ended
+fn release_live(play_state: &mut PlayState, rt: &mut RuntimeState, k: Keycode, velocity: f32, at: Instant) {
+    play_state.release_note(VoiceKey::Live(k), rt.release_scale(velocity));
+    record(rt, VoiceKey::Live(k), None, at);
+    if is_played_key(k) {
+        rt.looper.record(k, false, velocity, at);
+        rt.echo.note_off(k, at);
+    }
+}
+
+/// pedal down holds every key let go from now on; up releases the ones not held anymore
+fn set_sustain(play_state: &mut PlayState, rt: &mut RuntimeState, on: bool) {
+    rt.sustain = on;
+    release_sustained(play_state, rt);
+}
+
+/// the pedal that stays down: latched keys ring on until it is turned off, unless the real pedal
+/// still holds them
+fn set_latch(play_state: &mut PlayState, rt: &mut RuntimeState, on: bool) {
+    rt.latch = on;
+    release_sustained(play_state, rt);
+}
+
+/// lets go of the keys the pedal and latch were holding once neither is on
+fn release_sustained(play_state: &mut PlayState, rt: &mut RuntimeState) {
+    if rt.sustain || rt.latch {
+        return;
+    }
+    let at = Instant::now();
+    for k in std::mem::take(&mut rt.sustained) {
+        release_live(play_state, rt, k, VELOCITY_DEFAULT, at);
+    }
+}
+
+/// for a stuck note or runaway feedback: cuts every voice, drops the echoes still to come, stops
+/// the loop playing (keeping the loop), empties the reverb and freeze tails and lets go of the
+/// pedal, latch and bend
+fn panic(play_state: &mut PlayState, rt: &mut RuntimeState) {
+    let at = Instant::now();
+    let open: Vec<VoiceKey> = rt.chord_held.keys().copied().collect();
+    for k in open {
+        record(rt, k, None, at);
+    }
+    rt.looper.stop(at);
+    rt.echo.clear();
+    rt.sustain = false;
+    rt.latch = false;
+    rt.sustained.clear();
+    set_bend(rt, 0.0);
+    play_state.kill_all();
+    play_state.reverb.flush();
+    play_state.freeze.flush();
+    rt.freeze = false;
+    rt.notice = Some("panic: all sound stopped".into());
+}
+
 fn cycle_patch(rt: &mut RuntimeState) {
     if rt.avaliable_patches.is_empty() {
         return;
     }
     rt.toggle_index = (rt.toggle_index + 1) % rt.avaliable_patches.len();
-    rt.current_patch = basic_source(match rt.toggle_index {
-        0 => BasicKind::Sine,
-        1 => BasicKind::Saw,
-        2 => BasicKind::Square,
-        3 => BasicKind::Triangle,
-        4 => BasicKind::Noise,
-        _ => BasicKind::Sine,
-    });
+    rt.current_patch = rt.avaliable_patches[rt.toggle_index].clone();
+}
+
+/// puts `path` on the sampler patch, adding the patch the first time, and switches to it
+fn load_sample(rt: &mut RuntimeState, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let sample = Arc::new(Sample::load(path)?);
+    let patch: Arc<dyn AudioSource> = Arc::new(SamplerPatch::new(sample.clone(), rt.tuning.frequency(rt.sampler.root_key())));
+    let index = match rt.avaliable_patches.iter().position(|p| p.name() == patch.name()) {
+        Some(i) => {
+            rt.avaliable_patches[i] = patch;
+            i
+        }
+        None => {
+            rt.avaliable_patches.push(patch);
+            rt.avaliable_patches.len() - 1
+        }
+    };
+    rt.toggle_index = index;
+    rt.current_patch = rt.avaliable_patches[index].clone();
+    rt.sample = Some(sample);
+    Ok(())
+}
+
+/// puts `path` in place of any script running, letting go of the old one's notes; returns the
+/// note-offs for those and whatever the new script's top level asked for
+fn load_script(rt: &mut RuntimeState, path: &Path) -> Result<Vec<ScriptEvent>, Box<dyn std::error::Error>> {
+    let mut script = Script::load(path)?;
+    let mut events = stop_script(rt);
+    events.extend(script.take_startup());
+    rt.script = Some(script);
+    Ok(events)
+}
+
+/// unloads the script; returns the note-offs for what it still held
+fn stop_script(rt: &mut RuntimeState) -> Vec<ScriptEvent> {
+    rt.script.take().map(|mut s| s.stop()).unwrap_or_default()
+}
+
+async fn play_script(play_state: &mut PlayState, rt: &mut RuntimeState, events: Vec<ScriptEvent>) {
+    let at = Instant::now();
+    for e in events {
+        match e {
+            ScriptEvent::NoteOn(key, velocity) => {
+                play_note(play_state, rt, VoiceKey::Note(Track::Script, key), velocity).await;
+                record(rt, VoiceKey::Note(Track::Script, key), Some(velocity), at);
+            }
+            ScriptEvent::NoteOff(key) => {
+                play_state.stop_note(VoiceKey::Note(Track::Script, key));
+                record(rt, VoiceKey::Note(Track::Script, key), None, at);
+            }
+            ScriptEvent::Patch(name) => {
+                match rt.avaliable_patches.iter().position(|p| p.name().eq_ignore_ascii_case(&name)) {
+                    // scripts may ask every beat; only a change crossfades
+                    Some(index) if rt.current_patch.name() != rt.avaliable_patches[index].name() => {
+                        rt.toggle_index = index;
+                        rt.current_patch = rt.avaliable_patches[index].clone();
+                        crossfade_active_notes(play_state, rt).await;
+                    }
+                    Some(_) => {}
+                    None => rt.notice = Some(format!("script: no patch called {name}")),
+                }
+            }
+            ScriptEvent::Param { owner, id, value } => apply_param(play_state, rt, &owner, &id, value),
+            ScriptEvent::Tempo(bpm) => {
+                rt.transport.set_bpm(bpm, Instant::now());
+                follow_tempo(rt);
+            }
+            ScriptEvent::Message(text) => rt.notice = Some(text),
+        }
+    }
+}
+
+fn save_preset(rt: &mut RuntimeState, name: &str) {
+    let preset = Preset {
+        patch: rt.current_patch.name().to_string(),
+        adsr: rt.adsr,
+        filter_env: rt.filter_env,
+        volume: rt.volume,
+        tilt: rt.tilt(),
+        drive: rt.drive(),
+        eq: rt.eq_settings(),
+        chain: rt.chain().map_or(vec![], FxChain::saved),
+        lfos: rt.mod_matrix.settings(),
+        // a freeze holds whatever was playing, which is no part of a sound to come back to
+        master: rt
+            .master
+            .iter()
+            .filter(|n| n.name() != "Freeze")
+            .map(|n| (n.name().to_string(), param_values(n.as_ref())))
+            .collect(),
+    };
+    match presets::save_preset(name, &preset) {
+        Ok(_) => {
+            rt.presets = presets::list_presets();
+            rt.preset_name = Some(name.to_string());
+            rt.notice = Some(format!("saved preset {name}"));
+        }
+        Err(e) => rt.notice = Some(format!("could not save {name}: {e}")),
+    }
+}
+
+fn load_preset(play_state: &mut PlayState, rt: &mut RuntimeState, name: &str) -> bool {
+    let preset = match presets::load_preset(name) {
+        Ok(p) => p,
+        Err(e) => {
+            rt.notice = Some(format!("could not load {name}: {e}"));
+            return false;
+        }
+    };
+    let Some(index) = rt.avaliable_patches.iter().position(|p| p.name() == preset.patch) else {
+        rt.notice = Some(format!("preset {name} uses unknown patch {}", preset.patch));
+        return false;
+    };
+
+    rt.toggle_index = index;
+    rt.current_patch = rt.avaliable_patches[index].clone();
+    rt.adsr = preset.adsr.clamped();
+    rt.filter_env = preset.filter_env.clamped();
+    rt.volume = preset.volume.clamp(0.0, 2.0);
+    rt.tilt.insert(rt.current_patch.name(), preset.tilt.clamp(-1.0, 1.0));
+    rt.drive.insert(rt.current_patch.name(), preset.drive.clamped());
+    rt.eq.entry(rt.current_patch.name()).or_default().set(preset.eq);
+    rt.chains.insert(rt.current_patch.name(), FxChain::from_saved(&preset.chain, rt.transport.bpm()));
+    for (index, lfo) in preset.lfos.iter().enumerate() {
+        rt.mod_matrix.set(index, *lfo);
+    }
+    for node in &rt.master {
+        if let Some(values) = preset.master.get(node.name()) {
+            set_param_values(node.as_ref(), values);
+        }
+    }
+    read_back_master(play_state, rt);
+    play_state.set_volume(rt.volume);
+    rt.preset_name = Some(name.to_string());
+    rt.notice = Some(format!("loaded preset {name}"));
+    true
+}
+
+/// the patches and everything else `settings` start with
+fn runtime_state(settings: &Settings) -> Result<RuntimeState, Box<dyn std::error::Error>> {
+    let mut avaliable_patches: Vec<Arc<dyn AudioSource>> = vec![
+        Arc::from(basic_source(BasicKind::Sine)),
+        Arc::from(basic_source(BasicKind::Saw)),
+        Arc::from(basic_source(BasicKind::Square)),
+        Arc::from(basic_source(BasicKind::Triangle)),
+        Arc::from(basic_source(BasicKind::Noise)),
+        Arc::from(supersaw_source()),
+        Arc::from(fm_source(FmParams::default())),
+        Arc::from(pluck_source()),
+    ];
+
+    if let Some(path) = &settings.audio.soundfont {
+        let font = SoundFont::load(path)?;
+        if settings.audio.soundfont_presets.is_empty() {
+            avaliable_patches.push(Arc::new(font.patch(None)?));
+        }
+        for name in &settings.audio.soundfont_presets {
+            avaliable_patches.push(Arc::new(font.patch(Some(name))?));
+        }
+    }
+
+    let bindings = Arc::new(KeyBindings::from_settings(&settings.keymap)?);
+    let mut rt = RuntimeState {
+        volume: 1.0,
+        muted: false,
+        adsr: Adsr::new(ADSR_ATTACK_S, ADSR_DECAY_S, ADSR_SUSTAIN, ADSR_RELEASE_S),
+        filter_env: FilterEnvelope::default(),
+        current_patch: avaliable_patches[0].clone(),
+        avaliable_patches,
+        toggle_index: 0,
+        held_keys: HashSet::new(),
+        sustain: false,
+        latch: false,
+        sustained: HashSet::new(),
+        bend: 0.0,
+        bend_range: settings.input.bend_range.abs(),
+        presets: presets::list_presets(),
+        preset_name: None,
+        notice: None,
+        master_peak_db: to_db(0.0),
+        master_rms_db: to_db(0.0),
+        voices: 0,
+        audio_load: 0.0,
+        underruns: 0,
+        gain_advisor: GainAdvisor::new(),
+        gain_advice: None,
+        auto_gain: false,
+        mod_matrix: ModMatrix::default(),
+        chord_assist: Arc::new(AtomicBool::new(settings.input.chord_assist)),
+        chord: Chord::new(settings.input.chord, &settings.input.custom_chord),
+        chord_held: HashMap::new(),
+        rollover_test: None,
+        max_rollover: 0,
+        velocity_attack: settings.input.velocity_attack,
+        release_velocity: settings.input.release_velocity,
+        echo: NoteEcho::new(EchoSettings::default()),
+        transport: Transport::new(settings.transport.bpm, settings.transport.time_signature),
+        loop_bars: settings.looper.length_bars,
+        scale_lock: ScaleLock::default(),
+        tuning: Tuning::from_settings(&settings.tuning)?,
+        sampler: settings.sampler.clone(),
+        sample: None,
+        keymap: Keymap::new(ScaleLock::default(), settings.input.octave).with_bindings(bindings),
+        reverb: ReverbSettings::default(),
+        tilt: HashMap::new(),
+        ringmod: RingMod::new(),
+        // stand-ins until `connect_master` hands over the ones on the master bus
+        tremolo: Tremolo::new(settings.audio.master_tremolo),
+        autopan: AutoPan::new(settings.audio.master_autopan),
+        drive: HashMap::new(),
+        eq: HashMap::new(),
+        chains: HashMap::new(),
+        master_drive: settings.audio.master_drive.clamped(),
+        compressor: settings.audio.master_compressor.clamped(),
+        gain_reduction_db: 0.0,
+        freeze: false,
+        master: vec![],
+        tracks: [TrackSettings::default(); Track::COUNT],
+        recorder: Recorder::new(),
+        lesson: None,
+        lesson_progress: lessons::load_progress(),
+        script: None,
+        cc_bindings: settings.midi.cc.clone(),
+        cc_learn: None,
+        settings_file: settings.source.clone(),
+        looper: Looper::new(
+            (settings.looper.length_s > 0.0).then(|| Duration::from_secs_f32(settings.looper.length_s)),
+        ),
+    };
+
+    rt.mod_matrix.set_analog_cents(settings.audio.analog_cents);
+
+    if let Some(path) = &settings.sampler.file {
+        load_sample(&mut rt, path)?;
+    }
+
+    if let Some(name) = &settings.audio.patch {
+        let Some(index) = rt.avaliable_patches.iter().position(|p| p.name().eq_ignore_ascii_case(name)) else {
+            let names: Vec<&str> = rt.avaliable_patches.iter().map(|p| p.name()).collect();
+            return Err(format!("no patch called {name} (patches: {})", names.join(", ")).into());
+        };
+        rt.toggle_index = index;
+        rt.current_patch = rt.avaliable_patches[index].clone();
+    }
+
+    follow_tempo(&mut rt);
+    Ok(rt)
+}
+
+/// plays a MIDI file through the engine as fast as it will go, with the patch and effects
+/// `settings` start with (and `preset` over them), and writes the mix to `out` as a WAV file at
+/// the engine rate. channel 10 plays the drums. returns the length written, tail included
+pub async fn render(
+    settings: &Settings,
+    preset: Option<&str>,
+    midi: &Path,
+    out: &Path,
+) -> Result<Duration, Box<dyn std::error::Error>> {
+    let notes = render::read_midi(midi)?;
+    let mut rt = runtime_state(settings)?;
+    let (mut play_state, [mut left, mut right]) = PlayState::offline(&settings.audio)?;
+    connect_master(&mut play_state, &mut rt);
+    // after the master bus is up, so the preset's master effects have somewhere to go
+    if let Some(name) = preset
+        && !load_preset(&mut play_state, &mut rt, name)
+    {
+        return Err(rt.notice.take().unwrap_or_default().into());
+    }
+
+    let mut wav = WavWriter::create(out, SAMPLE_RATE)?;
+    let mut written = 0usize;
+    // the peak of what was pulled, for spotting the end of the tail
+    let mut pull = |wav: &mut WavWriter, frames: usize| -> Result<f32, Box<dyn std::error::Error>> {
+        let mut peak = 0.0f32;
+        for _ in 0..frames {
+            let frame = [left.next().unwrap_or(0.0), right.next().unwrap_or(0.0)];
+            peak = peak.max(frame[0].abs()).max(frame[1].abs());
+            wav.write(frame)?;
+        }
+        Ok(peak)
+    };
+
+    for n in notes {
+        let due = (n.at_s * SAMPLE_RATE as f64) as usize;
+        pull(&mut wav, due.saturating_sub(written))?;
+        written = written.max(due);
+        let track = if n.channel == MIDI_DRUM_CHANNEL { Track::Drums } else { Track::Live };
+        let voice_key = VoiceKey::Note(track, Key::from_midi(n.note));
+        if track == Track::Drums && voice_key.drum().is_none() {
+            continue;
+        }
+        if n.velocity > 0 {
+            play_note(&mut play_state, &rt, voice_key, n.velocity as f32 / 127.0).await;
+        } else {
+            play_state.stop_note(voice_key);
+        }
+        play_state.cleanup_finished();
+    }
+
+    // until every voice has finished and the effects have gone quiet
+    let tail_end = written + (RENDER_TAIL_MAX_S * SAMPLE_RATE as f32) as usize;
+    while written < tail_end {
+        let peak = pull(&mut wav, RENDER_BLOCK)?;
+        written += RENDER_BLOCK;
+        play_state.cleanup_finished();
+        if play_state.active_voices.is_empty() && play_state.releasing.is_empty() && play_state.engine.voice_count() == 0 && peak < RENDER_SILENCE {
+            break;
+        }
+    }
+    wav.finish()?;
+    Ok(Duration::from_secs_f64(written as f64 / SAMPLE_RATE as f64))
+}
+
+/// hands the master effects' controls to `rt` and the volume to the engine
+fn connect_master(play_state: &mut PlayState, rt: &mut RuntimeState) {
+    rt.reverb = play_state.reverb.settings();
+    rt.master = play_state.master_nodes();
+    rt.master.push(Box::new(rt.ringmod.clone()));
+    rt.tremolo = play_state.tremolo.clone();
+    rt.autopan = play_state.autopan.clone();
+    follow_tempo(rt);
+    play_state.set_volume(rt.volume);
+    play_state.set_muted(rt.muted);
 }
 
 pub async fn run_audio(
     mut shutdown: tokio::sync::watch::Receiver<bool>,
     focused: Arc<AtomicBool>,
+    settings: Settings,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let _handle = audio_system::get_handle().await.clone();
+    let handle = audio_system::get_handle().await.clone();
     let (mut cmd_rx, snapshot_tx, initial) = audio_system::take_runtime_channels().await;
 
-    let mut rt = RuntimeState {
-        volume: initial.volume,
-        muted: initial.muted,
-        adsr: Adsr::new(ADSR_ATTACK_S, ADSR_DECAY_S, ADSR_SUSTAIN, ADSR_RELEASE_S),
-        current_patch: basic_source(BasicKind::Sine),
-        avaliable_patches: vec![
-            basic_source(BasicKind::Sine),
-            basic_source(BasicKind::Saw),
-            basic_source(BasicKind::Square),
-            basic_source(BasicKind::Triangle),
-            basic_source(BasicKind::Noise),
-        ],
-        toggle_index: 0,
-        held_keys: HashSet::new(),
+    let mut rt = runtime_state(&settings)?;
+    // kept open until the engine stops
+    let _midi = match settings.midi.input.then(|| midi_in::listen(&settings.midi, &handle)) {
+        Some(Ok(connections)) => connections,
+        Some(Err(e)) => {
+            rt.notice = Some(format!("no MIDI input: {e}"));
+            vec![]
+        }
+        None => vec![],
+    };
+    rt.volume = initial.volume;
+    rt.muted = initial.muted;
+    rt.presets = initial.presets.clone();
+    rt.auto_gain = initial.auto_gain;
+    let startup = match &settings.script.file {
+        Some(path) => load_script(&mut rt, path)?,
+        None => vec![],
     };
 
-    let mut play_state = PlayState::new()?;
+    let mut play_state = PlayState::new(&settings.audio).map_err(crash::engine_failed)?;
+    connect_master(&mut play_state, &mut rt);
+    play_script(&mut play_state, &mut rt, startup).await;
     publish_snapshot(&snapshot_tx, &rt);
 
     let stop_flag = Arc::new(AtomicBool::new(false));
     let stop_flag_bg = stop_flag.clone();
 
-    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<Option<(HashSet<Keycode>, HashSet<Keycode>, bool)>>();
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<Option<(KeyUpdate, Instant)>>();
 
     let focused_bg = focused.clone();
+    let chord_assist_bg = rt.chord_assist.clone();
+    let chord_window = Duration::from_millis(settings.input.chord_window_ms);
+    let bindings_bg = rt.keymap.bindings().clone();
 
     let poll_handle = task::spawn_blocking(move || {
-        let device_state = DeviceState::new();
+        let Some(device_state) = DeviceState::checked_new() else {
+            // no X display (e.g. headless on a Pi): nothing to poll, the control socket still works
+            while !stop_flag_bg.load(Ordering::Relaxed) {
+                std::thread::sleep(Duration::from_millis(TICK));
+            }
+            let _ = tx.send(None);
+            return;
+        };
 
-        let mut prev: HashSet<Keycode> = HashSet::new();
+        let mut keys = KeyState::new();
         let mut was_focused = true;
+        let mut assist = ChordAssist::new(chord_window);
 
         loop {
             if stop_flag_bg.load(Ordering::Relaxed) {
@@ -199,10 +1458,9 @@ pub async fn run_audio(
 
             if !is_focused {
                 if was_focused {
-                    if !prev.is_empty() {
-                        let empty: HashSet<Keycode> = HashSet::new();
-                        let _ = tx.send(Some((empty, prev.clone(), false)));
-                        prev.clear();
+                    let update = keys.release_all();
+                    if !update.is_empty() {
+                        let _ = tx.send(Some((update, Instant::now())));
                     }
                     was_focused = false;
                 }
@@ -210,24 +1468,39 @@ pub async fn run_audio(
             }
 
             if !was_focused {
-                prev = device_state.get_keys().into_iter().collect();
+                let raw: HashSet<Keycode> = device_state.get_keys().into_iter().collect();
+                keys.resync(&bindings_bg.translate(&raw), Instant::now());
                 was_focused = true;
                 continue;
             }
 
-            let now: HashSet<Keycode> = device_state.get_keys().into_iter().collect();
+            let t = PROFILER.start();
+            let raw: HashSet<Keycode> = device_state.get_keys().into_iter().collect();
+            profiler::finish(&PROFILER.input_poll, t);
 
-            if now.contains(&Keycode::Escape)
-                || (now.contains(&Keycode::C) && now.contains(&Keycode::LControl))
+            if raw.contains(&Keycode::Escape)
+                || (raw.contains(&Keycode::C) && raw.contains(&Keycode::LControl))
             {
                 let _ = tx.send(None);
                 break;
             }
+            // with Ctrl down the letters are shortcuts (Ctrl+K is the panic), not notes; the poll
+            // picks up again once it comes up
+            if raw.contains(&Keycode::LControl) || raw.contains(&Keycode::RControl) {
+                continue;
+            }
+            let raw = bindings_bg.translate(&raw);
 
-            if now != prev {
-                let toggle_b = now.contains(&Keycode::B) && !prev.contains(&Keycode::B);
-                let _ = tx.send(Some((now.clone(), prev.clone(), toggle_b)));
-                prev = now;
+            let now = if chord_assist_bg.load(Ordering::Relaxed) {
+                assist.filter(&raw, Instant::now())
+            } else {
+                assist.reset();
+                raw
+            };
+
+            let update = keys.update(&now, Instant::now());
+            if !update.is_empty() {
+                let _ = tx.send(Some((update, Instant::now())));
             }
         }
     });
@@ -235,36 +1508,117 @@ pub async fn run_audio(
     let ctrl_c = ctrl_c();
     tokio::pin!(ctrl_c);
 
+    let mut meter_tick = tokio::time::interval(Duration::from_millis(METER_TICK_MS));
+    let mut schedule_tick = tokio::time::interval(Duration::from_millis(SCHEDULE_TICK_MS));
+
     loop {
         tokio::select! {
             _ = &mut ctrl_c => break,
 
+            _ = schedule_tick.tick() => {
+                let now = Instant::now();
+                let due = rt.looper.poll(now);
+                let echoes = rt.echo.poll(now);
+                let scripted = match rt.script.as_mut() {
+                    Some(script) => script.poll(now, &rt.transport),
+                    None => vec![],
+                };
+                if !due.is_empty() || !echoes.is_empty() {
+                    play_loop_notes(&mut play_state, &mut rt, due).await;
+                    play_echoes(&mut play_state, &mut rt, echoes).await;
+                    play_state.cleanup_finished();
+                }
+                if !scripted.is_empty() {
+                    play_script(&mut play_state, &mut rt, scripted).await;
+                    play_state.cleanup_finished();
+                    publish_snapshot(&snapshot_tx, &rt);
+                }
+            }
+
+            _ = meter_tick.tick() => {
+                if update_gain_staging(&play_state, &mut rt) {
+                    play_state.set_volume(rt.volume);
+                }
+                update_rollover_test(&mut rt);
+                rt.voices = play_state.engine.voice_count();
+                rt.audio_load = play_state.engine.load();
+                rt.underruns = play_state.engine.underruns();
+                if let Some(path) = crash::take_pending() {
+                    rt.notice = Some(format!("audio error, report saved to {}", path.display()));
+                }
+                publish_snapshot(&snapshot_tx, &rt);
+            }
+
             _ = shutdown.changed() => {
                 if *shutdown.borrow() { break; }
             }
 
             msg = rx.recv() => {
                 match msg {
-                    Some(Some((now, prev, toggle_b))) => {
-                        rt.held_keys = now.iter().copied().filter(|k| *k != Keycode::B).collect();
+                    Some(Some((update, seen))) => {
+                        rt.held_keys = update.held.iter().copied().filter(|k| !is_control_key(*k)).collect();
+                        rt.max_rollover = update.max_held;
 
-                        if toggle_b {
+                        if update.pressed.contains(&Keycode::B) {
                             cycle_patch(&mut rt);
                             publish_snapshot(&snapshot_tx, &rt);
-                            restart_active_notes(&mut play_state, &rt).await;
+                            crossfade_active_notes(&mut play_state, &rt).await;
+                        }
+
+                        // space is the sustain pedal; seen before the notes so a key let go in
+                        // the same poll as the pedal goes down is already held by it
+                        if update.pressed.contains(&Keycode::Space) {
+                            set_sustain(&mut play_state, &mut rt, true);
+                        } else if update.released.contains(&Keycode::Space) {
+                            set_sustain(&mut play_state, &mut rt, false);
                         }
 
-                        for k in now.difference(&prev) {
-                            if *k == Keycode::B { continue; }
-                            play_note(&mut play_state, &rt, *k).await;
+                        // the arrows bend while held and spring back when let go, like a wheel
+                        let arrows = [Keycode::Left, Keycode::Right];
+                        if arrows.iter().any(|k| update.pressed.contains(k) || update.released.contains(k)) {
+                            let held = |k| if update.held.contains(&k) { 1.0 } else { 0.0 };
+                            set_bend(&mut rt, held(Keycode::Right) - held(Keycode::Left));
                         }
 
-                        for k in prev.difference(&now) {
-                            if *k == Keycode::B { continue; }
-                            play_state.stop_note(*k);
+                        let at = Instant::now();
+                        // Shift held = accent
+                        let velocity = if update.held.contains(&Keycode::LShift) || update.held.contains(&Keycode::RShift) {
+                            VELOCITY_ACCENT
+                        } else {
+                            VELOCITY_DEFAULT
+                        };
+                        for k in &update.pressed {
+                            if is_control_key(*k) { continue; }
+                            // struck again under the pedal: the ringing voice lets go for the new one
+                            if rt.sustained.remove(k) {
+                                play_state.stop_note(VoiceKey::Live(*k));
+                                record(&mut rt, VoiceKey::Live(*k), None, at);
+                            }
+                            play_note(&mut play_state, &rt, VoiceKey::Live(*k), velocity).await;
+                            record(&mut rt, VoiceKey::Live(*k), Some(velocity), at);
+                            if is_played_key(*k) {
+                                rt.looper.record(*k, true, velocity, at);
+                                rt.echo.note_on(*k, velocity, rt.transport.bpm(), at);
+                                if let Some(key) = rt.keymap.key(*k) {
+                                    observe_lesson(&mut rt, key);
+                                }
+                            }
                         }
 
+                        for k in &update.released {
+                            if is_control_key(*k) { continue; }
+                            if rt.sustain || rt.latch {
+                                rt.sustained.insert(*k);
+                                continue;
+                            }
+                            // Shift still held as the key comes up = fast release
+                            release_live(&mut play_state, &mut rt, *k, velocity, at);
+                        }
+
+                        profiler::finish(&PROFILER.input_latency, PROFILER.enabled().then_some(seen));
+                        update_rollover_test(&mut rt);
                         play_state.cleanup_finished();
+                        publish_snapshot(&snapshot_tx, &rt);
                     }
                     Some(None) | None => break,
                 }
@@ -276,32 +1630,265 @@ pub async fn run_audio(
                 match cmd {
                     audio_system::AudioCommand::SetVolume(v) => {
                         rt.volume = v.clamp(0.0, 2.0);
-                        play_state.set_all_volume(rt.volume);
+                        play_state.set_volume(rt.volume);
                         publish_snapshot(&snapshot_tx, &rt);
                     }
                     audio_system::AudioCommand::SetMuted(m) => {
                         rt.muted = m;
-                        play_state.set_all_muted(rt.muted);
+                        play_state.set_muted(rt.muted);
                         publish_snapshot(&snapshot_tx, &rt);
                     }
                     audio_system::AudioCommand::TogglePatch(patches) => {
                         if !patches.is_empty() {
-                            rt.avaliable_patches = patches;
+                            rt.avaliable_patches = patches.into_iter().map(Arc::from).collect();
                             rt.toggle_index = 0;
-                            rt.current_patch = basic_source(BasicKind::Sine);
+                            rt.current_patch = rt.avaliable_patches[0].clone();
                             publish_snapshot(&snapshot_tx, &rt);
-                            restart_active_notes(&mut play_state, &rt).await;
+                            crossfade_active_notes(&mut play_state, &rt).await;
                         }
                     }
                     audio_system::AudioCommand::SetPatch(patch) => {
-                        rt.current_patch = patch;
+                        rt.current_patch = Arc::from(patch);
                         publish_snapshot(&snapshot_tx, &rt);
-                        restart_active_notes(&mut play_state, &rt).await;
+                        crossfade_active_notes(&mut play_state, &rt).await;
+                    }
+                    audio_system::AudioCommand::NextPatch => {
+                        cycle_patch(&mut rt);
+                        publish_snapshot(&snapshot_tx, &rt);
+                        crossfade_active_notes(&mut play_state, &rt).await;
+                    }
+                    audio_system::AudioCommand::SelectPatch(name) => {
+                        match rt.avaliable_patches.iter().position(|p| p.name().eq_ignore_ascii_case(&name)) {
+                            Some(index) => {
+                                rt.toggle_index = index;
+                                rt.current_patch = rt.avaliable_patches[index].clone();
+                                publish_snapshot(&snapshot_tx, &rt);
+                                crossfade_active_notes(&mut play_state, &rt).await;
+                            }
+                            None => {
+                                rt.notice = Some(format!("no patch called {name}"));
+                                publish_snapshot(&snapshot_tx, &rt);
+                            }
+                        }
                     }
                     audio_system::AudioCommand::SetAdsr(adsr) => {
                         rt.adsr = adsr;
                         publish_snapshot(&snapshot_tx, &rt);
-                        restart_active_notes(&mut play_state, &rt).await;
+                        crossfade_active_notes(&mut play_state, &rt).await;
+                    }
+                    audio_system::AudioCommand::SetFilterEnv(filter_env) => {
+                        rt.filter_env = filter_env.clamped();
+                        publish_snapshot(&snapshot_tx, &rt);
+                        crossfade_active_notes(&mut play_state, &rt).await;
+                    }
+                    audio_system::AudioCommand::SetParam { owner, id, value } => {
+                        apply_param(&play_state, &mut rt, &owner, &id, value);
+                        publish_snapshot(&snapshot_tx, &rt);
+                    }
+                    audio_system::AudioCommand::MidiCc { channel, cc, value } => {
+                        midi_cc(&play_state, &mut rt, channel, cc, value);
+                        publish_snapshot(&snapshot_tx, &rt);
+                    }
+                    audio_system::AudioCommand::LearnCc(param) => {
+                        learn_cc(&mut rt, param);
+                        publish_snapshot(&snapshot_tx, &rt);
+                    }
+                    audio_system::AudioCommand::ForgetCc { owner, id } => {
+                        forget_cc(&mut rt, &owner, &id);
+                        publish_snapshot(&snapshot_tx, &rt);
+                    }
+                    audio_system::AudioCommand::EditChain(edit) => {
+                        let changed = edit_chain(&mut rt, edit);
+                        publish_snapshot(&snapshot_tx, &rt);
+                        if changed {
+                            crossfade_active_notes(&mut play_state, &rt).await;
+                        }
+                    }
+                    audio_system::AudioCommand::SetTempo(bpm) => {
+                        rt.transport.set_bpm(bpm, Instant::now());
+                        follow_tempo(&mut rt);
+                        publish_snapshot(&snapshot_tx, &rt);
+                    }
+                    audio_system::AudioCommand::SetTimeSignature(signature) => {
+                        rt.transport.set_signature(signature);
+                        follow_tempo(&mut rt);
+                        publish_snapshot(&snapshot_tx, &rt);
+                    }
+                    audio_system::AudioCommand::Transport(action) => {
+                        transport_action(&mut rt, action);
+                        publish_snapshot(&snapshot_tx, &rt);
+                    }
+                    audio_system::AudioCommand::SetRecording(on) => {
+                        set_recording(&mut rt, on);
+                        publish_snapshot(&snapshot_tx, &rt);
+                    }
+                    audio_system::AudioCommand::SetTrack(track, settings) => {
+                        play_state.engine.set_track(track, settings);
+                        rt.tracks[track.index()] = play_state.engine.track(track);
+                        publish_snapshot(&snapshot_tx, &rt);
+                    }
+                    audio_system::AudioCommand::SetBend(amount) => {
+                        set_bend(&mut rt, amount);
+                        publish_snapshot(&snapshot_tx, &rt);
+                    }
+                    audio_system::AudioCommand::SetSustain(on) => {
+                        set_sustain(&mut play_state, &mut rt, on);
+                        play_state.cleanup_finished();
+                        publish_snapshot(&snapshot_tx, &rt);
+                    }
+                    audio_system::AudioCommand::Panic => {
+                        panic(&mut play_state, &mut rt);
+                        publish_snapshot(&snapshot_tx, &rt);
+                    }
+                    audio_system::AudioCommand::SetLatch(on) => {
+                        set_latch(&mut play_state, &mut rt, on);
+                        play_state.cleanup_finished();
+                        publish_snapshot(&snapshot_tx, &rt);
+                    }
+                    audio_system::AudioCommand::ApplyGainAdvice => {
+                        if apply_gain_advice(&mut rt) {
+                            play_state.set_volume(rt.volume);
+                        }
+                        publish_snapshot(&snapshot_tx, &rt);
+                    }
+                    audio_system::AudioCommand::SetAutoGain(on) => {
+                        rt.auto_gain = on;
+                        publish_snapshot(&snapshot_tx, &rt);
+                    }
+                    audio_system::AudioCommand::SetLfo(index, settings) => {
+                        // voices read the table every sample, nothing to restart
+                        rt.mod_matrix.set(index, settings);
+                        publish_snapshot(&snapshot_tx, &rt);
+                    }
+                    audio_system::AudioCommand::SetChord(kind) => {
+                        rt.chord.kind = kind;
+                        rt.notice = Some(format!("chord {}", kind.name()));
+                        publish_snapshot(&snapshot_tx, &rt);
+                    }
+                    audio_system::AudioCommand::SetChordAssist(on) => {
+                        rt.chord_assist.store(on, Ordering::Relaxed);
+                        rt.notice = Some(format!("chord assist {}", if on { "on" } else { "off" }));
+                        publish_snapshot(&snapshot_tx, &rt);
+                    }
+                    audio_system::AudioCommand::Looper(action) => {
+                        let now = Instant::now();
+                        let released = match action {
+                            LooperAction::Advance => {
+                                rt.looper.advance(now);
+                                vec![]
+                            }
+                            LooperAction::TogglePlay => rt.looper.toggle_play(now),
+                            LooperAction::UndoLayer => rt.looper.undo_layer(now),
+                            LooperAction::Clear => rt.looper.clear(),
+                        };
+                        play_loop_notes(&mut play_state, &mut rt, released).await;
+                        publish_snapshot(&snapshot_tx, &rt);
+                    }
+                    audio_system::AudioCommand::SetScaleLock(lock) => {
+                        rt.scale_lock = lock;
+                        rt.keymap = Keymap::new(lock, rt.keymap.octave()).with_bindings(rt.keymap.bindings().clone());
+                        publish_snapshot(&snapshot_tx, &rt);
+                    }
+                    audio_system::AudioCommand::Lesson(action) => {
+                        let start = match (action, &rt.lesson) {
+                            (LessonAction::Toggle, Some(_)) => None,
+                            (LessonAction::Next, Some(run)) => Some(run.index() + 1),
+                            (_, None) => Some(rt.lesson_progress.first_unfinished()),
+                        };
+                        rt.lesson = start.map(|i| LessonRun::new(i, rt.keymap.octave()));
+                        rt.notice = Some(match &rt.lesson {
+                            Some(run) => format!("lesson: {}", run.lesson().name),
+                            None => "lesson stopped".to_string(),
+                        });
+                        publish_snapshot(&snapshot_tx, &rt);
+                    }
+                    audio_system::AudioCommand::SetReverb(reverb) => {
+                        play_state.reverb.set(reverb);
+                        rt.reverb = play_state.reverb.settings();
+                        publish_snapshot(&snapshot_tx, &rt);
+                    }
+                    audio_system::AudioCommand::SetFreeze(on) => {
+                        play_state.freeze.set(on);
+                        rt.freeze = play_state.freeze.is_on();
+                        rt.notice = Some(if on { "freeze: holding the sound".to_string() } else { "freeze off".to_string() });
+                        publish_snapshot(&snapshot_tx, &rt);
+                    }
+                    audio_system::AudioCommand::SetDrive(target, drive) => {
+                        let drive = drive.clamped();
+                        let on = if drive.enabled {
+                            format!("{} {:+.0} dB", drive.curve.name(), drive.drive_db)
+                        } else {
+                            "off".to_string()
+                        };
+                        match target {
+                            DriveTarget::Patch => {
+                                rt.drive.insert(rt.current_patch.name(), drive);
+                                rt.notice = Some(format!("{} drive {on}", rt.current_patch.name()));
+                            }
+                            DriveTarget::Master => {
+                                play_state.drive.set(drive);
+                                rt.master_drive = play_state.drive.settings();
+                                rt.notice = Some(format!("master drive {on}"));
+                            }
+                        }
+                        publish_snapshot(&snapshot_tx, &rt);
+                    }
+                    audio_system::AudioCommand::SetTilt(tilt) => {
+                        rt.tilt.insert(rt.current_patch.name(), tilt.clamp(-1.0, 1.0));
+                        publish_snapshot(&snapshot_tx, &rt);
+                        crossfade_active_notes(&mut play_state, &rt).await;
+                    }
+                    audio_system::AudioCommand::SetEcho(echo) => {
+                        rt.echo.settings = echo;
+                        publish_snapshot(&snapshot_tx, &rt);
+                    }
+                    audio_system::AudioCommand::StartRolloverTest => {
+                        let test = RolloverTest::new(Instant::now());
+                        rt.notice = Some(test.prompt(&rt.keymap));
+                        rt.rollover_test = Some(test);
+                        publish_snapshot(&snapshot_tx, &rt);
+                    }
+                    audio_system::AudioCommand::SavePreset(name) => {
+                        save_preset(&mut rt, &name);
+                        publish_snapshot(&snapshot_tx, &rt);
+                    }
+                    audio_system::AudioCommand::LoadSample(path) => {
+                        match load_sample(&mut rt, &path) {
+                            Ok(()) => {
+                                let name = rt.sample.as_ref().map(|s| s.name()).unwrap_or_default();
+                                rt.notice = Some(format!("loaded sample {name}"));
+                                publish_snapshot(&snapshot_tx, &rt);
+                                crossfade_active_notes(&mut play_state, &rt).await;
+                            }
+                            Err(e) => {
+                                rt.notice = Some(format!("could not load sample: {e}"));
+                                publish_snapshot(&snapshot_tx, &rt);
+                            }
+                        }
+                    }
+                    audio_system::AudioCommand::LoadScript(path) => {
+                        match load_script(&mut rt, &path) {
+                            Ok(startup) => {
+                                let name = rt.script.as_ref().map(|s| s.name()).unwrap_or_default();
+                                rt.notice = Some(format!("loaded script {name}"));
+                                play_script(&mut play_state, &mut rt, startup).await;
+                            }
+                            Err(e) => rt.notice = Some(format!("could not load script: {e}")),
+                        }
+                        publish_snapshot(&snapshot_tx, &rt);
+                    }
+                    audio_system::AudioCommand::StopScript => {
+                        let held = stop_script(&mut rt);
+                        play_script(&mut play_state, &mut rt, held).await;
+                        publish_snapshot(&snapshot_tx, &rt);
+                    }
+                    audio_system::AudioCommand::LoadPreset(name) => {
+                        if load_preset(&mut play_state, &mut rt, &name) {
+                            publish_snapshot(&snapshot_tx, &rt);
+                            crossfade_active_notes(&mut play_state, &rt).await;
+                        } else {
+                            publish_snapshot(&snapshot_tx, &rt);
+                        }
                     }
                 }
 
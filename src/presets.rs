@@ -0,0 +1,166 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::audio_patch::ParamValues;
+use crate::config::{PRESETS_DIR, PRESET_EXT};
+use crate::fx::adsr::{Adsr, FilterEnvelope};
+use crate::fx::chain::SavedSlot;
+use crate::fx::drive::DriveSettings;
+use crate::fx::eq::EqSettings;
+use crate::fx::lfo::LfoSettings;
+use crate::settings::config_dir;
+
+/// everything needed to bring the synth back to a saved sound
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Preset {
+    pub patch: String,
+    pub adsr: Adsr,
+    /// older presets have none, which leaves the filter open
+    #[serde(default)]
+    pub filter_env: FilterEnvelope,
+    pub volume: f32,
+    /// tilt EQ of the patch; older presets have none
+    #[serde(default)]
+    pub tilt: f32,
+    /// the patch's waveshaper; older presets have none, which leaves it off
+    #[serde(default)]
+    pub drive: DriveSettings,
+    /// the patch's three-band EQ; older presets have none, which leaves it flat
+    #[serde(default)]
+    pub eq: EqSettings,
+    /// the patch's effect chain; older presets have none, which leaves it empty
+    #[serde(default)]
+    pub chain: Vec<SavedSlot>,
+    /// the LFO routes, one per target; older presets have none, which leaves them as they are
+    #[serde(default)]
+    pub lfos: Vec<LfoSettings>,
+    /// the master effects' parameters by effect name, as `set_param` names them; older presets
+    /// have none, which leaves those effects as they are
+    #[serde(default)]
+    pub master: BTreeMap<String, ParamValues>,
+}
+
+pub fn presets_dir() -> Option<PathBuf> {
+    config_dir().map(|d| d.join(PRESETS_DIR))
+}
+
+fn preset_path(name: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let name = name.trim();
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        return Err(format!("invalid preset name: {name:?}").into());
+    }
+    let dir = presets_dir().ok_or("could not resolve the config directory")?;
+    Ok(dir.join(format!("{name}.{PRESET_EXT}")))
+}
+
+pub fn save_preset(name: &str, preset: &Preset) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let path = preset_path(name)?;
+    write_preset(&path, preset)?;
+    Ok(path)
+}
+
+pub fn load_preset(name: &str) -> Result<Preset, Box<dyn std::error::Error>> {
+    read_preset(&preset_path(name)?)
+}
+
+fn write_preset(path: &Path, preset: &Preset) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, toml::to_string_pretty(preset)?)?;
+    Ok(())
+}
+
+fn read_preset(path: &Path) -> Result<Preset, Box<dyn std::error::Error>> {
+    let text = fs::read_to_string(path)?;
+    Ok(toml::from_str(&text)?)
+}
+
+/// names of every preset on disk, sorted
+pub fn list_presets() -> Vec<String> {
+    let Some(dir) = presets_dir() else { return vec![]; };
+    let Ok(entries) = fs::read_dir(dir) else { return vec![]; };
+
+    let mut names: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == PRESET_EXT))
+        .filter_map(|p| p.file_stem().map(|s| s.to_string_lossy().into_owned()))
+        .collect();
+    names.sort();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_patch::ParamValue;
+    use crate::config::{ADSR_TIME_MAX_S, ADSR_TIME_MIN_S};
+    use crate::fx::adsr::{Curve, Retrigger};
+    use crate::fx::chain::{FxChain, FxKind};
+    use crate::fx::lfo::ModTarget;
+
+    #[test]
+    fn a_saved_preset_loads_back_the_same() {
+        let mut chain = FxChain::default();
+        chain.push(FxKind::Phaser, 120.0);
+        chain.push(FxKind::Drive, 120.0);
+        chain.set_param(0, "rate_hz", ParamValue::Float(3.25)).unwrap();
+        chain.set_bypassed(1, true).unwrap();
+
+        let mut master = BTreeMap::new();
+        master.insert("Reverb".to_string(), BTreeMap::from([("mix".to_string(), ParamValue::Float(0.5))]));
+        let preset = Preset {
+            patch: "Supersaw".into(),
+            adsr: Adsr {
+                attack_curve: Curve::Exponential,
+                release_curve: Curve::SCurve,
+                retrigger: Retrigger::Legato,
+                ..Adsr::new(0.02, 0.3, 0.7, 1.5)
+            },
+            filter_env: FilterEnvelope { adsr: Adsr::new(0.005, 0.2, 0.1, 0.4), depth: 3.5 },
+            volume: 0.8,
+            tilt: -0.25,
+            drive: DriveSettings { enabled: true, ..DriveSettings::default() },
+            eq: EqSettings { low_db: -3.0, mid_hz: 800.0, mid_db: 4.5, high_db: 2.0, ..EqSettings::default() },
+            chain: chain.saved(),
+            lfos: vec![LfoSettings { enabled: true, depth: 0.4, ..LfoSettings::new(ModTarget::Pitch) }],
+            master,
+        };
+
+        let path = std::env::temp_dir().join(format!("tjam-preset-{}.toml", std::process::id()));
+        write_preset(&path, &preset).unwrap();
+        let loaded = read_preset(&path);
+        fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded, preset);
+
+        // the chain it builds is the one it was saved from
+        let rebuilt = FxChain::from_saved(&loaded.chain, 120.0);
+        assert_eq!(rebuilt.saved(), chain.saved());
+        assert!(rebuilt.slots()[1].bypassed());
+    }
+
+    #[test]
+    fn an_older_preset_loads_with_defaults() {
+        let text = "patch = \"Sine\"\nvolume = 1.0\n\n[adsr]\nattack_s = 0.5\ndecay_s = 0.5\nsustain = 0.4\nrelease_s = 1.0\n";
+        let preset: Preset = toml::from_str(text).unwrap();
+        assert_eq!(preset.adsr, Adsr::new(0.5, 0.5, 0.4, 1.0));
+        assert_eq!(preset.filter_env, FilterEnvelope::default());
+        assert_eq!(preset.eq, EqSettings::default());
+        assert!(preset.chain.is_empty() && preset.lfos.is_empty() && preset.master.is_empty());
+    }
+
+    #[test]
+    fn a_broken_envelope_is_clamped() {
+        let adsr = Adsr { attack_s: -1.0, decay_s: f32::NAN, sustain: 3.0, release_s: 1e9, ..Adsr::new(0.0, 0.0, 0.0, 0.0) };
+        let clamped = adsr.clamped();
+        assert_eq!((clamped.attack_s, clamped.decay_s), (ADSR_TIME_MIN_S, ADSR_TIME_MIN_S));
+        assert_eq!((clamped.sustain, clamped.release_s), (1.0, ADSR_TIME_MAX_S));
+        let env = FilterEnvelope { adsr, depth: 100.0 }.clamped();
+        assert_eq!(env.adsr, clamped);
+    }
+}
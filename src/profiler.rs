@@ -0,0 +1,135 @@
+//! lightweight timing for the profiling overlay. instrumentation points check one atomic flag
+//! and do nothing while the overlay is hidden
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use rodio::Source;
+
+use crate::audio_patch::{AtomicF32, SynthSource};
+use crate::config::{PROFILE_BLOCK, PROFILE_SMOOTHING};
+
+/// running average and worst case of one measurement, in microseconds
+#[derive(Debug, Default)]
+pub struct TimingStat {
+    avg_us: AtomicF32,
+    max_us: AtomicF32,
+}
+
+impl TimingStat {
+    const fn new() -> Self {
+        Self { avg_us: AtomicF32::new(0.0), max_us: AtomicF32::new(0.0) }
+    }
+
+    pub fn record(&self, d: Duration) {
+        let us = d.as_secs_f32() * 1e6;
+        let avg = self.avg_us.load();
+        self.avg_us.store(avg + (us - avg) * PROFILE_SMOOTHING);
+        self.max_us.fetch_max(us);
+    }
+
+    pub fn avg_us(&self) -> f32 { self.avg_us.load() }
+    pub fn max_us(&self) -> f32 { self.max_us.load() }
+
+    fn reset(&self) {
+        self.avg_us.store(0.0);
+        self.max_us.store(0.0);
+    }
+}
+
+pub struct Profiler {
+    enabled: AtomicBool,
+    /// one `get_keys` call in the keyboard poll thread
+    pub input_poll: TimingStat,
+    /// from the poll thread seeing a key change to the audio task having started/released the voices
+    pub input_latency: TimingStat,
+    /// rendering a block of `PROFILE_BLOCK` samples of the master bus, i.e. every sounding voice
+    pub voice_mix: TimingStat,
+    /// one `terminal.draw` of the TUI
+    pub draw: TimingStat,
+}
+
+pub static PROFILER: Profiler = Profiler {
+    enabled: AtomicBool::new(false),
+    input_poll: TimingStat::new(),
+    input_latency: TimingStat::new(),
+    voice_mix: TimingStat::new(),
+    draw: TimingStat::new(),
+};
+
+impl Profiler {
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// switching on starts from clean numbers
+    pub fn set_enabled(&self, on: bool) {
+        if on && !self.enabled() {
+            for stat in self.stats() {
+                stat.1.reset();
+            }
+        }
+        self.enabled.store(on, Ordering::Relaxed);
+    }
+
+    /// start timing something; `None` while profiling is off
+    #[inline]
+    pub fn start(&self) -> Option<Instant> {
+        self.enabled().then(Instant::now)
+    }
+
+    pub fn stats(&self) -> [(&'static str, &TimingStat); 4] {
+        [
+            ("input poll", &self.input_poll),
+            ("input → voice", &self.input_latency),
+            ("voice mix", &self.voice_mix),
+            ("ui draw", &self.draw),
+        ]
+    }
+}
+
+/// record the time since `start`, if it was taken
+#[inline]
+pub fn finish(stat: &TimingStat, start: Option<Instant>) {
+    if let Some(t) = start {
+        stat.record(t.elapsed());
+    }
+}
+
+/// wraps the master bus and adds up the time spent pulling samples from it. only the time inside
+/// `next()` counts, so waiting on the device doesn't show up as mix time
+pub struct ProfiledSource {
+    input: SynthSource,
+    spent: Duration,
+    count: usize,
+}
+
+impl ProfiledSource {
+    pub fn new(input: SynthSource) -> Self {
+        Self { input, spent: Duration::ZERO, count: 0 }
+    }
+}
+
+impl Iterator for ProfiledSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let Some(t) = PROFILER.start() else { return self.input.next(); };
+        let x = self.input.next();
+        self.spent += t.elapsed();
+        self.count += 1;
+        if self.count >= PROFILE_BLOCK {
+            PROFILER.voice_mix.record(self.spent);
+            self.spent = Duration::ZERO;
+            self.count = 0;
+        }
+        x
+    }
+}
+
+impl Source for ProfiledSource {
+    fn current_span_len(&self) -> Option<usize> { self.input.current_span_len() }
+    fn channels(&self) -> u16 { self.input.channels() }
+    fn sample_rate(&self) -> u32 { self.input.sample_rate() }
+    fn total_duration(&self) -> Option<Duration> { self.input.total_duration() }
+}
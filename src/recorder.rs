@@ -0,0 +1,184 @@
+//! performance recorder: logs every note that sounds (keys, loop, echoes, drums, script) with its timing,
+//! and writes the take out as a type-1 MIDI file, one MIDI track per mixer track, so a jam can be
+//! opened in a DAW afterwards
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::config::{MIDI_DRUM_CHANNEL, MIDI_PPQ, RECORDINGS_DIR};
+use crate::engine::Track;
+use crate::settings::data_dir;
+use crate::transport::{TimeSignature, Transport};
+
+#[derive(Debug, Clone, Copy)]
+struct RecordedEvent {
+    /// offset from the start of the take
+    at: Duration,
+    track: Track,
+    note: u8,
+    /// 1..127, or 0 for a note off
+    velocity: u8,
+}
+
+/// a take that was stopped, ready to be written
+pub struct Take {
+    events: Vec<RecordedEvent>,
+    length: Duration,
+    bpm: f32,
+    signature: TimeSignature,
+}
+
+#[derive(Default)]
+pub struct Recorder {
+    started: Option<Instant>,
+    events: Vec<RecordedEvent>,
+    /// notes on right now, counted, so a note struck twice needs two offs
+    open: HashMap<(Track, u8), usize>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.started.is_some()
+    }
+
+    /// starts a new take, dropping anything logged before
+    pub fn start(&mut self, now: Instant) {
+        self.started = Some(now);
+        self.events.clear();
+        self.open.clear();
+    }
+
+    /// `velocity` is 0..1
+    pub fn note_on(&mut self, track: Track, note: u8, velocity: f32, at: Instant) {
+        let Some(start) = self.started else { return; };
+        let velocity = (velocity * 127.0).round().clamp(1.0, 127.0) as u8;
+        self.events.push(RecordedEvent { at: at.saturating_duration_since(start), track, note, velocity });
+        *self.open.entry((track, note)).or_default() += 1;
+    }
+
+    /// offs for notes that started before the take are dropped
+    pub fn note_off(&mut self, track: Track, note: u8, at: Instant) {
+        let Some(start) = self.started else { return; };
+        let Some(count) = self.open.get_mut(&(track, note)) else { return; };
+        *count -= 1;
+        if *count == 0 {
+            self.open.remove(&(track, note));
+        }
+        self.events.push(RecordedEvent { at: at.saturating_duration_since(start), track, note, velocity: 0 });
+    }
+
+    /// ends the take, letting go of every note still on, with the transport's tempo and time
+    /// signature. `None` when nothing was played
+    pub fn stop(&mut self, now: Instant, transport: &Transport) -> Option<Take> {
+        let start = self.started.take()?;
+        let length = now.saturating_duration_since(start);
+        for ((track, note), count) in self.open.drain() {
+            for _ in 0..count {
+                self.events.push(RecordedEvent { at: length, track, note, velocity: 0 });
+            }
+        }
+        let events = std::mem::take(&mut self.events);
+        if events.is_empty() {
+            return None;
+        }
+        Some(Take { events, length, bpm: transport.bpm(), signature: transport.signature() })
+    }
+}
+
+impl Take {
+    pub fn notes(&self) -> usize {
+        self.events.iter().filter(|e| e.velocity > 0).count()
+    }
+
+    /// type-1 standard MIDI file: a tempo track, then one track per mixer track that has notes
+    pub fn to_midi(&self) -> Vec<u8> {
+        let tick = |at: Duration| (at.as_secs_f64() * self.bpm as f64 / 60.0 * MIDI_PPQ as f64).round() as u32;
+
+        let mut tracks = vec![];
+        let mut tempo = vec![];
+        let micros = (60_000_000.0 / self.bpm.max(1.0)).round() as u32;
+        meta(&mut tempo, 0, 0x03, b"tjam");
+        meta(&mut tempo, 0, 0x51, &micros.to_be_bytes()[1..]);
+        // the denominator as a power of two, 24 clocks per click, 8 32nds per quarter
+        let TimeSignature { beats, unit } = self.signature;
+        meta(&mut tempo, 0, 0x58, &[beats, unit.trailing_zeros() as u8, 24, 8]);
+        meta(&mut tempo, tick(self.length), 0x2F, &[]);
+        tracks.push(tempo);
+
+        for track in Track::ALL {
+            let mut events: Vec<&RecordedEvent> = self.events.iter().filter(|e| e.track == track).collect();
+            if events.is_empty() {
+                continue;
+            }
+            // stable, so an off and an on at the same moment keep their order
+            events.sort_by_key(|e| e.at);
+            let channel = if track == Track::Drums { MIDI_DRUM_CHANNEL } else { track.index() as u8 };
+
+            let mut data = vec![];
+            meta(&mut data, 0, 0x03, track.name().as_bytes());
+            let mut last = 0;
+            for e in events {
+                let t = tick(e.at);
+                write_varlen(&mut data, t - last);
+                last = t;
+                // note on with velocity 0 is a note off
+                data.extend_from_slice(&[0x90 | channel, e.note, e.velocity]);
+            }
+            meta(&mut data, tick(self.length).saturating_sub(last), 0x2F, &[]);
+            tracks.push(data);
+        }
+
+        let mut out = vec![];
+        out.extend_from_slice(b"MThd");
+        out.extend_from_slice(&6u32.to_be_bytes());
+        out.extend_from_slice(&1u16.to_be_bytes());
+        out.extend_from_slice(&(tracks.len() as u16).to_be_bytes());
+        out.extend_from_slice(&MIDI_PPQ.to_be_bytes());
+        for data in tracks {
+            out.extend_from_slice(b"MTrk");
+            out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            out.extend_from_slice(&data);
+        }
+        out
+    }
+
+    /// writes it to `~/.local/share/tjam/recordings/jam-<time>.mid`
+    pub fn save(&self) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let dir = data_dir().ok_or("could not resolve the data directory")?.join(RECORDINGS_DIR);
+        fs::create_dir_all(&dir)?;
+        let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let path = dir.join(format!("jam-{secs}.mid"));
+        fs::write(&path, self.to_midi())?;
+        Ok(path)
+    }
+}
+
+fn meta(data: &mut Vec<u8>, delta: u32, kind: u8, payload: &[u8]) {
+    write_varlen(data, delta);
+    data.extend_from_slice(&[0xFF, kind]);
+    write_varlen(data, payload.len() as u32);
+    data.extend_from_slice(payload);
+}
+
+/// MIDI variable-length quantity: 7 bits per byte, high bit set on all but the last
+fn write_varlen(data: &mut Vec<u8>, mut value: u32) {
+    let mut bytes = [0u8; 5];
+    let mut n = 0;
+    loop {
+        bytes[n] = (value & 0x7F) as u8;
+        n += 1;
+        value >>= 7;
+        if value == 0 {
+            break;
+        }
+    }
+    for i in (0..n).rev() {
+        data.push(bytes[i] | if i > 0 { 0x80 } else { 0 });
+    }
+}
@@ -0,0 +1,255 @@
+//! the file formats of offline rendering: notes are read from a standard MIDI file, with its
+//! tempo map, and the mix is written out as a 16-bit stereo WAV. the rendering itself is
+//! `play::render`, which runs them through the same engine live playing does
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// one note on or off from a MIDI file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MidiNote {
+    /// seconds from the start of the file
+    pub at_s: f64,
+    /// 0..15
+    pub channel: u8,
+    pub note: u8,
+    /// 1..127, or 0 for a note off
+    pub velocity: u8,
+}
+
+/// every note of every track, in time order, offs before ons at the same moment
+pub fn read_midi(path: &Path) -> Result<Vec<MidiNote>, Box<dyn std::error::Error>> {
+    let data = fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    parse_midi(&data).map_err(|e| format!("{}: {e}", path.display()).into())
+}
+
+fn parse_midi(data: &[u8]) -> Result<Vec<MidiNote>, String> {
+    let mut r = Reader { data, pos: 0 };
+    if r.take(4)? != b"MThd" {
+        return Err("not a MIDI file".into());
+    }
+    let header_len = r.u32()? as usize;
+    let header = r.take(header_len)?;
+    let &[_, _, _, _, hi, lo, ..] = header else { return Err("short header".into()); };
+    let division = u16::from_be_bytes([hi, lo]);
+    if division & 0x8000 != 0 || division == 0 {
+        return Err("SMPTE-timed files are not supported".into());
+    }
+
+    // (tick, microseconds per quarter) and (tick, note)
+    let mut tempos: Vec<(u64, u32)> = vec![];
+    let mut notes: Vec<(u64, MidiNote)> = vec![];
+    while r.pos < data.len() {
+        let id = r.take(4)?;
+        let len = r.u32()? as usize;
+        let chunk = r.take(len)?;
+        if id == b"MTrk" {
+            read_track(chunk, &mut tempos, &mut notes)?;
+        }
+    }
+
+    tempos.sort_by_key(|t| t.0);
+    notes.sort_by_key(|(tick, n)| (*tick, n.velocity > 0));
+    // walk the tempo map alongside the notes, which are in tick order
+    let mut tempo = tempos.iter().peekable();
+    let (mut base_tick, mut base_s, mut micros) = (0u64, 0.0f64, 500_000u32);
+    let seconds = |ticks: u64, micros: u32| ticks as f64 * micros as f64 / 1e6 / division as f64;
+    Ok(notes
+        .into_iter()
+        .map(|(tick, mut n)| {
+            while let Some(&&(at, next)) = tempo.peek() {
+                if at > tick {
+                    break;
+                }
+                base_s += seconds(at - base_tick, micros);
+                base_tick = at;
+                micros = next;
+                tempo.next();
+            }
+            n.at_s = base_s + seconds(tick - base_tick, micros);
+            n
+        })
+        .collect())
+}
+
+fn read_track(chunk: &[u8], tempos: &mut Vec<(u64, u32)>, notes: &mut Vec<(u64, MidiNote)>) -> Result<(), String> {
+    let mut r = Reader { data: chunk, pos: 0 };
+    let mut tick = 0u64;
+    let mut running = 0u8;
+    while r.pos < chunk.len() {
+        tick += r.varlen()? as u64;
+        let mut status = r.byte()?;
+        match status {
+            0xFF => {
+                let kind = r.byte()?;
+                let len = r.varlen()? as usize;
+                let payload = r.take(len)?;
+                if let (0x51, &[a, b, c]) = (kind, payload) {
+                    tempos.push((tick, u32::from_be_bytes([0, a, b, c])));
+                }
+                continue;
+            }
+            0xF0 | 0xF7 => {
+                let len = r.varlen()? as usize;
+                r.take(len)?;
+                continue;
+            }
+            // running status: the last status again, and this byte is already data
+            s if s < 0x80 => {
+                if running == 0 {
+                    return Err("data byte with no status".into());
+                }
+                status = running;
+                r.pos -= 1;
+            }
+            s => running = s,
+        }
+        let channel = status & 0x0F;
+        match status & 0xF0 {
+            0x80 | 0x90 => {
+                let note = r.byte()? & 0x7F;
+                let velocity = r.byte()? & 0x7F;
+                let velocity = if status & 0xF0 == 0x80 { 0 } else { velocity };
+                notes.push((tick, MidiNote { at_s: 0.0, channel, note, velocity }));
+            }
+            0xA0 | 0xB0 | 0xE0 => {
+                r.take(2)?;
+            }
+            0xC0 | 0xD0 => {
+                r.byte()?;
+            }
+            _ => return Err(format!("unknown event {status:#04x}")),
+        }
+    }
+    Ok(())
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let bytes = self.data.get(self.pos..self.pos + n).ok_or("file ends early")?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let b = self.take(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// MIDI variable-length quantity: 7 bits per byte, high bit set on all but the last
+    fn varlen(&mut self) -> Result<u32, String> {
+        let mut value = 0u32;
+        for _ in 0..4 {
+            let b = self.byte()?;
+            value = (value << 7) | (b & 0x7F) as u32;
+            if b & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("bad variable-length number".into())
+    }
+}
+
+/// a 16-bit stereo WAV written as it goes; the sizes in the header are filled in by `finish`
+pub struct WavWriter {
+    out: BufWriter<File>,
+    frames: u32,
+}
+
+impl WavWriter {
+    pub fn create(path: &Path, sample_rate: u32) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = File::create(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let mut out = BufWriter::new(file);
+        let (channels, bits) = (2u16, 16u16);
+        let block = channels * bits / 8;
+        out.write_all(b"RIFF")?;
+        out.write_all(&0u32.to_le_bytes())?;
+        out.write_all(b"WAVEfmt ")?;
+        out.write_all(&16u32.to_le_bytes())?;
+        out.write_all(&1u16.to_le_bytes())?;
+        out.write_all(&channels.to_le_bytes())?;
+        out.write_all(&sample_rate.to_le_bytes())?;
+        out.write_all(&(sample_rate * block as u32).to_le_bytes())?;
+        out.write_all(&block.to_le_bytes())?;
+        out.write_all(&bits.to_le_bytes())?;
+        out.write_all(b"data")?;
+        out.write_all(&0u32.to_le_bytes())?;
+        Ok(Self { out, frames: 0 })
+    }
+
+    pub fn write(&mut self, frame: [f32; 2]) -> Result<(), Box<dyn std::error::Error>> {
+        for x in frame {
+            let s = (x.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+            self.out.write_all(&s.to_le_bytes())?;
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let data = self.frames * 4;
+        self.out.seek(SeekFrom::Start(4))?;
+        self.out.write_all(&(36 + data).to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(40))?;
+        self.out.write_all(&data.to_le_bytes())?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::engine::Track;
+    use crate::recorder::Recorder;
+    use crate::transport::{TimeSignature, Transport};
+
+    /// a take at 90 bpm with a held note on the keys and a drum hit inside it, as a MIDI file
+    fn take_midi() -> Vec<u8> {
+        let now = Instant::now();
+        let at = |s: f64| now + Duration::from_secs_f64(s);
+        let mut recorder = Recorder::new();
+        recorder.start(now);
+        recorder.note_on(Track::Live, 60, 1.0, at(0.0));
+        recorder.note_on(Track::Drums, 36, 0.5, at(0.5));
+        recorder.note_off(Track::Drums, 36, at(0.75));
+        recorder.note_off(Track::Live, 60, at(1.25));
+        let take = recorder.stop(at(2.0), &Transport::new(90.0, TimeSignature::default())).unwrap();
+        take.to_midi()
+    }
+
+    #[test]
+    fn a_recorded_take_reads_back() {
+        let notes = parse_midi(&take_midi()).unwrap();
+        let expected = [(0.0, 0, 60, 127), (0.5, 9, 36, 64), (0.75, 9, 36, 0), (1.25, 0, 60, 0)];
+        assert_eq!(notes.len(), expected.len(), "{notes:?}");
+        for (n, (at_s, channel, note, velocity)) in notes.iter().zip(expected) {
+            assert!((n.at_s - at_s).abs() < 1e-4, "{n:?}");
+            assert_eq!((n.channel, n.note, n.velocity), (channel, note, velocity), "{n:?}");
+        }
+    }
+
+    #[test]
+    fn a_cut_off_file_is_an_error() {
+        let data = take_midi();
+        // every prefix either parses (it ends between chunks) or is refused, none panics
+        for len in 0..data.len() {
+            let _ = parse_midi(&data[..len]);
+        }
+        assert!(parse_midi(&data[..data.len() - 1]).is_err());
+        assert!(parse_midi(&data[..10]).is_err());
+        assert!(parse_midi(&[]).is_err());
+    }
+}
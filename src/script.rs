@@ -0,0 +1,326 @@
+//! generative scripts: a Rhai file whose `on_beat(beat)` and `on_tick(tick)` functions run in time
+//! with the transport and play notes, switch patches and move parameters. the file is watched and
+//! picked up again whenever it is saved, so it can be rewritten while it plays
+//!
+//! ```text
+//! fn on_beat(beat) {
+//!     if beat % 4 == 0 { note("C3", 2) }
+//! }
+//! fn on_tick(tick) {
+//!     if rand() < 0.3 { note(60 + rand(0, 12), 0.25, 0.6) }
+//! }
+//! ```
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use rhai::{AST, CallFnOptions, Dynamic, Engine, EvalAltResult, INT, Map, Scope};
+use serde::{Deserialize, Serialize};
+
+use crate::audio_patch::ParamValue;
+use crate::config::{SCRIPT_MAX_OPERATIONS, SCRIPT_RELOAD_CHECK_MS, SCRIPT_TICKS_PER_BEAT, VELOCITY_DEFAULT};
+use crate::key::Key;
+use crate::transport::Transport;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScriptSettings {
+    /// a Rhai script to run at startup
+    pub file: Option<PathBuf>,
+}
+
+/// what a script asks of the audio task
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptEvent {
+    NoteOn(Key, f32),
+    NoteOff(Key),
+    Patch(String),
+    Param { owner: String, id: String, value: ParamValue },
+    Tempo(f32),
+    /// `print`, or an error; shown as a notice
+    Message(String),
+}
+
+/// what the script's functions leave behind during one call, before it is put in time
+enum Call {
+    /// a note and how many beats it lasts
+    Note(Key, f32, f64),
+    On(Key, f32),
+    Off(Key),
+    Event(ScriptEvent),
+}
+
+type Calls = Arc<Mutex<Vec<Call>>>;
+
+pub struct Script {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    last_check: Instant,
+    engine: Engine,
+    ast: AST,
+    /// what the callbacks see as `this`; kept across calls and reloads
+    this: Dynamic,
+    calls: Calls,
+    /// the last tick the callbacks ran for, while the transport runs
+    last_tick: Option<u64>,
+    /// notes started with a length, and the beat they end on
+    offs: Vec<(f64, Key)>,
+    /// notes sounding, so stopping the transport can let go of them
+    held: HashSet<Key>,
+    /// set when a callback fails; the script waits for the file to change
+    failed: bool,
+}
+
+impl Script {
+    /// compiles `path` and runs its top level, which may already play something
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let calls: Calls = Arc::default();
+        let engine = script_engine(calls.clone());
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let ast = engine.compile(&text).map_err(|e| format!("{}: {e}", path.display()))?;
+        let script = Self {
+            path: path.to_path_buf(),
+            modified: modified(path),
+            last_check: Instant::now(),
+            engine,
+            ast,
+            this: Dynamic::from_map(Map::new()),
+            calls,
+            last_tick: None,
+            offs: vec![],
+            held: HashSet::new(),
+            failed: false,
+        };
+        script.engine.run_ast(&script.ast).map_err(|e| format!("{}: {e}", path.display()))?;
+        Ok(script)
+    }
+
+    /// the file name without its extension
+    pub fn name(&self) -> String {
+        self.path.file_stem().map_or_else(|| self.path.display().to_string(), |s| s.to_string_lossy().into_owned())
+    }
+
+    /// picks the file up again if it changed, runs the callbacks for every tick the transport
+    /// has passed, and ends the notes that are due. with the transport stopped nothing runs and
+    /// every note the script holds is let go
+    pub fn poll(&mut self, now: Instant, transport: &Transport) -> Vec<ScriptEvent> {
+        let mut events = vec![];
+        if now.saturating_duration_since(self.last_check) >= Duration::from_millis(SCRIPT_RELOAD_CHECK_MS) {
+            self.last_check = now;
+            self.reload_if_changed(&mut events);
+        }
+
+        if !transport.is_running() {
+            if self.last_tick.is_some() {
+                events.extend(self.stop());
+            }
+            return events;
+        }
+
+        let beats = transport.beats(now);
+        self.offs.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let due = self.offs.partition_point(|(at, _)| *at <= beats);
+        for (_, key) in self.offs.drain(..due) {
+            if self.held.remove(&key) {
+                events.push(ScriptEvent::NoteOff(key));
+            }
+        }
+
+        let tick = (beats * SCRIPT_TICKS_PER_BEAT as f64) as u64;
+        // after a stall only the last beat's worth is caught up on
+        let first = self.last_tick.map_or(tick, |t| t + 1).max(tick.saturating_sub(SCRIPT_TICKS_PER_BEAT - 1));
+        self.last_tick = Some(tick);
+        for t in first..=tick {
+            if self.failed {
+                break;
+            }
+            if t % SCRIPT_TICKS_PER_BEAT == 0 {
+                self.call("on_beat", (t / SCRIPT_TICKS_PER_BEAT) as INT, &mut events);
+            }
+            self.call("on_tick", t as INT, &mut events);
+            self.place_calls(t as f64 / SCRIPT_TICKS_PER_BEAT as f64, &mut events);
+        }
+        events
+    }
+
+    fn call(&mut self, name: &str, arg: INT, events: &mut Vec<ScriptEvent>) {
+        if !self.ast.iter_functions().any(|f| f.name == name && f.params.len() == 1) {
+            return;
+        }
+        let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(&mut self.this);
+        let res = self.engine.call_fn_with_options::<Dynamic>(options, &mut Scope::new(), &self.ast, name, (arg,));
+        if let Err(e) = res {
+            self.failed = true;
+            events.push(ScriptEvent::Message(format!("script {name}: {e} (stopped until the file is saved)")));
+        }
+    }
+
+    /// turns what the functions asked for into events, timing note lengths from `beat`
+    fn place_calls(&mut self, beat: f64, events: &mut Vec<ScriptEvent>) {
+        let calls: Vec<Call> = self.calls.lock().map(|mut c| c.drain(..).collect()).unwrap_or_default();
+        for call in calls {
+            match call {
+                Call::Note(key, velocity, beats) => {
+                    self.held.insert(key);
+                    self.offs.push((beat + beats, key));
+                    events.push(ScriptEvent::NoteOn(key, velocity));
+                }
+                Call::On(key, velocity) => {
+                    self.held.insert(key);
+                    events.push(ScriptEvent::NoteOn(key, velocity));
+                }
+                Call::Off(key) => {
+                    if self.held.remove(&key) {
+                        events.push(ScriptEvent::NoteOff(key));
+                    }
+                }
+                Call::Event(event) => events.push(event),
+            }
+        }
+    }
+
+    fn reload_if_changed(&mut self, events: &mut Vec<ScriptEvent>) {
+        let modified = modified(&self.path);
+        if modified == self.modified {
+            return;
+        }
+        self.modified = modified;
+        let compiled = fs::read_to_string(&self.path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| self.engine.compile(&text).map_err(|e| e.to_string()));
+        match compiled {
+            Ok(ast) => {
+                self.ast = ast;
+                self.failed = false;
+                match self.engine.run_ast(&self.ast) {
+                    Ok(()) => events.push(ScriptEvent::Message(format!("script {} reloaded", self.name()))),
+                    Err(e) => events.push(ScriptEvent::Message(format!("script {}: {e}", self.name()))),
+                }
+                // notes from the top level start on the current beat, or right away when stopped
+                let beat = self.last_tick.map_or(0.0, |t| t as f64 / SCRIPT_TICKS_PER_BEAT as f64);
+                self.place_calls(beat, events);
+            }
+            // the old version keeps playing
+            Err(e) => events.push(ScriptEvent::Message(format!("script {}: {e}", self.name()))),
+        }
+    }
+
+    /// lets go of every note the script holds and waits for the transport to start again
+    pub fn stop(&mut self) -> Vec<ScriptEvent> {
+        self.last_tick = None;
+        self.offs.clear();
+        self.held.drain().map(ScriptEvent::NoteOff).collect()
+    }
+
+    /// what the top level asked for when the script was loaded
+    pub fn take_startup(&mut self) -> Vec<ScriptEvent> {
+        let mut events = vec![];
+        self.place_calls(0.0, &mut events);
+        events
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// a sandboxed engine whose note and parameter functions record into `calls`
+fn script_engine(calls: Calls) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(SCRIPT_MAX_OPERATIONS);
+
+    let push = move |call: Call| {
+        if let Ok(mut c) = calls.lock() {
+            c.push(call);
+        }
+    };
+
+    let p = push.clone();
+    engine.register_fn("note", move |pitch: Dynamic, beats: Dynamic| -> Result<(), Box<EvalAltResult>> {
+        p(Call::Note(pitch_key(&pitch)?, VELOCITY_DEFAULT, number(&beats)?));
+        Ok(())
+    });
+    let p = push.clone();
+    engine.register_fn("note", move |pitch: Dynamic, beats: Dynamic, velocity: Dynamic| -> Result<(), Box<EvalAltResult>> {
+        p(Call::Note(pitch_key(&pitch)?, velocity_of(&velocity)?, number(&beats)?));
+        Ok(())
+    });
+    let p = push.clone();
+    engine.register_fn("note_on", move |pitch: Dynamic| -> Result<(), Box<EvalAltResult>> {
+        p(Call::On(pitch_key(&pitch)?, VELOCITY_DEFAULT));
+        Ok(())
+    });
+    let p = push.clone();
+    engine.register_fn("note_on", move |pitch: Dynamic, velocity: Dynamic| -> Result<(), Box<EvalAltResult>> {
+        p(Call::On(pitch_key(&pitch)?, velocity_of(&velocity)?));
+        Ok(())
+    });
+    let p = push.clone();
+    engine.register_fn("note_off", move |pitch: Dynamic| -> Result<(), Box<EvalAltResult>> {
+        p(Call::Off(pitch_key(&pitch)?));
+        Ok(())
+    });
+    let p = push.clone();
+    engine.register_fn("patch", move |name: &str| p(Call::Event(ScriptEvent::Patch(name.to_string()))));
+    let p = push.clone();
+    engine.register_fn("param", move |owner: &str, id: &str, value: Dynamic| -> Result<(), Box<EvalAltResult>> {
+        let value = match value.as_bool() {
+            Ok(on) => ParamValue::Bool(on),
+            Err(_) => ParamValue::Float(number(&value)? as f32),
+        };
+        p(Call::Event(ScriptEvent::Param { owner: owner.to_string(), id: id.to_string(), value }));
+        Ok(())
+    });
+    let p = push.clone();
+    engine.register_fn("tempo", move |bpm: Dynamic| -> Result<(), Box<EvalAltResult>> {
+        p(Call::Event(ScriptEvent::Tempo(number(&bpm)? as f32)));
+        Ok(())
+    });
+    let p = push.clone();
+    engine.on_print(move |text| p(Call::Event(ScriptEvent::Message(text.to_string()))));
+
+    let rng = Arc::new(Mutex::new(seed()));
+    let r = rng.clone();
+    engine.register_fn("rand", move || random(&r));
+    engine.register_fn("rand", move |lo: INT, hi: INT| {
+        let (lo, hi) = (lo.min(hi), lo.max(hi));
+        lo + (random(&rng) * (hi - lo + 1) as f64) as INT
+    });
+    engine
+}
+
+/// a MIDI note number or a name like `C4`
+fn pitch_key(pitch: &Dynamic) -> Result<Key, Box<EvalAltResult>> {
+    if let Ok(n) = pitch.as_int() {
+        return Ok(Key::from_midi(n.clamp(0, 127) as u8));
+    }
+    let name = pitch.clone().into_string().map_err(|_| format!("not a note: {pitch}"))?;
+    Key::parse(&name).ok_or_else(|| format!("not a note: {name}").into())
+}
+
+fn number(v: &Dynamic) -> Result<f64, Box<EvalAltResult>> {
+    v.as_float()
+        .or_else(|_| v.as_int().map(|i| i as f64))
+        .map_err(|_| format!("not a number: {v}").into())
+}
+
+fn velocity_of(v: &Dynamic) -> Result<f32, Box<EvalAltResult>> {
+    Ok((number(v)? as f32).clamp(0.0, 1.0))
+}
+
+fn seed() -> u32 {
+    let nanos = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |d| d.subsec_nanos());
+    nanos | 1
+}
+
+/// xorshift, 0..1
+fn random(rng: &Mutex<u32>) -> f64 {
+    let Ok(mut x) = rng.lock() else { return 0.0; };
+    *x ^= *x << 13;
+    *x ^= *x >> 17;
+    *x ^= *x << 5;
+    *x as f64 / (u32::MAX as f64 + 1.0)
+}
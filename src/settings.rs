@@ -0,0 +1,325 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{
+    CONFIG_DIR, SETTINGS_FILE, LIMITER_THRESHOLD_DB, LIMITER_LOOKAHEAD_MS, LIMITER_RELEASE_MS,
+    CHORD_ASSIST_WINDOW_MS, REVERB_ROOM_DEFAULT, REVERB_DAMPING_DEFAULT, REVERB_MIX_DEFAULT,
+    UI_MAX_FPS, UI_IDLE_FPS, UI_SIDE_WIDTH, UI_KEYBOARD_HEIGHT, KEYBOARD_BASE_OCTAVE, BEND_RANGE_DEFAULT, TEMPO_BPM_DEFAULT,
+};
+use crate::chord::ChordType;
+use crate::fx::autopan::AutoPanSettings;
+use crate::fx::compressor::CompressorSettings;
+use crate::fx::flanger::FlangerSettings;
+use crate::fx::phaser::PhaserSettings;
+use crate::fx::tremolo::TremoloSettings;
+use crate::fx::drive::DriveSettings;
+use crate::keymap::KeymapSettings;
+use crate::midi_in::CcBinding;
+use crate::patches::sampler::SamplerSettings;
+use crate::script::ScriptSettings;
+use crate::transport::TimeSignature;
+use crate::tuning::TuningSettings;
+
+/// user settings loaded from `config.toml` in the tjam config dir. every field has a default,
+/// so a missing file or a partial one is fine
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub ui: UiSettings,
+    pub audio: AudioSettings,
+    pub input: InputSettings,
+    pub looper: LooperSettings,
+    pub transport: TransportSettings,
+    pub keymap: KeymapSettings,
+    pub tuning: TuningSettings,
+    pub sampler: SamplerSettings,
+    pub script: ScriptSettings,
+    pub control: ControlSettings,
+    pub midi: MidiSettings,
+    /// the file these were read from, where MIDI learn writes its bindings back
+    #[serde(skip)]
+    pub source: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransportSettings {
+    /// tempo at startup, in quarter notes per minute
+    pub bpm: f32,
+    pub time_signature: TimeSignature,
+}
+
+impl Default for TransportSettings {
+    fn default() -> Self {
+        Self { bpm: TEMPO_BPM_DEFAULT, time_signature: TimeSignature::default() }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ControlSettings {
+    /// listen for JSON commands on a unix socket
+    pub socket: bool,
+    /// socket path; defaults to `$XDG_RUNTIME_DIR/tjam.sock`
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MidiSettings {
+    /// listen to MIDI controllers
+    pub input: bool,
+    /// input port, matched against part of its name; every port when unset
+    pub port: Option<String>,
+    /// follow an external MIDI clock: its tempo, start and stop drive the transport
+    pub clock: bool,
+    /// knobs bound to parameters, made with MIDI learn
+    pub cc: Vec<CcBinding>,
+}
+
+impl Default for MidiSettings {
+    fn default() -> Self {
+        Self { input: true, port: None, clock: true, cc: vec![] }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LooperSettings {
+    /// loop length in seconds; 0 lets the first take decide
+    pub length_s: f32,
+    /// loop length in bars of the transport; takes over `length_s` when set
+    pub length_bars: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputSettings {
+    /// start with chord assist on (toggle live with `c`)
+    pub chord_assist: bool,
+    /// how far apart presses can be and still land as one chord
+    pub chord_window_ms: u64,
+    /// accented (Shift) notes also get a shorter attack, softer ones a longer one
+    pub velocity_attack: bool,
+    /// keys let go with Shift held release faster, for staccato
+    pub release_velocity: bool,
+    /// octave the `a` key plays
+    pub octave: i32,
+    /// semitones the arrow keys (or a full `set_bend`) bend every note
+    pub bend_range: f32,
+    /// chord mode at startup (cycle live with `4`)
+    pub chord: ChordType,
+    /// semitones above the key for the `custom` chord
+    pub custom_chord: Vec<i32>,
+}
+
+impl Default for InputSettings {
+    fn default() -> Self {
+        Self {
+            chord_assist: false,
+            chord_window_ms: CHORD_ASSIST_WINDOW_MS,
+            velocity_attack: false,
+            release_velocity: false,
+            octave: KEYBOARD_BASE_OCTAVE,
+            bend_range: BEND_RANGE_DEFAULT,
+            chord: ChordType::Off,
+            custom_chord: vec![0, 7, 12],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    /// output device, matched against part of its name; the system default when unset
+    pub device: Option<String>,
+    /// rate to open the device at; synthesis stays at 48kHz and is resampled to it
+    pub sample_rate: Option<u32>,
+    /// patch to start on, by name
+    pub patch: Option<String>,
+    /// SF2 file whose presets are added to the patch list
+    pub soundfont: Option<PathBuf>,
+    /// presets to load from it by name; empty loads the first one
+    pub soundfont_presets: Vec<String>,
+    /// ceiling of the master limiter, dBFS
+    pub limiter_threshold_db: f32,
+    /// how far ahead the limiter looks for peaks (this is also the latency it adds)
+    pub limiter_lookahead_ms: f32,
+    pub limiter_release_ms: f32,
+    /// starting reverb controls, all 0..1 (mix 0 = dry)
+    pub reverb_room_size: f32,
+    pub reverb_damping: f32,
+    pub reverb_mix: f32,
+    /// waveshaper on the master bus at startup
+    pub master_drive: DriveSettings,
+    /// compressor on the master bus at startup
+    pub master_compressor: CompressorSettings,
+    /// phaser and flanger on the master bus at startup
+    pub master_phaser: PhaserSettings,
+    pub master_flanger: FlangerSettings,
+    /// tremolo and auto-pan on the master bus at startup
+    pub master_tremolo: TremoloSettings,
+    pub master_autopan: AutoPanSettings,
+    /// most voices sounding at once; past it the oldest one is cut, a releasing one first.
+    /// 0 is no limit
+    pub max_voices: usize,
+    /// analog instability: every note starts up to this many cents off and slowly wanders about
+    /// as far; 0 keeps them exact
+    pub analog_cents: f32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            device: None,
+            sample_rate: None,
+            patch: None,
+            soundfont: None,
+            soundfont_presets: vec![],
+            limiter_threshold_db: LIMITER_THRESHOLD_DB,
+            limiter_lookahead_ms: LIMITER_LOOKAHEAD_MS,
+            limiter_release_ms: LIMITER_RELEASE_MS,
+            reverb_room_size: REVERB_ROOM_DEFAULT,
+            reverb_damping: REVERB_DAMPING_DEFAULT,
+            reverb_mix: REVERB_MIX_DEFAULT,
+            master_drive: DriveSettings::default(),
+            master_compressor: CompressorSettings::default(),
+            master_phaser: PhaserSettings::default(),
+            master_flanger: FlangerSettings::default(),
+            master_tremolo: TremoloSettings::default(),
+            master_autopan: AutoPanSettings::default(),
+            max_voices: 0,
+            analog_cents: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiSettings {
+    /// segments shown in the status bar, left to right
+    pub status_bar: Vec<StatusSegment>,
+    /// redraw cap while meters and notes are changing
+    pub max_fps: f32,
+    /// redraw rate when nothing changes
+    pub idle_fps: f32,
+    /// draw the piano and the level bars; off leaves plain text, for slow terminals
+    pub visualizer: bool,
+    /// columns of the side panels (presets, looper, LFOs, levels); 0 hides them
+    pub side_width: u16,
+    /// rows of the on-screen piano
+    pub keyboard_height: u16,
+}
+
+impl Default for UiSettings {
+    fn default() -> Self {
+        Self {
+            status_bar: vec![
+                StatusSegment::Patch,
+                StatusSegment::Octave,
+                StatusSegment::Volume,
+                StatusSegment::Voices,
+                StatusSegment::Tempo,
+                StatusSegment::Recording,
+                StatusSegment::Audio,
+                StatusSegment::Preset,
+                StatusSegment::Echo,
+            ],
+            max_fps: UI_MAX_FPS,
+            idle_fps: UI_IDLE_FPS,
+            visualizer: true,
+            side_width: UI_SIDE_WIDTH,
+            keyboard_height: UI_KEYBOARD_HEIGHT,
+        }
+    }
+}
+
+/// one piece of the status bar
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatusSegment {
+    Patch,
+    Volume,
+    Octave,
+    Preset,
+    Fps,
+    Cpu,
+    Echo,
+    Scale,
+    Reverb,
+    Tilt,
+    Sustain,
+    /// whether let-go keys are latched on
+    Latch,
+    /// most keys the keyboard has delivered at once
+    Rollover,
+    /// whether a take is being recorded for MIDI export
+    Recording,
+    /// tempo, time signature and bar/beat
+    Tempo,
+    /// what chord every key plays
+    Chord,
+    /// the current patch's drive curve, or off
+    Drive,
+    /// notes sounding, releasing ones included
+    Voices,
+    /// the audio callback's load, flagged for a while after an underrun
+    Audio,
+}
+
+/// `$XDG_CONFIG_HOME/tjam`, falling back to `~/.config/tjam`
+pub fn config_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))?;
+    Some(base.join(CONFIG_DIR))
+}
+
+/// `$XDG_DATA_HOME/tjam`, falling back to `~/.local/share/tjam`
+pub fn data_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".local/share")))?;
+    Some(base.join(CONFIG_DIR))
+}
+
+pub fn settings_path() -> Option<PathBuf> {
+    config_dir().map(|d| d.join(SETTINGS_FILE))
+}
+
+/// reads the settings file; a missing file gives the defaults, a broken one is an error
+pub fn load_settings() -> Result<Settings, Box<dyn std::error::Error>> {
+    let Some(path) = settings_path() else { return Ok(Settings::default()); };
+    let settings = match fs::read_to_string(&path) {
+        Ok(text) => toml::from_str(&text)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Settings::default(),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(Settings { source: Some(path), ..settings })
+}
+
+/// reads a settings file given on the command line; unlike the default one it has to exist
+pub fn load_settings_from(path: &Path) -> Result<Settings, Box<dyn std::error::Error>> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let settings: Settings = toml::from_str(&text)?;
+    Ok(Settings { source: Some(path.to_path_buf()), ..settings })
+}
+
+/// writes the whole settings file, creating its directory. comments in it are not kept
+pub fn save_settings_to(settings: &Settings, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, toml::to_string_pretty(settings)?)?;
+    Ok(())
+}
+
+/// reads the file at `path` as it is now (the defaults if there is none), changes it and writes
+/// it back, so edits made to it since startup are kept
+pub fn update_settings_file(path: &Path, f: impl FnOnce(&mut Settings)) -> Result<(), Box<dyn std::error::Error>> {
+    let mut settings = if path.exists() { load_settings_from(path)? } else { Settings::default() };
+    f(&mut settings);
+    save_settings_to(&settings, path)
+}
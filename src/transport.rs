@@ -0,0 +1,263 @@
+//! the global clock: tempo, time signature and a bar/beat position that runs while the transport
+//! is started. everything tempo synced (echo, synced LFOs, the looper's bar lengths, MIDI export)
+//! reads its tempo from here
+
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{TAP_TEMPO_MAX_TAPS, TAP_TEMPO_TIMEOUT_S, TEMPO_BPM_DEFAULT, TEMPO_BPM_MAX, TEMPO_BPM_MIN};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimeSignature {
+    /// beats in a bar
+    pub beats: u8,
+    /// the note value of one beat: 4 is a quarter, 8 an eighth
+    pub unit: u8,
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        Self { beats: 4, unit: 4 }
+    }
+}
+
+impl TimeSignature {
+    pub fn clamped(self) -> Self {
+        let unit = match self.unit {
+            0..=1 => 1,
+            2..=3 => 2,
+            4..=7 => 4,
+            8..=15 => 8,
+            _ => 16,
+        };
+        Self { beats: self.beats.clamp(1, 32), unit }
+    }
+
+    pub fn name(self) -> String {
+        format!("{}/{}", self.beats, self.unit)
+    }
+}
+
+/// what the UI shows about the transport
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct TransportStatus {
+    pub bpm: f32,
+    pub signature: TimeSignature,
+    pub running: bool,
+    /// 1-based, like a DAW's counter; 1.1 while stopped at the start
+    pub bar: u32,
+    pub beat: u32,
+}
+
+impl TransportStatus {
+    /// "120 bpm 4/4, bar 3.2"
+    pub fn describe(&self) -> String {
+        let state = if self.running { format!("bar {}.{}", self.bar, self.beat) } else { "stopped".into() };
+        format!("{:.0} bpm {}, {state}", self.bpm, self.signature.name())
+    }
+}
+
+/// the tempo counts quarter notes whatever the time signature, like MIDI files and the echo
+/// divisions do; a bar of 6/8 is three of them
+pub struct Transport {
+    bpm: f32,
+    signature: TimeSignature,
+    running: bool,
+    /// when the position was last `anchor_beats`; moved on every tempo change so the count
+    /// carries on from where it was
+    anchor: Instant,
+    anchor_beats: f64,
+    taps: Vec<Instant>,
+}
+
+impl Transport {
+    pub fn new(bpm: f32, signature: TimeSignature) -> Self {
+        Self {
+            bpm: clamp_bpm(bpm),
+            signature: signature.clamped(),
+            running: false,
+            anchor: Instant::now(),
+            anchor_beats: 0.0,
+            taps: vec![],
+        }
+    }
+
+    pub fn bpm(&self) -> f32 { self.bpm }
+    pub fn signature(&self) -> TimeSignature { self.signature }
+    pub fn is_running(&self) -> bool { self.running }
+
+    pub fn set_bpm(&mut self, bpm: f32, now: Instant) {
+        self.anchor_beats = self.beats(now);
+        self.anchor = now;
+        self.bpm = clamp_bpm(bpm);
+    }
+
+    pub fn set_signature(&mut self, signature: TimeSignature) {
+        self.signature = signature.clamped();
+    }
+
+    /// starts counting from bar 1
+    pub fn start(&mut self, now: Instant) {
+        self.running = true;
+        self.anchor = now;
+        self.anchor_beats = 0.0;
+    }
+
+    pub fn stop(&mut self) {
+        self.running = false;
+        self.anchor_beats = 0.0;
+    }
+
+    /// quarter notes counted since the start, 0 while stopped
+    pub fn beats(&self, now: Instant) -> f64 {
+        if !self.running {
+            return 0.0;
+        }
+        self.anchor_beats + now.saturating_duration_since(self.anchor).as_secs_f64() * self.bpm as f64 / 60.0
+    }
+
+    pub fn beat_duration(&self) -> Duration {
+        Duration::from_secs_f32(60.0 / self.bpm)
+    }
+
+    pub fn bar_duration(&self) -> Duration {
+        self.beat_duration().mul_f32(self.signature.beats as f32 * 4.0 / self.signature.unit as f32)
+    }
+
+    /// one tap of tap tempo. from the second tap on the tempo follows the average gap between
+    /// the last few; a pause longer than `TAP_TEMPO_TIMEOUT_S` starts over. returns the new tempo
+    pub fn tap(&mut self, now: Instant) -> Option<f32> {
+        if let Some(last) = self.taps.last()
+            && now.saturating_duration_since(*last).as_secs_f32() > TAP_TEMPO_TIMEOUT_S
+        {
+            self.taps.clear();
+        }
+        self.taps.push(now);
+        if self.taps.len() > TAP_TEMPO_MAX_TAPS {
+            self.taps.remove(0);
+        }
+        let (first, last) = (self.taps.first()?, self.taps.last()?);
+        let gaps = self.taps.len() - 1;
+        if gaps == 0 {
+            return None;
+        }
+        let gap = last.saturating_duration_since(*first).as_secs_f32() / gaps as f32;
+        self.set_bpm(60.0 / gap.max(f32::EPSILON), now);
+        Some(self.bpm)
+    }
+
+    pub fn status(&self, now: Instant) -> TransportStatus {
+        // counted in the signature's own beats
+        let beats = (self.beats(now) * self.signature.unit as f64 / 4.0) as u64;
+        let per_bar = self.signature.beats as u64;
+        TransportStatus {
+            bpm: self.bpm,
+            signature: self.signature,
+            running: self.running,
+            bar: (beats / per_bar) as u32 + 1,
+            beat: (beats % per_bar) as u32 + 1,
+        }
+    }
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Self::new(TEMPO_BPM_DEFAULT, TimeSignature::default())
+    }
+}
+
+fn clamp_bpm(bpm: f32) -> f32 {
+    if bpm.is_finite() { bpm.clamp(TEMPO_BPM_MIN, TEMPO_BPM_MAX) } else { TEMPO_BPM_DEFAULT }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(t0: Instant, s: f64) -> Instant {
+        t0 + Duration::from_secs_f64(s)
+    }
+
+    #[test]
+    fn position_counts_bars_and_beats() {
+        let t0 = Instant::now();
+        let mut transport = Transport::new(120.0, TimeSignature::default());
+        assert_eq!(transport.beats(at(t0, 3.0)), 0.0);
+        transport.start(t0);
+        // two beats a second: 5.25 s is 10.5 beats, bar 3 beat 3
+        assert!((transport.beats(at(t0, 5.25)) - 10.5).abs() < 1e-9);
+        let status = transport.status(at(t0, 5.25));
+        assert_eq!((status.bar, status.beat), (3, 3));
+
+        // a tempo change carries on from where the count was
+        transport.set_bpm(60.0, at(t0, 5.0));
+        assert!((transport.beats(at(t0, 7.0)) - 12.0).abs() < 1e-9);
+
+        transport.stop();
+        let status = transport.status(at(t0, 8.0));
+        assert_eq!((status.bar, status.beat, status.running), (1, 1, false));
+    }
+
+    #[test]
+    fn bars_follow_the_time_signature() {
+        let mut transport = Transport::new(120.0, TimeSignature::default());
+        assert_eq!(transport.beat_duration(), Duration::from_millis(500));
+        assert_eq!(transport.bar_duration(), Duration::from_secs(2));
+        // 6/8 is three quarters
+        transport.set_signature(TimeSignature { beats: 6, unit: 8 });
+        assert_eq!(transport.bar_duration(), Duration::from_millis(1500));
+        transport.set_signature(TimeSignature { beats: 3, unit: 4 });
+        assert_eq!(transport.bar_duration(), Duration::from_millis(1500));
+
+        // and the counter counts eighths in 6/8: 1.75 s at 120 is 7 eighths, bar 2 beat 2
+        let t0 = Instant::now();
+        transport.set_signature(TimeSignature { beats: 6, unit: 8 });
+        transport.start(t0);
+        let status = transport.status(at(t0, 1.75));
+        assert_eq!((status.bar, status.beat), (2, 2));
+    }
+
+    #[test]
+    fn odd_signatures_are_clamped() {
+        assert_eq!(TimeSignature { beats: 0, unit: 5 }.clamped(), TimeSignature { beats: 1, unit: 4 });
+        assert_eq!(TimeSignature { beats: 40, unit: 64 }.clamped(), TimeSignature { beats: 32, unit: 16 });
+    }
+
+    #[test]
+    fn tap_tempo_averages_the_last_taps() {
+        let t0 = Instant::now();
+        let mut transport = Transport::default();
+        assert_eq!(transport.tap(t0), None);
+        // 0.5 s and 0.6 s apart: 0.55 on average
+        assert!(transport.tap(at(t0, 0.5)).is_some());
+        let bpm = transport.tap(at(t0, 1.1)).unwrap();
+        assert!((bpm - 60.0 / 0.55).abs() < 0.01, "{bpm}");
+
+        // only the last few taps count
+        let mut t = 1.1;
+        for _ in 0..TAP_TEMPO_MAX_TAPS {
+            t += 0.4;
+            transport.tap(at(t0, t));
+        }
+        assert!((transport.bpm() - 150.0).abs() < 0.01, "{}", transport.bpm());
+
+        // a long pause starts a new count
+        t += f64::from(TAP_TEMPO_TIMEOUT_S) + 1.0;
+        assert_eq!(transport.tap(at(t0, t)), None);
+    }
+
+    #[test]
+    fn tap_tempo_is_clamped() {
+        let t0 = Instant::now();
+        let mut transport = Transport::default();
+        transport.tap(t0);
+        assert_eq!(transport.tap(at(t0, 0.01)), Some(TEMPO_BPM_MAX));
+
+        // taps further apart than the timeout start over, so the slowest tempo comes from elsewhere
+        transport.set_bpm(5.0, t0);
+        assert_eq!(transport.bpm(), TEMPO_BPM_MIN);
+        assert_eq!(Transport::new(f32::NAN, TimeSignature::default()).bpm(), TEMPO_BPM_DEFAULT);
+    }
+}
@@ -0,0 +1,217 @@
+//! what frequency each key plays: equal temperament by default, or any scale loaded from a
+//! Scala `.scl` file, pitched so A4 sounds at the reference frequency. keys step one scale
+//! degree each away from the root in octave 4, so a 19-note scale spreads over more than an
+//! octave of keys
+
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{
+    A4_SEMITONES, BASE_FREQ, KEYBOARD_BASE_OCTAVE, SEMITONES_PER_OCTAVE, TUNING_REFERENCE_MAX_HZ, TUNING_REFERENCE_MIN_HZ,
+};
+use crate::key::{Key, Note};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TuningSettings {
+    /// the frequency A4 sounds at
+    pub reference_hz: f32,
+    /// a Scala scale file; equal temperament when unset
+    pub scala: Option<std::path::PathBuf>,
+    /// the note the scale's first degree sits on, in octave 4
+    pub root: Note,
+}
+
+impl Default for TuningSettings {
+    fn default() -> Self {
+        Self { reference_hz: BASE_FREQ, scala: None, root: Note::C }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tuning {
+    name: String,
+    /// cents above the root of every degree after it; the last one is the period, usually 1200
+    cents: Vec<f64>,
+    /// the key degree 0 is played on
+    root: Key,
+    /// the root's frequency, so A4 lands on the reference
+    root_hz: f64,
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Self::equal(BASE_FREQ)
+    }
+}
+
+impl Tuning {
+    /// twelve-tone equal temperament
+    pub fn equal(reference_hz: f32) -> Self {
+        let cents = (1..=SEMITONES_PER_OCTAVE).map(|i| i as f64 * 100.0).collect();
+        Self::new("12-TET".into(), cents, Note::C, reference_hz)
+    }
+
+    pub fn from_settings(s: &TuningSettings) -> Result<Self, Box<dyn std::error::Error>> {
+        let Some(path) = &s.scala else { return Ok(Self::equal(s.reference_hz)); };
+        let (name, cents) = load_scala(path)?;
+        Ok(Self::new(name, cents, s.root, s.reference_hz))
+    }
+
+    fn new(name: String, cents: Vec<f64>, root: Note, reference_hz: f32) -> Self {
+        let reference_hz = reference_hz.clamp(TUNING_REFERENCE_MIN_HZ, TUNING_REFERENCE_MAX_HZ) as f64;
+        let root = Key::new(root, KEYBOARD_BASE_OCTAVE);
+        let mut tuning = Self { name, cents, root, root_hz: 1.0 };
+        tuning.root_hz = reference_hz / tuning.ratio(A4_SEMITONES - root.absolute_semitone());
+        tuning
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn frequency(&self, key: Key) -> f32 {
+        (self.root_hz * self.ratio(key.absolute_semitone() - self.root.absolute_semitone())) as f32
+    }
+
+    /// frequency ratio `steps` degrees above the root
+    fn ratio(&self, steps: i32) -> f64 {
+        let n = self.cents.len() as i32;
+        let period = self.cents[self.cents.len() - 1];
+        let degree = steps.rem_euclid(n);
+        let cents = steps.div_euclid(n) as f64 * period + if degree == 0 { 0.0 } else { self.cents[degree as usize - 1] };
+        2f64.powf(cents / 1200.0)
+    }
+}
+
+/// a `.scl` file: `!` comment lines, a description, the number of notes, then one pitch per
+/// line in cents (has a `.`) or as a ratio (`3/2`, `2`). the 1/1 is implied
+fn load_scala(path: &Path) -> Result<(String, Vec<f64>), Box<dyn std::error::Error>> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let mut lines = text.lines().filter(|l| !l.starts_with('!'));
+    let bad = |what: &str| format!("{}: {what}", path.display());
+
+    let description = lines.next().ok_or_else(|| bad("empty scale file"))?.trim();
+    let count: usize = lines
+        .next()
+        .and_then(|l| l.trim().parse().ok())
+        .ok_or_else(|| bad("no note count on the second line"))?;
+    if count == 0 {
+        return Err(bad("a scale needs at least one note").into());
+    }
+    let cents = lines
+        .take(count)
+        .map(|l| parse_pitch(l).ok_or_else(|| bad(&format!("not a pitch: {}", l.trim()))))
+        .collect::<Result<Vec<f64>, _>>()?;
+    if cents.len() < count {
+        return Err(bad(&format!("expected {count} notes, found {}", cents.len())).into());
+    }
+    if cents[count - 1] <= 0.0 {
+        return Err(bad("the last note (the period) has to be above the root").into());
+    }
+
+    let name = match description {
+        "" => path.file_stem().map_or("scala".into(), |s| s.to_string_lossy().into_owned()),
+        d => d.to_string(),
+    };
+    Ok((name, cents))
+}
+
+fn parse_pitch(line: &str) -> Option<f64> {
+    let token = line.split_whitespace().next()?;
+    if token.contains('.') {
+        return token.parse().ok();
+    }
+    let (num, den) = token.split_once('/').unwrap_or((token, "1"));
+    let (num, den): (f64, f64) = (num.parse().ok()?, den.parse().ok()?);
+    (num > 0.0 && den > 0.0).then(|| 1200.0 * (num / den).log2())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    #[test]
+    fn pitches_are_cents_or_ratios() {
+        // a `.` makes it cents, anything else a ratio; what follows the pitch is a comment
+        assert_eq!(parse_pitch("701.955"), Some(701.955));
+        assert_eq!(parse_pitch(" 100.0 a semitone"), Some(100.0));
+        assert!(close(parse_pitch("3/2").unwrap(), 701.955));
+        assert!(close(parse_pitch("2").unwrap(), 1200.0));
+        assert!(close(parse_pitch("2/1 ! octave").unwrap(), 1200.0));
+        assert_eq!(parse_pitch("0/1"), None);
+        assert_eq!(parse_pitch("fifth"), None);
+        assert_eq!(parse_pitch(""), None);
+    }
+
+    #[test]
+    fn equal_temperament_at_440() {
+        let t = Tuning::equal(440.0);
+        let hz = |note, octave| f64::from(t.frequency(Key::new(note, octave)));
+        assert!(close(hz(Note::A, 4), 440.0));
+        assert!(close(hz(Note::A, 3), 220.0));
+        assert!(close(hz(Note::A, 5), 880.0));
+        assert!(close(hz(Note::C, 4), 261.626));
+        assert!(close(hz(Note::E, 5), 659.255));
+    }
+
+    #[test]
+    fn a4_is_the_reference_whatever_the_root() {
+        let pentatonic = vec![200.0, 400.0, 700.0, 900.0, 1200.0];
+        for root in [Note::C, Note::D, Note::Gb, Note::A, Note::B] {
+            let t = Tuning::new("pentatonic".into(), pentatonic.clone(), root, 432.0);
+            assert!(close(f64::from(t.frequency(Key::new(Note::A, 4))), 432.0), "{root:?}");
+        }
+        // from D4, the key above is one degree (200 cents) up, and five keys up is a whole period
+        let t = Tuning::new("pentatonic".into(), pentatonic, Note::D, 440.0);
+        let d4 = f64::from(t.frequency(Key::new(Note::D, 4)));
+        assert!(close(f64::from(t.frequency(Key::new(Note::Eb, 4))) / d4, 2f64.powf(200.0 / 1200.0)));
+        assert!(close(f64::from(t.frequency(Key::new(Note::G, 4))) / d4, 2.0));
+    }
+
+    #[test]
+    fn the_period_need_not_be_an_octave() {
+        // Bohlen-Pierce: 13 equal steps to the tritave, 3/1
+        let cents: Vec<f64> = (1..=13).map(|i| i as f64 * 1200.0 * 3f64.log2() / 13.0).collect();
+        let t = Tuning::new("bp".into(), cents, Note::C, 440.0);
+        let c4 = Key::new(Note::C, 4);
+        let hz = |steps| f64::from(t.frequency(c4.transpose(steps)));
+        assert!(close(hz(13) / hz(0), 3.0));
+        assert!(close(hz(-13) / hz(0), 1.0 / 3.0));
+        assert!(close(hz(26) / hz(0), 9.0));
+    }
+
+    #[test]
+    fn a_scala_file_loads() {
+        let path = std::env::temp_dir().join(format!("tjam-tuning-{}.scl", std::process::id()));
+        let scl = "! five.scl\n!\nfive notes, just\n 5\n!\n200.0\n5/4\n701.955 fifth\n9/5\n2/1\n";
+        fs::write(&path, scl).unwrap();
+        let loaded = load_scala(&path);
+        fs::write(&path, "short\n5\n200.0\n5/4\n").unwrap();
+        let short = load_scala(&path);
+        fs::remove_file(&path).unwrap();
+
+        let (name, cents) = loaded.unwrap();
+        assert_eq!(name, "five notes, just");
+        // the 1/1 is not listed, it is the root itself
+        let expected = [200.0, 386.314, 701.955, 1017.596, 1200.0];
+        assert_eq!(cents.len(), expected.len());
+        for (c, e) in cents.iter().zip(expected) {
+            assert!(close(*c, e), "{cents:?}");
+        }
+        assert!(short.is_err());
+
+        let t = Tuning::new(name, cents, Note::C, 440.0);
+        let c4 = Key::new(Note::C, 4);
+        let hz = |steps| f64::from(t.frequency(c4.transpose(steps)));
+        assert!(close(hz(2) / hz(0), 1.25));
+        assert!(close(hz(5) / hz(0), 2.0));
+        assert!(close(hz(7) / hz(0), 2.5));
+    }
+}
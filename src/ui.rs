@@ -1,6 +1,7 @@
 // mock ui for now with logo
 use std::io;
 use std::io::stdout;
+use std::path::PathBuf;
 use std::sync::{
     Arc,
     atomic::{AtomicBool, Ordering},
@@ -15,14 +16,45 @@ use crossterm::{
 use ratatui::{
     Terminal,
     backend::CrosstermBackend,
-    layout::{Alignment, Rect},
+    layout::{Alignment, Constraint, Layout, Rect},
     prelude::Stylize,
+    style::{Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Paragraph, Wrap},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap},
 };
 use tokio::sync::{watch, mpsc};
 
-use crate::audio_system::AudioHandle;
+use synth_rs::audio_system::{AudioHandle, AudioSnapshot, DriveTarget, LessonAction, LooperAction, TransportAction};
+use synth_rs::key::{note_from_semitone, note_semitone};
+use synth_rs::looper::{LooperState, LooperStatus};
+use synth_rs::profiler::{self, PROFILER};
+use synth_rs::echo::ECHO_PITCH_STEPS;
+use synth_rs::settings::UiSettings;
+use synth_rs::gain_staging::AdviceKind;
+use synth_rs::config::{GR_METER_CELLS, GR_METER_DB_PER_CELL, LFO_RATE_STEP, REVERB_STEP, TILT_STEP};
+use synth_rs::fx::reverb::ReverbSettings;
+use synth_rs::fx::lfo::LfoSettings;
+
+pub mod adsr_editor;
+pub mod frame_pacer;
+pub mod fx_chain_editor;
+pub mod keyboard_widget;
+pub mod level_meter;
+pub mod mixer_widget;
+pub mod sample_browser;
+pub mod settings_panel;
+pub mod status_bar;
+pub mod text_mode;
+
+use adsr_editor::{AdsrEditor, EditorAction};
+use frame_pacer::FramePacer;
+use fx_chain_editor::{ChainAction, ChainEditor};
+use keyboard_widget::PianoKeyboard;
+use level_meter::{LevelBars, MeterHold};
+use mixer_widget::{Mixer, MixerAction};
+use sample_browser::{BrowserAction, SampleBrowser};
+use settings_panel::{PanelAction, SettingsPanel};
+use status_bar::{CpuMonitor, FpsCounter, StatusInfo, UnderrunWatch, status_line};
 
 struct TuiGuard;
 
@@ -34,10 +66,31 @@ impl Drop for TuiGuard {
     }
 }
 
+/// what the UI draws from: latest audio snapshot plus local browser/prompt state
+struct UiState {
+    snapshot: AudioSnapshot,
+    preset_sel: usize,
+    lfo_sel: usize,
+    show_profiler: bool,
+    meter_hold: MeterHold,
+    naming: Option<String>,
+    settings_panel: Option<SettingsPanel>,
+    adsr_editor: Option<AdsrEditor>,
+    mixer: Option<Mixer>,
+    chain_editor: Option<ChainEditor>,
+    sample_browser: Option<SampleBrowser>,
+    settings: UiSettings,
+    fps: FpsCounter,
+    cpu: CpuMonitor,
+    underruns: UnderrunWatch,
+}
+
 pub async fn run_ui(
-    _handle: AudioHandle,
+    handle: AudioHandle,
     shutdown_tx: watch::Sender<bool>,
     focused: Arc<AtomicBool>,
+    settings: UiSettings,
+    config: Option<PathBuf>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let mut stdout = stdout();
 
@@ -60,10 +113,8 @@ pub async fn run_ui(
         while !stop_bg.load(Ordering::Relaxed) {
             if event::poll(Duration::from_millis(50)).ok() == Some(true) {
                 match event::read() {
-                    Ok(Event::Key(k)) => {
-                        if k.kind == KeyEventKind::Press {
-                            let _ = key_tx.send(k);
-                        }
+                    Ok(Event::Key(k)) if k.kind == KeyEventKind::Press => {
+                        let _ = key_tx.send(k);
                     }
                     Ok(Event::FocusLost) => {
                         focused_bg.store(false, Ordering::Relaxed);
@@ -77,34 +128,313 @@ pub async fn run_ui(
         }
     });
 
+    let mut snapshot_rx = handle.subscribe();
+    let mut state = UiState {
+        snapshot: snapshot_rx.borrow().clone(),
+        preset_sel: 0,
+        lfo_sel: 0,
+        show_profiler: false,
+        meter_hold: MeterHold::new(),
+        naming: None,
+        settings_panel: None,
+        adsr_editor: None,
+        mixer: None,
+        chain_editor: None,
+        sample_browser: None,
+        settings,
+        fps: FpsCounter::new(),
+        cpu: CpuMonitor::new(),
+        underruns: UnderrunWatch::default(),
+    };
+
     let ui_start = std::time::Instant::now();
     let mut show_intro = true;
+    let mut pacer = FramePacer::new(state.settings.max_fps, state.settings.idle_fps);
 
     loop {
         if show_intro && ui_start.elapsed() >= Duration::from_secs(1) {
             show_intro = false;
+            pacer.mark_urgent();
         }
 
-        if show_intro {
-            terminal.draw(draw_intro)?;
-        } else {
-            terminal.draw(draw_ui)?;
+        let now = std::time::Instant::now();
+        if pacer.should_draw(now) {
+            if show_intro {
+                terminal.draw(draw_intro)?;
+            } else {
+                let t = PROFILER.start();
+                terminal.draw(|f| draw_ui(f, &mut state))?;
+                profiler::finish(&PROFILER.draw, t);
+                state.fps.tick();
+            }
+            pacer.drawn(now);
         }
 
         tokio::select! {
             k = key_rx.recv() => {
                 let Some(k) = k else { break; };
+                pacer.mark_urgent();
 
                 if k.modifiers.contains(KeyModifiers::CONTROL) && matches!(k.code, KeyCode::Char('c')) {
                     let _ = shutdown_tx.send(true);
                     break;
                 }
-                if matches!(k.code, KeyCode::Char('q')) {
-                    let _ = shutdown_tx.send(true);
-                    break;
+                if k.modifiers.contains(KeyModifiers::CONTROL) && matches!(k.code, KeyCode::Char('k')) {
+                    handle.panic();
+                    continue;
+                }
+
+                if let Some(name) = state.naming.as_mut() {
+                    match k.code {
+                        KeyCode::Char(c) if c.is_ascii_alphanumeric() || c == '-' || c == '_' => name.push(c),
+                        KeyCode::Backspace => { name.pop(); }
+                        KeyCode::Enter => {
+                            if !name.is_empty() {
+                                handle.save_preset(name.clone());
+                            }
+                            state.naming = None;
+                            focused.store(true, Ordering::Relaxed);
+                        }
+                        KeyCode::Esc => {
+                            state.naming = None;
+                            focused.store(true, Ordering::Relaxed);
+                        }
+                        _ => {}
+                    }
+                    continue;
+                }
+
+                if let Some(editor) = state.adsr_editor.as_mut() {
+                    match editor.handle_key(k, state.snapshot.adsr, state.snapshot.filter_env) {
+                        EditorAction::None => {}
+                        EditorAction::Close => {
+                            state.adsr_editor = None;
+                            focused.store(true, Ordering::Relaxed);
+                        }
+                        EditorAction::SetAmp(adsr) => handle.set_adsr(adsr),
+                        EditorAction::SetFilter(filter_env) => handle.set_filter_env(filter_env),
+                    }
+                    continue;
+                }
+
+                if let Some(mixer) = state.mixer.as_mut() {
+                    match mixer.handle_key(k, &state.snapshot.tracks) {
+                        MixerAction::None => {}
+                        MixerAction::Close => {
+                            state.mixer = None;
+                            focused.store(true, Ordering::Relaxed);
+                        }
+                        MixerAction::Set(track, settings) => handle.set_track(track, settings),
+                    }
+                    continue;
+                }
+
+                if let Some(editor) = state.chain_editor.as_mut() {
+                    match editor.handle_key(k, &state.snapshot) {
+                        ChainAction::None => {}
+                        ChainAction::Close => {
+                            state.chain_editor = None;
+                            focused.store(true, Ordering::Relaxed);
+                        }
+                        ChainAction::Edit(edit) => handle.edit_chain(edit),
+                        ChainAction::SetParam(owner, id, value) => handle.set_param(owner, id, value),
+                        ChainAction::Learn(param) => handle.learn_cc(param),
+                    }
+                    continue;
+                }
+
+                if let Some(browser) = state.sample_browser.as_mut() {
+                    match browser.handle_key(k) {
+                        BrowserAction::None => {}
+                        BrowserAction::Close => state.sample_browser = None,
+                        BrowserAction::Load(path) => handle.load_sample(path),
+                    }
+                    continue;
+                }
+
+                if let Some(panel) = state.settings_panel.as_mut() {
+                    match panel.handle_key(k) {
+                        PanelAction::None => {}
+                        PanelAction::Close => {
+                            state.settings_panel = None;
+                            focused.store(true, Ordering::Relaxed);
+                        }
+                        PanelAction::Saved(saved) => {
+                            state.settings.visualizer = saved.visualizer;
+                            state.settings.max_fps = saved.max_fps;
+                            state.settings.idle_fps = saved.idle_fps;
+                            state.settings.side_width = saved.side_width;
+                            state.settings.keyboard_height = saved.keyboard_height;
+                            pacer = FramePacer::new(state.settings.max_fps, state.settings.idle_fps);
+                        }
+                    }
+                    continue;
+                }
+
+                // shortcuts moved in the keymap act as the key they stand for
+                let code = match k.code {
+                    KeyCode::Char(c) => match state.snapshot.keymap.bindings().shortcut(c) {
+                        Some(c) => KeyCode::Char(c),
+                        None => continue,
+                    },
+                    code => code,
+                };
+                match code {
+                    KeyCode::Char('q') => {
+                        let _ = shutdown_tx.send(true);
+                        break;
+                    }
+                    KeyCode::Up => state.preset_sel = state.preset_sel.saturating_sub(1),
+                    KeyCode::Down => {
+                        let last = state.snapshot.presets.len().saturating_sub(1);
+                        state.preset_sel = (state.preset_sel + 1).min(last);
+                    }
+                    KeyCode::Enter => {
+                        if let Some(name) = state.snapshot.presets.get(state.preset_sel) {
+                            handle.load_preset(name.clone());
+                        }
+                    }
+                    KeyCode::Char('n') => handle.apply_gain_advice(),
+                    KeyCode::Char('c') => handle.set_chord_assist(!state.snapshot.chord_assist),
+                    KeyCode::Char('x') => handle.start_rollover_test(),
+                    KeyCode::F(1) => {
+                        // a/d/s/r pick a stage on this screen, so they must not play notes
+                        focused.store(false, Ordering::Relaxed);
+                        state.adsr_editor = Some(AdsrEditor::new());
+                    }
+                    KeyCode::F(2) => {
+                        // same as the preset prompt: typed values must not play notes
+                        focused.store(false, Ordering::Relaxed);
+                        state.settings_panel = Some(SettingsPanel::open(config.clone()));
+                    }
+                    KeyCode::F(3) => {
+                        state.show_profiler = !state.show_profiler;
+                        PROFILER.set_enabled(state.show_profiler);
+                    }
+                    KeyCode::F(4) => {
+                        let mut echo = state.snapshot.echo;
+                        echo.enabled = !echo.enabled;
+                        handle.set_echo(echo);
+                    }
+                    KeyCode::F(5) => {
+                        let mut echo = state.snapshot.echo;
+                        echo.division = echo.division.next();
+                        handle.set_echo(echo);
+                    }
+                    KeyCode::F(6) => {
+                        let mut echo = state.snapshot.echo;
+                        let i = ECHO_PITCH_STEPS.iter().position(|s| *s == echo.pitch_step).unwrap_or(0);
+                        echo.pitch_step = ECHO_PITCH_STEPS[(i + 1) % ECHO_PITCH_STEPS.len()];
+                        handle.set_echo(echo);
+                    }
+                    KeyCode::F(7) => {
+                        let mut lock = state.snapshot.scale_lock;
+                        lock.enabled = !lock.enabled;
+                        handle.set_scale_lock(lock);
+                    }
+                    KeyCode::F(8) => {
+                        let mut lock = state.snapshot.scale_lock;
+                        lock.scale = lock.scale.next();
+                        handle.set_scale_lock(lock);
+                    }
+                    KeyCode::F(9) => {
+                        let mut lock = state.snapshot.scale_lock;
+                        if let Some(root) = note_from_semitone(note_semitone(lock.root) as u32 + 1) {
+                            lock.root = root;
+                        }
+                        handle.set_scale_lock(lock);
+                    }
+                    KeyCode::Char('[') => edit_reverb(&handle, &state, |r| r.mix -= REVERB_STEP),
+                    KeyCode::Char(']') => edit_reverb(&handle, &state, |r| r.mix += REVERB_STEP),
+                    KeyCode::Char('{') => edit_reverb(&handle, &state, |r| r.room_size -= REVERB_STEP),
+                    KeyCode::Char('}') => edit_reverb(&handle, &state, |r| r.room_size += REVERB_STEP),
+                    KeyCode::Char('\\') => edit_reverb(&handle, &state, |r| {
+                        r.damping = if r.damping >= 0.75 { 0.25 } else { r.damping + 0.25 };
+                    }),
+                    KeyCode::Char('9') => handle.set_tilt(state.snapshot.tilt - TILT_STEP),
+                    KeyCode::Char('0') => handle.set_tilt(state.snapshot.tilt + TILT_STEP),
+                    KeyCode::F(10) => handle.lesson(LessonAction::Toggle),
+                    KeyCode::F(11) => handle.lesson(LessonAction::Next),
+                    KeyCode::F(12) => handle.set_freeze(!state.snapshot.freeze),
+                    KeyCode::Char('z') => handle.looper(LooperAction::Advance),
+                    KeyCode::Char('Z') => handle.looper(LooperAction::TogglePlay),
+                    KeyCode::Backspace => handle.looper(LooperAction::UndoLayer),
+                    KeyCode::Delete => handle.looper(LooperAction::Clear),
+                    KeyCode::Char('N') => handle.set_auto_gain(!state.snapshot.auto_gain),
+                    KeyCode::Char('5') => toggle_param(&handle, &state, "Bitcrush", "enabled"),
+                    KeyCode::Char('%') => toggle_param(&handle, &state, "Compressor", "enabled"),
+                    KeyCode::Char('`') => toggle_param(&handle, &state, "RingMod", "enabled"),
+                    KeyCode::Char('6') => {
+                        let mut drive = state.snapshot.drive;
+                        drive.enabled = !drive.enabled;
+                        handle.set_drive(DriveTarget::Patch, drive);
+                    }
+                    KeyCode::Char('^') => {
+                        let mut drive = state.snapshot.drive;
+                        drive.curve = drive.curve.next();
+                        drive.enabled = true;
+                        handle.set_drive(DriveTarget::Patch, drive);
+                    }
+                    KeyCode::Char('4') => handle.set_chord(state.snapshot.chord.next()),
+                    KeyCode::Char('7') => handle.transport(TransportAction::Tap),
+                    KeyCode::Char('8') => handle.transport(TransportAction::Toggle),
+                    KeyCode::Char('R') => handle.set_recording(!state.snapshot.recording),
+                    KeyCode::Char('L') => handle.set_latch(!state.snapshot.latch),
+                    KeyCode::Char('M') => {
+                        // m and s are mute and solo on this screen, so they must not play notes
+                        focused.store(false, Ordering::Relaxed);
+                        state.mixer = Some(Mixer::new());
+                    }
+                    KeyCode::Char('E') => {
+                        // a, b, n and x are chain edits on this screen, so they must not play notes
+                        focused.store(false, Ordering::Relaxed);
+                        state.chain_editor = Some(ChainEditor::new());
+                    }
+                    KeyCode::Char('S') => {
+                        let snap = &state.snapshot;
+                        state.sample_browser = Some(SampleBrowser::open(snap.sample.as_deref(), snap.sample_dir.as_deref()));
+                    }
+                    KeyCode::Tab => {
+                        let n = state.snapshot.lfos.len().max(1);
+                        state.lfo_sel = (state.lfo_sel + 1) % n;
+                    }
+                    KeyCode::Char('m') => edit_lfo(&handle, &state, |s| s.enabled = !s.enabled),
+                    KeyCode::Char('i') => edit_lfo(&handle, &state, |s| s.shape = s.shape.next()),
+                    KeyCode::Char('r') => edit_lfo(&handle, &state, |s| {
+                        s.target = s.target.next();
+                        s.depth = s.depth.min(s.target.max_depth());
+                    }),
+                    KeyCode::Char(',') => edit_lfo(&handle, &state, |s| match s.sync {
+                        Some(d) => s.sync = Some(d.longer()),
+                        None => s.rate_hz /= LFO_RATE_STEP,
+                    }),
+                    KeyCode::Char('.') => edit_lfo(&handle, &state, |s| match s.sync {
+                        Some(d) => s.sync = Some(d.shorter()),
+                        None => s.rate_hz *= LFO_RATE_STEP,
+                    }),
+                    KeyCode::Char('/') => {
+                        let bpm = state.snapshot.transport.bpm;
+                        edit_lfo(&handle, &state, |s| s.toggle_sync(bpm));
+                    }
+                    KeyCode::Char('-') => edit_lfo(&handle, &state, |s| s.depth -= s.target.depth_step()),
+                    KeyCode::Char('=') => edit_lfo(&handle, &state, |s| s.depth += s.target.depth_step()),
+                    KeyCode::Char('v') => {
+                        // typing a name must not play notes, so drop keyboard focus while the prompt is open
+                        focused.store(false, Ordering::Relaxed);
+                        state.naming = Some(String::new());
+                    }
+                    _ => {}
                 }
             }
-            _ = tokio::time::sleep(Duration::from_millis(16)) => {}
+            changed = snapshot_rx.changed() => {
+                if changed.is_err() { break; }
+                state.snapshot = snapshot_rx.borrow_and_update().clone();
+                state.meter_hold.observe(state.snapshot.master_peak_db, std::time::Instant::now());
+                let last = state.snapshot.presets.len().saturating_sub(1);
+                state.preset_sel = state.preset_sel.min(last);
+                pacer.mark_dirty();
+            }
+            _ = tokio::time::sleep_until(pacer.next_deadline().into()) => {}
         }
     }
 
@@ -113,6 +443,26 @@ pub async fn run_ui(
     Ok(())
 }
 
+/// change the selected LFO route and send it back to the audio side
+fn edit_lfo(handle: &AudioHandle, state: &UiState, f: impl FnOnce(&mut LfoSettings)) {
+    let Some(mut s) = state.snapshot.lfos.get(state.lfo_sel).copied() else { return; };
+    f(&mut s);
+    handle.set_lfo(state.lfo_sel, s);
+}
+
+/// flips a switch parameter of an effect, as `SetParam` names it
+fn toggle_param(handle: &AudioHandle, state: &UiState, owner: &str, id: &str) {
+    let group = state.snapshot.params.iter().find(|g| g.owner == owner);
+    let Some(desc) = group.and_then(|g| g.params.iter().find(|p| p.id == id)) else { return; };
+    handle.set_param(owner, id, desc.nudged(true));
+}
+
+fn edit_reverb(handle: &AudioHandle, state: &UiState, f: impl FnOnce(&mut ReverbSettings)) {
+    let mut r = state.snapshot.reverb;
+    f(&mut r);
+    handle.set_reverb(r.clamped());
+}
+
 fn draw_intro(f: &mut ratatui::Frame) {
     let art: [&str; 23] = [
         r"          _____                    _____                    _____                    _____                    _____          ",
@@ -148,7 +498,7 @@ fn draw_intro(f: &mut ratatui::Frame) {
             let mut owned = s.to_string();
             let pad = max_w.saturating_sub(owned.chars().count());
             if pad > 0 {
-                owned.extend(std::iter::repeat(' ').take(pad));
+                owned.extend(std::iter::repeat_n(' ', pad));
             }
             Line::from(Span::raw(owned).bold())
         })
@@ -184,9 +534,252 @@ fn draw_intro(f: &mut ratatui::Frame) {
     f.render_widget(widget, centered);
 }
 
-fn draw_ui(f: &mut ratatui::Frame) {
+fn draw_ui(f: &mut ratatui::Frame, state: &mut UiState) {
     let block = Block::default()
         .borders(Borders::ALL)
         .title(" mugen ");
+    let inner = block.inner(f.area());
     f.render_widget(block, f.area());
+
+    let visualizer = state.settings.visualizer;
+    let [status, main, piano, prompt] = Layout::vertical([
+        Constraint::Length(2),
+        Constraint::Min(0),
+        Constraint::Length(if visualizer { state.settings.keyboard_height } else { 0 }),
+        Constraint::Length(1),
+    ])
+    .areas(inner);
+    let [info, side] = Layout::horizontal([Constraint::Min(0), Constraint::Length(state.settings.side_width)]).areas(main);
+    let [browser, looper, lfo, gain] = Layout::vertical([
+        Constraint::Min(0),
+        Constraint::Length(3),
+        Constraint::Length(state.snapshot.lfos.len() as u16 + 2),
+        // a row more for the compressor's gain reduction while it is on
+        Constraint::Length(if state.snapshot.compressor.enabled { 7 } else { 6 }),
+    ])
+    .areas(side);
+
+    let status_info = StatusInfo {
+        snapshot: &state.snapshot,
+        fps: state.fps.fps(),
+        cpu: state.cpu.usage(),
+        underruns: state.underruns.recent(state.snapshot.underruns),
+    };
+    f.render_widget(Paragraph::new(status_line(&state.settings.status_bar, &status_info)), status);
+
+    let snap = &state.snapshot;
+    let mut lines = vec![
+        Line::from("b patch · space sustain · ←/→ bend · ↑/↓ select preset · enter load · v save · q quit · ^K panic").dim(),
+        Line::from("n apply gain advice · N auto gain · c chord assist · x rollover test · M mixer · R record MIDI").dim(),
+        Line::from("F1 envelope · F2 settings · F3 profiler · F4 echo · F5 echo time · F6 echo pitch").dim(),
+        Line::from("F7 scale lock · F8 scale · F9 root · F10 lessons · F11 next lesson · F12 freeze").dim(),
+        Line::from("[/] reverb mix · {/} room size · \\ damping · 9/0 darker/brighter · 5/% bitcrush/compressor · 6/^ drive/curve · ` ring mod").dim(),
+        Line::from("4 chord · 7 tap tempo · 8 transport · S samples · L latch · E effects").dim(),
+        Line::from("1/2/3 kick/snare/hat · z rec/play/dub · Z stop/play · backspace undo layer · del clear loop").dim(),
+        Line::from("tab lfo · m on/off · i shape · r target · ,/. rate · / tempo sync · -/= depth").dim(),
+    ];
+    if let Some(lesson) = &snap.lesson {
+        lines.push(Line::from(""));
+        lines.push(Line::from(lesson.describe(&snap.keymap)).bold());
+    }
+    if let Some(notice) = &snap.notice {
+        lines.push(Line::from(""));
+        lines.push(Line::from(notice.clone()).italic());
+    }
+    f.render_widget(Paragraph::new(lines).wrap(Wrap { trim: false }), info);
+
+    draw_preset_browser(f, browser, state);
+    draw_looper(f, looper, &state.snapshot.looper);
+    draw_lfos(f, lfo, state);
+    draw_gain_staging(f, gain, &state.snapshot, visualizer.then_some(&state.meter_hold));
+    if visualizer {
+        draw_piano(f, piano, &state.snapshot);
+    }
+
+    if state.show_profiler {
+        draw_profiler(f, inner);
+    }
+
+    if let Some(editor) = &state.adsr_editor {
+        editor.draw(f, main, &state.snapshot.adsr, &state.snapshot.filter_env);
+    }
+
+    if let Some(mixer) = &state.mixer {
+        mixer.draw(f, main, &state.snapshot.tracks);
+    }
+
+    if let Some(editor) = &state.chain_editor {
+        editor.draw(f, main, &state.snapshot);
+    }
+
+    if let Some(browser) = &state.sample_browser {
+        browser.draw(f, main, state.snapshot.sample.as_deref());
+    }
+
+    if let Some(panel) = &state.settings_panel {
+        panel.draw(f, inner);
+    }
+
+    if let Some(name) = &state.naming {
+        let line = Line::from(vec![Span::raw("save preset as: "), Span::raw(format!("{name}_")).bold()]);
+        f.render_widget(Paragraph::new(line), prompt);
+    }
+}
+
+/// held and looped notes, plus the lesson's next one
+fn draw_piano(f: &mut ratatui::Frame, area: Rect, snap: &AudioSnapshot) {
+    let mut held = snap.active_keys.clone();
+    for k in &snap.loop_keys {
+        if !held.contains(k) {
+            held.push(*k);
+        }
+    }
+    let hint = snap.lesson.as_ref().and_then(|l| l.next);
+    let keyboard = PianoKeyboard::new(&held, &snap.keymap, snap.keymap.octave()).with_hint(hint);
+    f.render_widget(keyboard, area);
+}
+
+/// timings popup over the middle of the screen
+fn draw_profiler(f: &mut ratatui::Frame, area: Rect) {
+    let stats = PROFILER.stats();
+    let w = 44.min(area.width);
+    let h = (stats.len() as u16 + 3).min(area.height);
+    let popup = Rect {
+        x: area.x + (area.width - w) / 2,
+        y: area.y + (area.height - h) / 2,
+        width: w,
+        height: h,
+    };
+
+    let mut lines = vec![Line::from(format!("{:<14}{:>12}{:>12}", "", "avg ms", "max ms")).dim()];
+    for (name, stat) in stats {
+        lines.push(Line::from(format!(
+            "{name:<14}{:>12.3}{:>12.3}",
+            stat.avg_us() / 1000.0,
+            stat.max_us() / 1000.0,
+        )));
+    }
+
+    let block = Block::default().borders(Borders::ALL).title(" profiler (F3) ");
+    f.render_widget(Clear, popup);
+    f.render_widget(Paragraph::new(lines).block(block), popup);
+}
+
+fn draw_looper(f: &mut ratatui::Frame, area: Rect, status: &LooperStatus) {
+    let layers = match status.layers {
+        1 => " 1 layer ".to_string(),
+        n => format!(" {n} layers "),
+    };
+    let block = Block::default().borders(Borders::ALL).title(" looper ").title_bottom(layers);
+
+    let inner_w = area.width.saturating_sub(2) as usize;
+    let line = match (status.state, status.length_s) {
+        (LooperState::Empty, _) => Line::from("z to record").dim(),
+        (LooperState::Recording, _) => Line::from(format!("● rec {:.1}s", status.position_s)).red(),
+        (state, Some(length)) => {
+            let label = format!("{} {:>4.1}/{:.1}s ", state.name(), status.position_s, length);
+            let bar_w = inner_w.saturating_sub(label.chars().count());
+            let filled = ((status.position_s / length.max(0.001)) * bar_w as f32) as usize;
+            let bar = format!("{}{}", "█".repeat(filled.min(bar_w)), "░".repeat(bar_w.saturating_sub(filled)));
+            let line = Line::from(format!("{label}{bar}"));
+            match state {
+                LooperState::Overdubbing => line.red(),
+                LooperState::Stopped => line.dim(),
+                _ => line.green(),
+            }
+        }
+        (state, None) => Line::from(state.name()),
+    };
+    f.render_widget(Paragraph::new(line).block(block), area);
+}
+
+fn draw_lfos(f: &mut ratatui::Frame, area: Rect, state: &UiState) {
+    let block = Block::default().borders(Borders::ALL).title(" lfo ");
+    let lines: Vec<Line> = state
+        .snapshot
+        .lfos
+        .iter()
+        .enumerate()
+        .map(|(i, s)| {
+            let marker = if i == state.lfo_sel { "▸" } else { " " };
+            let rate = match s.sync {
+                Some(d) => d.name().to_string(),
+                None => format!("{:.2}Hz", s.rate_hz),
+            };
+            let line = Line::from(format!(
+                "{marker}{:<7} {:<6} {rate:>7} {:.2}{}",
+                s.target.name(),
+                s.shape.name(),
+                s.depth,
+                s.target.unit(),
+            ));
+            if s.enabled { line } else { line.dim() }
+        })
+        .collect();
+    f.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+/// level bars when `hold` is given, otherwise the same levels as numbers
+fn draw_gain_staging(f: &mut ratatui::Frame, area: Rect, snap: &AudioSnapshot, hold: Option<&MeterHold>) {
+    let title = if snap.auto_gain { " gain staging (auto) " } else { " gain staging " };
+    let block = Block::default().borders(Borders::ALL).title(title);
+
+    let advice = match snap.gain_advice {
+        Some(a) if a.kind == AdviceKind::Clipping => Line::from(format!("clipping → vol {:.2}", a.suggested_volume)).red(),
+        Some(a) => Line::from(format!("headroom → vol {:.2}", a.suggested_volume)).yellow(),
+        None => Line::from("levels ok").green(),
+    };
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+    let [bars, advice_area] = Layout::vertical([Constraint::Length(3), Constraint::Min(0)]).areas(inner);
+    match hold {
+        Some(hold) => {
+            let now = std::time::Instant::now();
+            f.render_widget(LevelBars::new(snap.master_peak_db, snap.master_rms_db, hold, now), bars);
+        }
+        None => {
+            let levels = vec![
+                Line::from(format!("peak {:>6.1} dB", snap.master_peak_db)),
+                Line::from(format!("rms  {:>6.1} dB", snap.master_rms_db)),
+            ];
+            f.render_widget(Paragraph::new(levels), bars);
+        }
+    }
+    let mut lines = vec![advice];
+    if snap.compressor.enabled {
+        lines.push(Line::from(format!("comp {}", gain_reduction_bar(snap.gain_reduction_db))));
+    }
+    f.render_widget(Paragraph::new(lines), advice_area);
+}
+
+/// the master compressor's gain reduction: a cell per `GR_METER_DB_PER_CELL`, then the figure
+fn gain_reduction_bar(gr_db: f32) -> String {
+    let cells = ((gr_db / GR_METER_DB_PER_CELL).round() as usize).min(GR_METER_CELLS);
+    format!("{}{} -{gr_db:.1} dB", "█".repeat(cells), "─".repeat(GR_METER_CELLS - cells))
+}
+
+fn draw_preset_browser(f: &mut ratatui::Frame, area: Rect, state: &UiState) {
+    let presets = &state.snapshot.presets;
+    let block = Block::default().borders(Borders::ALL).title(" presets ");
+
+    if presets.is_empty() {
+        let empty = Paragraph::new("no presets yet").dim().block(block);
+        f.render_widget(empty, area);
+        return;
+    }
+
+    let items: Vec<ListItem> = presets
+        .iter()
+        .map(|name| {
+            let active = state.snapshot.preset_name.as_deref() == Some(name.as_str());
+            let marker = if active { "● " } else { "  " };
+            ListItem::new(format!("{marker}{name}"))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut list_state = ListState::default().with_selected(Some(state.preset_sel));
+    f.render_stateful_widget(list, area, &mut list_state);
 }
@@ -0,0 +1,258 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    layout::{Constraint, Layout, Rect},
+    prelude::Stylize,
+    style::{Color, Style},
+    symbols::Marker,
+    text::{Line, Span},
+    widgets::{Axis, Block, Borders, Chart, Clear, Dataset, GraphType, Paragraph},
+};
+
+use synth_rs::config::{
+    ADSR_SUSTAIN_STEP, ADSR_TIME_MAX_S, ADSR_TIME_MIN_S, ADSR_TIME_STEP, FILTER_ENV_DEPTH_MAX,
+    FILTER_ENV_DEPTH_STEP,
+};
+use synth_rs::fx::adsr::{Adsr, Curve, FilterEnvelope};
+
+/// points drawn per sloped segment
+const SEGMENT_POINTS: usize = 48;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdsrParam {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    /// filter tab only: how far the envelope opens the cutoff
+    Depth,
+}
+
+impl AdsrParam {
+    const AMP: &[AdsrParam] = &[AdsrParam::Attack, AdsrParam::Decay, AdsrParam::Sustain, AdsrParam::Release];
+    const FILTER: &[AdsrParam] =
+        &[AdsrParam::Attack, AdsrParam::Decay, AdsrParam::Sustain, AdsrParam::Release, AdsrParam::Depth];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            AdsrParam::Attack => "A",
+            AdsrParam::Decay => "D",
+            AdsrParam::Sustain => "S",
+            AdsrParam::Release => "R",
+            AdsrParam::Depth => "depth",
+        }
+    }
+
+    fn curve(self, adsr: &mut Adsr) -> Option<&mut Curve> {
+        match self {
+            AdsrParam::Attack => Some(&mut adsr.attack_curve),
+            AdsrParam::Decay => Some(&mut adsr.decay_curve),
+            AdsrParam::Sustain | AdsrParam::Depth => None,
+            AdsrParam::Release => Some(&mut adsr.release_curve),
+        }
+    }
+
+    /// one press of ↑ (`up`) or ↓: times scale by `ADSR_TIME_STEP`, the sustain level steps
+    fn nudge(self, adsr: &mut Adsr, up: bool) {
+        let time = |t: &mut f32| {
+            let t2 = if up { *t * ADSR_TIME_STEP } else { *t / ADSR_TIME_STEP };
+            *t = t2.clamp(ADSR_TIME_MIN_S, ADSR_TIME_MAX_S);
+        };
+        match self {
+            AdsrParam::Attack => time(&mut adsr.attack_s),
+            AdsrParam::Decay => time(&mut adsr.decay_s),
+            AdsrParam::Release => time(&mut adsr.release_s),
+            AdsrParam::Sustain => {
+                let step = if up { ADSR_SUSTAIN_STEP } else { -ADSR_SUSTAIN_STEP };
+                adsr.sustain = (adsr.sustain + step).clamp(0.0, 1.0);
+            }
+            AdsrParam::Depth => {}
+        }
+    }
+}
+
+/// which of the voice's two envelopes the editor shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnvelopeTab {
+    Amp,
+    Filter,
+}
+
+impl EnvelopeTab {
+    fn params(self) -> &'static [AdsrParam] {
+        match self {
+            EnvelopeTab::Amp => AdsrParam::AMP,
+            EnvelopeTab::Filter => AdsrParam::FILTER,
+        }
+    }
+}
+
+/// what a key press on the editor asks of the UI
+pub enum EditorAction {
+    None,
+    Close,
+    SetAmp(Adsr),
+    SetFilter(FilterEnvelope),
+}
+
+/// F1 screen: the amp and filter envelopes as charts, one tab each, edited live
+pub struct AdsrEditor {
+    tab: EnvelopeTab,
+    sel: AdsrParam,
+}
+
+impl Default for AdsrEditor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AdsrEditor {
+    pub fn new() -> Self {
+        Self { tab: EnvelopeTab::Amp, sel: AdsrParam::Attack }
+    }
+
+    pub fn handle_key(&mut self, k: KeyEvent, amp: Adsr, mut filter: FilterEnvelope) -> EditorAction {
+        let params = self.tab.params();
+        let mut adsr = match self.tab {
+            EnvelopeTab::Amp => amp,
+            EnvelopeTab::Filter => filter.adsr,
+        };
+        match k.code {
+            KeyCode::Tab => {
+                self.tab = match self.tab {
+                    EnvelopeTab::Amp => EnvelopeTab::Filter,
+                    EnvelopeTab::Filter => EnvelopeTab::Amp,
+                };
+                if !self.tab.params().contains(&self.sel) {
+                    self.sel = AdsrParam::Attack;
+                }
+                return EditorAction::None;
+            }
+            KeyCode::Char('a') => self.sel = AdsrParam::Attack,
+            KeyCode::Char('d') => self.sel = AdsrParam::Decay,
+            KeyCode::Char('s') => self.sel = AdsrParam::Sustain,
+            KeyCode::Char('r') => self.sel = AdsrParam::Release,
+            KeyCode::Left => self.sel = params[self.sel.index().saturating_sub(1)],
+            KeyCode::Right => self.sel = params[(self.sel.index() + 1).min(params.len() - 1)],
+            KeyCode::Up | KeyCode::Down if self.sel == AdsrParam::Depth => {
+                let step = if k.code == KeyCode::Up { FILTER_ENV_DEPTH_STEP } else { -FILTER_ENV_DEPTH_STEP };
+                filter.depth = (filter.depth + step).clamp(0.0, FILTER_ENV_DEPTH_MAX);
+                return EditorAction::SetFilter(filter);
+            }
+            KeyCode::Up | KeyCode::Down => {
+                self.sel.nudge(&mut adsr, k.code == KeyCode::Up);
+                return self.set(adsr, filter);
+            }
+            KeyCode::Char('c') => {
+                let Some(curve) = self.sel.curve(&mut adsr) else { return EditorAction::None; };
+                *curve = curve.next();
+                return self.set(adsr, filter);
+            }
+            KeyCode::Char('t') if self.tab == EnvelopeTab::Amp => {
+                adsr.retrigger = adsr.retrigger.next();
+                return self.set(adsr, filter);
+            }
+            KeyCode::Esc | KeyCode::F(1) => return EditorAction::Close,
+            _ => {}
+        }
+        EditorAction::None
+    }
+
+    /// `adsr` edited on the current tab
+    fn set(&self, adsr: Adsr, filter: FilterEnvelope) -> EditorAction {
+        match self.tab {
+            EnvelopeTab::Amp => EditorAction::SetAmp(adsr),
+            EnvelopeTab::Filter => EditorAction::SetFilter(FilterEnvelope { adsr, ..filter }),
+        }
+    }
+
+    /// covers `area`, the main panels
+    pub fn draw(&self, f: &mut ratatui::Frame, area: Rect, amp: &Adsr, filter: &FilterEnvelope) {
+        let adsr = match self.tab {
+            EnvelopeTab::Amp => amp,
+            EnvelopeTab::Filter => &filter.adsr,
+        };
+        let tab = |name: &'static str, on: bool| if on { Span::raw(name).reversed() } else { Span::raw(name) };
+        let title = Line::from(vec![
+            Span::raw(" envelope (F1) "),
+            tab(" amp ", self.tab == EnvelopeTab::Amp),
+            Span::raw(" "),
+            tab(" filter ", self.tab == EnvelopeTab::Filter),
+            Span::raw(" "),
+        ]);
+        let block = Block::default().borders(Borders::ALL).title(title);
+        let inner = block.inner(area);
+        f.render_widget(Clear, area);
+        f.render_widget(block, area);
+
+        let [chart_area, values, help] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1), Constraint::Length(1)]).areas(inner);
+
+        let segments = segments(adsr);
+        let total = segments.last().and_then(|s| s.last()).map_or(1.0, |p| p.0);
+        let datasets: Vec<Dataset> = segments
+            .iter()
+            .enumerate()
+            .map(|(i, points)| {
+                let style = if i == self.sel.index() { Style::default().fg(Color::Yellow) } else { Style::default() };
+                Dataset::default().marker(Marker::Braille).graph_type(GraphType::Line).style(style).data(points)
+            })
+            .collect();
+        let chart = Chart::new(datasets)
+            .x_axis(Axis::default().bounds([0.0, total]).labels(["0".to_string(), format!("{total:.2}s")]))
+            .y_axis(Axis::default().bounds([0.0, 1.0]).labels(["0", "1"]));
+        f.render_widget(chart, chart_area);
+
+        let mut spans = vec![];
+        for &param in self.tab.params() {
+            let value = match param {
+                AdsrParam::Attack => format!("{:.3}s {}", adsr.attack_s, adsr.attack_curve.name()),
+                AdsrParam::Decay => format!("{:.3}s {}", adsr.decay_s, adsr.decay_curve.name()),
+                AdsrParam::Sustain => format!("{:.2}", adsr.sustain),
+                AdsrParam::Release => format!("{:.3}s {}", adsr.release_s, adsr.release_curve.name()),
+                AdsrParam::Depth if filter.depth <= 0.0 => "off".to_string(),
+                AdsrParam::Depth => format!("{:.2} oct", filter.depth),
+            };
+            let text = format!(" {} {value} ", param.label());
+            spans.push(if param == self.sel { Span::raw(text).reversed() } else { Span::raw(text) });
+            spans.push(Span::raw(" "));
+        }
+        if self.tab == EnvelopeTab::Amp {
+            spans.push(Span::raw(format!(" retrigger {} ", adsr.retrigger.name())).dim());
+        }
+        f.render_widget(Paragraph::new(Line::from(spans)), values);
+        let hint = match self.tab {
+            EnvelopeTab::Amp => "tab amp/filter · ←/→ or a/d/s/r select · ↑/↓ change · c curve · t retrigger · esc close",
+            EnvelopeTab::Filter => "tab amp/filter · ←/→ or a/d/s/r select · ↑/↓ change · c curve · esc close",
+        };
+        f.render_widget(Paragraph::new(Line::from(hint).dim()), help);
+    }
+}
+
+/// the envelope as attack, decay, sustain and release polylines in (seconds, level). sustain is
+/// drawn a quarter of the other segments long, just so it shows
+fn segments(adsr: &Adsr) -> [Vec<(f64, f64)>; 4] {
+    let sloped = |start: f32, len: f32, from: f32, to: f32, curve: Curve| -> Vec<(f64, f64)> {
+        (0..=SEGMENT_POINTS)
+            .map(|i| {
+                let t = i as f32 / SEGMENT_POINTS as f32;
+                let level = from + (to - from) * curve.shape(t);
+                ((start + t * len) as f64, level as f64)
+            })
+            .collect()
+    };
+    let (a, d, r) = (adsr.attack_s.max(0.0), adsr.decay_s.max(0.0), adsr.release_s.max(0.0));
+    let s = adsr.sustain.clamp(0.0, 1.0);
+    let hold = ((a + d + r) * 0.25).max(0.05);
+
+    [
+        sloped(0.0, a, 0.0, 1.0, adsr.attack_curve),
+        sloped(a, d, 1.0, s, adsr.decay_curve),
+        vec![((a + d) as f64, s as f64), ((a + d + hold) as f64, s as f64)],
+        sloped(a + d + hold, r, s, 0.0, adsr.release_curve),
+    ]
+}
@@ -0,0 +1,56 @@
+use std::time::{Duration, Instant};
+
+/// decides when the TUI redraws: right away after a key press so controls never feel slow, at up
+/// to `max_fps` while the audio side keeps publishing changes (meters, notes), and at `idle_fps`
+/// otherwise so the fps/cpu readouts still move. only drawing is paced; keyboard polling and
+/// audio run on their own threads at full rate
+pub struct FramePacer {
+    frame: Duration,
+    idle: Duration,
+    last: Instant,
+    dirty: bool,
+    urgent: bool,
+}
+
+impl FramePacer {
+    pub fn new(max_fps: f32, idle_fps: f32) -> Self {
+        let max_fps = max_fps.max(1.0);
+        Self {
+            frame: Duration::from_secs_f32(1.0 / max_fps),
+            idle: Duration::from_secs_f32(1.0 / idle_fps.clamp(0.1, max_fps)),
+            last: Instant::now(),
+            dirty: true,
+            urgent: true,
+        }
+    }
+
+    /// something on screen changed; draw within the frame cap
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    /// user input; draw on the next pass regardless of the cap
+    pub fn mark_urgent(&mut self) {
+        self.urgent = true;
+    }
+
+    pub fn next_deadline(&self) -> Instant {
+        if self.urgent {
+            self.last
+        } else if self.dirty {
+            self.last + self.frame
+        } else {
+            self.last + self.idle
+        }
+    }
+
+    pub fn should_draw(&self, now: Instant) -> bool {
+        now >= self.next_deadline()
+    }
+
+    pub fn drawn(&mut self, now: Instant) {
+        self.last = now;
+        self.dirty = false;
+        self.urgent = false;
+    }
+}
@@ -0,0 +1,166 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    layout::{Constraint, Layout, Rect},
+    prelude::Stylize,
+    text::Line,
+    widgets::{Block, Borders, Clear, Paragraph},
+};
+
+use synth_rs::audio_patch::{ParamDesc, ParamValue};
+use synth_rs::audio_system::{AudioSnapshot, ChainEdit, ParamGroup};
+use synth_rs::fx::chain::FxKind;
+
+/// what a key press on the chain editor asks of the UI
+pub enum ChainAction {
+    None,
+    Close,
+    Edit(ChainEdit),
+    /// owner, id and the new value, for `set_param`
+    SetParam(String, &'static str, ParamValue),
+    /// MIDI learn for a parameter (owner, id), or stop it
+    Learn(Option<(String, String)>),
+}
+
+/// E screen: the current patch's effect chain, top to bottom in the order notes go through it,
+/// and the parameters of the selected effect
+pub struct ChainEditor {
+    sel: usize,
+    param_sel: usize,
+    /// what `a` adds
+    adding: FxKind,
+}
+
+impl Default for ChainEditor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChainEditor {
+    pub fn new() -> Self {
+        Self { sel: 0, param_sel: 0, adding: FxKind::ALL[0] }
+    }
+
+    pub fn handle_key(&mut self, k: KeyEvent, snap: &AudioSnapshot) -> ChainAction {
+        let (chain, params) = (&snap.chain, &snap.params);
+        self.sel = self.sel.min(chain.len().saturating_sub(1));
+        let knobs = knobs(params, self.sel);
+        self.param_sel = self.param_sel.min(knobs.len().saturating_sub(1));
+        let index = self.sel;
+        match k.code {
+            KeyCode::Up => {
+                self.sel = self.sel.saturating_sub(1);
+                self.param_sel = 0;
+            }
+            KeyCode::Down => {
+                self.sel = (self.sel + 1).min(chain.len().saturating_sub(1));
+                self.param_sel = 0;
+            }
+            KeyCode::Left => self.param_sel = self.param_sel.saturating_sub(1),
+            KeyCode::Right => self.param_sel = (self.param_sel + 1).min(knobs.len().saturating_sub(1)),
+            KeyCode::Char('-') | KeyCode::Char('=') => {
+                let Some(desc) = knobs.get(self.param_sel) else { return ChainAction::None; };
+                let up = k.code == KeyCode::Char('=');
+                return ChainAction::SetParam(owner(index), desc.id, desc.nudged(up));
+            }
+            KeyCode::Char('n') => self.adding = self.adding.next(),
+            KeyCode::Char('a') => {
+                self.sel = chain.len();
+                self.param_sel = 0;
+                return ChainAction::Edit(ChainEdit::Add { kind: self.adding });
+            }
+            KeyCode::Char('x') | KeyCode::Delete if index < chain.len() => {
+                return ChainAction::Edit(ChainEdit::Remove { index });
+            }
+            KeyCode::Char('b') if index < chain.len() => {
+                return ChainAction::Edit(ChainEdit::Bypass { index, on: !chain[index].bypassed });
+            }
+            KeyCode::Char('[') if index > 0 => {
+                self.sel -= 1;
+                return ChainAction::Edit(ChainEdit::Move { index, to: index - 1 });
+            }
+            KeyCode::Char(']') if index + 1 < chain.len() => {
+                self.sel += 1;
+                return ChainAction::Edit(ChainEdit::Move { index, to: index + 1 });
+            }
+            KeyCode::Char('l') if snap.cc_learn.is_some() => return ChainAction::Learn(None),
+            KeyCode::Char('l') => {
+                let Some(desc) = knobs.get(self.param_sel) else { return ChainAction::None; };
+                return ChainAction::Learn(Some((owner(index), desc.id.to_string())));
+            }
+            KeyCode::Esc if snap.cc_learn.is_some() => return ChainAction::Learn(None),
+            KeyCode::Esc | KeyCode::Char('E') => return ChainAction::Close,
+            _ => {}
+        }
+        ChainAction::None
+    }
+
+    /// covers `area`, the main panels
+    pub fn draw(&self, f: &mut ratatui::Frame, area: Rect, snap: &AudioSnapshot) {
+        let (chain, params) = (&snap.chain, &snap.params);
+        let block = Block::default().borders(Borders::ALL).title(format!(" effects: {} (E) ", snap.patch_name));
+        let inner = block.inner(area);
+        f.render_widget(Clear, area);
+        f.render_widget(block, area);
+
+        let [body, adding, help] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1), Constraint::Length(1)]).areas(inner);
+        let [slots, knobs_area] = Layout::horizontal([Constraint::Length(24), Constraint::Min(0)]).spacing(2).areas(body);
+        let sel = self.sel.min(chain.len().saturating_sub(1));
+
+        let lines: Vec<Line> = if chain.is_empty() {
+            vec![Line::from("no effects yet").dim()]
+        } else {
+            chain
+                .iter()
+                .enumerate()
+                .map(|(i, slot)| {
+                    let label = if slot.bypassed { "(bypassed)" } else { "" };
+                    let line = Line::from(format!("{} {:<11}{label}", i + 1, slot.kind.name()));
+                    let line = if slot.bypassed { line.dim() } else { line };
+                    if i == sel { line.reversed() } else { line }
+                })
+                .collect()
+        };
+        f.render_widget(Paragraph::new(lines), slots);
+
+        let owner = owner(sel);
+        let knobs: Vec<Line> = knobs(params, sel)
+            .iter()
+            .enumerate()
+            .map(|(i, desc)| {
+                let range = match desc.value {
+                    ParamValue::Bool(_) => String::new(),
+                    ParamValue::Float(_) => format!("{:.2}..{:.2}", desc.min, desc.max),
+                };
+                // the knob that moves it, or that learn is waiting for one
+                let learning = snap.cc_learn.as_ref().is_some_and(|(o, id)| *o == owner && id == desc.id);
+                let bound = snap.cc_bindings.iter().find(|b| b.is_param(&owner, desc.id));
+                let cc = match bound {
+                    _ if learning => "  move a knob...".to_string(),
+                    Some(b) => format!("  cc {} ch {}", b.cc, b.channel),
+                    None => String::new(),
+                };
+                let line = Line::from(format!("{:<14}{:>9}  {range:<14}{cc}", desc.id, desc.value.to_string()));
+                if i == self.param_sel { line.reversed() } else { line }
+            })
+            .collect();
+        f.render_widget(Paragraph::new(knobs), knobs_area);
+
+        f.render_widget(Paragraph::new(Line::from(format!("a adds: {}", self.adding.name()))), adding);
+        let hint = "↑/↓ effect · ←/→ parameter · -/= change · l MIDI learn · n/a pick/add · x remove · [/] move · b bypass · esc close";
+        f.render_widget(Paragraph::new(Line::from(hint).dim()), help);
+    }
+}
+
+/// `set_param` owner of the effect at `index`
+fn owner(index: usize) -> String {
+    format!("chain.{index}")
+}
+
+/// the parameters of the effect at `index`, less its own on switch: bypass stands in for it here
+fn knobs(params: &[ParamGroup], index: usize) -> Vec<ParamDesc> {
+    let owner = owner(index);
+    let group = params.iter().find(|g| g.owner == owner);
+    group.map_or(vec![], |g| g.params.iter().filter(|p| p.id != "enabled").copied().collect())
+}
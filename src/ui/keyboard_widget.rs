@@ -0,0 +1,126 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Style},
+    widgets::Widget,
+};
+
+use synth_rs::key::{Key, Keymap, Note, note_name};
+
+const WHITE_W: u16 = 4;
+const BLACK_W: u16 = 3;
+const MAX_OCTAVES: u16 = 3;
+const WHITE_NOTES: [Note; 7] = [Note::C, Note::D, Note::E, Note::F, Note::G, Note::A, Note::B];
+/// black key sitting to the right of each white key, if any
+const BLACK_AFTER: [Option<Note>; 7] = [
+    Some(Note::Db), Some(Note::Eb), None, Some(Note::Gb), Some(Note::Ab), Some(Note::Bb), None,
+];
+
+const WHITE: Color = Color::Gray;
+const BLACK: Color = Color::Black;
+const PRESSED: Color = Color::LightMagenta;
+const HINT: Color = Color::LightCyan;
+
+/// on-screen piano with the currently sounding keys lit up and each piano key labelled with the
+/// computer key(s) that play it. draws as many whole octaves as fit (up to `MAX_OCTAVES`),
+/// starting at `start_octave`
+pub struct PianoKeyboard<'a> {
+    pressed: &'a [Key],
+    keymap: &'a Keymap,
+    hint: Option<Key>,
+    start_octave: i32,
+}
+
+impl<'a> PianoKeyboard<'a> {
+    pub fn new(pressed: &'a [Key], keymap: &'a Keymap, start_octave: i32) -> Self {
+        Self { pressed, keymap, hint: None, start_octave }
+    }
+
+    /// a key to point at, e.g. the next note of a lesson
+    pub fn with_hint(mut self, hint: Option<Key>) -> Self {
+        self.hint = hint;
+        self
+    }
+
+    fn color(&self, note: Note, octave: i32, idle: Color) -> Color {
+        if self.is_pressed(note, octave) {
+            PRESSED
+        } else if self.hint == Some(Key::new(note, octave)) {
+            HINT
+        } else {
+            idle
+        }
+    }
+
+    /// computer keys for a piano key, cut to what fits on it
+    fn label(&self, note: Note, octave: i32, width: u16) -> String {
+        let mut label: String = self.keymap.keycodes_for(Key::new(note, octave)).map(|k| self.keymap.label(k)).collect();
+        label.truncate(width as usize);
+        label
+    }
+
+    /// width needed to draw `octaves` octaves
+    pub fn width_for(octaves: u16) -> u16 {
+        octaves * 7 * WHITE_W
+    }
+
+    fn is_pressed(&self, note: Note, octave: i32) -> bool {
+        self.pressed.contains(&Key::new(note, octave))
+    }
+}
+
+impl Widget for PianoKeyboard<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if area.height < 2 || area.width < WHITE_W * 7 {
+            return;
+        }
+
+        let octaves = (area.width / Self::width_for(1)).clamp(1, MAX_OCTAVES);
+        let x0 = area.x + (area.width - Self::width_for(octaves)) / 2;
+        let black_h = (area.height * 3 / 5).max(1);
+
+        for o in 0..octaves {
+            let octave = self.start_octave + o as i32;
+            for (j, note) in WHITE_NOTES.iter().enumerate() {
+                let x = x0 + (o * 7 + j as u16) * WHITE_W;
+                let color = self.color(*note, octave, WHITE);
+                let fill = Style::default().bg(color);
+                for y in area.top()..area.bottom() {
+                    for dx in 0..WHITE_W - 1 {
+                        buf[(x + dx, y)].set_char(' ').set_style(fill);
+                    }
+                }
+                if *note == Note::C {
+                    let label = format!("{}{}", note_name(*note), octave);
+                    buf.set_string(x, area.bottom() - 1, label, fill.fg(Color::Black));
+                }
+                if area.height >= 3 {
+                    let label = self.label(*note, octave, WHITE_W - 2);
+                    buf.set_string(x + 1, area.bottom() - 2, label, fill.fg(Color::DarkGray));
+                }
+            }
+        }
+
+        for o in 0..octaves {
+            let octave = self.start_octave + o as i32;
+            for (j, black) in BLACK_AFTER.iter().enumerate() {
+                let Some(note) = black else { continue; };
+                // centred on the gap between this white key and the next
+                let x = x0 + (o * 7 + j as u16) * WHITE_W + WHITE_W - 1 - BLACK_W / 2;
+                let color = self.color(*note, octave, BLACK);
+                let fill = Style::default().bg(color);
+                for y in area.top()..area.top() + black_h {
+                    for dx in 0..BLACK_W {
+                        if x + dx < area.right() {
+                            buf[(x + dx, y)].set_char(' ').set_style(fill);
+                        }
+                    }
+                }
+                let label = self.label(*note, octave, BLACK_W - 1);
+                if x + 1 < area.right() {
+                    buf.set_string(x + 1, area.top() + black_h - 1, label, fill.fg(Color::Gray));
+                }
+            }
+        }
+    }
+}
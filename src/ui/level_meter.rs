@@ -0,0 +1,126 @@
+use std::time::{Duration, Instant};
+
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Style},
+    widgets::Widget,
+};
+
+use synth_rs::config::{GAIN_CLIP_DB, METER_CLIP_HOLD_S, METER_FLOOR_DB, METER_PEAK_HOLD_S};
+
+const SCALE_MARKS: [f32; 4] = [-48.0, -36.0, -24.0, -12.0];
+const LABEL_W: u16 = 4;
+const VALUE_W: u16 = 7;
+
+/// peak-hold marker and clip latch for the master meter, fed from each snapshot
+#[derive(Debug)]
+pub struct MeterHold {
+    peak_db: f32,
+    peak_until: Instant,
+    clip_until: Option<Instant>,
+}
+
+impl Default for MeterHold {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MeterHold {
+    pub fn new() -> Self {
+        Self { peak_db: METER_FLOOR_DB, peak_until: Instant::now(), clip_until: None }
+    }
+
+    pub fn observe(&mut self, peak_db: f32, now: Instant) {
+        if peak_db >= self.peak_db || now >= self.peak_until {
+            self.peak_db = peak_db;
+            self.peak_until = now + Duration::from_secs_f32(METER_PEAK_HOLD_S);
+        }
+        if peak_db >= GAIN_CLIP_DB {
+            self.clip_until = Some(now + Duration::from_secs_f32(METER_CLIP_HOLD_S));
+        }
+    }
+
+    pub fn clipped(&self, now: Instant) -> bool {
+        self.clip_until.is_some_and(|t| now < t)
+    }
+}
+
+/// peak and rms bars on a dBFS scale from `METER_FLOOR_DB` to 0, with the held peak as a tick
+/// and a clip light. needs three rows: peak, rms, scale
+pub struct LevelBars<'a> {
+    peak_db: f32,
+    rms_db: f32,
+    hold: &'a MeterHold,
+    now: Instant,
+}
+
+impl<'a> LevelBars<'a> {
+    pub fn new(peak_db: f32, rms_db: f32, hold: &'a MeterHold, now: Instant) -> Self {
+        Self { peak_db, rms_db, hold, now }
+    }
+}
+
+/// cell offset of `db` within a bar `width` cells wide
+fn cell(db: f32, width: u16) -> u16 {
+    let t = ((db - METER_FLOOR_DB) / -METER_FLOOR_DB).clamp(0.0, 1.0);
+    ((t * width as f32).round() as u16).min(width)
+}
+
+fn level_color(db: f32) -> Color {
+    if db >= GAIN_CLIP_DB {
+        Color::Red
+    } else if db >= -6.0 {
+        Color::Yellow
+    } else {
+        Color::Green
+    }
+}
+
+impl Widget for LevelBars<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if area.height < 3 || area.width <= LABEL_W + VALUE_W {
+            return;
+        }
+        let bar_x = area.x + LABEL_W;
+        let bar_w = area.width - LABEL_W - VALUE_W;
+        let dim = Style::default().fg(Color::DarkGray);
+
+        let rows = [("pk", self.peak_db, area.y), ("rms", self.rms_db, area.y + 1)];
+        for (label, db, y) in rows {
+            buf.set_string(area.x, y, label, dim);
+            let filled = cell(db, bar_w);
+            for i in 0..bar_w {
+                // colour by where the cell sits on the scale, so the top of a loud bar turns red
+                let cell_db = METER_FLOOR_DB * (1.0 - (i as f32 + 1.0) / bar_w as f32);
+                let (sym, style) = if i < filled {
+                    ("█", Style::default().fg(level_color(cell_db)))
+                } else {
+                    ("─", dim)
+                };
+                buf.set_string(bar_x + i, y, sym, style);
+            }
+            buf.set_string(bar_x + bar_w, y, format!("{db:>6.1}"), Style::default());
+        }
+
+        let hold = cell(self.hold.peak_db, bar_w);
+        if hold > 0 {
+            let style = Style::default().fg(level_color(self.hold.peak_db));
+            buf.set_string(bar_x + hold - 1, area.y, "│", style);
+        }
+
+        let scale_y = area.y + 2;
+        buf.set_string(area.x, scale_y, "dB", dim);
+        for mark in SCALE_MARKS {
+            let label = format!("{}", mark as i32);
+            let x = cell(mark, bar_w).saturating_sub(label.len() as u16 / 2);
+            buf.set_string(bar_x + x, scale_y, label, dim);
+        }
+        if self.hold.clipped(self.now) {
+            buf.set_string(bar_x + bar_w + 2, scale_y, "CLIP", Style::default().fg(Color::White).bg(Color::Red));
+        } else {
+            buf.set_string(bar_x + bar_w - 1, scale_y, "0", dim);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    layout::{Constraint, Layout, Rect},
+    prelude::Stylize,
+    style::{Color, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph},
+};
+
+use synth_rs::config::{TRACK_PAN_STEP, TRACK_VOLUME_MAX, TRACK_VOLUME_STEP};
+use synth_rs::engine::{Track, TrackSettings};
+
+/// what a key press on the mixer asks of the UI
+pub enum MixerAction {
+    None,
+    Close,
+    Set(Track, TrackSettings),
+}
+
+/// M screen: one channel strip per track, with a fader, pan, mute and solo
+pub struct Mixer {
+    sel: usize,
+}
+
+impl Default for Mixer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mixer {
+    pub fn new() -> Self {
+        Self { sel: 0 }
+    }
+
+    /// `tracks` in `Track::ALL` order, as the snapshot has them
+    pub fn handle_key(&mut self, k: KeyEvent, tracks: &[TrackSettings; Track::COUNT]) -> MixerAction {
+        let track = Track::ALL[self.sel];
+        let mut strip = tracks[self.sel];
+        match k.code {
+            KeyCode::Left => self.sel = self.sel.saturating_sub(1),
+            KeyCode::Right => self.sel = (self.sel + 1).min(Track::ALL.len() - 1),
+            KeyCode::Up => strip.volume += TRACK_VOLUME_STEP,
+            KeyCode::Down => strip.volume -= TRACK_VOLUME_STEP,
+            KeyCode::Char(',') => strip.pan -= TRACK_PAN_STEP,
+            KeyCode::Char('.') => strip.pan += TRACK_PAN_STEP,
+            KeyCode::Char('c') => strip.pan = 0.0,
+            KeyCode::Char('m') => strip.muted = !strip.muted,
+            KeyCode::Char('s') => strip.solo = !strip.solo,
+            KeyCode::Esc | KeyCode::Char('M') => return MixerAction::Close,
+            _ => {}
+        }
+        if strip == tracks[self.sel] {
+            return MixerAction::None;
+        }
+        MixerAction::Set(track, strip.clamped())
+    }
+
+    /// covers `area`, the main panels
+    pub fn draw(&self, f: &mut ratatui::Frame, area: Rect, tracks: &[TrackSettings; Track::COUNT]) {
+        let block = Block::default().borders(Borders::ALL).title(" mixer (M) ");
+        let inner = block.inner(area);
+        f.render_widget(Clear, area);
+        f.render_widget(block, area);
+
+        let [strips, help] = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(inner);
+        let columns = Layout::horizontal([Constraint::Length(12); Track::COUNT]).spacing(2).split(strips);
+        let soloing = tracks.iter().any(|t| t.solo);
+        for (i, (&track, column)) in Track::ALL.iter().zip(columns.iter()).enumerate() {
+            draw_strip(f, *column, track, &tracks[i], i == self.sel, soloing);
+        }
+        let hint = "←/→ track · ↑/↓ volume · ,/. pan · c center · m mute · s solo · esc close";
+        f.render_widget(Paragraph::new(Line::from(hint).dim()), help);
+    }
+}
+
+fn draw_strip(f: &mut ratatui::Frame, area: Rect, track: Track, strip: &TrackSettings, selected: bool, soloing: bool) {
+    let [name, fader, values, flags] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Min(0),
+        Constraint::Length(2),
+        Constraint::Length(1),
+    ])
+    .areas(area);
+
+    let title = Line::from(format!("{:^12}", track.name()));
+    f.render_widget(Paragraph::new(if selected { title.reversed() } else { title }), name);
+
+    // silenced by mute, or by another track's solo
+    let heard = !strip.muted && (!soloing || strip.solo);
+    let rows = fader.height as usize;
+    let lit = ((strip.volume / TRACK_VOLUME_MAX) * rows as f32).round() as usize;
+    let unity = rows - ((rows as f32 / TRACK_VOLUME_MAX).round() as usize).min(rows);
+    let color = if heard { Color::Green } else { Color::DarkGray };
+    let lines: Vec<Line> = (0..rows)
+        .map(|row| {
+            let mark = if row == unity { "-" } else { " " };
+            if rows - row <= lit {
+                Line::from(format!("{mark}   ████   {mark}")).style(Style::default().fg(color))
+            } else {
+                Line::from(format!("{mark}   ····   {mark}")).dim()
+            }
+        })
+        .collect();
+    f.render_widget(Paragraph::new(lines), fader);
+
+    let pan = match strip.pan {
+        p if p.abs() < 0.005 => "C".to_string(),
+        p if p < 0.0 => format!("L{:.0}", -p * 100.0),
+        p => format!("R{:.0}", p * 100.0),
+    };
+    let text = vec![
+        Line::from(format!("{:^12}", format!("{:.2}", strip.volume))),
+        Line::from(format!("{:^12}", format!("pan {pan}"))),
+    ];
+    f.render_widget(Paragraph::new(text), values);
+
+    let flag = |label: &'static str, on: bool, color: Color| {
+        if on { Span::styled(label, Style::default().fg(Color::Black).bg(color)) } else { label.dim() }
+    };
+    let flags_line = Line::from(vec![
+        "  ".into(),
+        flag(" M ", strip.muted, Color::Red),
+        "  ".into(),
+        flag(" S ", strip.solo, Color::Yellow),
+    ]);
+    f.render_widget(Paragraph::new(flags_line), flags);
+}
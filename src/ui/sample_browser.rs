@@ -0,0 +1,142 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    layout::{Constraint, Layout, Rect},
+    prelude::Stylize,
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph},
+};
+
+use synth_rs::config::SAMPLE_EXT;
+
+/// what a key press on the sample browser asks of the UI
+pub enum BrowserAction {
+    None,
+    Close,
+    Load(PathBuf),
+}
+
+struct Entry {
+    name: String,
+    path: PathBuf,
+    is_dir: bool,
+}
+
+/// S screen: walks directories and loads WAV files onto the sampler patch. it only takes arrows,
+/// enter and backspace, so the note keys keep playing and a sample can be tried out in place
+pub struct SampleBrowser {
+    dir: PathBuf,
+    entries: Vec<Entry>,
+    sel: usize,
+    error: Option<String>,
+}
+
+impl SampleBrowser {
+    /// opens next to the loaded sample, else in `dir`, else the working directory
+    pub fn open(loaded: Option<&Path>, dir: Option<&Path>) -> Self {
+        let start = loaded
+            .and_then(Path::parent)
+            .or(dir)
+            .filter(|d| d.is_dir())
+            .map_or_else(|| PathBuf::from("."), Path::to_path_buf);
+        let mut browser = Self { dir: start, entries: vec![], sel: 0, error: None };
+        browser.read_dir();
+        if let Some(i) = browser.entries.iter().position(|e| Some(e.path.as_path()) == loaded) {
+            browser.sel = i;
+        }
+        browser
+    }
+
+    /// subdirectories then WAV files, each sorted by name; `..` first unless at the root
+    fn read_dir(&mut self) {
+        self.dir = self.dir.canonicalize().unwrap_or_else(|_| self.dir.clone());
+        self.entries.clear();
+        self.sel = 0;
+        self.error = None;
+        if let Some(parent) = self.dir.parent() {
+            self.entries.push(Entry { name: "..".into(), path: parent.to_path_buf(), is_dir: true });
+        }
+        let listing = match fs::read_dir(&self.dir) {
+            Ok(l) => l,
+            Err(e) => {
+                self.error = Some(e.to_string());
+                return;
+            }
+        };
+        let mut found: Vec<Entry> = listing
+            .flatten()
+            .filter_map(|e| {
+                let name = e.file_name().to_string_lossy().into_owned();
+                let path = e.path();
+                let is_dir = path.is_dir();
+                let is_sample = path.extension().is_some_and(|x| x.eq_ignore_ascii_case(SAMPLE_EXT));
+                (!name.starts_with('.') && (is_dir || is_sample)).then_some(Entry { name, path, is_dir })
+            })
+            .collect();
+        found.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase())));
+        self.entries.extend(found);
+    }
+
+    pub fn handle_key(&mut self, k: KeyEvent) -> BrowserAction {
+        match k.code {
+            KeyCode::Up => self.sel = self.sel.saturating_sub(1),
+            KeyCode::Down => self.sel = (self.sel + 1).min(self.entries.len().saturating_sub(1)),
+            KeyCode::Enter => {
+                let Some(entry) = self.entries.get(self.sel) else { return BrowserAction::None; };
+                if !entry.is_dir {
+                    return BrowserAction::Load(entry.path.clone());
+                }
+                self.dir = entry.path.clone();
+                self.read_dir();
+            }
+            KeyCode::Backspace => {
+                if let Some(parent) = self.dir.parent() {
+                    let from = self.dir.clone();
+                    self.dir = parent.to_path_buf();
+                    self.read_dir();
+                    self.sel = self.entries.iter().position(|e| e.path == from).unwrap_or(0);
+                }
+            }
+            KeyCode::Esc | KeyCode::Char('S') => return BrowserAction::Close,
+            _ => {}
+        }
+        BrowserAction::None
+    }
+
+    /// covers `area`, the main panels; `loaded` is marked
+    pub fn draw(&self, f: &mut ratatui::Frame, area: Rect, loaded: Option<&Path>) {
+        let title = format!(" samples (S) · {} ", self.dir.display());
+        let block = Block::default().borders(Borders::ALL).title(title);
+        let inner = block.inner(area);
+        f.render_widget(Clear, area);
+        f.render_widget(block, area);
+
+        let [listing, help] = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(inner);
+        let mut hint = "↑/↓ select · enter open/load · backspace up · keys play the sampler · esc close".to_string();
+        if self.entries.iter().all(|e| e.is_dir) {
+            hint = format!("no .{SAMPLE_EXT} files here · {hint}");
+        }
+        f.render_widget(Paragraph::new(Line::from(hint).dim()), help);
+
+        if let Some(e) = &self.error {
+            f.render_widget(Paragraph::new(format!("can't read this folder: {e}")).red(), listing);
+            return;
+        }
+
+        let items: Vec<ListItem> = self
+            .entries
+            .iter()
+            .map(|e| {
+                let marker = if Some(e.path.as_path()) == loaded { "● " } else { "  " };
+                let suffix = if e.is_dir { "/" } else { "" };
+                ListItem::new(format!("{marker}{}{suffix}", e.name))
+            })
+            .collect();
+        let list = List::new(items).highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        let mut list_state = ListState::default().with_selected(Some(self.sel));
+        f.render_stateful_widget(list, listing, &mut list_state);
+    }
+}
@@ -0,0 +1,312 @@
+use std::path::PathBuf;
+
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    layout::Rect,
+    prelude::Stylize,
+    text::Line,
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+};
+
+use synth_rs::config::{TEMPO_BPM_MAX, TEMPO_BPM_MIN};
+use synth_rs::settings::{Settings, UiSettings, load_settings, load_settings_from, save_settings_to, settings_path};
+
+/// the config file fields worth changing without a text editor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Device,
+    SampleRate,
+    MaxVoices,
+    Patch,
+    Octave,
+    KeyboardLayout,
+    ChordAssist,
+    VelocityAttack,
+    ReleaseVelocity,
+    LoopLength,
+    Tempo,
+    Visualizer,
+    SideWidth,
+    KeyboardHeight,
+    MaxFps,
+    IdleFps,
+    ControlSocket,
+}
+
+const FIELDS: &[Field] = &[
+    Field::Device,
+    Field::SampleRate,
+    Field::MaxVoices,
+    Field::Patch,
+    Field::Octave,
+    Field::KeyboardLayout,
+    Field::ChordAssist,
+    Field::VelocityAttack,
+    Field::ReleaseVelocity,
+    Field::LoopLength,
+    Field::Tempo,
+    Field::Visualizer,
+    Field::SideWidth,
+    Field::KeyboardHeight,
+    Field::MaxFps,
+    Field::IdleFps,
+    Field::ControlSocket,
+];
+
+impl Field {
+    fn label(self) -> &'static str {
+        match self {
+            Field::Device => "output device",
+            Field::SampleRate => "sample rate",
+            Field::MaxVoices => "polyphony",
+            Field::Patch => "startup patch",
+            Field::Octave => "octave",
+            Field::KeyboardLayout => "keyboard layout",
+            Field::ChordAssist => "chord assist",
+            Field::VelocityAttack => "velocity attack",
+            Field::ReleaseVelocity => "release velocity",
+            Field::LoopLength => "loop length",
+            Field::Tempo => "tempo",
+            Field::Visualizer => "visualizer",
+            Field::SideWidth => "side panel width",
+            Field::KeyboardHeight => "piano height",
+            Field::MaxFps => "max fps",
+            Field::IdleFps => "idle fps",
+            Field::ControlSocket => "control socket",
+        }
+    }
+
+    /// on/off fields flip on enter instead of opening the editor, and the layout steps to the next
+    fn toggle(self, s: &mut Settings) -> bool {
+        let flag = match self {
+            Field::KeyboardLayout => {
+                s.keymap.layout = s.keymap.layout.next();
+                return true;
+            }
+            Field::ChordAssist => &mut s.input.chord_assist,
+            Field::VelocityAttack => &mut s.input.velocity_attack,
+            Field::ReleaseVelocity => &mut s.input.release_velocity,
+            Field::Visualizer => &mut s.ui.visualizer,
+            Field::ControlSocket => &mut s.control.socket,
+            _ => return false,
+        };
+        *flag = !*flag;
+        true
+    }
+
+    fn value(self, s: &Settings) -> String {
+        let on_off = |b: bool| if b { "on" } else { "off" }.to_string();
+        match self {
+            Field::Device => s.audio.device.clone().unwrap_or_else(|| "system default".into()),
+            Field::SampleRate => s.audio.sample_rate.map_or("device default".into(), |r| format!("{r} Hz")),
+            Field::MaxVoices if s.audio.max_voices == 0 => "unlimited".into(),
+            Field::MaxVoices => format!("{} voices", s.audio.max_voices),
+            Field::Patch => s.audio.patch.clone().unwrap_or_else(|| "first".into()),
+            Field::Octave => s.input.octave.to_string(),
+            Field::KeyboardLayout => s.keymap.layout.name().into(),
+            Field::ChordAssist => on_off(s.input.chord_assist),
+            Field::VelocityAttack => on_off(s.input.velocity_attack),
+            Field::ReleaseVelocity => on_off(s.input.release_velocity),
+            Field::LoopLength if s.looper.length_bars > 0 => format!("{} bars", s.looper.length_bars),
+            Field::LoopLength if s.looper.length_s <= 0.0 => "first take".into(),
+            Field::LoopLength => format!("{:.1} s", s.looper.length_s),
+            Field::Tempo => format!("{:.0} bpm", s.transport.bpm),
+            Field::Visualizer => on_off(s.ui.visualizer),
+            Field::SideWidth if s.ui.side_width == 0 => "hidden".into(),
+            Field::SideWidth => s.ui.side_width.to_string(),
+            Field::KeyboardHeight => s.ui.keyboard_height.to_string(),
+            Field::MaxFps => format!("{:.0}", s.ui.max_fps),
+            Field::IdleFps => format!("{:.0}", s.ui.idle_fps),
+            Field::ControlSocket => on_off(s.control.socket),
+        }
+    }
+
+    /// the text the editor opens with; empty means unset
+    fn raw(self, s: &Settings) -> String {
+        match self {
+            Field::Device => s.audio.device.clone().unwrap_or_default(),
+            Field::SampleRate => s.audio.sample_rate.map(|r| r.to_string()).unwrap_or_default(),
+            Field::MaxVoices if s.audio.max_voices == 0 => String::new(),
+            Field::MaxVoices => s.audio.max_voices.to_string(),
+            Field::Patch => s.audio.patch.clone().unwrap_or_default(),
+            Field::LoopLength if s.looper.length_s <= 0.0 => String::new(),
+            Field::LoopLength => s.looper.length_s.to_string(),
+            Field::Tempo => s.transport.bpm.to_string(),
+            Field::SideWidth => s.ui.side_width.to_string(),
+            _ => self.value(s),
+        }
+    }
+
+    fn set(self, s: &mut Settings, text: &str) -> Result<(), String> {
+        let text = text.trim();
+        let some = |t: &str| (!t.is_empty()).then(|| t.to_string());
+        match self {
+            Field::Device => s.audio.device = some(text),
+            Field::Patch => s.audio.patch = some(text),
+            Field::SampleRate if text.is_empty() => s.audio.sample_rate = None,
+            Field::SampleRate => {
+                let rate: u32 = text.parse().map_err(|_| format!("not a rate in Hz: {text}"))?;
+                s.audio.sample_rate = Some(rate);
+            }
+            Field::MaxVoices if text.is_empty() => s.audio.max_voices = 0,
+            Field::MaxVoices => s.audio.max_voices = text.parse().map_err(|_| format!("not a voice count: {text}"))?,
+            Field::Octave => s.input.octave = text.parse().map_err(|_| format!("not an octave: {text}"))?,
+            Field::LoopLength if text.is_empty() => s.looper.length_s = 0.0,
+            Field::LoopLength => {
+                let length: f32 = text.parse().map_err(|_| format!("not seconds: {text}"))?;
+                s.looper.length_s = length.max(0.0);
+            }
+            Field::Tempo => {
+                let bpm: f32 = text.parse().map_err(|_| format!("not a tempo: {text}"))?;
+                s.transport.bpm = bpm.clamp(TEMPO_BPM_MIN, TEMPO_BPM_MAX);
+            }
+            Field::MaxFps => {
+                let fps: f32 = text.parse().map_err(|_| format!("not a frame rate: {text}"))?;
+                s.ui.max_fps = fps.max(1.0);
+            }
+            Field::SideWidth => s.ui.side_width = text.parse().map_err(|_| format!("not a width: {text}"))?,
+            Field::KeyboardHeight => {
+                s.ui.keyboard_height = text.parse().map_err(|_| format!("not a height: {text}"))?;
+            }
+            Field::IdleFps => {
+                let fps: f32 = text.parse().map_err(|_| format!("not a frame rate: {text}"))?;
+                s.ui.idle_fps = fps.max(1.0);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// what a key press in the panel asks of the UI
+pub enum PanelAction {
+    None,
+    Close,
+    /// written to disk; the UI picks up its own part right away
+    Saved(UiSettings),
+}
+
+/// F2 popup that edits the config file. it works on the file as it is on disk, not on the running
+/// settings, so command-line overrides don't get saved into it
+pub struct SettingsPanel {
+    settings: Settings,
+    path: Option<PathBuf>,
+    sel: usize,
+    editing: Option<String>,
+    message: Option<String>,
+    dirty: bool,
+}
+
+impl SettingsPanel {
+    /// `config` is the `--config` file when one was given
+    pub fn open(config: Option<PathBuf>) -> Self {
+        let path = config.or_else(settings_path);
+        let loaded = match &path {
+            Some(p) if p.exists() => load_settings_from(p),
+            _ => load_settings(),
+        };
+        let (settings, message) = match loaded {
+            Ok(s) => (s, None),
+            Err(e) => (Settings::default(), Some(format!("could not read it ({e}), saving replaces it"))),
+        };
+        Self { settings, path, sel: 0, editing: None, message, dirty: false }
+    }
+
+    pub fn handle_key(&mut self, k: KeyEvent) -> PanelAction {
+        let field = FIELDS[self.sel];
+        if let Some(text) = self.editing.as_mut() {
+            match k.code {
+                KeyCode::Char(c) => text.push(c),
+                KeyCode::Backspace => { text.pop(); }
+                KeyCode::Enter => {
+                    let text = self.editing.take().unwrap_or_default();
+                    match field.set(&mut self.settings, &text) {
+                        Ok(()) => {
+                            self.dirty = true;
+                            self.message = None;
+                        }
+                        Err(e) => self.message = Some(e),
+                    }
+                }
+                KeyCode::Esc => self.editing = None,
+                _ => {}
+            }
+            return PanelAction::None;
+        }
+
+        match k.code {
+            KeyCode::Up => self.sel = self.sel.saturating_sub(1),
+            KeyCode::Down => self.sel = (self.sel + 1).min(FIELDS.len() - 1),
+            KeyCode::Enter => {
+                if field.toggle(&mut self.settings) {
+                    self.dirty = true;
+                } else {
+                    self.editing = Some(field.raw(&self.settings));
+                }
+            }
+            KeyCode::Char('s') => return self.save(),
+            KeyCode::Esc | KeyCode::F(2) => return PanelAction::Close,
+            _ => {}
+        }
+        PanelAction::None
+    }
+
+    fn save(&mut self) -> PanelAction {
+        let Some(path) = &self.path else {
+            self.message = Some("could not resolve the config directory".into());
+            return PanelAction::None;
+        };
+        match save_settings_to(&self.settings, path) {
+            Ok(()) => {
+                self.dirty = false;
+                self.message = Some(format!("saved to {}; audio and input changes apply on restart", path.display()));
+                PanelAction::Saved(self.settings.ui.clone())
+            }
+            Err(e) => {
+                self.message = Some(format!("could not save: {e}"));
+                PanelAction::None
+            }
+        }
+    }
+
+    /// popup over the middle of the screen
+    pub fn draw(&self, f: &mut ratatui::Frame, area: Rect) {
+        let w = 64.min(area.width);
+        let h = (FIELDS.len() as u16 + 6).min(area.height);
+        let popup = Rect {
+            x: area.x + (area.width - w) / 2,
+            y: area.y + (area.height - h) / 2,
+            width: w,
+            height: h,
+        };
+
+        let mut lines: Vec<Line> = FIELDS
+            .iter()
+            .enumerate()
+            .map(|(i, field)| {
+                let selected = i == self.sel;
+                let value = match &self.editing {
+                    Some(text) if selected => format!("{text}_"),
+                    _ => field.value(&self.settings),
+                };
+                let line = Line::from(format!("{}{:<18}{value}", if selected { "▸" } else { " " }, field.label()));
+                if selected { line.bold() } else { line }
+            })
+            .collect();
+        lines.push(Line::from(""));
+        let help = if self.editing.is_some() {
+            "enter set · esc cancel · empty for the default"
+        } else {
+            "↑/↓ select · enter edit/toggle · s save · esc close"
+        };
+        lines.push(Line::from(help).dim());
+        if let Some(message) = &self.message {
+            lines.push(Line::from(message.clone()).italic());
+        }
+
+        let title = if self.dirty { " settings (F2) * " } else { " settings (F2) " };
+        let block = Block::default().borders(Borders::ALL).title(title);
+        f.render_widget(Clear, popup);
+        f.render_widget(Paragraph::new(lines).wrap(Wrap { trim: false }).block(block), popup);
+    }
+}
@@ -0,0 +1,183 @@
+use std::time::{Duration, Instant};
+
+use ratatui::{
+    prelude::Stylize,
+    text::{Line, Span},
+};
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+
+use synth_rs::audio_system::AudioSnapshot;
+use synth_rs::config::{CPU_REFRESH_S, FPS_WINDOW_S, UNDERRUN_WARN_S};
+use synth_rs::settings::StatusSegment;
+
+/// everything a segment may need to render itself
+pub struct StatusInfo<'a> {
+    pub snapshot: &'a AudioSnapshot,
+    pub fps: f32,
+    pub cpu: Option<f32>,
+    /// underruns in the last `UNDERRUN_WARN_S`, if any
+    pub underruns: Option<u64>,
+}
+
+/// renders the configured segments left to right, separated by thin bars
+pub fn status_line(segments: &[StatusSegment], info: &StatusInfo) -> Line<'static> {
+    let mut spans = Vec::with_capacity(segments.len() * 3);
+    for (i, seg) in segments.iter().enumerate() {
+        if i > 0 {
+            spans.push(Span::raw(" │ ").dim());
+        }
+        let (label, value) = segment_text(*seg, info);
+        spans.push(Span::raw(format!("{label} ")).dim());
+        let value = Span::raw(value).bold();
+        let warning = *seg == StatusSegment::Audio && info.underruns.is_some();
+        spans.push(if warning { value.red() } else { value });
+    }
+    Line::from(spans)
+}
+
+fn segment_text(seg: StatusSegment, info: &StatusInfo) -> (&'static str, String) {
+    let snap = info.snapshot;
+    match seg {
+        StatusSegment::Patch => ("patch", snap.patch_name.clone()),
+        StatusSegment::Volume => {
+            let v = format!("{:.2}{}", snap.volume, if snap.muted { " (muted)" } else { "" });
+            ("vol", v)
+        }
+        StatusSegment::Octave => ("oct", snap.keymap.octave().to_string()),
+        StatusSegment::Preset => ("preset", snap.preset_name.clone().unwrap_or_else(|| "-".into())),
+        StatusSegment::Fps => ("fps", format!("{:.0}", info.fps)),
+        StatusSegment::Cpu => ("cpu", info.cpu.map_or_else(|| "-".into(), |c| format!("{c:.0}%"))),
+        StatusSegment::Reverb => {
+            let r = snap.reverb;
+            ("rev", format!("{:.0}% room {:.2}", r.mix * 100.0, r.room_size))
+        }
+        StatusSegment::Tilt => ("tilt", format!("{:+.1}", snap.tilt)),
+        StatusSegment::Rollover => ("rollover", format!("{} keys", snap.max_rollover)),
+        StatusSegment::Sustain => ("sustain", if snap.sustain { "on" } else { "off" }.into()),
+        StatusSegment::Latch => ("latch", if snap.latch { "on" } else { "off" }.into()),
+        StatusSegment::Tempo => {
+            let t = snap.transport;
+            let position = if t.running { format!(" {}.{}", t.bar, t.beat) } else { String::new() };
+            ("tempo", format!("{:.0} {}{position}", t.bpm, t.signature.name()))
+        }
+        StatusSegment::Chord => ("chord", snap.chord.name().into()),
+        StatusSegment::Drive => ("drive", if snap.drive.enabled { snap.drive.curve.name() } else { "off" }.into()),
+        StatusSegment::Recording => ("rec", if snap.recording { "on" } else { "off" }.into()),
+        StatusSegment::Voices => ("voices", snap.voices.to_string()),
+        StatusSegment::Audio => {
+            let load = format!("{:.0}%", snap.audio_load * 100.0);
+            match info.underruns {
+                Some(n) => ("audio", format!("{load} ⚠ {n} underrun{}", if n == 1 { "" } else { "s" })),
+                None => ("audio", load),
+            }
+        }
+        StatusSegment::Scale => {
+            let lock = snap.scale_lock;
+            ("scale", if lock.enabled { lock.name() } else { "off".into() })
+        }
+        StatusSegment::Echo => {
+            let e = snap.echo;
+            if !e.enabled {
+                return ("echo", "off".into());
+            }
+            let pitch = if e.pitch_step == 0 { String::new() } else { format!(" {:+}st", e.pitch_step) };
+            ("echo", format!("{} ×{}{pitch} @{:.0}", e.division.name(), e.repeats, snap.transport.bpm))
+        }
+    }
+}
+
+/// frames drawn per second, averaged over a short window
+pub struct FpsCounter {
+    window_start: Instant,
+    frames: u32,
+    fps: f32,
+}
+
+impl Default for FpsCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FpsCounter {
+    pub fn new() -> Self {
+        Self { window_start: Instant::now(), frames: 0, fps: 0.0 }
+    }
+
+    pub fn tick(&mut self) {
+        self.frames += 1;
+        let elapsed = self.window_start.elapsed().as_secs_f32();
+        if elapsed >= FPS_WINDOW_S {
+            self.fps = self.frames as f32 / elapsed;
+            self.frames = 0;
+            self.window_start = Instant::now();
+        }
+    }
+
+    pub fn fps(&self) -> f32 { self.fps }
+}
+
+/// how many underruns came in lately: the count since the first one in a row, until
+/// `UNDERRUN_WARN_S` pass without another
+#[derive(Default)]
+pub struct UnderrunWatch {
+    seen: u64,
+    since: u64,
+    last: Option<Instant>,
+}
+
+impl UnderrunWatch {
+    /// `total` is the engine's running count
+    pub fn recent(&mut self, total: u64) -> Option<u64> {
+        let warn = Duration::from_secs_f32(UNDERRUN_WARN_S);
+        if self.last.is_some_and(|t| t.elapsed() >= warn) {
+            self.last = None;
+        }
+        if total > self.seen {
+            if self.last.is_none() {
+                self.since = self.seen;
+            }
+            self.last = Some(Instant::now());
+        }
+        self.seen = total;
+        self.last.map(|_| total - self.since)
+    }
+}
+
+/// cpu usage of this process (percent of one core), refreshed at most once per `CPU_REFRESH_S`
+pub struct CpuMonitor {
+    sys: System,
+    pid: Option<Pid>,
+    last: Option<Instant>,
+    usage: Option<f32>,
+}
+
+impl Default for CpuMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CpuMonitor {
+    pub fn new() -> Self {
+        Self { sys: System::new(), pid: sysinfo::get_current_pid().ok(), last: None, usage: None }
+    }
+
+    pub fn usage(&mut self) -> Option<f32> {
+        let pid = self.pid?;
+        let due = self.last.is_none_or(|t| t.elapsed() >= Duration::from_secs_f32(CPU_REFRESH_S));
+        if due {
+            self.sys.refresh_processes_specifics(
+                ProcessesToUpdate::Some(&[pid]),
+                true,
+                ProcessRefreshKind::nothing().with_cpu(),
+            );
+            // the first refresh only sets the baseline, the number means something from the second on
+            if self.last.is_some() {
+                self.usage = self.sys.process(pid).map(|p| p.cpu_usage());
+            }
+            self.last = Some(Instant::now());
+        }
+        self.usage
+    }
+}
@@ -0,0 +1,281 @@
+//! plain line-oriented output for screen readers and dumb terminals: no raw mode, no alternate
+//! screen, no drawing. a short line is printed whenever something audible changes, and a few
+//! typed commands (one per line) ask for the rest.
+
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::watch;
+
+use synth_rs::audio_system::{AudioHandle, AudioSnapshot, LessonAction, LooperAction};
+use synth_rs::config::TILT_STEP;
+use synth_rs::key::Key;
+use synth_rs::profiler::PROFILER;
+
+const HELP: &str = "commands: status, levels, lfo, assist, chord, echo, scale, keys, params, reverb, darker, brighter, freeze, lesson, skip, rollover, loop, stop, undo, clear, profile, help, quit";
+
+pub async fn run_text_ui(
+    handle: AudioHandle,
+    shutdown_tx: watch::Sender<bool>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut snapshot_rx = handle.subscribe();
+    let mut last = snapshot_rx.borrow().clone();
+
+    println!("tjam text mode. {HELP}");
+    print_status(&last);
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+
+    loop {
+        tokio::select! {
+            changed = snapshot_rx.changed() => {
+                if changed.is_err() { break; }
+                let snap = snapshot_rx.borrow_and_update().clone();
+                for line in describe_changes(&last, &snap) {
+                    println!("{line}");
+                }
+                last = snap;
+            }
+            line = lines.next_line() => {
+                let Some(line) = line? else { break; };
+                match line.trim() {
+                    "" => {}
+                    "s" | "status" => print_status(&last),
+                    "l" | "levels" => println!("{}", levels_line(&last)),
+                    "profile" => print_profile(),
+                    "loop" => handle.looper(LooperAction::Advance),
+                    "stop" => handle.looper(LooperAction::TogglePlay),
+                    "undo" => handle.looper(LooperAction::UndoLayer),
+                    "clear" => handle.looper(LooperAction::Clear),
+                    "assist" => handle.set_chord_assist(!last.chord_assist),
+                    "chord" => handle.set_chord(last.chord.next()),
+                    "echo" => {
+                        let mut echo = last.echo;
+                        echo.enabled = !echo.enabled;
+                        handle.set_echo(echo);
+                    }
+                    "scale" => {
+                        let mut lock = last.scale_lock;
+                        lock.enabled = !lock.enabled;
+                        handle.set_scale_lock(lock);
+                    }
+                    "rollover" => handle.start_rollover_test(),
+                    "lfo" => {
+                        for line in lfo_lines(&last) {
+                            println!("{line}");
+                        }
+                    }
+                    "keys" => println!("{}", keys_line(&last)),
+                    "params" => {
+                        for line in param_lines(&last) {
+                            println!("{line}");
+                        }
+                    }
+                    "lesson" => handle.lesson(LessonAction::Toggle),
+                    "skip" => handle.lesson(LessonAction::Next),
+                    "reverb" => println!("{}", reverb_line(&last)),
+                    "darker" => handle.set_tilt(last.tilt - TILT_STEP),
+                    "brighter" => handle.set_tilt(last.tilt + TILT_STEP),
+                    "freeze" => handle.set_freeze(!last.freeze),
+                    "h" | "help" => println!("{HELP}"),
+                    "q" | "quit" => break,
+                    other => println!("unknown command {other}. {HELP}"),
+                }
+            }
+        }
+    }
+
+    let _ = shutdown_tx.send(true);
+    Ok(())
+}
+
+/// output side of text mode on its own, for headless runs: the status once, then a line per
+/// change until the audio side goes away
+pub async fn run_status_lines(handle: AudioHandle) {
+    let mut snapshot_rx = handle.subscribe();
+    let mut last = snapshot_rx.borrow().clone();
+    print_status(&last);
+
+    while snapshot_rx.changed().await.is_ok() {
+        let snap = snapshot_rx.borrow_and_update().clone();
+        for line in describe_changes(&last, &snap) {
+            println!("{line}");
+        }
+        last = snap;
+    }
+}
+
+fn print_status(snap: &AudioSnapshot) {
+    println!("patch {}", snap.patch_name);
+    println!("{}", volume_line(snap));
+    println!("preset {}", snap.preset_name.as_deref().unwrap_or("none"));
+    println!("{}", notes_line(&snap.active_keys));
+    println!("{}", levels_line(snap));
+    println!("tempo {}", snap.transport.describe());
+    println!("octave {}", snap.keymap.octave());
+    println!("{} voices, audio load {:.0}%, {} underruns", snap.voices, snap.audio_load * 100.0, snap.underruns);
+    if snap.max_rollover > 0 {
+        println!("keyboard delivered up to {} keys at once", snap.max_rollover);
+    }
+}
+
+fn describe_changes(old: &AudioSnapshot, new: &AudioSnapshot) -> Vec<String> {
+    let mut out = vec![];
+    if old.patch_name != new.patch_name {
+        out.push(format!("patch {}", new.patch_name));
+    }
+    if old.volume != new.volume || old.muted != new.muted {
+        out.push(volume_line(new));
+    }
+    if old.preset_name != new.preset_name {
+        out.push(format!("preset {}", new.preset_name.as_deref().unwrap_or("none")));
+    }
+    if old.active_keys != new.active_keys {
+        out.push(notes_line(&new.active_keys));
+    }
+    if old.looper.state != new.looper.state || old.looper.layers != new.looper.layers {
+        out.push(looper_line(new));
+    }
+    if old.scale_lock != new.scale_lock {
+        let lock = new.scale_lock;
+        out.push(if lock.enabled { format!("scale lock {}", lock.name()) } else { "scale lock off".to_string() });
+    }
+    if old.lesson != new.lesson
+        && let Some(lesson) = &new.lesson
+    {
+        out.push(lesson.describe(&new.keymap));
+    }
+    if old.sustain != new.sustain {
+        out.push(format!("sustain {}", if new.sustain { "on" } else { "off" }));
+    }
+    if old.latch != new.latch {
+        out.push(format!("latch {}", if new.latch { "on" } else { "off" }));
+    }
+    if old.reverb != new.reverb {
+        out.push(reverb_line(new));
+    }
+    if old.tilt != new.tilt {
+        out.push(tilt_line(new));
+    }
+    let (was, is) = (old.transport, new.transport);
+    if was.bpm != is.bpm || was.signature != is.signature || was.running != is.running {
+        out.push(format!("tempo {}", is.describe()));
+    }
+    if old.echo != new.echo {
+        out.push(echo_line(new));
+    }
+    if old.notice != new.notice
+        && let Some(notice) = &new.notice
+    {
+        out.push(notice.clone());
+    }
+    out
+}
+
+fn echo_line(snap: &AudioSnapshot) -> String {
+    let e = snap.echo;
+    if !e.enabled {
+        return "echo off".to_string();
+    }
+    format!(
+        "echo {} at {:.0} bpm, {} repeats, {:+} semitones each",
+        e.division.name(),
+        snap.transport.bpm,
+        e.repeats,
+        e.pitch_step
+    )
+}
+
+/// every tweakable parameter, one line per patch or effect
+fn param_lines(snap: &AudioSnapshot) -> Vec<String> {
+    snap.params
+        .iter()
+        .map(|g| {
+            let params: Vec<String> = g.params.iter().map(|p| format!("{} {}", p.id, p.value)).collect();
+            format!("{}: {}", g.owner, params.join(", "))
+        })
+        .collect()
+}
+
+/// what each computer key plays right now
+fn keys_line(snap: &AudioSnapshot) -> String {
+    let pairs: Vec<String> = snap.keymap.iter().map(|(kc, key)| format!("{} {key}", snap.keymap.label(kc))).collect();
+    format!("keys {}", pairs.join(", "))
+}
+
+fn tilt_line(snap: &AudioSnapshot) -> String {
+    match snap.tilt {
+        t if t.abs() < 0.05 => "tilt flat".to_string(),
+        t if t < 0.0 => format!("tilt {t:+.1}, darker"),
+        t => format!("tilt {t:+.1}, brighter"),
+    }
+}
+
+fn reverb_line(snap: &AudioSnapshot) -> String {
+    let r = snap.reverb;
+    format!("reverb mix {:.0}%, room {:.2}, damping {:.2}", r.mix * 100.0, r.room_size, r.damping)
+}
+
+fn volume_line(snap: &AudioSnapshot) -> String {
+    if snap.muted {
+        format!("volume {:.2}, muted", snap.volume)
+    } else {
+        format!("volume {:.2}", snap.volume)
+    }
+}
+
+fn notes_line(keys: &[Key]) -> String {
+    if keys.is_empty() {
+        return "notes none".to_string();
+    }
+    let mut keys = keys.to_vec();
+    keys.sort_by_key(|k| k.absolute_semitone());
+    let names: Vec<String> = keys.iter().map(|k| k.to_string()).collect();
+    format!("notes {}", names.join(" "))
+}
+
+fn lfo_lines(snap: &AudioSnapshot) -> Vec<String> {
+    snap.lfos
+        .iter()
+        .map(|s| {
+            let rate = match s.sync {
+                Some(d) => format!("every {} note", d.name()),
+                None => format!("at {:.2} Hz", s.rate_hz),
+            };
+            format!(
+                "{} {}, {} {rate}, depth {:.2}{}",
+                s.target.name(),
+                if s.enabled { "on" } else { "off" },
+                s.shape.name(),
+                s.depth,
+                s.target.unit(),
+            )
+        })
+        .collect()
+}
+
+/// the first call switches profiling on, later ones print what was collected since
+fn print_profile() {
+    if !PROFILER.enabled() {
+        PROFILER.set_enabled(true);
+        println!("profiling on, type profile again for timings");
+        return;
+    }
+    for (name, stat) in PROFILER.stats() {
+        println!("{name}: avg {:.3} ms, max {:.3} ms", stat.avg_us() / 1000.0, stat.max_us() / 1000.0);
+    }
+}
+
+fn looper_line(snap: &AudioSnapshot) -> String {
+    let l = &snap.looper;
+    match l.length_s {
+        Some(length) => format!("looper {}, {:.1} s, {} layers", l.state.name(), length, l.layers),
+        None => format!("looper {}", l.state.name()),
+    }
+}
+
+fn levels_line(snap: &AudioSnapshot) -> String {
+    let levels = format!("peak {:.0} dB, rms {:.0} dB", snap.master_peak_db, snap.master_rms_db);
+    if snap.compressor.enabled {
+        return format!("{levels}, compressor -{:.1} dB", snap.gain_reduction_db);
+    }
+    levels
+}
@@ -0,0 +1,198 @@
+use std::sync::{
+    Arc, Barrier,
+    atomic::{AtomicBool, Ordering},
+};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rodio::Source;
+use synth_rs::fx::adsr::{Adsr, AdsrSource, Curve, EnvelopeState, Gate, Stage};
+
+const SR: u32 = 1_000;
+
+/// endless DC source so the envelope is the only thing deciding when a voice ends
+struct Ones;
+
+impl Iterator for Ones {
+    type Item = f32;
+    fn next(&mut self) -> Option<f32> { Some(1.0) }
+}
+
+impl Source for Ones {
+    fn current_span_len(&self) -> Option<usize> { None }
+    fn channels(&self) -> u16 { 1 }
+    fn sample_rate(&self) -> u32 { SR }
+    fn total_duration(&self) -> Option<Duration> { None }
+}
+
+fn adsr() -> Adsr {
+    Adsr::new(0.05, 0.05, 0.5, 0.1)
+}
+
+fn voice(gate: &Gate) -> AdsrSource {
+    AdsrSource::new(Box::new(Ones), adsr(), SR, gate.clone())
+}
+
+/// upper bound on samples a released voice may still produce: release length plus slack
+fn release_budget() -> usize {
+    (adsr().release_s * SR as f32) as usize + 2
+}
+
+#[test]
+fn gate_drop_in_every_stage_reaches_done() {
+    let env = adsr().to_envelope(SR);
+
+    for hold in 0..200 {
+        let mut state = EnvelopeState::new();
+        for _ in 0..hold {
+            state.step(&env, true);
+        }
+        let held_stage = state.stage();
+
+        let mut steps = 0;
+        while state.stage() != Stage::Done {
+            state.step(&env, false);
+            steps += 1;
+            assert!(steps <= release_budget(), "stuck in {:?} after release (held {hold} samples from {held_stage:?})", state.stage());
+        }
+    }
+}
+
+#[test]
+fn closed_gate_never_advances_into_sustain() {
+    let env = adsr().to_envelope(SR);
+    let mut state = EnvelopeState::new();
+    state.step(&env, true);
+
+    for _ in 0..release_budget() {
+        state.step(&env, false);
+        assert!(matches!(state.stage(), Stage::Release | Stage::Done));
+    }
+    assert_eq!(state.stage(), Stage::Done);
+}
+
+#[test]
+fn gate_closed_before_first_sample_ends_quickly() {
+    let gate: Gate = Arc::new(AtomicBool::new(false));
+    let n = voice(&gate).count();
+    assert!(n <= 1, "voice released before it started produced {n} samples");
+}
+
+#[test]
+fn gate_dropped_from_another_thread_always_ends_voice() {
+    for round in 0..50 {
+        let gate: Gate = Arc::new(AtomicBool::new(true));
+        let barrier = Arc::new(Barrier::new(2));
+
+        let releaser = {
+            let gate = gate.clone();
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                barrier.wait();
+                gate.store(false, Ordering::Release);
+            })
+        };
+
+        let mut src = voice(&gate);
+        // let the voice get into a different stage each round before the release races in
+        for _ in 0..round * 3 {
+            src.next();
+        }
+        barrier.wait();
+
+        let started = Instant::now();
+        let mut after_release = 0usize;
+        while src.next().is_some() {
+            if !gate.load(Ordering::Acquire) {
+                after_release += 1;
+                assert!(after_release <= release_budget(), "round {round}: voice stuck in {:?}", src.stage());
+            }
+            assert!(started.elapsed() < Duration::from_secs(5), "round {round}: gate never observed");
+        }
+
+        releaser.join().unwrap();
+        assert_eq!(src.stage(), Stage::Done);
+    }
+}
+
+#[test]
+fn rapid_press_release_sequences_leave_no_voice_sounding() {
+    let mut voices: Vec<(Gate, AdsrSource)> = Vec::new();
+
+    for i in 0..64 {
+        let gate: Gate = Arc::new(AtomicBool::new(true));
+        let mut src = voice(&gate);
+        for _ in 0..(i % 7) {
+            src.next();
+        }
+        gate.store(false, Ordering::Release);
+        voices.push((gate, src));
+    }
+
+    for (i, (_gate, src)) in voices.iter_mut().enumerate() {
+        let remaining = src.by_ref().count();
+        assert!(remaining <= release_budget(), "voice {i} kept sounding for {remaining} samples");
+        assert_eq!(src.stage(), Stage::Done);
+    }
+}
+
+#[test]
+fn every_curve_lands_on_its_segment_ends() {
+    for curve in Curve::ALL {
+        let mut adsr = adsr();
+        adsr.attack_curve = curve;
+        adsr.decay_curve = curve;
+        adsr.release_curve = curve;
+        let env = adsr.to_envelope(SR);
+        let mut state = EnvelopeState::new();
+
+        let attack = (adsr.attack_s * SR as f32) as usize;
+        for _ in 0..attack - 1 {
+            assert!(state.step(&env, true) < 1.0);
+        }
+        assert_eq!(state.step(&env, true), 1.0, "{curve:?} attack");
+        assert_eq!(state.stage(), Stage::Decay);
+
+        let decay = (adsr.decay_s * SR as f32) as usize;
+        for _ in 0..decay {
+            state.step(&env, true);
+        }
+        assert_eq!(state.level(), adsr.sustain, "{curve:?} decay");
+        assert_eq!(state.stage(), Stage::Sustain);
+
+        let release = (adsr.release_s * SR as f32) as usize;
+        for _ in 0..release {
+            state.step(&env, false);
+        }
+        assert_eq!(state.level(), 0.0, "{curve:?} release");
+        assert_eq!(state.stage(), Stage::Done);
+    }
+}
+
+#[test]
+fn resumed_envelope_carries_on_from_its_level() {
+    let adsr = adsr();
+    let env = adsr.to_envelope(SR);
+
+    // legato: from where the old voice was straight down to sustain, never back up to the peak
+    let mut state = EnvelopeState::resume(0.8, Stage::Decay);
+    let first = state.step(&env, true);
+    assert!(first <= 0.8 && first > adsr.sustain);
+    for _ in 0..(adsr.decay_s * SR as f32) as usize {
+        assert!(state.step(&env, true) <= first);
+    }
+    assert_eq!(state.stage(), Stage::Sustain);
+
+    // from current: the attack starts at the old level rather than at zero
+    let mut state = EnvelopeState::resume(0.3, Stage::Attack);
+    assert!(state.step(&env, true) > 0.3);
+    for _ in 0..(adsr.attack_s * SR as f32) as usize {
+        state.step(&env, true);
+    }
+    assert_eq!(state.stage(), Stage::Decay);
+
+    // nothing left to carry on from: a fresh attack
+    let mut state = EnvelopeState::resume(0.0, Stage::Decay);
+    assert_eq!(state.stage(), Stage::Attack);
+    assert!(state.step(&env, true) < 0.1);
+}
@@ -0,0 +1,106 @@
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use device_query::Keycode;
+use synth_rs::config::{KEY_REPEAT_DELAY_MS, KEY_REPEAT_GAP_MS, TICK};
+use synth_rs::input::KeyState;
+
+fn keys(ks: &[Keycode]) -> HashSet<Keycode> {
+    ks.iter().copied().collect()
+}
+
+/// drives a `KeyState` with one poll every `TICK` ms, like the poll thread
+struct Poller {
+    state: KeyState,
+    now: Instant,
+}
+
+impl Poller {
+    fn new() -> Self {
+        Self { state: KeyState::new(), now: Instant::now() }
+    }
+
+    fn poll(&mut self, down: &[Keycode]) -> (Vec<Keycode>, Vec<Keycode>) {
+        self.now += Duration::from_millis(TICK);
+        let update = self.state.update(&keys(down), self.now);
+        (update.pressed, update.released)
+    }
+
+    /// polls with `down` held for `ms`, returning every press and release seen
+    fn hold(&mut self, down: &[Keycode], ms: u64) -> (Vec<Keycode>, Vec<Keycode>) {
+        let (mut pressed, mut released) = (vec![], vec![]);
+        for _ in 0..ms.div_ceil(TICK) {
+            let (p, r) = self.poll(down);
+            pressed.extend(p);
+            released.extend(r);
+        }
+        (pressed, released)
+    }
+}
+
+#[test]
+fn a_held_key_is_pressed_once() {
+    let mut p = Poller::new();
+    let (pressed, released) = p.hold(&[Keycode::A], 1_000);
+    assert_eq!(pressed, vec![Keycode::A]);
+    assert!(released.is_empty());
+}
+
+#[test]
+fn a_quick_tap_releases_on_the_next_poll() {
+    let mut p = Poller::new();
+    p.hold(&[Keycode::A], 50);
+    let (_, released) = p.poll(&[]);
+    assert_eq!(released, vec![Keycode::A]);
+}
+
+#[test]
+fn a_repeat_blip_on_a_long_hold_never_retriggers() {
+    let mut p = Poller::new();
+    p.hold(&[Keycode::A, Keycode::S, Keycode::D], KEY_REPEAT_DELAY_MS + 100);
+
+    // the OS repeat lets one key up for a poll in the middle of the chord
+    let (pressed, released) = p.poll(&[Keycode::A, Keycode::S]);
+    assert!(pressed.is_empty() && released.is_empty());
+    let (pressed, released) = p.hold(&[Keycode::A, Keycode::S, Keycode::D], 500);
+    assert!(pressed.is_empty(), "retriggered {pressed:?}");
+    assert!(released.is_empty(), "released {released:?}");
+    assert_eq!(p.state.suppressed(), 1);
+}
+
+#[test]
+fn a_real_release_after_a_long_hold_goes_through_after_the_gap() {
+    let mut p = Poller::new();
+    p.hold(&[Keycode::A], KEY_REPEAT_DELAY_MS + 100);
+    let (_, released) = p.hold(&[], KEY_REPEAT_GAP_MS + TICK);
+    assert_eq!(released, vec![Keycode::A]);
+
+    // and a new press after that is a new note
+    let (pressed, _) = p.poll(&[Keycode::A]);
+    assert_eq!(pressed, vec![Keycode::A]);
+}
+
+#[test]
+fn rollover_counts_note_keys_only() {
+    let mut p = Poller::new();
+    p.hold(&[Keycode::A, Keycode::S, Keycode::LShift, Keycode::Space], 50);
+    p.hold(&[Keycode::A, Keycode::S, Keycode::D, Keycode::F], 50);
+    p.hold(&[Keycode::A], 50);
+    assert_eq!(p.state.max_held(), 4);
+}
+
+#[test]
+fn losing_focus_releases_everything_and_regaining_it_plays_nothing() {
+    let mut p = Poller::new();
+    p.hold(&[Keycode::A, Keycode::S], KEY_REPEAT_DELAY_MS + 100);
+    let update = p.state.release_all();
+    assert_eq!(keys(&update.released), keys(&[Keycode::A, Keycode::S]));
+    assert!(update.held.is_empty());
+
+    // still held as focus comes back: no new notes for them, only releases later
+    p.state.resync(&keys(&[Keycode::A]), p.now);
+    let (pressed, _) = p.hold(&[Keycode::A], 50);
+    assert!(pressed.is_empty());
+    let (_, released) = p.poll(&[]);
+    assert_eq!(released, vec![Keycode::A]);
+}
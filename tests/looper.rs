@@ -0,0 +1,112 @@
+use std::time::{Duration, Instant};
+
+use device_query::Keycode;
+use synth_rs::looper::{LoopNote, Looper, LooperState};
+
+fn ms(t0: Instant, ms: u64) -> Instant {
+    t0 + Duration::from_millis(ms)
+}
+
+fn on(keycode: Keycode) -> LoopNote {
+    LoopNote { keycode, on: true, velocity: 0.8 }
+}
+
+fn off(keycode: Keycode) -> LoopNote {
+    LoopNote { keycode, on: false, velocity: 0.0 }
+}
+
+/// a one second loop of A held from 100 to 300 ms, playing from `t0 + 1s`
+fn one_second_loop(t0: Instant) -> Looper {
+    let mut looper = Looper::new(None);
+    looper.advance(t0);
+    looper.record(Keycode::A, true, 0.8, ms(t0, 100));
+    looper.record(Keycode::A, false, 0.0, ms(t0, 300));
+    looper.advance(ms(t0, 1000));
+    looper
+}
+
+#[test]
+fn the_first_take_sets_the_length_and_loops() {
+    let t0 = Instant::now();
+    let mut looper = one_second_loop(t0);
+    let status = looper.status(ms(t0, 1000));
+    assert_eq!(status.state, LooperState::Playing);
+    assert_eq!(status.length_s, Some(1.0));
+    assert_eq!(status.layers, 1);
+
+    assert_eq!(looper.poll(ms(t0, 1050)), vec![]);
+    assert_eq!(looper.poll(ms(t0, 1150)), vec![on(Keycode::A)]);
+    assert_eq!(looper.poll(ms(t0, 1350)), vec![off(Keycode::A)]);
+    // and again on the next pass
+    assert_eq!(looper.poll(ms(t0, 2150)), vec![on(Keycode::A)]);
+    assert_eq!(looper.poll(ms(t0, 2350)), vec![off(Keycode::A)]);
+}
+
+#[test]
+fn a_fixed_length_ends_the_first_take_and_closes_held_keys() {
+    let t0 = Instant::now();
+    let mut looper = Looper::new(Some(Duration::from_secs(2)));
+    looper.advance(t0);
+    looper.record(Keycode::A, true, 0.8, ms(t0, 500));
+    // the take ends at 2 s, however late the poll that notices
+    assert_eq!(looper.poll(ms(t0, 2100)), vec![]);
+    let status = looper.status(ms(t0, 2100));
+    assert_eq!(status.state, LooperState::Playing);
+    assert_eq!(status.length_s, Some(2.0));
+
+    assert_eq!(looper.poll(ms(t0, 2600)), vec![on(Keycode::A)]);
+    // A was still held when the take ended, so it lets go at the end of the loop
+    assert_eq!(looper.poll(ms(t0, 4050)), vec![off(Keycode::A)]);
+}
+
+#[test]
+fn overdubs_layer_on_top_and_undo_takes_them_off() {
+    let t0 = Instant::now();
+    let mut looper = one_second_loop(t0);
+    looper.advance(ms(t0, 1500));
+    assert_eq!(looper.state(), LooperState::Overdubbing);
+    looper.record(Keycode::B, true, 0.8, ms(t0, 1600));
+    looper.record(Keycode::B, false, 0.0, ms(t0, 1700));
+    looper.advance(ms(t0, 1900));
+    assert_eq!(looper.status(ms(t0, 1900)).layers, 2);
+
+    // B sounded live in the pass it was played in, so playback only picks it up from the next
+    assert_eq!(looper.poll(ms(t0, 1950)), vec![on(Keycode::A), off(Keycode::A)]);
+    assert_eq!(looper.poll(ms(t0, 2650)), vec![on(Keycode::A), off(Keycode::A), on(Keycode::B)]);
+
+    // undo lets go of what the dropped layer was holding
+    assert_eq!(looper.undo_layer(ms(t0, 2660)), vec![off(Keycode::B)]);
+    assert_eq!(looper.status(ms(t0, 2660)).layers, 1);
+    assert_eq!(looper.poll(ms(t0, 3650)), vec![on(Keycode::A), off(Keycode::A)]);
+
+    // undoing the only layer clears the loop
+    assert_eq!(looper.undo_layer(ms(t0, 3700)), vec![]);
+    assert_eq!(looper.state(), LooperState::Empty);
+    assert_eq!(looper.status(ms(t0, 3700)).length_s, None);
+}
+
+#[test]
+fn an_overdub_with_nothing_played_adds_no_layer() {
+    let t0 = Instant::now();
+    let mut looper = one_second_loop(t0);
+    looper.advance(ms(t0, 1200));
+    assert_eq!(looper.status(ms(t0, 1200)).layers, 2);
+    looper.advance(ms(t0, 1800));
+    assert_eq!(looper.state(), LooperState::Playing);
+    assert_eq!(looper.status(ms(t0, 1800)).layers, 1);
+}
+
+#[test]
+fn stopping_lets_go_and_starting_plays_from_the_top() {
+    let t0 = Instant::now();
+    let mut looper = one_second_loop(t0);
+    assert_eq!(looper.poll(ms(t0, 1150)), vec![on(Keycode::A)]);
+    assert_eq!(looper.toggle_play(ms(t0, 1200)), vec![off(Keycode::A)]);
+    assert_eq!(looper.state(), LooperState::Stopped);
+    assert_eq!(looper.poll(ms(t0, 1400)), vec![]);
+
+    looper.toggle_play(ms(t0, 5000));
+    assert_eq!(looper.state(), LooperState::Playing);
+    assert_eq!(looper.poll(ms(t0, 5150)), vec![on(Keycode::A)]);
+    assert_eq!(looper.status(ms(t0, 5150)).position_s, 0.15);
+}